@@ -11,7 +11,9 @@ import java.util.concurrent.atomic.AtomicBoolean
  */
 class AudioEncoder(
     private val capture: AudioCapture,
-    private val outputStream: OutputStream
+    private val outputStream: OutputStream,
+    /** Where the stream header goes (the connection, when packets go over UDP) */
+    private val headerStream: OutputStream = outputStream
 ) {
     private var inputThread: Thread? = null
     private val isRunning = AtomicBoolean(false)
@@ -82,8 +84,8 @@ class AudioEncoder(
         header.put(0x00.toByte()) // 0 = RAW PCM i16
         
         synchronized(outputStream) {
-            outputStream.write(header.array())
-            outputStream.flush()
+            headerStream.write(header.array())
+            headerStream.flush()
         }
     }
 }
@@ -1,5 +1,10 @@
 package dev.nl.mirror.audio
 
+import dev.nl.mirror.network.Connection
+import dev.nl.mirror.network.SecureTransport
+import dev.nl.mirror.network.SocketConnection
+import dev.nl.mirror.network.UdpStream
+import dev.nl.mirror.network.readHandshakeLine
 import java.net.ServerSocket
 import java.net.Socket
 
@@ -21,7 +26,7 @@ class AudioServer(private val port: Int = 8890) {
                 while (isRunning) {
                     try {
                         val client = serverSocket?.accept() ?: break
-                        handleClient(client)
+                        acceptClient(client)
                     } catch (e: Exception) {
                         if (!isRunning) break
                     }
@@ -37,40 +42,61 @@ class AudioServer(private val port: Int = 8890) {
         try { serverSocket?.close() } catch (_: Exception) {}
     }
 
-    private fun handleClient(socket: Socket) {
+    private fun acceptClient(socket: Socket) {
         Thread({
-            // Stop previous encoder if exists
-            currentEncoder?.stop()
-            
             try {
                 socket.tcpNoDelay = true
                 socket.sendBufferSize = 64 * 1024
-                
-                val capture = AudioCapture()
-                
-                if (!capture.checkCompatibility()) {
-                    socket.close()
-                    return@Thread
-                }
-                
-                val encoder = AudioEncoder(capture, socket.getOutputStream())
-                currentEncoder = encoder
-                
-                if (!encoder.start()) {
-                    socket.close()
-                    return@Thread
-                }
-                
-                // Keep connection alive until socket closes
-                while (!socket.isClosed && isRunning) {
-                    Thread.sleep(1000)
+                val streams = SecureTransport.accept(socket)
+                // Hosts before UDP support send no handshake line
+                var line: String? = null
+                try {
+                    socket.soTimeout = 500
+                    line = readHandshakeLine(streams.input)
+                } catch (_: Exception) {
+                } finally {
+                    socket.soTimeout = 0
                 }
+                handleClient(SocketConnection(socket, streams), line)
             } catch (_: Exception) {
-            } finally {
-                currentEncoder?.stop()
-                currentEncoder = null
                 try { socket.close() } catch (_: Exception) {}
             }
         }, "audio-client").start()
     }
+
+    /**
+     * Stream audio to one client (a socket on our port or a mux channel) until it goes away.
+     * [line] is the client's handshake, which may offer UDP.
+     */
+    fun handleClient(connection: Connection, line: String?) {
+        // Stop previous encoder if exists
+        currentEncoder?.stop()
+
+        try {
+            val capture = AudioCapture()
+
+            if (!capture.checkCompatibility()) {
+                return
+            }
+
+            // The stream header stays on the connection, packets may go over UDP
+            val udp = UdpStream.offeredPort(line)?.let { UdpStream.accept(connection, it) }
+            val encoder = AudioEncoder(capture, udp ?: connection.output, connection.output)
+            currentEncoder = encoder
+
+            if (!encoder.start()) {
+                return
+            }
+
+            // Keep connection alive until socket closes
+            while (!connection.isClosed && isRunning) {
+                Thread.sleep(1000)
+            }
+        } catch (_: Exception) {
+        } finally {
+            currentEncoder?.stop()
+            currentEncoder = null
+            connection.close()
+        }
+    }
 }
@@ -2,15 +2,40 @@ package dev.nl.mirror.control
 
 import android.content.ClipData
 import android.content.ClipboardManager
+import android.content.ContentValues
 import android.content.Context
+import android.graphics.Bitmap
+import android.graphics.BitmapFactory
+import android.net.Uri
+import android.os.Build
+import android.provider.MediaStore
 import dev.nl.mirror.util.FakeContext
 import android.os.Looper
+import java.io.ByteArrayOutputStream
 
 /**
  * ClipboardController handles clipboard operations via ClipboardManager.
  * Uses FakeContext to access system services properly.
  */
 object ClipboardController {
+
+    // Image transfer is chunked to keep each JSON line small (must match nl-host)
+    const val IMAGE_CHUNK_SIZE = 64 * 1024
+    const val MAX_IMAGE_SIZE = 16 * 1024 * 1024
+
+    // MediaStore name of images pasted from the host
+    private const val IMAGE_NAME_PREFIX = "nl-clipboard-"
+
+    // Reassembly buffer for images sent by the host, with the declared size and chunk count
+    // and the index of the chunk expected next
+    private var incomingImage: ByteArrayOutputStream? = null
+    private var incomingSize = 0
+    private var incomingTotal = 0
+    private var incomingIndex = 0
+    // MediaStore entry of the image this process last put on the clipboard
+    private var pastedImage: Uri? = null
+    // PNG of the current clipboard image, cached while the host pulls its chunks
+    private var outgoingImage: ByteArray? = null
     
     private val clipboardManager: ClipboardManager? by lazy {
         try {
@@ -80,4 +105,145 @@ object ClipboardController {
         } catch (_: Exception) {}
         return getText()
     }
+
+    fun hasImage(): Boolean {
+        return try {
+            val manager = clipboardManager ?: return false
+            val clipData = manager.primaryClip ?: return false
+            if (clipData.itemCount == 0) return false
+            val mimeType = clipData.description.getMimeType(0) ?: ""
+            clipData.getItemAt(0).uri != null && mimeType.startsWith("image/")
+        } catch (e: Exception) {
+            println("[CLIPBOARD] ERROR hasImage: ${e.message}")
+            false
+        }
+    }
+
+    /**
+     * Append one chunk of a host image. Returns the complete PNG once the last chunk arrives.
+     * Chunks must come in order and add up to the declared size; otherwise the image is
+     * discarded and the host has to start over at chunk 0.
+     */
+    @Synchronized
+    fun receiveImageChunk(index: Int, total: Int, size: Int, data: ByteArray): ByteArray? {
+        if (index == 0) {
+            incomingImage = null
+            if (size <= 0 || size > MAX_IMAGE_SIZE) {
+                throw IllegalArgumentException("Invalid image size: $size bytes")
+            }
+            if (total != (size + IMAGE_CHUNK_SIZE - 1) / IMAGE_CHUNK_SIZE) {
+                throw IllegalArgumentException("Invalid chunk count $total for $size bytes")
+            }
+            incomingImage = ByteArrayOutputStream(size)
+            incomingSize = size
+            incomingTotal = total
+            incomingIndex = 0
+        }
+        val buffer = incomingImage ?: throw IllegalStateException("Missing first image chunk")
+        try {
+            if (index != incomingIndex || total != incomingTotal || size != incomingSize) {
+                throw IllegalArgumentException("Unexpected image chunk $index of $total (expected $incomingIndex of $incomingTotal)")
+            }
+            if (buffer.size() + data.size > incomingSize) {
+                throw IllegalArgumentException("Image exceeds declared size of $incomingSize bytes")
+            }
+            buffer.write(data)
+        } catch (e: IllegalArgumentException) {
+            incomingImage = null
+            throw e
+        }
+        incomingIndex++
+        if (incomingIndex < incomingTotal) return null
+        incomingImage = null
+        if (buffer.size() != incomingSize) {
+            throw IllegalArgumentException("Image incomplete: ${buffer.size()} of $incomingSize bytes")
+        }
+        return buffer.toByteArray()
+    }
+
+    /**
+     * Return chunk [index] of the current clipboard image as (total, size, bytes).
+     * The image is encoded to PNG when chunk 0 is requested. Returns null if there is no image.
+     */
+    @Synchronized
+    fun getImageChunk(index: Int): Triple<Int, Int, ByteArray>? {
+        if (index == 0) outgoingImage = readImagePng()
+        val png = outgoingImage ?: return null
+        val total = (png.size + IMAGE_CHUNK_SIZE - 1) / IMAGE_CHUNK_SIZE
+        if (index >= total) return null
+        val start = index * IMAGE_CHUNK_SIZE
+        val end = minOf(start + IMAGE_CHUNK_SIZE, png.size)
+        if (index == total - 1) outgoingImage = null
+        return Triple(total, png.size, png.copyOfRange(start, end))
+    }
+
+    private fun readImagePng(): ByteArray? {
+        return try {
+            if (!hasImage()) return null
+            val uri = clipboardManager?.primaryClip?.getItemAt(0)?.uri ?: return null
+            val resolver = FakeContext.get().contentResolver
+            val bitmap = resolver.openInputStream(uri)?.use { BitmapFactory.decodeStream(it) } ?: return null
+            val out = ByteArrayOutputStream()
+            bitmap.compress(Bitmap.CompressFormat.PNG, 100, out)
+            bitmap.recycle()
+            val png = out.toByteArray()
+            if (png.size > MAX_IMAGE_SIZE) null else png
+        } catch (e: Exception) {
+            println("[CLIPBOARD] ERROR readImagePng: ${e.message}")
+            null
+        }
+    }
+
+    /**
+     * Store the PNG in MediaStore (so other apps can read it) and put its URI on the clipboard.
+     * A FileProvider needs an installed app, so the entry goes to MediaStore; only the entry
+     * this process inserted for the previous paste is deleted, never images it did not create.
+     */
+    @Synchronized
+    fun setImageAndPaste(png: ByteArray, paste: Boolean): Boolean {
+        val success = try {
+            val manager = clipboardManager ?: return false
+            val resolver = FakeContext.get().contentResolver
+            pastedImage?.let { previous ->
+                try {
+                    resolver.delete(previous, null, null)
+                } catch (e: Exception) {
+                    println("[CLIPBOARD] ERROR deleting previous image: ${e.message}")
+                }
+                pastedImage = null
+            }
+            val values = ContentValues().apply {
+                put(MediaStore.Images.Media.DISPLAY_NAME, "$IMAGE_NAME_PREFIX${System.currentTimeMillis()}.png")
+                put(MediaStore.Images.Media.MIME_TYPE, "image/png")
+                if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.Q) {
+                    put(MediaStore.Images.Media.RELATIVE_PATH, "Pictures/NL-Mirror")
+                }
+            }
+            val uri = resolver.insert(MediaStore.Images.Media.EXTERNAL_CONTENT_URI, values) ?: return false
+            pastedImage = uri
+            resolver.openOutputStream(uri)?.use { it.write(png) } ?: throw IllegalStateException("Cannot write $uri")
+            manager.setPrimaryClip(ClipData.newUri(resolver, "image", uri))
+            true
+        } catch (e: Exception) {
+            println("[CLIPBOARD] ERROR setImage: ${e.message}")
+            // Don't leave a half-written entry behind
+            pastedImage?.let { uri ->
+                try {
+                    FakeContext.get().contentResolver.delete(uri, null, null)
+                } catch (_: Exception) {}
+            }
+            pastedImage = null
+            false
+        }
+        if (success && paste) {
+            try {
+                val ctrl = 113; val v = 50; val metaCtrl = 4096
+                dev.nl.mirror.input.InputController.injectKey(ctrl, android.view.KeyEvent.ACTION_DOWN)
+                dev.nl.mirror.input.InputController.injectKey(v, android.view.KeyEvent.ACTION_DOWN, metaCtrl)
+                dev.nl.mirror.input.InputController.injectKey(v, android.view.KeyEvent.ACTION_UP, metaCtrl)
+                dev.nl.mirror.input.InputController.injectKey(ctrl, android.view.KeyEvent.ACTION_UP)
+            } catch (_: Exception) {}
+        }
+        return success
+    }
 }
@@ -0,0 +1,149 @@
+package dev.nl.mirror.control
+
+import java.io.File
+import java.io.FileOutputStream
+import java.util.zip.ZipFile
+
+/**
+ * FileTransferController receives files sent by the host in chunks
+ * (drag-and-drop onto the mirror window) and pushes or installs them.
+ */
+object FileTransferController {
+
+    private const val INSTALL_DIR = "/data/local/tmp"
+
+    /**
+     * The transfer in progress on one connection. Chunks must arrive in order; anything else
+     * aborts the transfer and deletes the partial file, as does the connection going away.
+     */
+    class Receiver {
+        private var requested: File? = null
+        private var written: File? = null
+        private var stream: FileOutputStream? = null
+        private var total = 0
+        private var nextIndex = 0
+
+        /**
+         * Append chunk [index] of [total] for [target]. Chunk 0 starts a new transfer, dropping
+         * an unfinished one. Unless [overwrite] is set an existing file is kept and the transfer
+         * goes to a free "name (n).ext" instead. Returns the file written and whether it is complete.
+         */
+        fun receive(target: File, index: Int, total: Int, data: ByteArray, overwrite: Boolean): Pair<File, Boolean> {
+            if (index == 0) {
+                abort()
+                if (total < 1) throw IllegalArgumentException("Invalid chunk count $total")
+                target.parentFile?.mkdirs()
+                val file = if (overwrite) target else freeName(target)
+                written = file
+                stream = FileOutputStream(file)
+                requested = target
+                this.total = total
+                nextIndex = 0
+            }
+            val stream = stream ?: throw IllegalStateException("Missing first chunk")
+            val file = written ?: throw IllegalStateException("Missing first chunk")
+            try {
+                if (target.path != requested?.path || total != this.total || index != nextIndex) {
+                    throw IllegalArgumentException("Unexpected chunk $index of $total (expected $nextIndex of ${this.total})")
+                }
+                stream.write(data)
+            } catch (e: Exception) {
+                abort()
+                throw e
+            }
+            nextIndex++
+            if (nextIndex < this.total) return Pair(file, false)
+            this.stream = null
+            written = null
+            requested = null
+            stream.close()
+            return Pair(file, true)
+        }
+
+        /** Drop the unfinished transfer, deleting what was written of it */
+        fun abort() {
+            val file = written
+            try { stream?.close() } catch (_: Exception) {}
+            stream = null
+            written = null
+            requested = null
+            file?.delete()
+        }
+    }
+
+    /** [target], or the first free "name (n).ext" next to it; the file is created empty */
+    private fun freeName(target: File): File {
+        if (target.createNewFile()) return target
+        val extension = if (target.extension.isEmpty()) "" else "." + target.extension
+        for (n in 1..999) {
+            val candidate = File(target.parentFile, "${target.nameWithoutExtension} ($n)$extension")
+            if (candidate.createNewFile()) return candidate
+        }
+        throw IllegalStateException("No free file name in ${target.parent}")
+    }
+
+    /** Destination for a pushed file, keeping only the base name of [name]. */
+    fun pushTarget(dir: String, name: String): File = File(dir, File(name).name)
+
+    /** Temporary location for an APK before installation. */
+    fun installTarget(name: String): File = File(INSTALL_DIR, "nl-install-" + File(name).name)
+
+    /**
+     * Install an .apk directly or an .apks bundle via a package installer session.
+     * Returns (success, package manager output).
+     */
+    fun install(file: File): Pair<Boolean, String> {
+        return try {
+            if (file.name.endsWith(".apks", ignoreCase = true)) installBundle(file)
+            else runPm("install", "-r", "-t", file.path)
+        } finally {
+            file.delete()
+        }
+    }
+
+    private fun installBundle(bundle: File): Pair<Boolean, String> {
+        val splitDir = File(INSTALL_DIR, "nl-install-splits")
+        splitDir.deleteRecursively()
+        splitDir.mkdirs()
+        try {
+            val splits = mutableListOf<File>()
+            ZipFile(bundle).use { zip ->
+                for (entry in zip.entries()) {
+                    if (entry.isDirectory || !entry.name.endsWith(".apk")) continue
+                    val out = File(splitDir, File(entry.name).name)
+                    zip.getInputStream(entry).use { input ->
+                        FileOutputStream(out).use { input.copyTo(it) }
+                    }
+                    splits.add(out)
+                }
+            }
+            if (splits.isEmpty()) return Pair(false, "No APKs found in bundle")
+
+            val (created, createOutput) = runPm("install-create", "-r", "-t")
+            val sessionId = Regex("\\[(\\d+)]").find(createOutput)?.groupValues?.get(1)
+            if (!created || sessionId == null) return Pair(false, createOutput)
+
+            for (split in splits) {
+                val (written, writeOutput) = runPm(
+                    "install-write", "-S", split.length().toString(), sessionId, split.name, split.path
+                )
+                if (!written) {
+                    runPm("install-abandon", sessionId)
+                    return Pair(false, writeOutput)
+                }
+            }
+            return runPm("install-commit", sessionId)
+        } finally {
+            splitDir.deleteRecursively()
+        }
+    }
+
+    private fun runPm(vararg args: String): Pair<Boolean, String> {
+        val process = ProcessBuilder(listOf("pm") + args)
+            .redirectErrorStream(true)
+            .start()
+        val output = process.inputStream.bufferedReader().readText().trim()
+        val exitCode = process.waitFor()
+        return Pair(exitCode == 0 && !output.contains("Failure"), output)
+    }
+}
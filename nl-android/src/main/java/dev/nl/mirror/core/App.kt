@@ -2,6 +2,7 @@ package dev.nl.mirror.core
 
 import dev.nl.mirror.audio.AudioServer
 import dev.nl.mirror.network.CommandServer
+import dev.nl.mirror.network.SecureTransport
 import dev.nl.mirror.network.SocketServer
 import android.os.Build
 import org.lsposed.hiddenapibypass.HiddenApiBypass
@@ -25,20 +26,24 @@ object App {
         dev.nl.mirror.util.FakeContext.get()
         
         try {
-            // Video Server (port 8888)
-            val videoServer = SocketServer(8888)
-            Thread { videoServer.start() }.start()
-            
+            // Pre-shared key from the launcher turns on encryption for every port
+            SecureTransport.loadKey()
+
             // Command Server (port 8889)
             val commandServer = CommandServer(8889)
             commandServer.start()
             
             // Audio Server (port 8890) - Android 11+ only
+            var audioServer: AudioServer? = null
             if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) {
-                val audioServer = AudioServer(8890)
+                audioServer = AudioServer(8890)
                 audioServer.start()
             }
             
+            // Video Server (port 8888), also carries every service for multiplexed clients
+            val videoServer = SocketServer(8888, commandServer, audioServer)
+            Thread { videoServer.start() }.start()
+            
             Thread.currentThread().join()
         } catch (e: Exception) {
             System.exit(1)
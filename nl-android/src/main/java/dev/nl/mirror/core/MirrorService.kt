@@ -1,57 +1,96 @@
 package dev.nl.mirror.core
 
+import dev.nl.mirror.network.Connection
 import dev.nl.mirror.network.PacketWriter
 import dev.nl.mirror.video.DisplayManager
 import dev.nl.mirror.video.ScreenEncoder
-import java.net.Socket
+import dev.nl.mirror.video.StreamConfig
+import java.io.OutputStream
 
 object MirrorService {
     private var currentSessionThread: Thread? = null
     private var isSessionRunning = false
 
-    fun startSession(socket: Socket, bitrate: Int, maxResolution: Int) {
+    @Volatile private var config = StreamConfig()
+    @Volatile private var currentEncoder: ScreenEncoder? = null
+    /** Set when a size or frame-rate change needs a new encoder */
+    @Volatile private var restartRequested = false
+
+    /** Stream to [output]: the connection itself, or a UdpStream negotiated on it */
+    fun startSession(connection: Connection, initialConfig: StreamConfig, output: OutputStream) {
         stopSession()
+        config = initialConfig
         isSessionRunning = true
         currentSessionThread = Thread {
-            val packetWriter = PacketWriter(socket.getOutputStream())
+            val packetWriter = PacketWriter(output)
             packetWriter.start()
             val watcher = DisplayManager.RotationWatcher()
             watcher.start()
             var encoder: ScreenEncoder? = null
 
             try {
-                while (isSessionRunning && !socket.isClosed && !Thread.interrupted()) {
+                while (isSessionRunning && !connection.isClosed && !Thread.interrupted()) {
                     val (w_phys, h_phys) = DisplayManager.getDisplaySize()
                     val rotation = watcher.getCurrentRotation()
                     val isLandscape = (rotation == 1 || rotation == 3)
                     val width = if (isLandscape) h_phys else w_phys
                     val height = if (isLandscape) w_phys else h_phys
-                    val scale = if (width > maxResolution) maxResolution.toFloat() / width else 1.0f
-                    val encW = (width * scale).toInt()
-                    val encH = (height * scale).toInt()
 
-                    encoder = ScreenEncoder(width, height, bitrate, packetWriter)
+                    restartRequested = false
+                    encoder = ScreenEncoder(width, height, config, packetWriter)
                     encoder.start()
+                    currentEncoder = encoder
                     watcher.resetChangeFlag()
 
-                    while (isSessionRunning && !socket.isClosed) {
-                        if (watcher.hasChanged()) break
+                    // Rotation or a new size/frame rate: restart the encoder (the host gets a new SPS)
+                    while (isSessionRunning && !connection.isClosed) {
+                        if (watcher.hasChanged() || restartRequested) break
                         Thread.sleep(100)
                     }
+                    currentEncoder = null
                     encoder.stop()
                     encoder = null
                 }
             } catch (_: Exception) {
             } finally {
+                currentEncoder = null
                 encoder?.stop()
                 watcher.stopWatcher()
                 packetWriter.stop()
-                try { socket.close() } catch (_: Exception) {}
+                connection.close()
             }
         }
         currentSessionThread?.start()
     }
 
+    /**
+     * Change encoder settings mid-session; null keeps the current value.
+     * Bitrate changes apply to the running encoder, size and frame-rate changes restart it.
+     */
+    @Synchronized
+    fun updateConfig(bitrate: Int?, maxSize: Int?, maxFps: Int?): StreamConfig {
+        val old = config
+        val new = old.copy(
+            bitrate = bitrate ?: old.bitrate,
+            maxSize = maxSize ?: old.maxSize,
+            maxFps = maxFps ?: old.maxFps
+        )
+        config = new
+        if (new.maxSize != old.maxSize || new.maxFps != old.maxFps) {
+            restartRequested = true
+        } else if (new.bitrate != old.bitrate) {
+            currentEncoder?.setBitrate(new.bitrate)
+        }
+        return new
+    }
+
+    /** Request an IDR frame from the running encoder; false if no encoder is running */
+    fun requestKeyframe(): Boolean {
+        val encoder = currentEncoder ?: return false
+        encoder.requestKeyframe()
+        return true
+    }
+
     fun stopSession() {
         isSessionRunning = false
         currentSessionThread?.interrupt()
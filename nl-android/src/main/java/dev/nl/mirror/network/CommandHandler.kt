@@ -3,6 +3,7 @@ package dev.nl.mirror.network
 import android.view.KeyEvent
 import android.view.MotionEvent
 import dev.nl.mirror.control.ClipboardController
+import dev.nl.mirror.control.FileTransferController
 import dev.nl.mirror.input.InputController
 import dev.nl.mirror.input.TouchScaler
 import dev.nl.mirror.util.PerformanceMonitor
@@ -16,7 +17,7 @@ import java.io.InputStream
  */
 object CommandHandler {
 
-    fun handleCommand(reader: java.io.BufferedReader): String {
+    fun handleCommand(reader: java.io.BufferedReader, uploads: FileTransferController.Receiver): String {
         val line = reader.readLine() ?: return """{"error": "Empty command"}"""
         
         return try {
@@ -72,7 +73,67 @@ object CommandHandler {
                         ClipboardController.getText() ?: ""
                     }
                     val escaped = text.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
-                    """{"cmd": "get_clipboard", "text": "$escaped"}"""
+                    val hasImage = text.isEmpty() && ClipboardController.hasImage()
+                    """{"cmd": "get_clipboard", "text": "$escaped", "image": $hasImage}"""
+                }
+                // Image clipboard: PNG bytes in base64 chunks (see ClipboardController.IMAGE_CHUNK_SIZE)
+                "set_clipboard_image" -> {
+                    val index = json.getInt("index")
+                    val total = json.getInt("total")
+                    val size = json.getInt("size")
+                    val paste = json.optBoolean("paste", false)
+                    val data = android.util.Base64.decode(json.getString("data"), android.util.Base64.DEFAULT)
+                    val png = ClipboardController.receiveImageChunk(index, total, size, data)
+                    if (png != null) {
+                        Thread {
+                            ClipboardController.setImageAndPaste(png, paste)
+                        }.start()
+                    }
+                    """{"cmd": "set_clipboard_image", "index": $index, "success": true}"""
+                }
+                "get_clipboard_image" -> {
+                    val index = json.optInt("index", 0)
+                    val chunk = ClipboardController.getImageChunk(index)
+                    if (chunk == null) {
+                        """{"cmd": "get_clipboard_image", "index": $index, "total": 0, "size": 0}"""
+                    } else {
+                        val (total, size, bytes) = chunk
+                        val data = android.util.Base64.encodeToString(bytes, android.util.Base64.NO_WRAP)
+                        """{"cmd": "get_clipboard_image", "index": $index, "total": $total, "size": $size, "data": "$data"}"""
+                    }
+                }
+                // File transfer: base64 chunks, acknowledged one by one (see FileTransferController)
+                "push_file" -> {
+                    val index = json.getInt("index")
+                    val total = json.getInt("total")
+                    val dir = json.optString("dir", "/sdcard/Download")
+                    val target = FileTransferController.pushTarget(dir, json.getString("name"))
+                    val data = android.util.Base64.decode(json.getString("data"), android.util.Base64.DEFAULT)
+                    val (file, _) = uploads.receive(target, index, total, data, overwrite = false)
+                    JSONObject()
+                        .put("cmd", "push_file")
+                        .put("index", index)
+                        .put("success", true)
+                        .put("path", file.path)
+                        .toString()
+                }
+                "install_apk" -> {
+                    val index = json.getInt("index")
+                    val total = json.getInt("total")
+                    val target = FileTransferController.installTarget(json.getString("name"))
+                    val data = android.util.Base64.decode(json.getString("data"), android.util.Base64.DEFAULT)
+                    val (file, complete) = uploads.receive(target, index, total, data, overwrite = true)
+                    val (success, message) = if (complete) {
+                        FileTransferController.install(file)
+                    } else {
+                        Pair(true, "")
+                    }
+                    JSONObject()
+                        .put("cmd", "install_apk")
+                        .put("index", index)
+                        .put("success", success)
+                        .put("message", message)
+                        .toString()
                 }
                 // Legacy commands (kept for compatibility)
                 "tap" -> {
@@ -114,11 +175,47 @@ object CommandHandler {
                     val stats = PerformanceMonitor.getStats()
                     """{"cmd": "stats", "data": $stats}"""
                 }
+                // Clock sync: the monotonic clock that video and audio PTS are taken from
+                "clock" -> {
+                    val now = System.nanoTime() / 1000
+                    """{"cmd": "clock", "time_us": $now}"""
+                }
+                // Liveness check from the host
+                "ping" -> """{"cmd": "ping"}"""
                 "set_screen_power_mode" -> {
                     val mode = json.getInt("mode") // 0=OFF, 2=NORMAL
                     val success = dev.nl.mirror.video.DisplayControl.setPowerMode(mode)
                     """{"cmd": "set_screen_power_mode", "success": $success}"""
                 }
+                // Toggle portrait/landscape
+                "rotate" -> {
+                    val rotation = dev.nl.mirror.video.RotationControl.toggle()
+                    if (rotation != null) {
+                        """{"cmd": "rotate", "success": true, "rotation": $rotation}"""
+                    } else {
+                        """{"cmd": "rotate", "success": false}"""
+                    }
+                }
+                // Encoder settings; omitted fields keep their value
+                "set_video" -> {
+                    val config = dev.nl.mirror.core.MirrorService.updateConfig(
+                        if (json.has("bitrate")) json.getInt("bitrate") else null,
+                        if (json.has("max_size")) json.getInt("max_size") else null,
+                        if (json.has("max_fps")) json.getInt("max_fps") else null
+                    )
+                    JSONObject()
+                        .put("cmd", "set_video")
+                        .put("success", true)
+                        .put("bitrate", config.bitrate)
+                        .put("max_size", config.maxSize)
+                        .put("max_fps", config.maxFps)
+                        .toString()
+                }
+                // Host decoder lost sync; a restarting encoder starts with an IDR anyway
+                "request_keyframe" -> {
+                    val success = dev.nl.mirror.core.MirrorService.requestKeyframe()
+                    """{"cmd": "request_keyframe", "success": $success}"""
+                }
                 "start_mock_location" -> {
                     dev.nl.mirror.input.LocationController.startMocking()
                     """{"cmd": "start_mock_location", "success": true}"""
@@ -1,5 +1,6 @@
 package dev.nl.mirror.network
 
+import dev.nl.mirror.control.FileTransferController
 import java.net.ServerSocket
 import java.util.concurrent.Executors
 import java.util.concurrent.TimeUnit
@@ -20,16 +21,8 @@ class CommandServer(private val port: Int) {
                         val client = serverSocket?.accept() ?: break
                         executor.execute {
                             try {
-                                val inputStream = client.getInputStream()
-                                val outputStream = client.getOutputStream()
-                                val reader = inputStream.bufferedReader()
-                                while (!client.isClosed && isRunning) {
-                                    val response = CommandHandler.handleCommand(reader)
-                                    outputStream.write((response + "\n").toByteArray())
-                                    outputStream.flush()
-                                }
+                                handleClient(SocketConnection(client, SecureTransport.accept(client)))
                             } catch (_: Exception) {
-                            } finally {
                                 try { client.close() } catch (_: Exception) {}
                             }
                         }
@@ -44,6 +37,23 @@ class CommandServer(private val port: Int) {
         }.start()
     }
 
+    /** Answer commands until the client goes away (a socket on our port or a mux channel) */
+    fun handleClient(connection: Connection) {
+        val uploads = FileTransferController.Receiver()
+        try {
+            val reader = connection.input.bufferedReader()
+            while (!connection.isClosed && isRunning) {
+                val response = CommandHandler.handleCommand(reader, uploads)
+                connection.output.write((response + "\n").toByteArray())
+                connection.output.flush()
+            }
+        } catch (_: Exception) {
+        } finally {
+            uploads.abort()
+            connection.close()
+        }
+    }
+
     fun stop() {
         isRunning = false
         try { serverSocket?.close() } catch (_: Exception) {}
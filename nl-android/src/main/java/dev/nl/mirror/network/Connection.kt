@@ -0,0 +1,46 @@
+package dev.nl.mirror.network
+
+import java.io.ByteArrayOutputStream
+import java.io.InputStream
+import java.io.OutputStream
+import java.net.InetAddress
+import java.net.Socket
+
+/** One client of a server: its own TCP socket, or a channel of a [MuxSession] */
+interface Connection {
+    val input: InputStream
+    val output: OutputStream
+    val isClosed: Boolean
+    /** Where the client connects from (loopback for adb-forwarded connections) */
+    val remoteAddress: InetAddress?
+    fun close()
+}
+
+/** A client on its own port, with streams from [SecureTransport.accept] */
+class SocketConnection(
+    private val socket: Socket,
+    streams: SecureTransport.Streams
+) : Connection {
+    override val input: InputStream = streams.input
+    override val output: OutputStream = streams.output
+    override val isClosed: Boolean get() = socket.isClosed
+    override val remoteAddress: InetAddress? get() = socket.inetAddress
+
+    override fun close() {
+        try { socket.close() } catch (_: Exception) {}
+    }
+}
+
+/**
+ * Read a handshake line byte by byte, so nothing after it is consumed
+ * (binary data may follow right away)
+ */
+fun readHandshakeLine(input: InputStream): String? {
+    val line = ByteArrayOutputStream()
+    while (true) {
+        val b = input.read()
+        if (b == -1) return if (line.size() == 0) null else line.toString("UTF-8")
+        if (b == '\n'.code) return line.toString("UTF-8").trimEnd('\r')
+        line.write(b)
+    }
+}
@@ -0,0 +1,181 @@
+package dev.nl.mirror.network
+
+import dev.nl.mirror.util.Logger
+import java.io.DataInputStream
+import java.io.IOException
+import java.io.InputStream
+import java.io.OutputStream
+import java.net.InetAddress
+import java.net.Socket
+import java.nio.ByteBuffer
+import java.util.concurrent.ConcurrentHashMap
+import java.util.concurrent.LinkedBlockingQueue
+
+/**
+ * Video, audio and control over one socket (nl-host `--mux`).
+ *
+ * The host opens it on the video port with a "mux" handshake line, then sends frames:
+ *
+ *   kind (1) | channel (2, BE) | length (4, BE) | payload
+ *
+ * OPEN carries the service, DATA a piece of the channel's byte stream and CLOSE nothing.
+ * Each channel behaves like a connection to that service's own port, so the servers handle
+ * it exactly like a TCP client. Must match nl-host's network/mux.rs.
+ */
+class MuxSession(
+    private val socket: Socket,
+    streams: SecureTransport.Streams,
+    private val open: (service: Int, channel: Connection) -> Unit
+) {
+    companion object {
+        const val HELLO = "mux"
+
+        const val SERVICE_VIDEO = 0
+        const val SERVICE_AUDIO = 1
+        const val SERVICE_CONTROL = 2
+
+        private const val TAG = "MUX"
+        private const val OPEN = 0
+        private const val DATA = 1
+        private const val CLOSE = 2
+        private const val HEADER_SIZE = 7
+        /** Largest payload per frame; bigger writes are split */
+        private const val MAX_FRAME = 64 * 1024
+        private val EOF = ByteArray(0)
+    }
+
+    private val input = DataInputStream(streams.input)
+    private val output = streams.output
+    private val channels = ConcurrentHashMap<Int, Channel>()
+
+    /** Demultiplex until the socket closes; closes every channel on exit */
+    fun run() {
+        Logger.i(TAG, "Multiplexed session started")
+        try {
+            while (!socket.isClosed) {
+                val kind = input.readUnsignedByte()
+                val id = input.readUnsignedShort()
+                val length = input.readInt()
+                if (length < 0 || length > MAX_FRAME) {
+                    throw IOException("Invalid frame length $length")
+                }
+                val payload = ByteArray(length)
+                input.readFully(payload)
+
+                when (kind) {
+                    OPEN -> {
+                        val channel = Channel(id)
+                        channels.put(id, channel)?.closeLocal()
+                        val service = if (payload.isNotEmpty()) payload[0].toInt() else -1
+                        Thread({
+                            try {
+                                open(service, channel)
+                            } catch (_: Exception) {
+                                channel.close()
+                            }
+                        }, "mux-$id").start()
+                    }
+                    DATA -> channels[id]?.deliver(payload)
+                    CLOSE -> channels.remove(id)?.closeLocal()
+                }
+            }
+        } catch (_: Exception) {
+        } finally {
+            for (channel in channels.values) channel.closeLocal()
+            channels.clear()
+            try { socket.close() } catch (_: Exception) {}
+            Logger.i(TAG, "Multiplexed session ended")
+        }
+    }
+
+    private fun send(kind: Int, id: Int, data: ByteArray, off: Int, len: Int) {
+        val frame = ByteBuffer.allocate(HEADER_SIZE + len)
+        frame.put(kind.toByte())
+        frame.putShort(id.toShort())
+        frame.putInt(len)
+        frame.put(data, off, len)
+        synchronized(output) {
+            output.write(frame.array())
+            output.flush()
+        }
+    }
+
+    private inner class Channel(private val id: Int) : Connection {
+        private val queue = LinkedBlockingQueue<ByteArray>()
+        @Volatile private var closed = false
+
+        override val isClosed: Boolean get() = closed || socket.isClosed
+        override val remoteAddress: InetAddress? get() = socket.inetAddress
+
+        override val input: InputStream = object : InputStream() {
+            private var current = ByteArray(0)
+            private var pos = 0
+
+            /** Wait for the next DATA payload; false once the channel is closed */
+            private fun fill(): Boolean {
+                while (pos == current.size) {
+                    val next = queue.take()
+                    if (next === EOF) {
+                        queue.offer(EOF)
+                        return false
+                    }
+                    current = next
+                    pos = 0
+                }
+                return true
+            }
+
+            override fun read(): Int {
+                if (!fill()) return -1
+                return current[pos++].toInt() and 0xFF
+            }
+
+            override fun read(b: ByteArray, off: Int, len: Int): Int {
+                if (len == 0) return 0
+                if (!fill()) return -1
+                val n = minOf(len, current.size - pos)
+                System.arraycopy(current, pos, b, off, n)
+                pos += n
+                return n
+            }
+
+            override fun available(): Int = current.size - pos
+
+            override fun close() = this@Channel.close()
+        }
+
+        override val output: OutputStream = object : OutputStream() {
+            override fun write(b: Int) = write(byteArrayOf(b.toByte()), 0, 1)
+
+            override fun write(b: ByteArray, off: Int, len: Int) {
+                if (isClosed) throw IOException("Channel closed")
+                var offset = off
+                val end = off + len
+                while (offset < end) {
+                    val n = minOf(MAX_FRAME, end - offset)
+                    send(DATA, id, b, offset, n)
+                    offset += n
+                }
+            }
+
+            override fun close() = this@Channel.close()
+        }
+
+        fun deliver(payload: ByteArray) {
+            if (payload.isNotEmpty()) queue.offer(payload)
+        }
+
+        /** Closed by the host or with the session: wake up readers */
+        fun closeLocal() {
+            closed = true
+            queue.offer(EOF)
+        }
+
+        override fun close() {
+            if (closed) return
+            closeLocal()
+            channels.remove(id, this)
+            try { send(CLOSE, id, EOF, 0, 0) } catch (_: Exception) {}
+        }
+    }
+}
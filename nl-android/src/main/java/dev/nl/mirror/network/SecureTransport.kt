@@ -0,0 +1,201 @@
+package dev.nl.mirror.network
+
+import dev.nl.mirror.util.Logger
+import java.io.DataInputStream
+import java.io.File
+import java.io.IOException
+import java.io.InputStream
+import java.io.OutputStream
+import java.net.Socket
+import java.security.MessageDigest
+import java.security.SecureRandom
+import javax.crypto.Cipher
+import javax.crypto.Mac
+import javax.crypto.spec.GCMParameterSpec
+import javax.crypto.spec.SecretKeySpec
+
+/**
+ * Optional authenticated encryption for the video, audio and control ports.
+ *
+ * Enabled when the launcher has pushed a pre-shared key (64 hex chars) to [KEY_PATH] before
+ * starting the server. Every connection must then prove knowledge of the key:
+ *
+ *   host   -> device: "NLS1" | client nonce (32)
+ *   device -> host:   server nonce (32) | HMAC(psk, "nl-server" | nonces)
+ *   host   -> device: HMAC(psk, "nl-client" | nonces)
+ *
+ * Each direction gets its own HKDF-derived key; records are [length(4)][AES-256-GCM] with a
+ * per-direction counter as nonce. Must match nl-host's network/secure.rs.
+ */
+object SecureTransport {
+    const val KEY_PATH = "/data/local/tmp/nl-mirror.key"
+
+    private const val TAG = "SECURE"
+    private val MAGIC = "NLS1".toByteArray()
+    private const val NONCE_LEN = 32
+    private const val MAC_LEN = 32
+    private const val TAG_BITS = 128
+    /** Largest plaintext per record; bigger writes are split */
+    private const val MAX_RECORD = 64 * 1024
+    private const val HANDSHAKE_TIMEOUT_MS = 5000
+
+    @Volatile private var psk: ByteArray? = null
+
+    val enabled: Boolean get() = psk != null
+
+    /** Read the key pushed by the launcher; without one all ports stay plain TCP */
+    fun loadKey() {
+        val file = File(KEY_PATH)
+        if (!file.exists()) return
+        try {
+            val hex = file.readText().trim()
+            require(hex.length == 64) { "expected 64 hex characters" }
+            psk = ByteArray(32) { i -> hex.substring(i * 2, i * 2 + 2).toInt(16).toByte() }
+            Logger.i(TAG, "Secure transport enabled")
+        } catch (e: Exception) {
+            // A broken key must not silently fall back to plain TCP
+            Logger.e(TAG, "Invalid key file: ${e.message}")
+            throw e
+        }
+    }
+
+    class Streams(val input: InputStream, val output: OutputStream)
+
+    /** Socket streams, wrapped after a successful handshake when a key is configured */
+    fun accept(socket: Socket): Streams {
+        val key = psk ?: return Streams(socket.getInputStream(), socket.getOutputStream())
+
+        val previousTimeout = socket.soTimeout
+        socket.soTimeout = HANDSHAKE_TIMEOUT_MS
+        val input = DataInputStream(socket.getInputStream())
+        val output = socket.getOutputStream()
+
+        val hello = ByteArray(MAGIC.size + NONCE_LEN)
+        input.readFully(hello)
+        if (!hello.copyOfRange(0, MAGIC.size).contentEquals(MAGIC)) {
+            throw IOException("Client did not start a secure handshake")
+        }
+        val clientNonce = hello.copyOfRange(MAGIC.size, hello.size)
+        val serverNonce = ByteArray(NONCE_LEN).also { SecureRandom().nextBytes(it) }
+
+        output.write(serverNonce + hmac(key, "nl-server".toByteArray() + clientNonce + serverNonce))
+        output.flush()
+
+        val clientMac = ByteArray(MAC_LEN)
+        input.readFully(clientMac)
+        val expected = hmac(key, "nl-client".toByteArray() + clientNonce + serverNonce)
+        if (!MessageDigest.isEqual(clientMac, expected)) {
+            throw IOException("Client failed authentication")
+        }
+        socket.soTimeout = previousTimeout
+
+        val salt = clientNonce + serverNonce
+        val c2s = hkdf(key, salt, "nl-mirror c2s")
+        val s2c = hkdf(key, salt, "nl-mirror s2c")
+        return Streams(SecureInputStream(input, c2s), SecureOutputStream(output, s2c))
+    }
+
+    private fun hmac(key: ByteArray, data: ByteArray): ByteArray {
+        val mac = Mac.getInstance("HmacSHA256")
+        mac.init(SecretKeySpec(key, "HmacSHA256"))
+        return mac.doFinal(data)
+    }
+
+    /** RFC 5869 HKDF-SHA256 for a single 32-byte output block */
+    private fun hkdf(ikm: ByteArray, salt: ByteArray, info: String): ByteArray {
+        val prk = hmac(salt, ikm)
+        return hmac(prk, info.toByteArray() + byteArrayOf(1))
+    }
+
+    private fun nonce(counter: Long): ByteArray {
+        val nonce = ByteArray(12)
+        for (i in 0 until 8) {
+            nonce[4 + i] = (counter ushr (56 - 8 * i)).toByte()
+        }
+        return nonce
+    }
+
+    private class SecureInputStream(
+        private val input: DataInputStream,
+        key: ByteArray
+    ) : InputStream() {
+        private val key = SecretKeySpec(key, "AES")
+        private val cipher = Cipher.getInstance("AES/GCM/NoPadding")
+        private var counter = 0L
+        private var plain = ByteArray(0)
+        private var pos = 0
+
+        /** Decrypt the next record; false at end of stream */
+        private fun fill(): Boolean {
+            val length = try {
+                input.readInt()
+            } catch (_: java.io.EOFException) {
+                return false
+            }
+            if (length < TAG_BITS / 8 || length > MAX_RECORD + TAG_BITS / 8) {
+                throw IOException("Invalid record length $length")
+            }
+            val record = ByteArray(length)
+            input.readFully(record)
+            cipher.init(Cipher.DECRYPT_MODE, key, GCMParameterSpec(TAG_BITS, nonce(counter++)))
+            // Throws AEADBadTagException on tampering
+            plain = cipher.doFinal(record)
+            pos = 0
+            return true
+        }
+
+        override fun read(): Int {
+            while (pos == plain.size) {
+                if (!fill()) return -1
+            }
+            return plain[pos++].toInt() and 0xFF
+        }
+
+        override fun read(b: ByteArray, off: Int, len: Int): Int {
+            if (len == 0) return 0
+            while (pos == plain.size) {
+                if (!fill()) return -1
+            }
+            val n = minOf(len, plain.size - pos)
+            System.arraycopy(plain, pos, b, off, n)
+            pos += n
+            return n
+        }
+
+        override fun available(): Int = plain.size - pos
+
+        override fun close() = input.close()
+    }
+
+    /** Seals every write as its own record(s), so latency matches the plain socket */
+    private class SecureOutputStream(
+        private val output: OutputStream,
+        key: ByteArray
+    ) : OutputStream() {
+        private val key = SecretKeySpec(key, "AES")
+        private val cipher = Cipher.getInstance("AES/GCM/NoPadding")
+        private var counter = 0L
+
+        override fun write(b: Int) = write(byteArrayOf(b.toByte()), 0, 1)
+
+        @Synchronized
+        override fun write(b: ByteArray, off: Int, len: Int) {
+            var offset = off
+            val end = off + len
+            while (offset < end) {
+                val n = minOf(MAX_RECORD, end - offset)
+                cipher.init(Cipher.ENCRYPT_MODE, key, GCMParameterSpec(TAG_BITS, nonce(counter++)))
+                val sealed = cipher.doFinal(b, offset, n)
+                val record = java.nio.ByteBuffer.allocate(4 + sealed.size)
+                record.putInt(sealed.size)
+                record.put(sealed)
+                output.write(record.array())
+                offset += n
+            }
+        }
+
+        override fun flush() = output.flush()
+
+        override fun close() = output.close()
+    }
+}
@@ -1,12 +1,20 @@
 package dev.nl.mirror.network
 
+import dev.nl.mirror.audio.AudioServer
 import dev.nl.mirror.core.MirrorService
+import dev.nl.mirror.video.StreamConfig
 import java.net.ServerSocket
 import java.net.Socket
-import java.io.BufferedReader
-import java.io.InputStreamReader
 
-class SocketServer(private val port: Int) {
+/**
+ * Video server. A client whose handshake line is [MuxSession.HELLO] gets every service over
+ * this one socket instead, handed to [commandServer] and [audioServer] per channel.
+ */
+class SocketServer(
+    private val port: Int,
+    private val commandServer: CommandServer,
+    private val audioServer: AudioServer?
+) {
     private var serverSocket: ServerSocket? = null
     private var isRunning = false
 
@@ -31,35 +39,46 @@ class SocketServer(private val port: Int) {
                 socket.tcpNoDelay = true
                 socket.sendBufferSize = 64 * 1024
 
-                var bitrate = 8_000_000
-                var maxResolution = 1080
+                val streams = SecureTransport.accept(socket)
+                var line: String? = null
                 try {
                     socket.soTimeout = 500
-                    val reader = BufferedReader(InputStreamReader(socket.getInputStream()))
-                    val line = reader.readLine()
-                    if (line != null) {
-                        val parts = line.split("&")
-                        for (part in parts) {
-                            val kv = part.split("=")
-                            if (kv.size == 2) {
-                                when(kv[0]) {
-                                    "bitrate" -> bitrate = kv[1].toIntOrNull() ?: bitrate
-                                    "max_size" -> maxResolution = kv[1].toIntOrNull() ?: maxResolution
-                                }
-                            }
-                        }
-                    }
+                    line = readHandshakeLine(streams.input)
                     socket.soTimeout = 0
                 } catch (_: Exception) {
                     socket.soTimeout = 0
                 }
-                MirrorService.startSession(socket, bitrate, maxResolution)
+
+                if (line == MuxSession.HELLO) {
+                    MuxSession(socket, streams, ::openChannel).run()
+                } else {
+                    startVideo(SocketConnection(socket, streams), line)
+                }
             } catch (_: Exception) {
                 try { socket.close() } catch (_: Exception) {}
             }
         }.start()
     }
 
+    private fun openChannel(service: Int, channel: Connection) {
+        when (service) {
+            // Same handshakes as on the separate ports, but no timeout: mux hosts always send them
+            MuxSession.SERVICE_VIDEO -> startVideo(channel, readHandshakeLine(channel.input))
+            MuxSession.SERVICE_AUDIO -> {
+                val line = readHandshakeLine(channel.input)
+                audioServer?.handleClient(channel, line) ?: channel.close()
+            }
+            MuxSession.SERVICE_CONTROL -> commandServer.handleClient(channel)
+            else -> channel.close()
+        }
+    }
+
+    /** Start mirroring for a handshake line; packets go over UDP if the host offered it */
+    private fun startVideo(connection: Connection, line: String?) {
+        val udp = UdpStream.offeredPort(line)?.let { UdpStream.accept(connection, it) }
+        MirrorService.startSession(connection, StreamConfig.parse(line), udp ?: connection.output)
+    }
+
     fun stop() {
         isRunning = false
         try { serverSocket?.close() } catch (_: Exception) {}
@@ -0,0 +1,215 @@
+package dev.nl.mirror.network
+
+import dev.nl.mirror.util.Logger
+import java.io.IOException
+import java.io.OutputStream
+import java.net.DatagramPacket
+import java.net.DatagramSocket
+import java.net.InetSocketAddress
+import java.nio.ByteBuffer
+
+/**
+ * Sends the [pts][size][data] packet stream of a video or audio connection as datagrams,
+ * for hosts that offered `udp=<port>` in their handshake (nl-host `--udp`).
+ *
+ *   kind (1) | seq (4) | frame (4) | index (2) | count (2) | pts (8) | payload (<= 1200)
+ *
+ * Each packet becomes `count` DATA datagrams with consecutive sequence numbers, plus one
+ * PARITY datagram (XOR of lengths and payloads) per group of up to [FEC_GROUP] fragments.
+ * The host answers losses it cannot repair with NACKs, served from [HISTORY].
+ * Datagrams are not encrypted, so a server with a pre-shared key never accepts an offer.
+ * Must match nl-host's network/udp.rs.
+ */
+class UdpStream private constructor(private val socket: DatagramSocket) : OutputStream() {
+    companion object {
+        private const val TAG = "UDP"
+        private val MAGIC = "UDP1".toByteArray()
+        private const val DATA = 0
+        private const val PARITY = 1
+        private const val NACK = 2
+        private const val HEADER_SIZE = 21
+        private const val MAX_PAYLOAD = 1200
+        private const val FEC_GROUP = 8
+        /** Recent datagrams kept for retransmission */
+        private const val HISTORY = 4096
+
+        /** Port from a `udp=<port>` handshake parameter, if the host offered one */
+        fun offeredPort(line: String?): Int? {
+            if (line == null) return null
+            for (part in line.split("&")) {
+                val kv = part.split("=")
+                if (kv.size == 2 && kv[0] == "udp") return kv[1].toIntOrNull()
+            }
+            return null
+        }
+
+        /**
+         * Accept the host's offer: answer `UDP1` + our port on the connection, after which the
+         * packet stream goes to the returned sink. Null keeps the stream on the connection,
+         * e.g. for adb-forwarded clients whose real address is unknown, or in secure mode.
+         */
+        fun accept(connection: Connection, hostPort: Int): UdpStream? {
+            if (SecureTransport.enabled) {
+                Logger.i(TAG, "Declining UDP: datagrams would bypass the secure transport")
+                return null
+            }
+            val address = connection.remoteAddress ?: return null
+            if (address.isLoopbackAddress || hostPort !in 1..65535) return null
+            val stream = try {
+                val socket = DatagramSocket()
+                socket.sendBufferSize = 1024 * 1024
+                socket.connect(InetSocketAddress(address, hostPort))
+                UdpStream(socket)
+            } catch (e: Exception) {
+                Logger.e(TAG, "UDP unavailable: ${e.message}")
+                return null
+            }
+
+            val answer = ByteBuffer.allocate(MAGIC.size + 2)
+            answer.put(MAGIC)
+            answer.putShort(stream.socket.localPort.toShort())
+            connection.output.write(answer.array())
+            connection.output.flush()
+            stream.start(connection)
+            Logger.i(TAG, "Streaming to $address:$hostPort over UDP")
+            return stream
+        }
+    }
+
+    @Volatile private var running = true
+    private val history = arrayOfNulls<ByteArray>(HISTORY)
+    private val historySeq = IntArray(HISTORY)
+    private var seq = 1
+    private var frame = 0
+    /** Bytes written but not yet forming a complete packet */
+    private var buffer = ByteArray(64 * 1024)
+    private var size = 0
+
+    /** Serve NACKs, and stop once the connection that negotiated us goes away */
+    private fun start(connection: Connection) {
+        Thread({ serveNacks() }, "udp-nack").start()
+        Thread({
+            try {
+                while (connection.input.read() != -1) {}
+            } catch (_: Exception) {}
+            connection.close()
+            close()
+        }, "udp-watch").start()
+    }
+
+    override fun write(b: Int) = write(byteArrayOf(b.toByte()), 0, 1)
+
+    @Synchronized
+    override fun write(b: ByteArray, off: Int, len: Int) {
+        if (!running) throw IOException("UDP stream closed")
+        if (size + len > buffer.size) {
+            buffer = buffer.copyOf(maxOf(buffer.size * 2, size + len))
+        }
+        System.arraycopy(b, off, buffer, size, len)
+        size += len
+
+        var pos = 0
+        while (size - pos >= 12) {
+            val header = ByteBuffer.wrap(buffer, pos, 12)
+            val pts = header.long
+            val length = header.int
+            if (size - pos < 12 + length) break
+            sendPacket(pts, buffer, pos + 12, length)
+            pos += 12 + length
+        }
+        System.arraycopy(buffer, pos, buffer, 0, size - pos)
+        size -= pos
+    }
+
+    private fun sendPacket(pts: Long, data: ByteArray, off: Int, len: Int) {
+        val count = maxOf(1, (len + MAX_PAYLOAD - 1) / MAX_PAYLOAD)
+        val id = frame++
+        var groupStart = 0
+        while (groupStart < count) {
+            val groupEnd = minOf(groupStart + FEC_GROUP, count)
+            // A lone fragment is cheaper to NACK than to duplicate
+            val parity = if (groupEnd - groupStart > 1) ByteArray(2 + MAX_PAYLOAD) else null
+            for (index in groupStart until groupEnd) {
+                val start = off + index * MAX_PAYLOAD
+                val n = minOf(MAX_PAYLOAD, off + len - start)
+                val datagram = datagram(DATA, seq, id, index, count, pts, data, start, n)
+                remember(seq, datagram)
+                seq++
+                send(datagram)
+                if (parity != null) {
+                    parity[0] = (parity[0].toInt() xor (n shr 8)).toByte()
+                    parity[1] = (parity[1].toInt() xor n).toByte()
+                    for (i in 0 until n) {
+                        parity[2 + i] = (parity[2 + i].toInt() xor data[start + i].toInt()).toByte()
+                    }
+                }
+            }
+            if (parity != null) {
+                send(datagram(PARITY, 0, id, groupStart, count, pts, parity, 0, parity.size))
+            }
+            groupStart = groupEnd
+        }
+    }
+
+    private fun datagram(
+        kind: Int, seq: Int, frame: Int, index: Int, count: Int, pts: Long,
+        data: ByteArray, off: Int, len: Int
+    ): ByteArray {
+        val datagram = ByteBuffer.allocate(HEADER_SIZE + len)
+        datagram.put(kind.toByte())
+        datagram.putInt(seq)
+        datagram.putInt(frame)
+        datagram.putShort(index.toShort())
+        datagram.putShort(count.toShort())
+        datagram.putLong(pts)
+        datagram.put(data, off, len)
+        return datagram.array()
+    }
+
+    private fun remember(seq: Int, datagram: ByteArray) {
+        val slot = Math.floorMod(seq, HISTORY)
+        synchronized(history) {
+            history[slot] = datagram
+            historySeq[slot] = seq
+        }
+    }
+
+    private fun send(datagram: ByteArray) {
+        try {
+            socket.send(DatagramPacket(datagram, datagram.size))
+        } catch (_: Exception) {
+            // Host port unreachable for now; the TCP connection decides when we are done
+        }
+    }
+
+    /** NACK: kind (1) | count (2) | seq (4) * count */
+    private fun serveNacks() {
+        val buf = ByteArray(2048)
+        val packet = DatagramPacket(buf, buf.size)
+        while (running) {
+            try {
+                socket.receive(packet)
+            } catch (_: Exception) {
+                if (socket.isClosed) break
+                continue
+            }
+            if (packet.length < 3) continue
+            val nack = ByteBuffer.wrap(buf, 0, packet.length)
+            if (nack.get().toInt() != NACK) continue
+            val count = minOf(nack.short.toInt() and 0xFFFF, (packet.length - 3) / 4)
+            repeat(count) {
+                val seq = nack.int
+                val slot = Math.floorMod(seq, HISTORY)
+                val datagram = synchronized(history) {
+                    if (historySeq[slot] == seq) history[slot] else null
+                }
+                if (datagram != null) send(datagram)
+            }
+        }
+    }
+
+    override fun close() {
+        running = false
+        socket.close()
+    }
+}
@@ -0,0 +1,60 @@
+package dev.nl.mirror.video
+
+import android.os.IBinder
+
+/**
+ * Rotates the device display by locking the window manager's rotation
+ * (needs the shell user the server runs as).
+ */
+object RotationControl {
+    private const val TAG = "RotationControl"
+    private const val CALLER = "nl-mirror"
+
+    private val windowManager: Any? by lazy {
+        try {
+            val serviceManager = Class.forName("android.os.ServiceManager")
+            val binder = serviceManager.getMethod("getService", String::class.java)
+                .invoke(null, "window") as IBinder
+            Class.forName("android.view.IWindowManager\$Stub")
+                .getMethod("asInterface", IBinder::class.java)
+                .invoke(null, binder)
+        } catch (e: Exception) {
+            android.util.Log.w(TAG, "IWindowManager unavailable: ${e.message}")
+            null
+        }
+    }
+
+    /** Switch between portrait and landscape; returns the new rotation, or null on failure */
+    fun toggle(): Int? {
+        val wm = windowManager ?: return null
+        return try {
+            val current = wm.javaClass.getMethod("getDefaultDisplayRotation").invoke(wm) as Int
+            val next = (current and 1) xor 1
+            freeze(wm, next)
+            next
+        } catch (e: Exception) {
+            android.util.Log.w(TAG, "Rotate failed: ${e.message}")
+            null
+        }
+    }
+
+    // The signature changed across releases; try the newest first
+    private fun freeze(wm: Any, rotation: Int) {
+        val cls = wm.javaClass
+        val int = Int::class.javaPrimitiveType
+        try {
+            cls.getMethod("freezeDisplayRotation", int, int, String::class.java)
+                .invoke(wm, 0, rotation, CALLER)
+            return
+        } catch (e: NoSuchMethodException) {}
+        try {
+            cls.getMethod("freezeDisplayRotation", int, int).invoke(wm, 0, rotation)
+            return
+        } catch (e: NoSuchMethodException) {}
+        try {
+            cls.getMethod("freezeRotation", int, String::class.java).invoke(wm, rotation, CALLER)
+            return
+        } catch (e: NoSuchMethodException) {}
+        cls.getMethod("freezeRotation", int).invoke(wm, rotation)
+    }
+}
@@ -4,6 +4,7 @@ import android.media.MediaCodec
 import android.media.MediaCodecInfo
 import android.media.MediaFormat
 import android.os.Build
+import android.os.Bundle
 import android.view.Surface
 import dev.nl.mirror.input.TouchScaler
 import dev.nl.mirror.network.PacketWriter
@@ -11,7 +12,7 @@ import dev.nl.mirror.network.PacketWriter
 class ScreenEncoder(
     private val width: Int,
     private val height: Int,
-    private val bitrate: Int,
+    private val config: StreamConfig,
     private val packetWriter: PacketWriter
 ) {
     private var codec: MediaCodec? = null
@@ -20,8 +21,9 @@ class ScreenEncoder(
 
     fun start() {
         try {
-            var encoderWidth = ((width + 15) / 16) * 16
-            var encoderHeight = ((height + 15) / 16) * 16
+            val (scaledWidth, scaledHeight) = config.encodeSize(width, height)
+            var encoderWidth = ((scaledWidth + 15) / 16) * 16
+            var encoderHeight = ((scaledHeight + 15) / 16) * 16
             
             // Try full resolution first, fallback to 720p if it fails
             var configured = false
@@ -30,8 +32,8 @@ class ScreenEncoder(
             while (!configured && tries < 2) {
                 try {
                     val format = MediaFormat.createVideoFormat("video/avc", encoderWidth, encoderHeight).apply {
-                        setInteger(MediaFormat.KEY_BIT_RATE, bitrate)
-                        setInteger(MediaFormat.KEY_FRAME_RATE, 30)
+                        setInteger(MediaFormat.KEY_BIT_RATE, config.bitrate)
+                        setInteger(MediaFormat.KEY_FRAME_RATE, if (config.maxFps > 0) config.maxFps else 30)
                         setInteger(MediaFormat.KEY_I_FRAME_INTERVAL, 1)
                         setLong(MediaFormat.KEY_REPEAT_PREVIOUS_FRAME_AFTER, 100_000L)
                         setInteger(MediaFormat.KEY_COLOR_FORMAT, MediaCodecInfo.CodecCapabilities.COLOR_FormatSurface)
@@ -39,6 +41,10 @@ class ScreenEncoder(
                         if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.N) {
                             setInteger("prepend-sps-pps-to-idr-frames", 1)
                         }
+                        if (config.maxFps > 0) {
+                            // Public as KEY_MAX_FPS_TO_ENCODER since Android 10, honoured earlier too
+                            setFloat("max-fps-to-encoder", config.maxFps.toFloat())
+                        }
                     }
 
                     codec = MediaCodec.createEncoderByType("video/avc")
@@ -80,6 +86,24 @@ class ScreenEncoder(
         }
     }
 
+    /** Change the bitrate of the running encoder (no restart, takes effect within a few frames) */
+    fun setBitrate(bitrate: Int) {
+        try {
+            codec?.setParameters(Bundle().apply {
+                putInt(MediaCodec.PARAMETER_KEY_VIDEO_BITRATE, bitrate)
+            })
+        } catch (_: Exception) {}
+    }
+
+    /** Ask for an IDR frame now instead of at the next I-frame interval (SPS/PPS are prepended) */
+    fun requestKeyframe() {
+        try {
+            codec?.setParameters(Bundle().apply {
+                putInt(MediaCodec.PARAMETER_KEY_REQUEST_SYNC_FRAME, 0)
+            })
+        } catch (_: Exception) {}
+    }
+
     fun stop() {
         isRunning = false
         try {
@@ -0,0 +1,46 @@
+package dev.nl.mirror.video
+
+/**
+ * Encoder settings for a mirror session.
+ * Sent by the host in the handshake line and changed mid-session with the `set_video` command.
+ */
+data class StreamConfig(
+    val bitrate: Int = 8_000_000,
+    /** Largest encoded dimension (width or height) in pixels, 0 = native resolution */
+    val maxSize: Int = 0,
+    /** Frame rate cap, 0 = as fast as the display updates */
+    val maxFps: Int = 0
+) {
+    /**
+     * Encoded size for a display of [width] x [height], keeping the aspect ratio.
+     * The longer side is capped, so rotating the device keeps the resolution; both sides are
+     * rounded down to a multiple of 8 because some encoders refuse other sizes.
+     */
+    fun encodeSize(width: Int, height: Int): Pair<Int, Int> {
+        val longest = maxOf(width, height)
+        val scale = if (maxSize in 1 until longest) maxSize.toDouble() / longest else 1.0
+        return Pair(alignSize(width * scale), alignSize(height * scale))
+    }
+
+    private fun alignSize(size: Double): Int = maxOf(2, size.toInt() / 8 * 8)
+
+    companion object {
+        /** Parse the handshake line, e.g. `bitrate=8000000&max_size=1080&max_fps=60` */
+        fun parse(line: String?): StreamConfig {
+            var config = StreamConfig()
+            if (line == null) return config
+            for (part in line.split("&")) {
+                val kv = part.split("=")
+                if (kv.size != 2) continue
+                val value = kv[1].toIntOrNull() ?: continue
+                config = when (kv[0]) {
+                    "bitrate" -> config.copy(bitrate = value)
+                    "max_size" -> config.copy(maxSize = value)
+                    "max_fps" -> config.copy(maxFps = value)
+                    else -> config
+                }
+            }
+            return config
+        }
+    }
+}
@@ -10,20 +10,32 @@ pub use decoder::start_audio_decoder;
 pub use playback::start_audio_playback;
 pub use receiver::start_audio_receiver;
 
-use crossbeam_channel::bounded;
+use crate::core::Session;
+use crate::network::Endpoint;
+use crossbeam_channel::{bounded, Receiver};
+use std::sync::Arc;
+
+/// Start the complete audio pipeline; it runs until the `session` is closed
+pub fn start_audio_pipeline(endpoint: Endpoint, port: u16, session: Arc<Session>) {
+    start_audio_playback(start_audio_stream(endpoint, port, session));
+}
 
-/// Start the complete audio pipeline
-pub fn start_audio_pipeline(host: String, port: u16) {
+/// Receive and decode the device audio without playing it. Samples are interleaved
+/// stereo f32 at 48 kHz; they are dropped while the receiver is full.
+pub fn start_audio_stream(
+    endpoint: Endpoint,
+    port: u16,
+    session: Arc<Session>,
+) -> Receiver<Vec<f32>> {
     // Receiver -> Decoder channel (encoded packets)
     let (encoded_tx, encoded_rx) = bounded::<AudioPacket>(64);
 
     // Decoder -> Playback channel (PCM samples)
     let (pcm_tx, pcm_rx) = bounded::<Vec<f32>>(64);
 
-    // Start pipeline threads
-    start_audio_receiver(host, port, encoded_tx);
+    start_audio_receiver(endpoint, port, encoded_tx, session);
     start_audio_decoder(encoded_rx, pcm_tx);
-    start_audio_playback(pcm_rx);
+    pcm_rx
 }
 
 /// Encoded audio packet from network
@@ -2,7 +2,6 @@
 //!
 //! Plays PCM audio samples using cpal.
 
-use crate::log_error;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::StreamConfig;
 use crossbeam_channel::Receiver;
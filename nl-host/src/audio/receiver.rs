@@ -3,44 +3,97 @@
 //! Connects to device and receives encoded audio packets.
 
 use super::{AudioHeader, AudioPacket};
+use crate::core::{Link, Session};
+use crate::network::transport::{self, Connection, Endpoint, Service};
+use crate::network::udp::{self, Route, UdpReceiver};
 use crossbeam_channel::Sender;
-use std::io::Read;
-use std::net::TcpStream;
+use std::io::{Cursor, Read, Write};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-/// Start the audio receiver thread
-pub fn start_audio_receiver(host: String, port: u16, tx: Sender<AudioPacket>) -> JoinHandle<()> {
+/// Start the audio receiver thread; it reconnects until the `session` is closed
+pub fn start_audio_receiver(
+    endpoint: Endpoint,
+    port: u16,
+    tx: Sender<AudioPacket>,
+    session: Arc<Session>,
+) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut reconnect_delay = 1;
-
-        loop {
-            match TcpStream::connect(format!("{}:{}", host, port)) {
-                Ok(mut stream) => {
-                    // Read header first
-                    match read_header(&mut stream) {
-                        Ok(_) => {
-                            reconnect_delay = 1;
-
-                            // Receive loop
-                            let _ = receive_packets(&mut stream, &tx);
+        while !session.is_closed() {
+            match transport::connect(&endpoint, port, Service::Audio, Duration::from_secs(5)) {
+                // The handle keeps a multiplexed channel open while reading
+                Ok(Connection {
+                    handle,
+                    mut reader,
+                    mut writer,
+                }) => {
+                    // Handshake line: empty, or a UDP offer with --udp
+                    let offer = udp::offer(&endpoint);
+                    let line = offer
+                        .as_ref()
+                        .and_then(|socket| udp::add_offer("\n", socket).ok())
+                        .unwrap_or_else(|| "\n".to_string());
+                    let _ = writer.write_all(line.as_bytes());
+                    let _ = writer.flush();
+
+                    let route = match offer {
+                        Some(socket) => udp::read_answer(socket, &handle, &mut reader),
+                        None => Ok(Route::Tcp(Vec::new())),
+                    };
+                    match route {
+                        // The header always comes over the connection
+                        Ok(Route::Udp(mut receiver)) => match read_header(&mut reader) {
+                            Ok(_) => {
+                                session.link_up(Link::Audio);
+                                let _ = receive_datagrams(&mut receiver, &tx);
+                            }
+                            Err(e) => {
+                                log_error!("AUDIO", "Failed to read header: {}", e);
+                            }
+                        },
+                        Ok(Route::Tcp(prefix)) => {
+                            let mut stream = Cursor::new(prefix).chain(&mut reader);
+                            match read_header(&mut stream) {
+                                Ok(_) => {
+                                    session.link_up(Link::Audio);
+
+                                    // Receive loop
+                                    let _ = receive_packets(&mut stream, &tx);
+                                }
+                                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                                    // Closed right away: no audio capture on this device
+                                    log_verbose!("AUDIO", "Audio not available");
+                                }
+                                Err(e) => {
+                                    log_error!("AUDIO", "Failed to read header: {}", e);
+                                }
+                            }
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                            log_verbose!("AUDIO", "Audio not available");
                         }
                         Err(e) => {
-                            log_error!("AUDIO", "Failed to read header: {}", e);
+                            log_error!("AUDIO", "Failed to read UDP answer: {}", e);
                         }
                     }
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    log_error!("AUDIO", "Secure handshake failed: {}", e);
+                }
                 Err(e) => {
                     log_verbose!("AUDIO", "Connect failed: {}", e);
                 }
             }
 
-            thread::sleep(std::time::Duration::from_secs(reconnect_delay));
-            reconnect_delay = (reconnect_delay * 2).min(10);
+            if !session.backoff(Link::Audio, || false) {
+                break;
+            }
         }
     })
 }
 
-fn read_header(stream: &mut TcpStream) -> std::io::Result<AudioHeader> {
+fn read_header(stream: &mut impl Read) -> std::io::Result<AudioHeader> {
     let mut header_buf = [0u8; 12];
     stream.read_exact(&mut header_buf)?;
 
@@ -64,7 +117,7 @@ fn read_header(stream: &mut TcpStream) -> std::io::Result<AudioHeader> {
     })
 }
 
-fn receive_packets(stream: &mut TcpStream, tx: &Sender<AudioPacket>) -> Result<(), ()> {
+fn receive_packets(stream: &mut impl Read, tx: &Sender<AudioPacket>) -> Result<(), ()> {
     let mut header_buf = [0u8; 12];
 
     loop {
@@ -93,3 +146,18 @@ fn receive_packets(stream: &mut TcpStream, tx: &Sender<AudioPacket>) -> Result<(
         }
     }
 }
+
+fn receive_datagrams(receiver: &mut UdpReceiver, tx: &Sender<AudioPacket>) -> Result<(), ()> {
+    loop {
+        match receiver.recv() {
+            Ok(Some(packet)) => {
+                let _ = tx.try_send(AudioPacket {
+                    pts: packet.pts,
+                    data: packet.data,
+                });
+            }
+            Ok(None) => {}
+            Err(_) => return Err(()),
+        }
+    }
+}
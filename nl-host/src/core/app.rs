@@ -1,24 +1,85 @@
 //! Core application logic
 
-use crate::core::{FrameBuffer, FrameData};
+use crate::audio::start_audio_pipeline;
+use crate::core::events::Event;
+use crate::core::ipc::{start_ipc_server, IpcServer};
+use crate::core::window::{self, WindowGeometry};
+use crate::core::{
+    ColorOverride, DeviceStats, FrameBuffer, FrameData, MirrorOptions, Session, SessionCommand,
+    SessionState, SessionStats, ShortcutMod, Stage, StatsSampler, TouchOverlay, VideoRect, Viewport,
+    ZOOM_STEP,
+};
 use crate::input::{map_keycode, start_input_thread, InputCommand};
-use crate::network::{start_video_receiver, VideoReceiverHandle};
+use crate::network::{
+    start_adaptive_thread, start_clock_sync_thread, start_device_stats_thread,
+    start_transfer_thread, start_video_receiver, AdaptiveBounds, Endpoint, TransferStatus,
+    VideoPacket, VideoReceiverHandle, VideoSettings,
+};
 use crate::utils::save_screenshot_yuv;
-use crate::video::{start_decoder_thread, MirrorRenderer};
+use crate::video::{start_decoder_thread, MirrorRenderer, Recorder, ScaleFilter};
 use crate::{log_debug, log_error, log_info, log_verbose};
 use crossbeam_channel::Sender;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use winit::application::ApplicationHandler;
-use winit::event::{ElementState, MouseButton, WindowEvent};
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow};
 use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{Fullscreen, WindowLevel};
+
+const WINDOW_TITLE: &str = "NL-Mirror";
+
+/// Sharpening strength for MOD+J when `--sharpen` is not given
+const DEFAULT_SHARPEN: f32 = 0.5;
+
+/// HUD refresh interval
+const HUD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Redraw interval for animating touch markers on a static screen
+const OVERLAY_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Pan distance per wheel line, as a fraction of the visible area
+const SCROLL_PAN_STEP: f32 = 0.1;
+
+/// Delay before saving the window geometry after the last move/resize
+const GEOMETRY_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Title refresh interval while a file transfer is running
+const TITLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Interval of the periodic render stats log
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Interval of the stats event with `--events`
+const STATS_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Events sent to the event loop from other threads
+#[derive(Debug, Clone, Copy)]
+pub enum AppEvent {
+    /// The decoder left a new frame in `FrameBuffer`
+    FrameReady,
+    /// The connection state in `Session` changed
+    SessionChanged,
+    /// A command from the local control socket
+    Command(SessionCommand),
+}
 
 pub struct MirrorApp {
-    pub host: String,
+    pub endpoint: Endpoint,
     pub port: u16,
-    pub bitrate: u32,
-    pub max_size: u32,
+    /// Encoder settings, shared with the video receiver for reconnect handshakes
+    pub video_settings: Arc<Mutex<VideoSettings>>,
+    pub adaptive: Option<AdaptiveBounds>,
+    pub audio: bool,
+    /// Connection state shared with the video, audio and control threads
+    pub session: Arc<Session>,
+    /// Last session state shown in the window
+    pub session_state: SessionState,
     pub turn_screen_off: bool,
+    pub push_target: String,
+    pub color_override: ColorOverride,
     pub frame_buffer: Arc<FrameBuffer>,
     pub renderer: Option<MirrorRenderer>,
     pub current_width: u32,
@@ -36,27 +97,93 @@ pub struct MirrorApp {
     pub ctrl_pressed: bool,
     pub cmd_pressed: bool,
     pub shift_pressed: bool,
+    pub alt_pressed: bool,
+    /// Modifier for the window shortcuts
+    pub shortcut_mod: ShortcutMod,
     // Store the last rendered frame for screenshots
     pub last_frame: Arc<Mutex<Option<FrameData>>>,
     // Video receiver handle to keep thread alive
     pub video_receiver: Option<VideoReceiverHandle>,
+    // Drag-and-drop file transfer
+    pub transfer_sender: Option<Sender<PathBuf>>,
+    pub transfer_status: Arc<TransferStatus>,
+    pub hovering_file: bool,
+    pub window_title: String,
+    // Window management
+    pub fullscreen: bool,
+    pub always_on_top: bool,
+    pub borderless: bool,
+    pub pixel_perfect: bool,
+    /// Key for the remembered window geometry (device serial, or host:port)
+    pub window_key: String,
+    /// True while the window still has the remembered size, so the first frame keeps it
+    pub geometry_restored: bool,
+    /// Last move/resize not yet saved (saved once the window settles)
+    pub geometry_changed_at: Option<std::time::Instant>,
+    // Scaling
+    pub scale_filter: ScaleFilter,
+    /// Sharpening strength used when sharpening is on
+    pub sharpen_strength: f32,
+    pub sharpen: bool,
+    // Performance HUD
+    pub stats: Arc<SessionStats>,
+    pub show_hud: bool,
+    pub hud_sampler: StatsSampler,
+    pub last_hud_update: Instant,
+    /// Rates for the `--events` stats, sampled separately from the HUD
+    pub events_sampler: StatsSampler,
+    pub last_stats_event: Instant,
+    /// Decode-to-display time of the last rendered frame
+    pub frame_age: Option<Duration>,
+    pub device_stats: Arc<Mutex<Option<DeviceStats>>>,
+    /// Device stats are only polled while the HUD is visible
+    pub device_stats_enabled: Arc<AtomicBool>,
+    // Touch visualization
+    pub touch_overlay: TouchOverlay,
+    pub show_touches: bool,
+    /// Include touch markers in screenshots
+    pub burn_touches: bool,
+    /// Markers were drawn last time (one more update clears them)
+    pub touches_drawn: bool,
+    pub last_overlay_draw: Instant,
+    // Zoom, pan and loupe
+    pub viewport: Viewport,
+    /// Last cursor position of a middle-button pan drag
+    pub pan_from: Option<(f64, f64)>,
+    /// Viewport or loupe position changed since the last draw
+    pub view_changed: bool,
+    /// Redraw the last frame even if no new one arrives (overlay or render option changed)
+    pub needs_redraw: bool,
+    /// A dropped file was queued; poll the transfer status for the title
+    pub transfer_queued: bool,
+    /// Where to listen for control commands (`--ipc`)
+    pub ipc_path: Option<PathBuf>,
+    pub ipc: Option<IpcServer>,
+    /// Records the video stream on request over `--ipc`
+    pub recorder: Arc<Recorder>,
 }
 
 impl MirrorApp {
-    pub fn new(
-        host: String,
-        port: u16,
-        bitrate: u32,
-        max_size: u32,
-        turn_screen_off: bool,
-    ) -> Self {
+    pub fn new(options: MirrorOptions) -> Self {
+        let window_key = options
+            .window
+            .serial
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", options.endpoint.host(), options.port));
+        let session = Arc::new(Session::new(options.retry).with_events(options.events));
         Self {
-            host,
-            port,
-            bitrate,
-            max_size,
-            turn_screen_off,
-            frame_buffer: Arc::new(FrameBuffer::new()),
+            endpoint: options.endpoint,
+            port: options.port,
+            video_settings: Arc::new(Mutex::new(options.video)),
+            adaptive: options.adaptive,
+            audio: options.audio,
+            recorder: Arc::new(Recorder::new(session.events().clone())),
+            session,
+            session_state: SessionState::Connecting,
+            turn_screen_off: options.turn_screen_off,
+            push_target: options.push_target,
+            color_override: options.color,
+            frame_buffer: Arc::new(FrameBuffer::with_delay(options.buffer)),
             renderer: None,
             current_width: 0,
             current_height: 0,
@@ -72,109 +199,679 @@ impl MirrorApp {
             ctrl_pressed: false,
             cmd_pressed: false,
             shift_pressed: false,
+            alt_pressed: false,
+            shortcut_mod: options.shortcut_mod,
             last_frame: Arc::new(Mutex::new(None)),
             video_receiver: None,
+            transfer_sender: None,
+            transfer_status: Arc::new(TransferStatus::new()),
+            hovering_file: false,
+            window_title: WINDOW_TITLE.to_string(),
+            fullscreen: options.window.fullscreen,
+            always_on_top: options.window.always_on_top,
+            borderless: options.window.borderless,
+            pixel_perfect: options.window.pixel_perfect,
+            window_key,
+            geometry_restored: false,
+            geometry_changed_at: None,
+            scale_filter: options.scale_filter,
+            sharpen_strength: if options.sharpen > 0.0 {
+                options.sharpen
+            } else {
+                DEFAULT_SHARPEN
+            },
+            sharpen: options.sharpen > 0.0,
+            stats: Arc::new(SessionStats::new()),
+            show_hud: options.hud,
+            hud_sampler: StatsSampler::new(),
+            last_hud_update: Instant::now(),
+            events_sampler: StatsSampler::new(),
+            last_stats_event: Instant::now(),
+            frame_age: None,
+            device_stats: Arc::new(Mutex::new(None)),
+            device_stats_enabled: Arc::new(AtomicBool::new(options.hud)),
+            touch_overlay: TouchOverlay::new(),
+            show_touches: options.show_touches || options.burn_touches,
+            burn_touches: options.burn_touches,
+            touches_drawn: false,
+            last_overlay_draw: Instant::now(),
+            viewport: Viewport::new(),
+            pan_from: None,
+            view_changed: false,
+            needs_redraw: false,
+            transfer_queued: false,
+            ipc_path: options.ipc,
+            ipc: None,
         }
     }
 
-    fn window_to_video(&self, pos: (f64, f64)) -> (f32, f32) {
+    /// Where the letterboxed video sits in the window (see MirrorRenderer)
+    fn video_rect(&self) -> VideoRect {
         let window_size = self
             .window
             .as_ref()
             .map(|w| w.inner_size())
             .unwrap_or(winit::dpi::PhysicalSize::new(1, 1));
+        VideoRect::fit(
+            (window_size.width, window_size.height),
+            (self.current_width, self.current_height),
+        )
+    }
 
-        // Map window coordinates to video coordinates
-        let x = (pos.0 / window_size.width as f64) * self.current_width as f64;
-        let y = (pos.1 / window_size.height as f64) * self.current_height as f64;
+    fn window_to_video(&self, pos: (f64, f64)) -> (f32, f32) {
+        // Letterboxed area -> visible (zoomed/panned) region -> frame pixels
+        let rect = self.video_rect();
+        let [fx, fy] = self.viewport.to_frame(rect.normalize(pos));
+        let x = fx * self.current_width as f32;
+        let y = fy * self.current_height as f32;
 
         log_verbose!(
             "INPUT",
-            "window_to_video: window={}x{}, video={}x{}, pos=({:.0},{:.0}) -> ({:.0},{:.0})",
-            window_size.width,
-            window_size.height,
+            "window_to_video: video={}x{}, zoom={:.2}, pos=({:.0},{:.0}) -> ({:.0},{:.0})",
             self.current_width,
             self.current_height,
+            self.viewport.zoom(),
             pos.0,
             pos.1,
             x,
             y
         );
 
-        (x as f32, y as f32)
+        (x, y)
     }
 
-    fn send_tap(&mut self, x: f32, y: f32) {
+    /// Queue a command for the input thread and the touch overlay. The overlay records even
+    /// while hidden, for screenshots with the markers burned in.
+    fn send_input(&mut self, command: InputCommand) {
         if let Some(tx) = &self.input_sender {
-            let _ = tx.try_send(InputCommand::Tap(x, y));
+            self.touch_overlay.record(&command);
+            let _ = tx.try_send(command);
         }
     }
 
+    fn send_tap(&mut self, x: f32, y: f32) {
+        self.send_input(InputCommand::Tap(x, y));
+    }
+
     fn send_swipe(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
-        if let Some(tx) = &self.input_sender {
-            let _ = tx.try_send(InputCommand::Swipe(x1, y1, x2, y2, 100));
-        }
+        self.send_input(InputCommand::Swipe(x1, y1, x2, y2, 100));
     }
 
     fn send_long_press(&mut self, x: f32, y: f32) {
-        if let Some(tx) = &self.input_sender {
-            let _ = tx.try_send(InputCommand::LongPress(x, y, 500));
-        }
+        self.send_input(InputCommand::LongPress(x, y, 500));
     }
 
     fn get_android_clipboard(&mut self) {
-        if let Some(tx) = &self.input_sender {
-            // true = request device to inject COPY key before reading
-            let _ = tx.try_send(InputCommand::GetClipboard(true));
-        }
+        // true = request device to inject COPY key before reading
+        self.send_input(InputCommand::GetClipboard(true));
     }
 
     fn paste_to_android(&mut self) {
         if let Ok(mut clipboard) = arboard::Clipboard::new() {
             if let Ok(text) = clipboard.get_text() {
-                if let Some(tx) = &self.input_sender {
-                    // Use SetClipboard with paste=true for atomic operation on server side
-                    // This avoids race conditions where paste happens before clipboard sets
-                    let _ = tx.try_send(InputCommand::SetClipboard(text, true));
-                }
+                // Use SetClipboard with paste=true for atomic operation on server side
+                // This avoids race conditions where paste happens before clipboard sets
+                self.send_input(InputCommand::SetClipboard(text, true));
+            } else if let Ok(image) = clipboard.get_image() {
+                // PNG encoding happens on the input thread to keep the UI responsive
+                self.send_input(InputCommand::SetClipboardImage(
+                    image.width as u32,
+                    image.height as u32,
+                    image.bytes.into_owned(),
+                    true,
+                ));
             }
         }
     }
 
     fn send_keycode(&mut self, action: &str, keycode: i32) {
-        if let Some(tx) = &self.input_sender {
-            let mut meta = 0;
-            if self.ctrl_pressed {
-                meta |= crate::network::ControlClient::META_CTRL_ON;
+        let mut meta = 0;
+        if self.ctrl_pressed {
+            meta |= crate::network::ControlClient::META_CTRL_ON;
+        }
+        if self.cmd_pressed {
+            meta |= crate::network::ControlClient::META_META_ON;
+        }
+        if self.shift_pressed {
+            meta |= crate::network::ControlClient::META_SHIFT_ON;
+        }
+        self.send_input(InputCommand::Keycode(action.to_string(), keycode, meta));
+    }
+
+    /// Save the last rendered frame as a PNG (in the background), with the touch markers
+    /// drawn in if `burn_touches`
+    fn take_screenshot(&mut self, burn_touches: bool) {
+        let last_frame_mutex = self.last_frame.clone();
+        let touches =
+            burn_touches.then(|| self.touch_overlay.frame(self.current_width, self.current_height));
+        let events = self.session.events().clone();
+        std::thread::spawn(move || {
+            let pending = last_frame_mutex.lock().unwrap();
+            if let Some(frame) = pending.clone() {
+                save_screenshot_yuv(frame, touches, events);
+            }
+        });
+    }
+
+    /// Run a command from the local control socket
+    fn run_command(&mut self, command: SessionCommand, event_loop: &ActiveEventLoop) {
+        log_info!("IPC", "{:?}", command);
+        match command {
+            SessionCommand::Screenshot { burn_touches } => {
+                self.take_screenshot(burn_touches.unwrap_or(self.burn_touches))
             }
-            if self.cmd_pressed {
-                meta |= crate::network::ControlClient::META_META_ON;
+            SessionCommand::Rotate => self.send_input(InputCommand::Rotate),
+            SessionCommand::SetVideo {
+                bitrate,
+                max_size,
+                max_fps,
+            } => self.change_video(|v| VideoSettings {
+                bitrate: bitrate.unwrap_or(v.bitrate),
+                max_size: max_size.unwrap_or(v.max_size),
+                max_fps: max_fps.unwrap_or(v.max_fps),
+            }),
+            SessionCommand::ScreenPower { on } => {
+                // Turned back on when the session ends
+                self.turn_screen_off = !on;
+                self.set_screen_power_mode(if on { 2 } else { 0 });
             }
-            if self.shift_pressed {
-                meta |= crate::network::ControlClient::META_SHIFT_ON;
+            SessionCommand::Quit => {
+                self.save_window_geometry();
+                self.session.close();
+                event_loop.exit();
             }
-            let _ = tx.try_send(InputCommand::Keycode(action.to_string(), keycode, meta));
         }
     }
 
     fn set_screen_power_mode(&mut self, mode: i32) {
-        if let Some(tx) = &self.input_sender {
-            // mode: 0 = OFF, 2 = NORMAL
-            let _ = tx.try_send(InputCommand::SetScreenPowerMode(mode));
+        // mode: 0 = OFF, 2 = NORMAL
+        self.send_input(InputCommand::SetScreenPowerMode(mode));
+    }
+
+    fn toggle_touches(&mut self) {
+        self.show_touches = !self.show_touches;
+        log_info!(
+            "REN",
+            "Touch visualization: {}",
+            if self.show_touches { "on" } else { "off" }
+        );
+        if !self.show_touches {
+            self.touch_overlay = TouchOverlay::new();
+        }
+    }
+
+    /// Advance the touch marker animation; returns true if the markers changed
+    fn update_touches(&mut self) -> bool {
+        let active = self.show_touches && self.touch_overlay.is_active();
+        if !active && !self.touches_drawn {
+            return false;
+        }
+        let Some(r) = &mut self.renderer else {
+            return false;
+        };
+        let touches = self
+            .touch_overlay
+            .frame(self.current_width, self.current_height);
+        self.touches_drawn = active;
+        r.set_touches(self.touches_drawn.then_some(&touches));
+        self.last_overlay_draw = Instant::now();
+        true
+    }
+
+    /// The `--shortcut-mod` modifier is held
+    fn shortcut_mod_pressed(&self) -> bool {
+        match self.shortcut_mod {
+            ShortcutMod::Alt => self.alt_pressed,
+            ShortcutMod::Ctrl => self.ctrl_pressed,
+            ShortcutMod::Super => self.cmd_pressed,
+        }
+    }
+
+    /// Zoom by `factor` around the cursor (or the center when the cursor is outside)
+    fn zoom_by(&mut self, factor: f32) {
+        let rect = self.video_rect();
+        let anchor = self
+            .cursor_position
+            .filter(|&pos| rect.contains(pos))
+            .map(|pos| rect.normalize(pos))
+            .unwrap_or([0.5, 0.5]);
+        self.viewport.zoom_by(factor, anchor);
+        log_verbose!("REN", "Zoom: {:.2}x", self.viewport.zoom());
+        self.view_changed = true;
+    }
+
+    fn reset_zoom(&mut self) {
+        self.viewport.reset();
+        log_verbose!("REN", "Zoom reset");
+        self.view_changed = true;
+    }
+
+    /// Pan by a window-pixel distance (content follows the pointer)
+    fn pan_by_pixels(&mut self, dx: f64, dy: f64) {
+        let rect = self.video_rect();
+        self.viewport
+            .pan([(-dx / rect.width) as f32, (-dy / rect.height) as f32]);
+        self.view_changed = true;
+    }
+
+    fn toggle_loupe(&mut self) {
+        self.viewport.loupe = !self.viewport.loupe;
+        log_info!(
+            "REN",
+            "Loupe: {}",
+            if self.viewport.loupe { "on" } else { "off" }
+        );
+        self.view_changed = true;
+    }
+
+    /// Push the zoom region and loupe to the renderer; returns true if they changed
+    fn update_view(&mut self) -> bool {
+        if !std::mem::take(&mut self.view_changed) {
+            return false;
+        }
+        let loupe = self
+            .viewport
+            .loupe_at(&self.video_rect(), self.cursor_position);
+        if let Some(r) = &mut self.renderer {
+            r.set_view(self.viewport.region(), loupe);
+        }
+        true
+    }
+
+    /// Ask the device for new encoder settings (bitrate applies live, size/fps restart it)
+    fn change_video(&mut self, change: impl FnOnce(VideoSettings) -> VideoSettings) {
+        let settings = {
+            let mut current = self.video_settings.lock().unwrap();
+            let next = change(*current);
+            if next == *current {
+                return;
+            }
+            *current = next;
+            next
+        };
+        log_info!("NET", "Video settings: {}", settings);
+        self.send_input(InputCommand::SetVideo(settings));
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        self.fullscreen = !self.fullscreen;
+        if let Some(w) = &self.window {
+            w.set_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)));
+        }
+    }
+
+    fn toggle_always_on_top(&mut self) {
+        self.always_on_top = !self.always_on_top;
+        if let Some(w) = &self.window {
+            w.set_window_level(window_level(self.always_on_top));
+        }
+    }
+
+    fn toggle_borderless(&mut self) {
+        self.borderless = !self.borderless;
+        if let Some(w) = &self.window {
+            w.set_decorations(!self.borderless);
+        }
+    }
+
+    fn cycle_scale_filter(&mut self) {
+        self.scale_filter = self.scale_filter.next();
+        log_info!("REN", "Scale filter: {}", self.scale_filter);
+        self.apply_render_options();
+    }
+
+    fn toggle_sharpen(&mut self) {
+        self.sharpen = !self.sharpen;
+        log_info!(
+            "REN",
+            "Sharpening: {}",
+            if self.sharpen { "on" } else { "off" }
+        );
+        self.apply_render_options();
+    }
+
+    /// Push the scaling filter and sharpening to the renderer
+    fn apply_render_options(&mut self) {
+        if let Some(r) = &mut self.renderer {
+            r.set_banner(session_banner(self.session_state).as_deref());
+            r.set_scale_filter(self.scale_filter);
+            r.set_sharpen(if self.sharpen {
+                self.sharpen_strength
+            } else {
+                0.0
+            });
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Show the new session state; a lost session closes the window
+    fn update_session(&mut self, event_loop: &ActiveEventLoop) {
+        let state = self.session.state();
+        if state == self.session_state {
+            return;
+        }
+        let recovered = matches!(self.session_state, SessionState::Reconnecting { .. })
+            && state == SessionState::Streaming;
+        self.session_state = state;
+        if let Some(r) = &mut self.renderer {
+            r.set_banner(session_banner(state).as_deref());
+            self.needs_redraw = true;
+        }
+        if let Some(w) = &self.window {
+            w.request_redraw();
+        }
+
+        match state {
+            SessionState::Connecting => {}
+            SessionState::Streaming => {
+                self.session.events().emit(Event::Connected);
+                // The device may have restarted its server, which turns the screen back on
+                if recovered && self.turn_screen_off {
+                    self.set_screen_power_mode(0);
+                }
+            }
+            SessionState::Reconnecting { attempt } => {
+                self.session.events().emit(Event::Reconnecting { attempt });
+            }
+            SessionState::Lost => {
+                log_error!("APP", "Lost the connection to the device, closing");
+                self.session.events().emit(Event::Error {
+                    message: "Connection to the device lost".to_string(),
+                    fatal: true,
+                });
+                self.save_window_geometry();
+                event_loop.exit();
+            }
+        }
+    }
+
+    fn toggle_hud(&mut self) {
+        self.show_hud = !self.show_hud;
+        self.device_stats_enabled
+            .store(self.show_hud, Ordering::Relaxed);
+        if self.show_hud {
+            // Start a fresh interval so the first rates are not averaged over the hidden time
+            self.hud_sampler = StatsSampler::new();
+            self.hud_sampler
+                .sample(&self.stats, self.last_count, None, None);
+            self.last_hud_update = Instant::now() - HUD_INTERVAL;
+        } else if let Some(r) = &mut self.renderer {
+            r.set_hud(None);
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Refresh the HUD text when due; returns true if it changed
+    fn update_hud(&mut self) -> bool {
+        if !self.show_hud || self.last_hud_update.elapsed() < HUD_INTERVAL {
+            return false;
+        }
+        self.last_hud_update = Instant::now();
+        let device = *self.device_stats.lock().unwrap();
+        let lines = self
+            .hud_sampler
+            .sample(&self.stats, self.last_count, self.frame_age, device);
+        if let Some(r) = &mut self.renderer {
+            r.set_hud(Some(&lines));
+        }
+        true
+    }
+
+    /// True if an overlay animation or HUD refresh is due
+    fn overlay_due(&self) -> bool {
+        let touches =
+            self.touches_animating() && self.last_overlay_draw.elapsed() >= OVERLAY_INTERVAL;
+        let hud = self.show_hud && self.last_hud_update.elapsed() >= HUD_INTERVAL;
+        touches || hud
+    }
+
+    fn touches_animating(&self) -> bool {
+        self.renderer.is_some()
+            && ((self.show_touches && self.touch_overlay.is_active()) || self.touches_drawn)
+    }
+
+    /// Earliest time a timer needs the event loop (overlays, title, geometry save, stats log)
+    fn next_wakeup(&self) -> Instant {
+        let mut deadlines = vec![self.last_log + STATS_LOG_INTERVAL];
+        if self.session.events().is_enabled() {
+            deadlines.push(self.last_stats_event + STATS_EVENT_INTERVAL);
+        }
+        if self.touches_animating() {
+            deadlines.push(self.last_overlay_draw + OVERLAY_INTERVAL);
+        }
+        if self.show_hud {
+            deadlines.push(self.last_hud_update + HUD_INTERVAL);
+        }
+        if let Some(changed_at) = self.geometry_changed_at {
+            deadlines.push(changed_at + GEOMETRY_SAVE_DELAY);
+        }
+        if self.transfer_queued || self.transfer_status.message().is_some() {
+            deadlines.push(Instant::now() + TITLE_INTERVAL);
+        }
+        // Never in the past: an occluded window may not get the RedrawRequested that
+        // resets an overdue timer, and must not spin meanwhile
+        let now = Instant::now();
+        let earliest = now + OVERLAY_INTERVAL;
+        let timers = deadlines
+            .into_iter()
+            .min()
+            .map_or(earliest, |deadline| deadline.max(earliest));
+        // Buffered frames are paced exactly (a due one has already requested a redraw)
+        match self.frame_buffer.next_due() {
+            Some(due) if due > now => timers.min(due),
+            _ => timers,
+        }
+    }
+
+    /// A buffered frame is due for presentation
+    fn frame_due(&self) -> bool {
+        self.frame_buffer
+            .next_due()
+            .is_some_and(|due| due <= Instant::now())
+    }
+
+    /// Render the pending frame, or the last one again if an overlay changed
+    fn redraw(&mut self) {
+        let frame = self.frame_buffer.consume();
+        SessionStats::add(&self.stats.frames_dropped, self.frame_buffer.take_dropped());
+        if let Some(frame) = &frame {
+            self.last_count += 1;
+
+            // Save last frame for screenshot (use try_lock to avoid blocking render)
+            if let Ok(mut last) = self.last_frame.try_lock() {
+                *last = Some(frame.clone());
+            }
+
+            if frame.width != self.current_width || frame.height != self.current_height {
+                self.handle_frame_size(frame.width, frame.height);
+            }
+        }
+
+        // Before rendering, so a new frame already shows the new zoom/loupe and overlays
+        let view_changed = self.update_view();
+        let touches_changed = self.update_touches();
+        let hud_changed = self.update_hud();
+        let needs_redraw = std::mem::take(&mut self.needs_redraw);
+
+        let Some(renderer) = &mut self.renderer else {
+            return;
+        };
+        if let Some(frame) = frame {
+            if let Err(e) = renderer.render_yuv_frame(&frame) {
+                log_error!("REN", "Render failed: {}", e);
+            }
+            let presented = Instant::now();
+            let age = presented.saturating_duration_since(frame.ready_at);
+            self.frame_age = Some(age);
+            SessionStats::set(&self.stats.frame_age_ms, age.as_millis() as u64);
+            self.stats.latency.record(
+                Stage::Present,
+                presented.saturating_duration_since(frame.decoded_at),
+            );
+            if frame.pts != 0 {
+                if let Some(total) = self.stats.clock.since_capture(frame.pts, presented) {
+                    self.stats.latency.record(Stage::Total, total);
+                }
+            }
+        } else if view_changed || touches_changed || hud_changed || needs_redraw {
+            let _ = renderer.redraw();
+        }
+    }
+
+    /// New frame size: create the renderer, or resize it (rotation/resolution change)
+    fn handle_frame_size(&mut self, width: u32, height: u32) {
+        log_verbose!(
+            "REN",
+            "Resolution change: {}x{} -> {}x{}",
+            self.current_width,
+            self.current_height,
+            width,
+            height
+        );
+        let old_size = (self.current_width, self.current_height);
+        self.current_width = width;
+        self.current_height = height;
+        self.session.events().emit(Event::Resolution { width, height });
+
+        if let Some(renderer) = &mut self.renderer {
+            // Rotation or resolution change: keep the GPU stack, swap the textures
+            renderer.set_frame_size(width, height);
+            if same_aspect(old_size, (width, height)) {
+                // Max size change: same picture at a different resolution, keep window and zoom
+                return;
+            }
+            self.rescale_window(old_size);
+            // The zoomed region would point at different content after a rotation
+            self.viewport.reset();
+            self.view_changed = true;
+        } else if let Some(window) = self.window.clone() {
+            // Keep the remembered size for the first frame, then follow the frame
+            if !std::mem::take(&mut self.geometry_restored) {
+                self.apply_frame_size();
+            }
+
+            match MirrorRenderer::new(window, width, height) {
+                Ok(renderer) => {
+                    self.renderer = Some(renderer);
+                    self.apply_render_options();
+                    self.view_changed = true;
+                }
+                Err(e) => {
+                    log_error!("REN", "Renderer init failed: {}", e);
+                    // Retry with the next frame
+                    self.current_width = 0;
+                    self.current_height = 0;
+                }
+            }
+        }
+    }
+
+    fn toggle_pixel_perfect(&mut self) {
+        self.pixel_perfect = !self.pixel_perfect;
+        self.apply_frame_size();
+    }
+
+    fn fit_to_screen(&mut self) {
+        self.pixel_perfect = false;
+        if self.fullscreen || self.current_width == 0 {
+            return;
+        }
+        if let Some(w) = &self.window {
+            let size = window::fit_to_screen_size(w, self.current_width, self.current_height);
+            let _ = w.request_inner_size(size);
+        }
+    }
+
+    /// Size the window for the current frame (1:1 or the default fit)
+    fn apply_frame_size(&mut self) {
+        if self.fullscreen || self.current_width == 0 {
+            return;
+        }
+        if let Some(w) = &self.window {
+            let size = if self.pixel_perfect {
+                window::pixel_perfect_size(self.current_width, self.current_height)
+            } else {
+                window::default_size(self.current_width, self.current_height)
+            };
+            let _ = w.request_inner_size(size);
+        }
+    }
+
+    /// Resize the window for a new frame size at the current scale (device rotation)
+    fn rescale_window(&mut self, old_size: (u32, u32)) {
+        if self.fullscreen || self.pixel_perfect {
+            self.apply_frame_size();
+            return;
+        }
+        if let Some(w) = &self.window {
+            let size =
+                window::rescaled_size(w, old_size, (self.current_width, self.current_height));
+            let _ = w.request_inner_size(size);
+        }
+    }
+
+    fn save_window_geometry(&self) {
+        if self.fullscreen {
+            return;
+        }
+        if let Some(geometry) = self.window.as_deref().and_then(WindowGeometry::of) {
+            window::save_geometry(&self.window_key, geometry);
+        }
+    }
+
+    /// Show transfer progress (or the drop hint) in the window title
+    fn update_window_title(&mut self) {
+        let title = if let Some(status) = self.transfer_status.message() {
+            format!("{} - {}", WINDOW_TITLE, status)
+        } else if self.hovering_file {
+            format!("{} - Drop to install APK or push file", WINDOW_TITLE)
+        } else {
+            WINDOW_TITLE.to_string()
+        };
+        if title != self.window_title {
+            if let Some(w) = &self.window {
+                w.set_title(&title);
+            }
+            self.window_title = title;
         }
     }
 }
 
+/// True if two frame sizes show the same picture (encoder sizes are rounded to 16 pixels)
+fn same_aspect(a: (u32, u32), b: (u32, u32)) -> bool {
+    if a.1 == 0 || b.1 == 0 {
+        return false;
+    }
+    let ratio_a = a.0 as f64 / a.1 as f64;
+    let ratio_b = b.0 as f64 / b.1 as f64;
+    (ratio_a / ratio_b - 1.0).abs() < 0.02
+}
+
+fn window_level(always_on_top: bool) -> WindowLevel {
+    if always_on_top {
+        WindowLevel::AlwaysOnTop
+    } else {
+        WindowLevel::Normal
+    }
+}
+
 impl Drop for MirrorApp {
     fn drop(&mut self) {
+        // Stop the reconnect loops before waiting on the receiver threads
+        self.session.close();
+        if self.recorder.is_recording() {
+            if let Err(e) = self.recorder.stop() {
+                log_error!("REC", "Recording not saved: {}", e);
+            }
+        }
         if self.turn_screen_off {
             log_info!("APP", "Exiting: Restoring screen power...");
             // Try to send power on command via control port directly
             // We use a new connection here to ensure it's sent even if channel is closed
-            if let Ok(mut stream) = std::net::TcpStream::connect_timeout(
-                &format!("{}:{}", self.host, self.port + 1)
-                    .parse()
-                    .unwrap_or("127.0.0.1:8889".parse().unwrap()),
+            if let Ok(mut connection) = crate::network::transport::connect(
+                &self.endpoint,
+                self.port + 1,
+                crate::network::transport::Service::Control,
                 std::time::Duration::from_millis(500),
             ) {
                 use std::io::Write;
@@ -182,18 +879,33 @@ impl Drop for MirrorApp {
                     "cmd": "set_screen_power_mode",
                     "mode": 2
                 });
-                let _ = stream.write_all(format!("{}\n", cmd.to_string()).as_bytes());
-                let _ = stream.flush();
+                let _ = connection
+                    .writer
+                    .write_all(format!("{}\n", cmd).as_bytes());
+                let _ = connection.writer.flush();
             }
         }
     }
 }
 
-impl ApplicationHandler for MirrorApp {
+impl ApplicationHandler<AppEvent> for MirrorApp {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window_attrs = winit::window::Window::default_attributes()
-            .with_title("NL-Mirror")
-            .with_inner_size(winit::dpi::LogicalSize::new(360, 800));
+        let mut window_attrs = winit::window::Window::default_attributes()
+            .with_title(WINDOW_TITLE)
+            .with_inner_size(winit::dpi::LogicalSize::new(360, 800))
+            .with_decorations(!self.borderless)
+            .with_window_level(window_level(self.always_on_top))
+            .with_fullscreen(self.fullscreen.then_some(Fullscreen::Borderless(None)));
+
+        // Restore the remembered position/size for this device (1:1 mode sizes from the frame)
+        if let Some(geometry) = window::load_geometry(&self.window_key) {
+            log_verbose!("APP", "Restoring window geometry: {:?}", geometry);
+            window_attrs = window_attrs.with_position(geometry.position());
+            if !self.pixel_perfect {
+                window_attrs = window_attrs.with_inner_size(geometry.size());
+                self.geometry_restored = true;
+            }
+        }
 
         if let Ok(window) = event_loop.create_window(window_attrs) {
             self.window = Some(Arc::new(window));
@@ -213,8 +925,24 @@ impl ApplicationHandler for MirrorApp {
 
         // Input Thread - larger buffer for fast typing
         let (input_tx, input_rx) = crossbeam_channel::bounded::<InputCommand>(256);
-        self.input_sender = Some(input_tx);
-        start_input_thread(self.host.clone(), self.port + 1, input_rx);
+        self.input_sender = Some(input_tx.clone());
+        start_input_thread(
+            self.endpoint.clone(),
+            self.port + 1,
+            input_rx,
+            self.session.clone(),
+        );
+
+        // File Transfer Thread - dropped files are queued and sent one at a time
+        let (transfer_tx, transfer_rx) = crossbeam_channel::unbounded::<PathBuf>();
+        self.transfer_sender = Some(transfer_tx);
+        start_transfer_thread(
+            self.endpoint.clone(),
+            self.port + 1,
+            self.push_target.clone(),
+            transfer_rx,
+            self.transfer_status.clone(),
+        );
 
         // Send screen off command if requested
         if self.turn_screen_off {
@@ -223,21 +951,62 @@ impl ApplicationHandler for MirrorApp {
         }
 
         // Network -> Decoder Channel (larger buffer for high bitrate)
-        let (tx, rx) = crossbeam_channel::bounded::<Vec<u8>>(256);
+        let (tx, rx) = crossbeam_channel::bounded::<VideoPacket>(256);
 
         log_verbose!("APP", "Starting decoder and network threads...");
 
         // Decoder Thread
-        start_decoder_thread(rx, self.frame_buffer.clone());
+        start_decoder_thread(
+            rx,
+            self.frame_buffer.clone(),
+            self.color_override,
+            input_tx,
+            self.stats.clone(),
+            self.recorder.clone(),
+            self.session.events().clone(),
+        );
 
         // Network Receiver Thread
         self.video_receiver = Some(start_video_receiver(
-            self.host.clone(),
+            self.endpoint.clone(),
             self.port,
-            self.bitrate,
-            self.max_size,
+            self.video_settings.clone(),
             tx,
+            self.session.clone(),
+            self.stats.clone(),
         ));
+
+        if self.audio {
+            start_audio_pipeline(self.endpoint.clone(), self.port + 2, self.session.clone());
+        }
+
+        // Device clock, for latencies measured from capture
+        start_clock_sync_thread(
+            self.endpoint.clone(),
+            self.port + 1,
+            self.stats.clone(),
+            self.session.clone(),
+        );
+
+        // Device stats for the HUD
+        start_device_stats_thread(
+            self.endpoint.clone(),
+            self.port + 1,
+            self.device_stats_enabled.clone(),
+            self.device_stats.clone(),
+        );
+
+        // Adaptive bitrate
+        if let Some(bounds) = self.adaptive {
+            start_adaptive_thread(
+                self.endpoint.clone(),
+                self.port + 1,
+                bounds,
+                self.video_settings.clone(),
+                self.stats.clone(),
+                self.session.clone(),
+            );
+        }
     }
 
     fn window_event(
@@ -247,21 +1016,97 @@ impl ApplicationHandler for MirrorApp {
         event: WindowEvent,
     ) {
         match event {
-            WindowEvent::Resized(size) => {
-                if size.width > 0 && size.height > 0 {
-                    if let Some(r) = &mut self.renderer {
-                        let _ = r.resize_surface(size.width, size.height);
-                    }
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                if let Some(r) = &mut self.renderer {
+                    let _ = r.resize_surface(size.width, size.height);
                 }
+                self.view_changed = true;
+                self.geometry_changed_at = Some(std::time::Instant::now());
+            }
+            WindowEvent::Moved(_) => {
+                self.geometry_changed_at = Some(std::time::Instant::now());
+            }
+            WindowEvent::CloseRequested => {
+                self.save_window_geometry();
+                self.session.close();
+                event_loop.exit();
             }
-            WindowEvent::CloseRequested => event_loop.exit(),
             WindowEvent::RedrawRequested => {
-                if let Some(w) = &self.window {
-                    w.request_redraw();
+                self.redraw();
+            }
+            WindowEvent::HoveredFile(_) => {
+                self.hovering_file = true;
+                self.update_window_title();
+            }
+            WindowEvent::HoveredFileCancelled => {
+                self.hovering_file = false;
+                self.update_window_title();
+            }
+            WindowEvent::DroppedFile(path) => {
+                self.hovering_file = false;
+                self.transfer_queued = true;
+                log_info!("APP", "File dropped: {:?}", path);
+                if let Some(tx) = &self.transfer_sender {
+                    let _ = tx.send(path);
                 }
+                self.update_window_title();
             }
             WindowEvent::CursorMoved { position, .. } => {
                 self.cursor_position = Some((position.x, position.y));
+                if let Some((x, y)) = self.pan_from {
+                    self.pan_by_pixels(position.x - x, position.y - y);
+                    self.pan_from = Some((position.x, position.y));
+                }
+                if self.viewport.loupe {
+                    self.view_changed = true;
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+                if self.viewport.loupe {
+                    self.view_changed = true;
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                if self.shortcut_mod_pressed() {
+                    // MOD + wheel zooms around the cursor
+                    let notches = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(p) => (p.y / 50.0) as f32,
+                    };
+                    if notches != 0.0 {
+                        self.zoom_by(ZOOM_STEP.powf(notches));
+                    }
+                } else if self.viewport.is_zoomed() {
+                    // Plain wheel pans while zoomed (Shift swaps the axes for mice)
+                    let (dx, dy) = match delta {
+                        MouseScrollDelta::LineDelta(x, y) => {
+                            let rect = self.video_rect();
+                            (
+                                x as f64 * SCROLL_PAN_STEP as f64 * rect.width,
+                                y as f64 * SCROLL_PAN_STEP as f64 * rect.height,
+                            )
+                        }
+                        MouseScrollDelta::PixelDelta(p) => (p.x, p.y),
+                    };
+                    let (dx, dy) = if self.shift_pressed {
+                        (dy, dx)
+                    } else {
+                        (dx, dy)
+                    };
+                    self.pan_by_pixels(dx, dy);
+                }
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Middle,
+                ..
+            } => {
+                // Middle-button drag pans the zoomed view
+                self.pan_from = match state {
+                    ElementState::Pressed => self.cursor_position,
+                    ElementState::Released => None,
+                };
             }
             WindowEvent::MouseInput {
                 state,
@@ -324,6 +1169,7 @@ impl ApplicationHandler for MirrorApp {
                 self.ctrl_pressed = modifiers.state().control_key();
                 self.cmd_pressed = modifiers.state().super_key();
                 self.shift_pressed = modifiers.state().shift_key();
+                self.alt_pressed = modifiers.state().alt_key();
             }
             WindowEvent::KeyboardInput { event, .. } => {
                 if let PhysicalKey::Code(keycode) = event.physical_key {
@@ -337,17 +1183,13 @@ impl ApplicationHandler for MirrorApp {
                     if keycode == KeyCode::ShiftLeft || keycode == KeyCode::ShiftRight {
                         self.shift_pressed = event.state == ElementState::Pressed;
                     }
-
-                    // Revert to original: CTRL or CMD triggers shortcuts
-                    let modifier_pressed = self.ctrl_pressed || self.cmd_pressed;
+                    if keycode == KeyCode::AltLeft || keycode == KeyCode::AltRight {
+                        self.alt_pressed = event.state == ElementState::Pressed;
+                    }
 
                     if event.state == ElementState::Pressed {
-                        if modifier_pressed {
-                            log_verbose!(
-                                "INPUT",
-                                "Modifier pressed, checking shortcut: {:?}",
-                                keycode
-                            );
+                        // Clipboard and screenshot keep CTRL or CMD, like any desktop app
+                        if self.ctrl_pressed || self.cmd_pressed {
                             match keycode {
                                 KeyCode::KeyC => {
                                     log_verbose!("INPUT", "Shortcut: Copy (Ctrl+C)");
@@ -361,13 +1203,109 @@ impl ApplicationHandler for MirrorApp {
                                 }
                                 KeyCode::KeyS => {
                                     log_verbose!("INPUT", "Shortcut: Screenshot");
-                                    let last_frame_mutex = self.last_frame.clone();
-                                    std::thread::spawn(move || {
-                                        let pending = last_frame_mutex.lock().unwrap();
-                                        if let Some(frame) = pending.clone() {
-                                            save_screenshot_yuv(frame);
-                                        }
-                                    });
+                                    self.take_screenshot(self.burn_touches);
+                                    return;
+                                }
+                                _ => {}
+                            }
+                        }
+
+                        // Everything else needs MOD, so other Ctrl combinations reach the device
+                        if self.shortcut_mod_pressed() {
+                            log_verbose!(
+                                "INPUT",
+                                "Modifier pressed, checking shortcut: {:?}",
+                                keycode
+                            );
+                            match keycode {
+                                KeyCode::KeyF => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle fullscreen");
+                                    self.toggle_fullscreen();
+                                    return;
+                                }
+                                KeyCode::KeyT => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle always-on-top");
+                                    self.toggle_always_on_top();
+                                    return;
+                                }
+                                KeyCode::KeyB => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle borderless");
+                                    self.toggle_borderless();
+                                    return;
+                                }
+                                KeyCode::KeyG => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle 1:1 pixel scale");
+                                    self.toggle_pixel_perfect();
+                                    return;
+                                }
+                                KeyCode::KeyW => {
+                                    log_verbose!("INPUT", "Shortcut: Fit to screen");
+                                    self.fit_to_screen();
+                                    return;
+                                }
+                                KeyCode::KeyK => {
+                                    log_verbose!("INPUT", "Shortcut: Cycle scale filter");
+                                    self.cycle_scale_filter();
+                                    return;
+                                }
+                                KeyCode::KeyJ => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle sharpening");
+                                    self.toggle_sharpen();
+                                    return;
+                                }
+                                KeyCode::KeyI => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle HUD");
+                                    self.toggle_hud();
+                                    return;
+                                }
+                                KeyCode::KeyO => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle touch visualization");
+                                    self.toggle_touches();
+                                    return;
+                                }
+                                KeyCode::Equal | KeyCode::NumpadAdd => {
+                                    log_verbose!("INPUT", "Shortcut: Zoom in");
+                                    self.zoom_by(ZOOM_STEP);
+                                    return;
+                                }
+                                KeyCode::Minus | KeyCode::NumpadSubtract => {
+                                    log_verbose!("INPUT", "Shortcut: Zoom out");
+                                    self.zoom_by(1.0 / ZOOM_STEP);
+                                    return;
+                                }
+                                KeyCode::Digit0 | KeyCode::Numpad0 => {
+                                    log_verbose!("INPUT", "Shortcut: Reset zoom");
+                                    self.reset_zoom();
+                                    return;
+                                }
+                                KeyCode::KeyL => {
+                                    log_verbose!("INPUT", "Shortcut: Toggle loupe");
+                                    self.toggle_loupe();
+                                    return;
+                                }
+                                KeyCode::BracketRight => {
+                                    log_verbose!("INPUT", "Shortcut: Bitrate up");
+                                    self.change_video(|v| v.step_bitrate(true));
+                                    return;
+                                }
+                                KeyCode::BracketLeft => {
+                                    log_verbose!("INPUT", "Shortcut: Bitrate down");
+                                    self.change_video(|v| v.step_bitrate(false));
+                                    return;
+                                }
+                                KeyCode::Period => {
+                                    log_verbose!("INPUT", "Shortcut: Max size up");
+                                    self.change_video(|v| v.step_max_size(true));
+                                    return;
+                                }
+                                KeyCode::Comma => {
+                                    log_verbose!("INPUT", "Shortcut: Max size down");
+                                    self.change_video(|v| v.step_max_size(false));
+                                    return;
+                                }
+                                KeyCode::Semicolon => {
+                                    log_verbose!("INPUT", "Shortcut: Cycle max fps");
+                                    self.change_video(VideoSettings::next_max_fps);
                                     return;
                                 }
                                 _ => {}
@@ -471,53 +1409,39 @@ impl ApplicationHandler for MirrorApp {
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: AppEvent) {
+        match event {
+            AppEvent::FrameReady => {
+                if let Some(w) = &self.window {
+                    w.request_redraw();
+                }
+            }
+            AppEvent::SessionChanged => self.update_session(event_loop),
+            AppEvent::Command(command) => self.run_command(command, event_loop),
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         if self.last_60s_log.elapsed().as_secs() >= 60 {
             log_verbose!("REN", "about_to_wait active");
             self.last_60s_log = std::time::Instant::now();
         }
 
-        if let Some(frame) = self.frame_buffer.consume() {
-            self.last_count += 1;
-
-            // Save last frame for screenshot (use try_lock to avoid blocking render)
-            if let Ok(mut last) = self.last_frame.try_lock() {
-                *last = Some(frame.clone());
-            }
-
-            if frame.width != self.current_width || frame.height != self.current_height {
-                log_verbose!(
-                    "REN",
-                    "Resolution change: {}x{} -> {}x{}",
-                    self.current_width,
-                    self.current_height,
-                    frame.width,
-                    frame.height
-                );
-                if let Some(window) = &self.window {
-                    let _ = window.request_inner_size(winit::dpi::PhysicalSize::new(
-                        frame.width,
-                        frame.height,
-                    ));
-
-                    if let Ok(renderer) =
-                        MirrorRenderer::new(window.clone(), frame.width, frame.height)
-                    {
-                        self.renderer = Some(renderer);
-                        self.current_width = frame.width;
-                        self.current_height = frame.height;
-                    }
-                }
-            }
+        self.update_window_title();
+        if self.transfer_status.message().is_some() {
+            // The transfer thread has picked the file up; the title now tracks it
+            self.transfer_queued = false;
+        }
 
-            if let Some(renderer) = &mut self.renderer {
-                if let Err(e) = renderer.render_yuv_frame(&frame) {
-                    log_error!("REN", "Render failed: {}", e);
-                }
+        // Save window geometry once moving/resizing has settled (the launcher may kill us)
+        if let Some(changed_at) = self.geometry_changed_at {
+            if changed_at.elapsed() >= GEOMETRY_SAVE_DELAY {
+                self.geometry_changed_at = None;
+                self.save_window_geometry();
             }
         }
 
-        if self.last_log.elapsed().as_secs() >= 10 {
+        if self.last_log.elapsed() >= STATS_LOG_INTERVAL {
             static mut LAST_RENDER_COUNT: u64 = 0;
             unsafe {
                 let rendered = self.last_count - LAST_RENDER_COUNT;
@@ -532,26 +1456,91 @@ impl ApplicationHandler for MirrorApp {
                 );
                 LAST_RENDER_COUNT = self.last_count;
             }
+            // p50/p95/p99 per stage
+            let latency: Vec<String> = Stage::ALL
+                .iter()
+                .filter_map(|&stage| {
+                    let p = self.stats.latency.percentiles(stage)?;
+                    Some(format!("{} {}", stage.label(), p))
+                })
+                .collect();
+            if !latency.is_empty() {
+                log_info!("REN", "Latency p50/p95/p99: {}", latency.join(", "));
+            }
             self.last_log = std::time::Instant::now();
         }
 
-        if let Some(w) = &self.window {
-            w.request_redraw();
+        if self.session.events().is_enabled()
+            && self.last_stats_event.elapsed() >= STATS_EVENT_INTERVAL
+        {
+            let rates = self.events_sampler.rates(&self.stats, self.last_count);
+            let latency = self.stats.latency.percentiles(Stage::Total);
+            self.session.events().emit(Event::Stats {
+                render_fps: rates.render_fps,
+                decode_fps: rates.decode_fps,
+                bitrate_mbps: rates.mbps,
+                latency_ms: latency.map(|p| p.p50.as_secs_f64() * 1000.0),
+            });
+            self.last_stats_event = Instant::now();
         }
-        _event_loop.set_control_flow(ControlFlow::Poll);
-        // Removed yield_now() - it can cause frame drops
+
+        // New frames request a redraw through AppEvent::FrameReady (buffered ones once they
+        // are due); everything else that changes the picture is handled here, then the loop
+        // sleeps until the next timer
+        if self.view_changed || self.needs_redraw || self.overlay_due() || self.frame_due() {
+            if let Some(w) = &self.window {
+                w.request_redraw();
+            }
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_wakeup()));
     }
 }
 
-pub fn run(
-    host: String,
-    port: u16,
-    bitrate: u32,
-    max_size: u32,
-    turn_screen_off: bool,
-) -> anyhow::Result<()> {
-    let event_loop = winit::event_loop::EventLoop::new()?;
-    let mut app = MirrorApp::new(host, port, bitrate, max_size, turn_screen_off);
+pub fn run(options: MirrorOptions) -> anyhow::Result<()> {
+    let event_loop = winit::event_loop::EventLoop::<AppEvent>::with_user_event().build()?;
+    let mut app = MirrorApp::new(options);
+
+    // The decoder wakes the loop when a frame is ready instead of the loop polling for it
+    let proxy = event_loop.create_proxy();
+    app.frame_buffer.set_waker(Box::new(move || {
+        let _ = proxy.send_event(AppEvent::FrameReady);
+    }));
+    let proxy = event_loop.create_proxy();
+    app.session.set_waker(Box::new(move || {
+        let _ = proxy.send_event(AppEvent::SessionChanged);
+    }));
+    if let Some(path) = app.ipc_path.clone() {
+        match start_ipc_server(
+            path,
+            event_loop.create_proxy(),
+            app.session.clone(),
+            app.recorder.clone(),
+        ) {
+            Ok(server) => app.ipc = Some(server),
+            Err(e) => {
+                log_error!("IPC", "Control socket unavailable: {}", e);
+            }
+        }
+    }
+
     event_loop.run_app(&mut app)?;
+    let lost = app.session.state() == SessionState::Lost;
+    // Restores the device screen before the exit status reaches the launcher
+    drop(app);
+    if lost {
+        anyhow::bail!("Connection to the device lost");
+    }
     Ok(())
 }
+
+/// Window banner for a session state (none while streaming)
+fn session_banner(state: SessionState) -> Option<String> {
+    match state {
+        SessionState::Streaming => None,
+        SessionState::Connecting => Some("CONNECTING...".to_string()),
+        SessionState::Reconnecting { attempt } => {
+            Some(format!("RECONNECTING (ATTEMPT {})...", attempt))
+        }
+        SessionState::Lost => Some("CONNECTION LOST".to_string()),
+    }
+}
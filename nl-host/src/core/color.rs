@@ -0,0 +1,181 @@
+//! YUV colour space description and YUV -> RGB coefficients
+//!
+//! Shared by the GPU shader (via `MirrorRenderer`) and the screenshot converter so both
+//! produce the same colours.
+
+use anyhow::anyhow;
+use std::fmt;
+use std::str::FromStr;
+
+/// YUV -> RGB matrix coefficients (H.264 `matrix_coefficients`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMatrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+/// Sample range of the Y/U/V planes (H.264 `video_full_range_flag`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorRange {
+    /// Y in 16-235, U/V in 16-240 (the H.264 default)
+    Limited,
+    /// All planes use 0-255
+    Full,
+}
+
+/// Colour description signalled in the stream (SPS VUI). `None` means not signalled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VideoSignal {
+    pub matrix: Option<ColorMatrix>,
+    pub range: Option<ColorRange>,
+}
+
+/// Manual override of the signalled colour description (`--color-matrix`, `--color-range`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ColorOverride {
+    pub matrix: Option<ColorMatrix>,
+    pub range: Option<ColorRange>,
+}
+
+/// Colour space a frame is converted with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpace {
+    pub matrix: ColorMatrix,
+    pub range: ColorRange,
+}
+
+impl Default for ColorSpace {
+    fn default() -> Self {
+        Self {
+            matrix: ColorMatrix::Bt709,
+            range: ColorRange::Limited,
+        }
+    }
+}
+
+impl ColorSpace {
+    /// Resolve the colour space for a frame: override first, then the stream's VUI,
+    /// then the same size-based defaults Android uses for unspecified video.
+    pub fn resolve(
+        signal: VideoSignal,
+        color_override: ColorOverride,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let default_matrix = if width >= 1280 || height >= 1280 || width * height >= 1280 * 576 {
+            ColorMatrix::Bt709
+        } else {
+            ColorMatrix::Bt601
+        };
+        Self {
+            matrix: color_override
+                .matrix
+                .or(signal.matrix)
+                .unwrap_or(default_matrix),
+            range: color_override
+                .range
+                .or(signal.range)
+                .unwrap_or(ColorRange::Limited),
+        }
+    }
+
+    /// Conversion coefficients for this colour space
+    pub fn conversion(&self) -> YuvToRgb {
+        let (kr, kb) = match self.matrix {
+            ColorMatrix::Bt601 => (0.299, 0.114),
+            ColorMatrix::Bt709 => (0.2126, 0.0722),
+            ColorMatrix::Bt2020 => (0.2627, 0.0593),
+        };
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, uv_scale) = match self.range {
+            ColorRange::Limited => (16.0 / 255.0, 255.0 / 219.0, 255.0 / 224.0),
+            ColorRange::Full => (0.0, 1.0, 1.0),
+        };
+        YuvToRgb {
+            y_offset,
+            y_scale,
+            uv_offset: 128.0 / 255.0,
+            uv_scale,
+            cr_r: 2.0 * (1.0 - kr),
+            cb_g: 2.0 * kb * (1.0 - kb) / kg,
+            cr_g: 2.0 * kr * (1.0 - kr) / kg,
+            cb_b: 2.0 * (1.0 - kb),
+        }
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let matrix = match self.matrix {
+            ColorMatrix::Bt601 => "BT.601",
+            ColorMatrix::Bt709 => "BT.709",
+            ColorMatrix::Bt2020 => "BT.2020",
+        };
+        let range = match self.range {
+            ColorRange::Limited => "limited",
+            ColorRange::Full => "full",
+        };
+        write!(f, "{} {} range", matrix, range)
+    }
+}
+
+/// YUV -> RGB coefficients on normalized (0-1) samples:
+/// `y' = (y - y_offset) * y_scale`, `c' = (c - uv_offset) * uv_scale`, then
+/// `R = y' + cr_r*v'`, `G = y' - cb_g*u' - cr_g*v'`, `B = y' + cb_b*u'`
+#[derive(Debug, Clone, Copy)]
+pub struct YuvToRgb {
+    pub y_offset: f32,
+    pub y_scale: f32,
+    pub uv_offset: f32,
+    pub uv_scale: f32,
+    pub cr_r: f32,
+    pub cb_g: f32,
+    pub cr_g: f32,
+    pub cb_b: f32,
+}
+
+impl YuvToRgb {
+    /// Convert one 8-bit YUV sample to 8-bit RGB
+    #[inline]
+    pub fn convert(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let y = (y as f32 / 255.0 - self.y_offset) * self.y_scale;
+        let u = (u as f32 / 255.0 - self.uv_offset) * self.uv_scale;
+        let v = (v as f32 / 255.0 - self.uv_offset) * self.uv_scale;
+
+        let r = y + self.cr_r * v;
+        let g = y - self.cb_g * u - self.cr_g * v;
+        let b = y + self.cb_b * u;
+
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [to_u8(r), to_u8(g), to_u8(b)]
+    }
+}
+
+impl FromStr for ColorMatrix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('.', "").as_str() {
+            "bt601" | "601" => Ok(Self::Bt601),
+            "bt709" | "709" => Ok(Self::Bt709),
+            "bt2020" | "2020" => Ok(Self::Bt2020),
+            _ => Err(anyhow!(
+                "unknown color matrix '{}' (bt601, bt709, bt2020)",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for ColorRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "limited" | "tv" => Ok(Self::Limited),
+            "full" | "pc" => Ok(Self::Full),
+            _ => Err(anyhow!("unknown color range '{}' (limited, full)", s)),
+        }
+    }
+}
@@ -1,8 +1,19 @@
 //! Global configuration for nl-host
 
+use crate::core::events::EventSink;
+use crate::core::{ColorOverride, RetryPolicy};
+use crate::network::{AdaptiveBounds, Endpoint, VideoSettings};
+use crate::video::ScaleFilter;
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-/// Global configuration flags
+/// Log switches for the whole process, not per session: `log_verbose!` (`--verbose`) and
+/// `log_debug!` output. Library users set them before starting a session; everything else
+/// a session reports goes to its own `EventSink`.
 pub static VERBOSE: AtomicBool = AtomicBool::new(false);
 pub static DEBUG: AtomicBool = AtomicBool::new(false);
 
@@ -17,3 +28,92 @@ pub fn is_verbose() -> bool {
 pub fn is_debug() -> bool {
     DEBUG.load(Ordering::Relaxed)
 }
+
+/// Options for a mirror session (built from the `mirror` CLI arguments)
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    /// Device host, with the key, `--mux` and `--udp` settings of the session
+    pub endpoint: Endpoint,
+    pub port: u16,
+    /// Initial encoder settings (changeable mid-session)
+    pub video: VideoSettings,
+    /// Adapt bitrate and size to congestion within these bounds
+    pub adaptive: Option<AdaptiveBounds>,
+    /// Stream device audio (Android 11+)
+    pub audio: bool,
+    /// How dropped connections are retried before the session is lost
+    pub retry: RetryPolicy,
+    pub turn_screen_off: bool,
+    /// Device folder for files dropped onto the window
+    pub push_target: String,
+    /// Forced colour matrix/range instead of what the stream signals
+    pub color: ColorOverride,
+    pub scale_filter: ScaleFilter,
+    /// Luma sharpening strength (0 = off)
+    pub sharpen: f32,
+    /// Presentation delay for smooth playback (zero = show every frame at once)
+    pub buffer: Duration,
+    /// Show the performance HUD from the start
+    pub hud: bool,
+    /// Draw markers for injected touches and key presses
+    pub show_touches: bool,
+    /// Also draw them into screenshots (implies `show_touches`)
+    pub burn_touches: bool,
+    /// Local control socket (Unix socket path or Windows pipe name)
+    pub ipc: Option<PathBuf>,
+    /// Where session events go (`--events`)
+    pub events: EventSink,
+    /// Modifier for the window shortcuts
+    pub shortcut_mod: ShortcutMod,
+    pub window: WindowOptions,
+}
+
+/// Initial window state for a mirror session
+#[derive(Debug, Clone, Default)]
+pub struct WindowOptions {
+    pub fullscreen: bool,
+    pub always_on_top: bool,
+    pub borderless: bool,
+    /// Size the window so one device pixel maps to one screen pixel
+    pub pixel_perfect: bool,
+    /// Key for the remembered window position/size (device serial)
+    pub serial: Option<String>,
+}
+
+/// Modifier held for the window shortcuts (MOD+F etc.), so the usual Ctrl combinations
+/// still reach the device. Ctrl/Cmd+C, V and S always work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShortcutMod {
+    #[default]
+    Alt,
+    Ctrl,
+    /// Cmd on macOS, the Windows key elsewhere
+    Super,
+}
+
+impl fmt::Display for ShortcutMod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Alt => "alt",
+            Self::Ctrl => "ctrl",
+            Self::Super => "super",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ShortcutMod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "alt" | "option" => Ok(Self::Alt),
+            "ctrl" | "control" => Ok(Self::Ctrl),
+            "super" | "cmd" | "meta" => Ok(Self::Super),
+            _ => Err(anyhow!(
+                "unknown shortcut modifier '{}' (alt, ctrl, super)",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Mirror sessions for other programs: the stream without the window
+//!
+//! `Session::builder(host)` configures a session like the `mirror` command does and
+//! `start()` runs its threads, returning a `SessionHandle` with channels for decoded frames,
+//! audio samples and state changes, plus the input sender. The handle can also record the
+//! video stream to MP4. Dropping the handle ends it.
+//!
+//! Events (resolution, reconnects, decoder resets...) go to the `EventSink` given to the
+//! builder. Logs go to stderr for every session of the process alike; `core::VERBOSE` and
+//! `core::DEBUG` turn on the chattier ones.
+//!
+//! ```no_run
+//! use nl_host::core::events::{Event, EventSink};
+//! use nl_host::{AudioMode, Session};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let handle = Session::builder("127.0.0.1")
+//!     .port(8888)
+//!     .audio(AudioMode::Play)
+//!     .events(EventSink::new(|event| {
+//!         if let Event::Resolution { width, height } = event {
+//!             println!("Streaming at {}x{}", width, height);
+//!         }
+//!     }))
+//!     .start()?;
+//!
+//! let path = handle.start_recording(None)?;
+//! for frame in handle.frames().iter().take(600) {
+//!     // I420 planes, e.g. for a texture upload
+//!     let _ = (frame.width, frame.height, &frame.y_plane, &frame.u_plane, &frame.v_plane);
+//! }
+//! let recording = handle.stop_recording()?;
+//! println!("{} frames in {}", recording.frames, path.display());
+//!
+//! // Closes the connections and waits for the video thread
+//! drop(handle);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::audio::{start_audio_playback, start_audio_stream};
+use crate::core::events::EventSink;
+use crate::core::{
+    ColorOverride, FrameBuffer, FrameData, RetryPolicy, Session, SessionState, SessionStats,
+};
+use crate::input::{start_input_thread, InputCommand};
+use crate::log_error;
+use crate::network::secure::Psk;
+use crate::network::{
+    start_adaptive_thread, start_clock_sync_thread, start_video_receiver, AdaptiveBounds, Endpoint,
+    VideoPacket, VideoReceiverHandle, VideoSettings,
+};
+use crate::video::{start_decoder_thread, Recorder, RecordingSummary};
+use anyhow::Result;
+use crossbeam_channel::{Receiver, Sender, TrySendError};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Decoded frames waiting for the consumer; older ones are dropped for newer ones
+const FRAME_QUEUE: usize = 2;
+
+/// What to do with the device audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioMode {
+    #[default]
+    Off,
+    /// Play it on the default output device, like the `mirror` command
+    Play,
+    /// Hand the samples to `SessionHandle::audio`
+    Samples,
+}
+
+/// Options of a session; see `Session::builder`
+pub struct SessionBuilder {
+    host: String,
+    port: u16,
+    control_port: Option<u16>,
+    audio_port: Option<u16>,
+    video: VideoSettings,
+    adaptive: Option<AdaptiveBounds>,
+    audio: AudioMode,
+    retry: RetryPolicy,
+    color: ColorOverride,
+    key: Option<Psk>,
+    mux: bool,
+    udp: bool,
+    events: EventSink,
+}
+
+impl Session {
+    /// Configure a session with the device server at `host` (port 8888 unless changed)
+    pub fn builder(host: impl Into<String>) -> SessionBuilder {
+        SessionBuilder {
+            host: host.into(),
+            port: 8888,
+            control_port: None,
+            audio_port: None,
+            video: VideoSettings::default(),
+            adaptive: None,
+            audio: AudioMode::Off,
+            retry: RetryPolicy::default(),
+            color: ColorOverride::default(),
+            key: None,
+            mux: false,
+            udp: false,
+            events: EventSink::default(),
+        }
+    }
+}
+
+impl SessionBuilder {
+    /// Video port; control and audio use the next two unless set
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn control_port(mut self, port: u16) -> Self {
+        self.control_port = Some(port);
+        self
+    }
+
+    pub fn audio_port(mut self, port: u16) -> Self {
+        self.audio_port = Some(port);
+        self
+    }
+
+    /// Initial encoder settings (changeable with `SessionHandle::set_video`)
+    pub fn video(mut self, settings: VideoSettings) -> Self {
+        self.video = settings;
+        self
+    }
+
+    /// Adapt bitrate and size to congestion within `bounds`
+    pub fn adaptive(mut self, bounds: AdaptiveBounds) -> Self {
+        self.adaptive = Some(bounds);
+        self
+    }
+
+    pub fn audio(mut self, mode: AudioMode) -> Self {
+        self.audio = mode;
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Force the colour description attached to decoded frames
+    pub fn color(mut self, color: ColorOverride) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Encrypt the connections with this pre-shared key (`--key-file`)
+    pub fn key(mut self, key: Psk) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Carry every connection over the video port (`--mux`)
+    pub fn mux(mut self, mux: bool) -> Self {
+        self.mux = mux;
+        self
+    }
+
+    /// Offer UDP for video and audio (`--udp`)
+    pub fn udp(mut self, udp: bool) -> Self {
+        self.udp = udp;
+        self
+    }
+
+    /// Report decoder resets, handshake failures and other events to `events`
+    pub fn events(mut self, events: EventSink) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Connect and start streaming
+    pub fn start(self) -> Result<SessionHandle> {
+        let mut endpoint = Endpoint::new(self.host).with_udp(self.udp);
+        if let Some(key) = self.key {
+            endpoint = endpoint.with_key(key);
+        }
+        if self.mux {
+            endpoint = endpoint.multiplexed(self.port);
+        }
+
+        let control_port = self.control_port.unwrap_or(self.port + 1);
+        let audio_port = self.audio_port.unwrap_or(self.port + 2);
+        let session = Arc::new(Session::new(self.retry).with_events(self.events));
+        let stats = Arc::new(SessionStats::new());
+        let video_settings = Arc::new(Mutex::new(self.video));
+
+        // State changes, as they happen
+        let (state_tx, states) = crossbeam_channel::unbounded();
+        let weak = Arc::downgrade(&session);
+        session.set_waker(Box::new(move || {
+            if let Some(session) = weak.upgrade() {
+                let _ = state_tx.send(session.state());
+            }
+        }));
+
+        // Decoded frames leave the buffer as soon as they are pushed
+        let (frame_tx, frames) = crossbeam_channel::bounded::<FrameData>(FRAME_QUEUE);
+        let frame_buffer = Arc::new(FrameBuffer::new());
+        let weak = Arc::downgrade(&frame_buffer);
+        let stale = frames.clone();
+        frame_buffer.set_waker(Box::new(move || {
+            let Some(frame) = weak.upgrade().and_then(|buffer| buffer.consume()) else {
+                return;
+            };
+            if let Err(TrySendError::Full(frame)) = frame_tx.try_send(frame) {
+                let _ = stale.try_recv();
+                let _ = frame_tx.try_send(frame);
+            }
+        }));
+
+        let (input, input_rx) = crossbeam_channel::bounded::<InputCommand>(256);
+        start_input_thread(endpoint.clone(), control_port, input_rx, session.clone());
+
+        let recorder = Arc::new(Recorder::new(session.events().clone()));
+        let (packet_tx, packet_rx) = crossbeam_channel::bounded::<VideoPacket>(256);
+        start_decoder_thread(
+            packet_rx,
+            frame_buffer,
+            self.color,
+            input.clone(),
+            stats.clone(),
+            recorder.clone(),
+            session.events().clone(),
+        );
+        let video = start_video_receiver(
+            endpoint.clone(),
+            self.port,
+            video_settings.clone(),
+            packet_tx,
+            session.clone(),
+            stats.clone(),
+        );
+
+        let audio = match self.audio {
+            AudioMode::Off => None,
+            AudioMode::Play => {
+                start_audio_playback(start_audio_stream(
+                    endpoint.clone(),
+                    audio_port,
+                    session.clone(),
+                ));
+                None
+            }
+            AudioMode::Samples => Some(start_audio_stream(
+                endpoint.clone(),
+                audio_port,
+                session.clone(),
+            )),
+        };
+
+        start_clock_sync_thread(
+            endpoint.clone(),
+            control_port,
+            stats.clone(),
+            session.clone(),
+        );
+        if let Some(bounds) = self.adaptive {
+            start_adaptive_thread(
+                endpoint.clone(),
+                control_port,
+                bounds,
+                video_settings.clone(),
+                stats.clone(),
+                session.clone(),
+            );
+        }
+
+        Ok(SessionHandle {
+            endpoint,
+            session,
+            stats,
+            video_settings,
+            input,
+            frames,
+            states,
+            audio,
+            recorder,
+            _video: video,
+        })
+    }
+}
+
+/// A running session; dropping it closes the session and waits for the video thread
+pub struct SessionHandle {
+    endpoint: Endpoint,
+    session: Arc<Session>,
+    stats: Arc<SessionStats>,
+    video_settings: Arc<Mutex<VideoSettings>>,
+    input: Sender<InputCommand>,
+    frames: Receiver<FrameData>,
+    states: Receiver<SessionState>,
+    audio: Option<Receiver<Vec<f32>>>,
+    recorder: Arc<Recorder>,
+    _video: VideoReceiverHandle,
+}
+
+impl SessionHandle {
+    pub fn state(&self) -> SessionState {
+        self.session.state()
+    }
+
+    /// Every state change, in order. `Lost` is the last one.
+    pub fn states(&self) -> &Receiver<SessionState> {
+        &self.states
+    }
+
+    /// Decoded I420 frames. A slow consumer misses frames instead of falling behind.
+    pub fn frames(&self) -> &Receiver<FrameData> {
+        &self.frames
+    }
+
+    /// Interleaved stereo f32 samples at 48 kHz, with `AudioMode::Samples`
+    pub fn audio(&self) -> Option<&Receiver<Vec<f32>>> {
+        self.audio.as_ref()
+    }
+
+    /// Sender for taps, keys, clipboard and other device commands
+    pub fn input(&self) -> Sender<InputCommand> {
+        self.input.clone()
+    }
+
+    pub fn stats(&self) -> &Arc<SessionStats> {
+        &self.stats
+    }
+
+    pub fn video_settings(&self) -> VideoSettings {
+        *self.video_settings.lock().unwrap()
+    }
+
+    /// Change the device encoder; also used for later reconnects
+    pub fn set_video(&self, settings: VideoSettings) {
+        *self.video_settings.lock().unwrap() = settings;
+        let _ = self.input.send(InputCommand::SetVideo(settings));
+    }
+
+    /// Record the video stream to `path` (a new file; `recording_<time>.mp4` on the Desktop
+    /// if `None`) from the next keyframe on. Returns the path.
+    pub fn start_recording(&self, path: Option<PathBuf>) -> Result<PathBuf> {
+        self.recorder.start(path)
+    }
+
+    /// Finish the recording; it is also finished when the handle is dropped
+    pub fn stop_recording(&self) -> Result<RecordingSummary> {
+        self.recorder.stop()
+    }
+
+    /// Stop streaming and reconnecting
+    pub fn close(&self) {
+        self.session.close();
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        // The video receiver is joined next, which ends the decoder behind it
+        self.session.close();
+        self.endpoint.close();
+        if self.recorder.is_recording() {
+            if let Err(e) = self.recorder.stop() {
+                log_error!("REC", "Recording not saved: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn dropping_the_handle_ends_the_session() {
+        // A device that takes the multiplexed connection and never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = Session::builder("127.0.0.1")
+            .port(port)
+            .mux(true)
+            .start()
+            .unwrap();
+        let (mut device, _) = listener.accept().unwrap();
+        device
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut hello = [0; 4];
+        device.read_exact(&mut hello).unwrap();
+        assert_eq!(&hello, b"mux\n");
+
+        // Dropping joins the video thread, so it returns once that thread has ended
+        let (dropped_tx, dropped) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || {
+            drop(handle);
+            let _ = dropped_tx.send(());
+        });
+        dropped
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the video thread did not end");
+
+        // The socket is closed: the rest of what was sent ends in EOF, not a read timeout
+        let mut rest = Vec::new();
+        device.read_to_end(&mut rest).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//! Machine-readable session events (`--events json`)
+//!
+//! One JSON object per stdout line, for the launcher; logs stay on stderr. Library users
+//! can receive the same events through their own `EventSink`.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Output format of `--events`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFormat {
+    Json,
+}
+
+impl FromStr for EventFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("unknown event format '{}' (json)", s)),
+        }
+    }
+}
+
+/// Something the launcher UI may want to show, e.g. `{"event":"resolution","width":1080,...}`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Video and control are up (again, after a reconnect)
+    Connected,
+    /// A connection dropped and is being retried
+    Reconnecting {
+        attempt: u32,
+    },
+    /// Size of the decoded video, sent with the first frame and on every change
+    Resolution {
+        width: u32,
+        height: u32,
+    },
+    /// Throughput over the last second
+    Stats {
+        render_fps: f64,
+        decode_fps: f64,
+        bitrate_mbps: f64,
+        /// Capture-to-presentation p50, once the device clock is synced
+        latency_ms: Option<f64>,
+    },
+    /// The decoder dropped its state and waits for the next keyframe
+    DecoderReset {
+        count: u64,
+        reason: String,
+    },
+    ScreenshotSaved {
+        path: String,
+    },
+    /// A recording was started (`start_recording`); frames follow from the next keyframe
+    RecordingStarted {
+        path: String,
+    },
+    /// A recording was finished, on request, on a video format change or at the session end
+    RecordingStopped {
+        path: String,
+        frames: u64,
+        duration_ms: u64,
+    },
+    /// Something failed; `fatal` when mirroring cannot go on
+    Error {
+        message: String,
+        fatal: bool,
+    },
+}
+
+/// Receives the events of a session
+pub type EventHandler = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Where the events of one session go; every thread that reports something holds a clone.
+/// The default sink drops them.
+#[derive(Clone, Default)]
+pub struct EventSink {
+    handler: Option<EventHandler>,
+}
+
+impl std::fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSink")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl EventSink {
+    /// Hand every event to `handler`, on the thread that reports it
+    pub fn new(handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Some(Arc::new(handler)),
+        }
+    }
+
+    /// One line per event on stdout (`--events`)
+    pub fn stdout(format: EventFormat) -> Self {
+        match format {
+            EventFormat::Json => Self::new(|event| {
+                let Ok(line) = serde_json::to_string(event) else {
+                    return;
+                };
+                let mut out = std::io::stdout().lock();
+                let _ = writeln!(out, "{}", line);
+                let _ = out.flush();
+            }),
+        }
+    }
+
+    /// False for the default sink, so periodic events need not be put together
+    pub fn is_enabled(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    pub fn emit(&self, event: Event) {
+        if let Some(handler) = &self.handler {
+            handler(&event);
+        }
+    }
+}
@@ -1,5 +1,15 @@
+use crate::core::ColorSpace;
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most frames held by the presentation buffer, whatever their PTS say
+const MAX_QUEUED_FRAMES: usize = 64;
+/// The network's best-case transit is re-measured over windows this long (clock drift)
+const TRANSIT_WINDOW: Duration = Duration::from_secs(10);
+/// A PTS this far off the schedule means a new timeline (e.g. an encoder restart)
+const MAX_SCHEDULE_ERROR_US: i64 = 1_000_000;
 
 /// Represents a single decoded video frame with YUV I420 data
 /// YUV planes are uploaded directly to GPU for shader-based RGB conversion
@@ -12,32 +22,81 @@ pub struct FrameData {
     pub v_plane: Arc<Vec<u8>>,
     pub y_stride: usize,
     pub uv_stride: usize,
+    /// Colour space used to convert the planes to RGB
+    pub color: ColorSpace,
+    /// Device capture time of the packet it was decoded from (0 if unknown)
+    pub pts: u64,
+    /// When the decoder produced the frame
+    pub decoded_at: Instant,
+    /// When the frame was ready to present: when it was decoded, or its scheduled time with
+    /// a presentation delay. The HUD's frame age counts from here, so the delay is not in it.
+    pub ready_at: Instant,
 }
 
+/// Called by the decoder thread when a frame becomes pending (wakes the event loop)
+pub type FrameWaker = Box<dyn Fn() + Send + Sync>;
+
 /// Frame buffer with mutex synchronization
 ///
 /// Holds at most 1 pending frame. If a new frame arrives before
 /// the previous one is consumed, the old frame is dropped (to minimize latency).
+/// With a presentation delay (`--buffer-ms`) frames are queued instead and released by
+/// PTS, so network jitter up to the delay no longer shows as stutter.
 pub struct FrameBuffer {
     pending_frame: Mutex<Option<FrameData>>,
+    playout: Option<Mutex<Playout>>,
     frame_count: AtomicU64,
+    /// Frames the playout skipped because a later one was already due
+    dropped: AtomicU64,
+    waker: Mutex<Option<FrameWaker>>,
 }
 
 impl FrameBuffer {
     pub fn new() -> Self {
+        Self::with_delay(Duration::ZERO)
+    }
+
+    /// Present each frame `delay` after the earliest it could have arrived
+    pub fn with_delay(delay: Duration) -> Self {
         Self {
             pending_frame: Mutex::new(None),
+            playout: (!delay.is_zero()).then(|| Mutex::new(Playout::new(delay))),
             frame_count: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            waker: Mutex::new(None),
         }
     }
 
+    /// Call `waker` whenever a frame becomes pending
+    pub fn set_waker(&self, waker: FrameWaker) {
+        *self.waker.lock().unwrap() = Some(waker);
+    }
+
     /// Push a new frame, replacing any pending frame
     /// Returns true if previous frame was skipped, None if lock failed
     pub fn push(&self, frame: FrameData) -> bool {
+        if let Some(playout) = &self.playout {
+            let (skipped, first) = playout.lock().unwrap().push(frame);
+            self.frame_count.fetch_add(1, Ordering::Relaxed);
+            // The event loop sleeps until the head of the queue is due; tell it about a new head
+            if first {
+                if let Some(wake) = self.waker.lock().unwrap().as_ref() {
+                    wake();
+                }
+            }
+            return skipped;
+        }
         if let Ok(mut pending) = self.pending_frame.try_lock() {
             let skipped = pending.is_some();
             *pending = Some(frame);
+            drop(pending);
             self.frame_count.fetch_add(1, Ordering::Relaxed);
+            // A replaced frame already has a wake-up queued
+            if !skipped {
+                if let Some(wake) = self.waker.lock().unwrap().as_ref() {
+                    wake();
+                }
+            }
             skipped
         } else {
             // Lock contention - drop this frame to avoid blocking
@@ -45,18 +104,38 @@ impl FrameBuffer {
         }
     }
 
-    /// Consume the pending frame, if any
+    /// Consume the pending frame, if any.
+    /// Blocks briefly on contention: a frame left pending here would get no new wake-up.
     pub fn consume(&self) -> Option<FrameData> {
-        self.pending_frame
-            .try_lock()
-            .ok()
-            .and_then(|mut p| p.take())
+        if let Some(playout) = &self.playout {
+            let (frame, dropped) = playout.lock().unwrap().pop_due(Instant::now());
+            self.dropped.fetch_add(dropped, Ordering::Relaxed);
+            return frame;
+        }
+        self.pending_frame.lock().ok().and_then(|mut p| p.take())
+    }
+
+    /// When the next frame is to be presented (now, if one is pending without a delay)
+    pub fn next_due(&self) -> Option<Instant> {
+        match &self.playout {
+            Some(playout) => playout.lock().unwrap().next_due(),
+            None => self
+                .pending_frame
+                .lock()
+                .ok()
+                .and_then(|p| p.as_ref().map(|_| Instant::now())),
+        }
     }
 
     /// Get total frame count received
     pub fn get_count(&self) -> u64 {
         self.frame_count.load(Ordering::Relaxed)
     }
+
+    /// Frames skipped by `consume` since the last call
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
 }
 
 impl Default for FrameBuffer {
@@ -64,3 +143,234 @@ impl Default for FrameBuffer {
         Self::new()
     }
 }
+
+/// Schedules frames by PTS: each is due `delay` after the best-case arrival time of its PTS
+struct Playout {
+    delay: Duration,
+    queue: VecDeque<(Instant, FrameData)>,
+    /// Arrival time and PTS of the frame the schedule is anchored to
+    anchor: Option<(Instant, u64)>,
+    /// Smallest transit seen (arrival minus PTS, relative to the anchor; microseconds)
+    base_us: i64,
+    /// Smallest transit in the current window, and when the window started
+    window_min_us: i64,
+    window_start: Instant,
+}
+
+impl Playout {
+    fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            queue: VecDeque::new(),
+            anchor: None,
+            base_us: 0,
+            window_min_us: i64::MAX,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Queue a frame; returns (a frame was dropped, it is the only frame queued)
+    fn push(&mut self, frame: FrameData) -> (bool, bool) {
+        let due = self.schedule(frame.pts, frame.decoded_at);
+        // A lowered base can put a new frame before the ones already queued; keep PTS order
+        let due = self.queue.back().map_or(due, |(last, _)| due.max(*last));
+        self.queue.push_back((due, frame));
+        let skipped = self.queue.len() > MAX_QUEUED_FRAMES;
+        if skipped {
+            self.queue.pop_front();
+        }
+        (skipped, self.queue.len() == 1)
+    }
+
+    fn schedule(&mut self, pts: u64, arrival: Instant) -> Instant {
+        // No timestamp: present right away
+        if pts == 0 {
+            return arrival;
+        }
+        let Some((anchor_time, anchor_pts)) = self.anchor else {
+            return self.reanchor(pts, arrival);
+        };
+
+        let media_us = pts as i64 - anchor_pts as i64;
+        let transit_us =
+            arrival.saturating_duration_since(anchor_time).as_micros() as i64 - media_us;
+        if media_us < 0 || (transit_us - self.base_us).abs() > MAX_SCHEDULE_ERROR_US {
+            return self.reanchor(pts, arrival);
+        }
+
+        // Frames that made it faster than any before lower the base at once; a base that
+        // became too low (slower route, clock drift) is raised once per window
+        self.base_us = self.base_us.min(transit_us);
+        self.window_min_us = self.window_min_us.min(transit_us);
+        if arrival.saturating_duration_since(self.window_start) >= TRANSIT_WINDOW {
+            self.base_us = self.window_min_us;
+            self.window_min_us = i64::MAX;
+            self.window_start = arrival;
+        }
+
+        let due_us = media_us + self.base_us + self.delay.as_micros() as i64;
+        anchor_time + Duration::from_micros(due_us.max(0) as u64)
+    }
+
+    fn reanchor(&mut self, pts: u64, arrival: Instant) -> Instant {
+        self.anchor = Some((arrival, pts));
+        self.base_us = 0;
+        self.window_min_us = i64::MAX;
+        self.window_start = arrival;
+        arrival + self.delay
+    }
+
+    /// The newest frame that is due, and how many frames it overtook (they are dropped)
+    fn pop_due(&mut self, now: Instant) -> (Option<FrameData>, u64) {
+        let mut frame = None;
+        let mut dropped = 0;
+        while self.queue.front().is_some_and(|(due, _)| *due <= now) {
+            let (due, mut next) = self.queue.pop_front().unwrap();
+            next.ready_at = due;
+            dropped += u64::from(frame.replace(next).is_some());
+        }
+        (frame, dropped)
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.queue.front().map(|(due, _)| *due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELAY: Duration = Duration::from_millis(50);
+
+    fn frame(pts: u64, arrival: Instant) -> FrameData {
+        FrameData {
+            width: 2,
+            height: 2,
+            y_plane: Arc::new(vec![0; 4]),
+            u_plane: Arc::new(vec![0; 1]),
+            v_plane: Arc::new(vec![0; 1]),
+            y_stride: 2,
+            uv_stride: 1,
+            color: ColorSpace::default(),
+            pts,
+            decoded_at: arrival,
+            ready_at: arrival,
+        }
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn frames_are_due_delay_after_their_pts() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        assert_eq!(playout.schedule(1_000_000, start), start + DELAY);
+        // Arrived 10ms late: still scheduled by PTS
+        assert_eq!(
+            playout.schedule(1_016_000, start + ms(26)),
+            start + ms(16) + DELAY
+        );
+    }
+
+    #[test]
+    fn reanchors_on_a_large_schedule_error() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        playout.schedule(1_000_000, start);
+        // 2s of PTS in 16ms of arrival: an encoder restart
+        let arrival = start + ms(16);
+        assert_eq!(playout.schedule(3_000_000, arrival), arrival + DELAY);
+        assert_eq!(playout.anchor, Some((arrival, 3_000_000)));
+        // So is a PTS going backwards
+        let arrival = start + ms(32);
+        assert_eq!(playout.schedule(500_000, arrival), arrival + DELAY);
+    }
+
+    #[test]
+    fn faster_frames_lower_the_base_at_once() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        playout.schedule(1_000_000, start);
+        // Took 5ms less than the anchor frame
+        let due = playout.schedule(1_020_000, start + ms(15));
+        assert_eq!(playout.base_us, -5_000);
+        assert_eq!(due, start + ms(15) + DELAY);
+    }
+
+    #[test]
+    fn a_too_low_base_is_raised_after_a_window() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        playout.schedule(1_000_000, start);
+        // The route got 20ms slower: the base holds until the window ends
+        let mut pts = 1_000_000;
+        let mut arrival = start;
+        while arrival < start + TRANSIT_WINDOW {
+            pts += 100_000;
+            arrival = start + Duration::from_micros(pts - 1_000_000) + ms(20);
+            playout.schedule(pts, arrival);
+        }
+        assert_eq!(playout.base_us, 20_000);
+        // Still lowered right away
+        let pts = pts + 100_000;
+        playout.schedule(pts, start + Duration::from_micros(pts - 1_000_000) + ms(5));
+        assert_eq!(playout.base_us, 5_000);
+    }
+
+    #[test]
+    fn due_times_stay_in_queue_order() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        playout.push(frame(1_000_000, start));
+        playout.push(frame(1_100_000, start + ms(100)));
+        // Lowers the base by 96ms, which would put it 80ms before the frame queued ahead of it
+        playout.push(frame(1_116_000, start + ms(20)));
+        let dues: Vec<Instant> = playout.queue.iter().map(|(due, _)| *due).collect();
+        assert_eq!(
+            dues,
+            [
+                start + DELAY,
+                start + ms(100) + DELAY,
+                start + ms(100) + DELAY
+            ]
+        );
+    }
+
+    #[test]
+    fn oldest_frame_is_dropped_past_the_queue_limit() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        for i in 0..MAX_QUEUED_FRAMES as u64 {
+            let (skipped, only) = playout.push(frame(1_000_000 + i * 1_000, start));
+            assert!(!skipped);
+            assert_eq!(only, i == 0);
+        }
+        let (skipped, _) = playout.push(frame(1_100_000, start));
+        assert!(skipped);
+        assert_eq!(playout.queue.len(), MAX_QUEUED_FRAMES);
+        assert_eq!(playout.queue.front().unwrap().1.pts, 1_001_000);
+    }
+
+    #[test]
+    fn pop_due_returns_the_newest_due_frame_and_counts_the_rest() {
+        let start = Instant::now();
+        let mut playout = Playout::new(DELAY);
+        for i in 0..4 {
+            playout.push(frame(1_000_000 + i * 10_000, start + ms(i * 10)));
+        }
+        assert!(playout.pop_due(start).0.is_none());
+
+        let (frame, dropped) = playout.pop_due(start + DELAY + ms(25));
+        let frame = frame.unwrap();
+        assert_eq!((frame.pts, dropped), (1_020_000, 2));
+        assert_eq!(frame.ready_at, start + DELAY + ms(20));
+        assert_eq!(playout.next_due(), Some(start + DELAY + ms(30)));
+
+        let (frame, dropped) = playout.pop_due(start + ms(1_000));
+        assert_eq!((frame.unwrap().pts, dropped), (1_030_000, 0));
+        assert_eq!(playout.pop_due(start + ms(1_000)).1, 0);
+    }
+}
@@ -0,0 +1,280 @@
+//! Local control socket of a running session (`--ipc`)
+//!
+//! Clients send one JSON command per line and get one JSON reply per line, e.g.
+//! `{"cmd": "set_video", "bitrate": 4000000}` -> `{"ok": true}`. A Unix domain socket on
+//! Unix, a named pipe (`\\.\pipe\...`) on Windows. Clients are served side by side, so one
+//! that stays connected does not lock out the others.
+//! `{"cmd": "start_recording", "path": ...}` records the video stream to MP4 (`path` is
+//! optional) and answers with the path; `stop_recording` answers with the path, frame count
+//! and duration.
+
+use crate::core::app::AppEvent;
+use crate::core::Session;
+use crate::video::Recorder;
+use crate::{log_info, log_verbose};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use winit::event_loop::EventLoopProxy;
+
+/// A command for the session, run on the event loop
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum SessionCommand {
+    /// Save a screenshot like Ctrl+S (reported by the `screenshot_saved` event).
+    /// `burn_touches` draws the touch markers into it, or leaves them out; `--burn-touches`
+    /// decides if omitted.
+    Screenshot { burn_touches: Option<bool> },
+    /// Switch the device between portrait and landscape
+    Rotate,
+    /// Change the device encoder; omitted fields keep their value
+    SetVideo {
+        bitrate: Option<u32>,
+        max_size: Option<u32>,
+        max_fps: Option<u32>,
+    },
+    /// Turn the device screen on or off (it is turned back on when the session ends)
+    ScreenPower { on: bool },
+    /// Close the window and end the session
+    Quit,
+}
+
+/// The listening socket; its file is removed when the session ends
+pub struct IpcServer {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    path: PathBuf,
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Arguments of `start_recording`
+#[derive(Debug, Deserialize)]
+struct StartRecording {
+    path: Option<PathBuf>,
+}
+
+/// Listen on `path` and forward commands to the event loop through `proxy`.
+/// Recording commands go to `recorder` directly.
+pub fn start_ipc_server(
+    path: PathBuf,
+    proxy: EventLoopProxy<AppEvent>,
+    session: Arc<Session>,
+    recorder: Arc<Recorder>,
+) -> Result<IpcServer> {
+    platform::serve(&path, move |line| {
+        handle(line, &proxy, &session, &recorder).to_string()
+    })?;
+    log_info!("IPC", "Listening on {}", path.display());
+    Ok(IpcServer { path })
+}
+
+fn handle(
+    line: &str,
+    proxy: &EventLoopProxy<AppEvent>,
+    session: &Session,
+    recorder: &Recorder,
+) -> Value {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return error(format!("Invalid JSON: {}", e)),
+    };
+    log_verbose!("IPC", "Request: {}", request);
+    match request.get("cmd").and_then(|c| c.as_str()) {
+        None => error("Missing 'cmd'".to_string()),
+        Some("status") => json!({ "ok": true, "state": session.state().to_string() }),
+        Some("start_recording") => {
+            let started = serde_json::from_value::<StartRecording>(request)
+                .map_err(anyhow::Error::from)
+                .and_then(|args| recorder.start(args.path));
+            match started {
+                Ok(path) => json!({ "ok": true, "path": path }),
+                Err(e) => error(e.to_string()),
+            }
+        }
+        Some("stop_recording") => match recorder.stop() {
+            Ok(summary) => json!({
+                "ok": true,
+                "path": summary.path,
+                "frames": summary.frames,
+                "duration_ms": summary.duration_ms,
+            }),
+            Err(e) => error(e.to_string()),
+        },
+        Some(_) => match serde_json::from_value::<SessionCommand>(request) {
+            Ok(command) => match proxy.send_event(AppEvent::Command(command)) {
+                Ok(()) => json!({ "ok": true }),
+                Err(_) => error("Session is closing".to_string()),
+            },
+            Err(e) => error(e.to_string()),
+        },
+    }
+}
+
+fn error(message: String) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    pub fn serve(
+        path: &Path,
+        handler: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Result<()> {
+        // Left behind by a session that crashed; binding fails while it exists
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let handler = Arc::new(handler);
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut writer) = stream else {
+                    continue;
+                };
+                let Ok(reader) = writer.try_clone() else {
+                    continue;
+                };
+                let handler = handler.clone();
+                std::thread::spawn(move || {
+                    for line in BufReader::new(reader).lines() {
+                        let Ok(line) = line else {
+                            break;
+                        };
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        if writeln!(writer, "{}", handler(&line)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use crate::log_error;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    pub fn serve(
+        path: &Path,
+        handler: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_io()
+            .build()?;
+        let handler = Arc::new(handler);
+        let name = path.as_os_str().to_owned();
+        // Created up front, so a name already in use fails the session start
+        let mut server = {
+            let _context = runtime.enter();
+            ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&name)?
+        };
+
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                loop {
+                    let connected = server.connect().await;
+                    // The next instance exists before this client is served, so a client
+                    // connecting meanwhile waits instead of finding no pipe
+                    let client = match ServerOptions::new().create(&name) {
+                        Ok(next) => std::mem::replace(&mut server, next),
+                        Err(e) => {
+                            log_error!("IPC", "Failed to create pipe: {}", e);
+                            return;
+                        }
+                    };
+                    if connected.is_err() {
+                        continue;
+                    }
+
+                    let handler = handler.clone();
+                    tokio::spawn(async move {
+                        let (reader, mut writer) = tokio::io::split(client);
+                        let mut lines = BufReader::new(reader).lines();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line.trim().is_empty() {
+                                continue;
+                            }
+                            let reply = format!("{}\n", handler(&line));
+                            if writer.write_all(reply.as_bytes()).await.is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+        });
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    fn request(stream: &mut UnixStream, line: &str) -> String {
+        writeln!(stream, "{}", line).unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream.try_clone().unwrap())
+            .read_line(&mut reply)
+            .unwrap();
+        reply.trim_end().to_string()
+    }
+
+    #[test]
+    fn screenshot_burn_touches_is_optional() {
+        let parse = |line| serde_json::from_str::<SessionCommand>(line).unwrap();
+        assert!(matches!(
+            parse(r#"{"cmd": "screenshot"}"#),
+            SessionCommand::Screenshot { burn_touches: None }
+        ));
+        assert!(matches!(
+            parse(r#"{"cmd": "screenshot", "burn_touches": true}"#),
+            SessionCommand::Screenshot {
+                burn_touches: Some(true)
+            }
+        ));
+    }
+
+    #[test]
+    fn clients_are_served_side_by_side() {
+        let path = std::env::temp_dir().join(format!("nl-ipc-test-{}.sock", std::process::id()));
+        platform::serve(&path, |line| format!("echo {}", line)).unwrap();
+
+        // The first client stays connected while the second one is answered
+        let mut first = UnixStream::connect(&path).unwrap();
+        let mut second = UnixStream::connect(&path).unwrap();
+        for stream in [&first, &second] {
+            stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+        }
+        assert_eq!(request(&mut second, "b"), "echo b");
+        assert_eq!(request(&mut first, "a"), "echo a");
+        let _ = std::fs::remove_file(&path);
+    }
+}
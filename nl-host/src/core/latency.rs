@@ -0,0 +1,224 @@
+//! End-to-end latency: device clock mapping and per-stage percentiles
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Samples kept per stage (~8s at 60 FPS)
+const WINDOW: usize = 512;
+
+/// Maps device timestamps (the monotonic clock of video and audio PTS) to host time.
+/// Set by the clock sync thread; unknown until its first exchange.
+pub struct DeviceClock {
+    /// Host time 0
+    epoch: Instant,
+    /// Device time minus host time (microseconds)
+    offset_us: AtomicI64,
+    /// Round trip of the exchange the offset came from (microseconds)
+    rtt_us: AtomicU64,
+    synced: AtomicBool,
+}
+
+impl DeviceClock {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            offset_us: AtomicI64::new(0),
+            rtt_us: AtomicU64::new(0),
+            synced: AtomicBool::new(false),
+        }
+    }
+
+    /// `instant` in host microseconds
+    pub fn host_us(&self, instant: Instant) -> i64 {
+        instant.saturating_duration_since(self.epoch).as_micros() as i64
+    }
+
+    /// Use one request/response exchange: host times of sending and receiving, and the
+    /// device time in between. The error is at most half the round trip.
+    pub fn update(&self, sent: Instant, received: Instant, device_us: u64) {
+        let midpoint = (self.host_us(sent) + self.host_us(received)) / 2;
+        let rtt = received.saturating_duration_since(sent);
+        self.offset_us
+            .store(device_us as i64 - midpoint, Ordering::Relaxed);
+        self.rtt_us.store(rtt.as_micros() as u64, Ordering::Relaxed);
+        self.synced.store(true, Ordering::Release);
+    }
+
+    /// (offset, round trip) of the current sync
+    pub fn sync(&self) -> Option<(i64, Duration)> {
+        if !self.synced.load(Ordering::Acquire) {
+            return None;
+        }
+        Some((
+            self.offset_us.load(Ordering::Relaxed),
+            Duration::from_micros(self.rtt_us.load(Ordering::Relaxed)),
+        ))
+    }
+
+    /// Time from device capture at `pts` until `now`
+    pub fn since_capture(&self, pts: u64, now: Instant) -> Option<Duration> {
+        let (offset, _) = self.sync()?;
+        let captured = pts as i64 - offset;
+        let elapsed = self.host_us(now) - captured;
+        // Negative only within sync error; clamp rather than drop the sample
+        Some(Duration::from_micros(elapsed.max(0) as u64))
+    }
+}
+
+impl Default for DeviceClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A pipeline stage measured for every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Device capture to the packet arriving (needs clock sync)
+    Network,
+    /// Packet arrival to the decoded frame
+    Decode,
+    /// Decoded frame to presentation
+    Present,
+    /// Device capture to presentation (needs clock sync)
+    Total,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Network, Stage::Decode, Stage::Present, Stage::Total];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Network => "CAP>RCV",
+            Stage::Decode => "RCV>DEC",
+            Stage::Present => "DEC>PRS",
+            Stage::Total => "CAP>PRS",
+        }
+    }
+}
+
+/// p50/p95/p99 of a stage's recent samples
+#[derive(Debug, Clone, Copy)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl std::fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.1}/{:.1}/{:.1} ms",
+            self.p50.as_secs_f64() * 1000.0,
+            self.p95.as_secs_f64() * 1000.0,
+            self.p99.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// Recent latency samples per stage, recorded by the network, decoder and render threads
+#[derive(Default)]
+pub struct LatencyStats {
+    windows: [Mutex<VecDeque<Duration>>; 4],
+}
+
+impl LatencyStats {
+    pub fn record(&self, stage: Stage, latency: Duration) {
+        let mut window = self.windows[stage as usize].lock().unwrap();
+        if window.len() == WINDOW {
+            window.pop_front();
+        }
+        window.push_back(latency);
+    }
+
+    /// Percentiles over the recent window, if anything was recorded
+    pub fn percentiles(&self, stage: Stage) -> Option<Percentiles> {
+        let mut samples: Vec<Duration> = self.windows[stage as usize]
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let at = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Some(Percentiles {
+            p50: at(50),
+            p95: at(95),
+            p99: at(99),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn offset_is_taken_at_the_midpoint_of_the_exchange() {
+        let clock = DeviceClock::new();
+        assert!(clock.sync().is_none());
+        let sent = clock.epoch + ms(1_000);
+        clock.update(sent, sent + ms(20), 3_000_000);
+        // Device read at host 1.010s
+        assert_eq!(clock.sync(), Some((1_990_000, ms(20))));
+    }
+
+    #[test]
+    fn capture_latency_uses_the_offset() {
+        let clock = DeviceClock::new();
+        assert!(clock.since_capture(1, clock.epoch).is_none());
+        clock.update(clock.epoch, clock.epoch, 10_000_000);
+        // Captured at host 0.5s, shown at 0.58s
+        let shown = clock.epoch + ms(580);
+        assert_eq!(clock.since_capture(10_500_000, shown), Some(ms(80)));
+        // Within sync error of the future: clamped
+        assert_eq!(clock.since_capture(10_600_000, shown), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn percentiles_index_the_sorted_window() {
+        let stats = LatencyStats::default();
+        assert!(stats.percentiles(Stage::Decode).is_none());
+        // 1..=101 ms, shuffled
+        for i in 0..101 {
+            stats.record(Stage::Decode, ms((i * 37) % 101 + 1));
+        }
+        let p = stats.percentiles(Stage::Decode).unwrap();
+        assert_eq!((p.p50, p.p95, p.p99), (ms(51), ms(96), ms(100)));
+        assert!(stats.percentiles(Stage::Present).is_none());
+    }
+
+    #[test]
+    fn percentiles_of_few_samples() {
+        let stats = LatencyStats::default();
+        stats.record(Stage::Total, ms(7));
+        let p = stats.percentiles(Stage::Total).unwrap();
+        assert_eq!((p.p50, p.p95, p.p99), (ms(7), ms(7), ms(7)));
+
+        stats.record(Stage::Total, ms(3));
+        let p = stats.percentiles(Stage::Total).unwrap();
+        assert_eq!((p.p50, p.p99), (ms(3), ms(3)));
+    }
+
+    #[test]
+    fn only_the_recent_window_counts() {
+        let stats = LatencyStats::default();
+        for _ in 0..WINDOW {
+            stats.record(Stage::Network, ms(500));
+        }
+        for _ in 0..WINDOW {
+            stats.record(Stage::Network, ms(5));
+        }
+        assert_eq!(stats.percentiles(Stage::Network).unwrap().p99, ms(5));
+    }
+}
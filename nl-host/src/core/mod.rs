@@ -1,11 +1,33 @@
 //! Core module - Application foundation
 
 mod app;
+mod color;
 mod config;
+mod embed;
+pub mod events;
 mod frame;
+mod ipc;
+mod latency;
+mod session;
+mod stats;
+mod touches;
+mod viewport;
 #[macro_use]
 pub mod logger;
+mod window;
 
 pub use app::run;
-pub use config::{is_debug, is_verbose, VERBOSE};
-pub use frame::{FrameBuffer, FrameData};
+pub use color::{
+    ColorMatrix, ColorOverride, ColorRange, ColorSpace, VideoSignal, YuvToRgb,
+};
+pub use config::{
+    is_debug, is_verbose, MirrorOptions, ShortcutMod, WindowOptions, DEBUG, VERBOSE,
+};
+pub use embed::{AudioMode, SessionBuilder, SessionHandle};
+pub use ipc::SessionCommand;
+pub use frame::{FrameBuffer, FrameData, FrameWaker};
+pub use latency::{DeviceClock, LatencyStats, Percentiles, Stage};
+pub use session::{Link, RetryPolicy, Session, SessionState, SessionWaker};
+pub use stats::{DeviceStats, Rates, SessionStats, StatsSampler};
+pub use touches::{burn_in, Shape, TouchFrame, TouchOverlay};
+pub use viewport::{Loupe, VideoRect, Viewport, ZOOM_STEP};
@@ -0,0 +1,340 @@
+//! Connection state of a mirror session, shared by the video, audio and control threads
+
+use crate::core::events::EventSink;
+use crate::{log_error, log_info};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Granularity of retry sleeps, so closing the session is not held up
+const SLEEP_SLICE: Duration = Duration::from_millis(100);
+
+/// Where the session is, as shown in the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Connecting,
+    Streaming,
+    /// A link dropped and is being retried (attempt of the retry policy)
+    Reconnecting {
+        attempt: u32,
+    },
+    /// Retries are exhausted; the session ends
+    Lost,
+}
+
+impl std::fmt::Display for SessionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionState::Connecting => write!(f, "connecting"),
+            SessionState::Streaming => write!(f, "streaming"),
+            SessionState::Reconnecting { attempt } => {
+                write!(f, "reconnecting (attempt {})", attempt)
+            }
+            SessionState::Lost => write!(f, "lost"),
+        }
+    }
+}
+
+/// A connection to one device service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    Video,
+    Audio,
+    Control,
+}
+
+impl Link {
+    /// Audio is optional (not every device can capture it); it never ends the session
+    fn is_essential(self) -> bool {
+        !matches!(self, Link::Audio)
+    }
+}
+
+/// How often and how patiently dropped links are retried
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Reconnect attempts before the session is lost (0 = retry forever)
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after failed attempt `attempt` (1-based): doubles up to the max
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32 << attempt.saturating_sub(1).min(16);
+        self.initial_delay
+            .saturating_mul(factor)
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LinkState {
+    /// Not connected yet
+    Connecting,
+    Up,
+    /// Failed attempts in a row; `was_up` tells a reconnect from a first connect
+    Retrying {
+        attempt: u32,
+        was_up: bool,
+    },
+    Failed,
+}
+
+/// Called when the session state changes (wakes the event loop)
+pub type SessionWaker = Box<dyn Fn() + Send + Sync>;
+
+/// Tracks every link and derives the session state from the essential ones
+pub struct Session {
+    policy: RetryPolicy,
+    links: Mutex<[LinkState; 3]>,
+    closed: AtomicBool,
+    waker: Mutex<Option<SessionWaker>>,
+    events: EventSink,
+}
+
+impl Session {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            links: Mutex::new([LinkState::Connecting; 3]),
+            closed: AtomicBool::new(false),
+            waker: Mutex::new(None),
+            events: EventSink::default(),
+        }
+    }
+
+    /// Report the session's events to `events` (dropped by default)
+    pub fn with_events(mut self, events: EventSink) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn events(&self) -> &EventSink {
+        &self.events
+    }
+
+    /// Call `waker` whenever the state changes
+    pub fn set_waker(&self, waker: SessionWaker) {
+        *self.waker.lock().unwrap() = Some(waker);
+    }
+
+    pub fn state(&self) -> SessionState {
+        Self::derive(&self.links.lock().unwrap())
+    }
+
+    /// `link` is connected and carrying data; its failure count starts over
+    pub fn link_up(&self, link: Link) {
+        self.set(link, |_| LinkState::Up);
+    }
+
+    /// Count a failed or dropped connection of `link` and wait before the next attempt.
+    /// Returns false when it should not be retried: the session was closed, or an
+    /// essential link used up its attempts (the session is then lost).
+    pub fn backoff(&self, link: Link, stopped: impl Fn() -> bool) -> bool {
+        let max_attempts = self.policy.max_attempts;
+        let state = self.set(link, |state| {
+            let (attempt, was_up) = match state {
+                LinkState::Up => (1, true),
+                LinkState::Retrying { attempt, was_up } => (attempt + 1, was_up),
+                LinkState::Connecting | LinkState::Failed => (1, false),
+            };
+            if link.is_essential() && max_attempts > 0 && attempt > max_attempts {
+                LinkState::Failed
+            } else {
+                LinkState::Retrying { attempt, was_up }
+            }
+        });
+        let LinkState::Retrying { attempt, .. } = state else {
+            log_error!(
+                "SESSION",
+                "{:?} failed {} times, giving up",
+                link,
+                max_attempts
+            );
+            return false;
+        };
+
+        // Optional links keep trying at the slowest pace
+        let mut remaining = self.policy.delay(attempt);
+        while !remaining.is_zero() {
+            if self.is_closed() || stopped() {
+                return false;
+            }
+            let slice = remaining.min(SLEEP_SLICE);
+            thread::sleep(slice);
+            remaining -= slice;
+        }
+        !self.is_closed() && !stopped()
+    }
+
+    /// Stop every retry loop (window closed)
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Closed, or lost: nothing should reconnect any more
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) || self.state() == SessionState::Lost
+    }
+
+    /// Update one link; logs and wakes on session state changes. Returns the new link state.
+    fn set(&self, link: Link, update: impl FnOnce(LinkState) -> LinkState) -> LinkState {
+        let (before, after, state) = {
+            let mut links = self.links.lock().unwrap();
+            let before = Self::derive(&links);
+            let state = update(links[link as usize]);
+            links[link as usize] = state;
+            (before, Self::derive(&links), state)
+        };
+        if before != after {
+            log_info!("SESSION", "Session {}", after);
+            if let Some(wake) = self.waker.lock().unwrap().as_ref() {
+                wake();
+            }
+        }
+        state
+    }
+
+    fn derive(links: &[LinkState; 3]) -> SessionState {
+        let essential = [Link::Video, Link::Control].map(|link| links[link as usize]);
+        if essential.contains(&LinkState::Failed) {
+            return SessionState::Lost;
+        }
+        let reconnecting = essential
+            .iter()
+            .filter_map(|state| match state {
+                LinkState::Retrying {
+                    attempt,
+                    was_up: true,
+                } => Some(*attempt),
+                _ => None,
+            })
+            .max();
+        if let Some(attempt) = reconnecting {
+            return SessionState::Reconnecting { attempt };
+        }
+        if essential.iter().all(|state| *state == LinkState::Up) {
+            SessionState::Streaming
+        } else {
+            SessionState::Connecting
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: LinkState = LinkState::Up;
+    const CONNECTING: LinkState = LinkState::Connecting;
+    const FAILED: LinkState = LinkState::Failed;
+
+    fn retrying(attempt: u32, was_up: bool) -> LinkState {
+        LinkState::Retrying { attempt, was_up }
+    }
+
+    fn quick_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn state_is_derived_from_the_essential_links() {
+        use SessionState::*;
+        // [video, audio, control]
+        let cases = [
+            ([CONNECTING, CONNECTING, CONNECTING], Connecting),
+            ([UP, CONNECTING, CONNECTING], Connecting),
+            ([UP, CONNECTING, UP], Streaming),
+            ([UP, FAILED, UP], Streaming),
+            ([UP, retrying(3, true), UP], Streaming),
+            ([retrying(2, false), UP, UP], Connecting),
+            ([retrying(2, true), UP, UP], Reconnecting { attempt: 2 }),
+            (
+                [retrying(2, true), UP, retrying(4, true)],
+                Reconnecting { attempt: 4 },
+            ),
+            (
+                [retrying(1, true), UP, retrying(3, false)],
+                Reconnecting { attempt: 1 },
+            ),
+            ([FAILED, UP, UP], Lost),
+            ([UP, UP, FAILED], Lost),
+            ([FAILED, UP, retrying(1, true)], Lost),
+        ];
+        for (links, expected) in cases {
+            assert_eq!(Session::derive(&links), expected, "{:?}", links);
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_max() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u64> = (1..=6)
+            .map(|attempt| policy.delay(attempt).as_secs())
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(policy.delay(u32::MAX), policy.max_delay);
+    }
+
+    #[test]
+    fn backoff_gives_up_after_max_attempts() {
+        let session = Session::new(quick_policy(3));
+        session.link_up(Link::Video);
+        session.link_up(Link::Control);
+        for attempt in 1..=3 {
+            assert!(session.backoff(Link::Video, || false));
+            assert_eq!(session.state(), SessionState::Reconnecting { attempt });
+        }
+        assert!(!session.backoff(Link::Video, || false));
+        assert_eq!(session.state(), SessionState::Lost);
+        assert!(session.is_closed());
+    }
+
+    #[test]
+    fn link_up_resets_the_attempts() {
+        let session = Session::new(quick_policy(2));
+        for _ in 0..2 {
+            assert!(session.backoff(Link::Control, || false));
+        }
+        session.link_up(Link::Control);
+        assert!(session.backoff(Link::Control, || false));
+        assert_eq!(
+            session.links.lock().unwrap()[Link::Control as usize],
+            retrying(1, true)
+        );
+    }
+
+    #[test]
+    fn audio_is_retried_forever() {
+        let session = Session::new(quick_policy(1));
+        for _ in 0..10 {
+            assert!(session.backoff(Link::Audio, || false));
+        }
+        assert_ne!(session.state(), SessionState::Lost);
+    }
+
+    #[test]
+    fn backoff_stops_when_closed() {
+        let session = Session::new(quick_policy(0));
+        assert!(!session.backoff(Link::Video, || true));
+        session.close();
+        assert!(!session.backoff(Link::Video, || false));
+    }
+}
@@ -0,0 +1,193 @@
+//! Session statistics shared between the network, decoder and render threads
+
+use crate::core::{DeviceClock, LatencyStats, Stage};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counters updated by the pipeline threads (monotonic unless marked as a gauge)
+#[derive(Default)]
+pub struct SessionStats {
+    pub bytes_received: AtomicU64,
+    pub packets_received: AtomicU64,
+    /// Packets dropped because the decoder channel was full
+    pub packets_dropped: AtomicU64,
+    pub frames_decoded: AtomicU64,
+    /// Decoded frames replaced before the renderer consumed them
+    pub frames_dropped: AtomicU64,
+    pub decoder_resets: AtomicU64,
+    /// IDR frames requested from the device after a reset or decode error
+    pub keyframe_requests: AtomicU64,
+    /// Gauge: output frozen on the last good frame until the next IDR
+    pub degraded: AtomicBool,
+    /// Gauge: packets waiting in the decoder channel
+    pub channel_backlog: AtomicU64,
+    /// Gauge: how late the last rendered frame was displayed (milliseconds), from decoding or
+    /// from its scheduled time with a presentation delay
+    pub frame_age_ms: AtomicU64,
+    /// Device time, for latencies measured from capture
+    pub clock: DeviceClock,
+    pub latency: LatencyStats,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn set(gauge: &AtomicU64, value: u64) {
+        gauge.store(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(value: &AtomicU64) -> u64 {
+        value.load(Ordering::Relaxed)
+    }
+
+    fn snapshot(&self) -> Counters {
+        Counters {
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            frames_decoded: self.frames_decoded.load(Ordering::Relaxed),
+            packets_dropped: self.packets_dropped.load(Ordering::Relaxed),
+            frames_dropped: self.frames_dropped.load(Ordering::Relaxed),
+            decoder_resets: self.decoder_resets.load(Ordering::Relaxed),
+            keyframe_requests: self.keyframe_requests.load(Ordering::Relaxed),
+            degraded: self.degraded.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    bytes_received: u64,
+    frames_decoded: u64,
+    packets_dropped: u64,
+    frames_dropped: u64,
+    decoder_resets: u64,
+    keyframe_requests: u64,
+    degraded: bool,
+}
+
+/// Device-side figures from the `stats` control command
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeviceStats {
+    /// CPU usage since the previous poll (percent)
+    pub cpu_percent: Option<f64>,
+    /// Mirror server heap usage (bytes)
+    pub memory_used: u64,
+    pub memory_max: u64,
+}
+
+/// Per-second rates over one sampling interval
+#[derive(Debug, Clone, Copy)]
+pub struct Rates {
+    pub render_fps: f64,
+    pub decode_fps: f64,
+    pub mbps: f64,
+}
+
+/// Turns counter snapshots into per-second rates for the HUD
+pub struct StatsSampler {
+    last: Counters,
+    last_rendered: u64,
+    last_time: Instant,
+}
+
+impl StatsSampler {
+    pub fn new() -> Self {
+        Self {
+            last: Counters::default(),
+            last_rendered: 0,
+            last_time: Instant::now(),
+        }
+    }
+
+    /// Rates for the interval since the previous call
+    pub fn rates(&mut self, stats: &SessionStats, rendered: u64) -> Rates {
+        self.advance(stats, rendered).0
+    }
+
+    /// HUD lines for the interval since the previous call
+    pub fn sample(
+        &mut self,
+        stats: &SessionStats,
+        rendered: u64,
+        frame_age: Option<Duration>,
+        device: Option<DeviceStats>,
+    ) -> Vec<String> {
+        let (rates, now) = self.advance(stats, rendered);
+        let mut lines = vec![
+            format!("RENDER  {:5.1} FPS", rates.render_fps),
+            format!("DECODE  {:5.1} FPS", rates.decode_fps),
+            format!("BITRATE {:5.2} MBPS", rates.mbps),
+            format!(
+                "DROPPED {} PKT / {} FRM",
+                now.packets_dropped, now.frames_dropped
+            ),
+            format!("STREAM  {}", if now.degraded { "DEGRADED" } else { "OK" }),
+            format!(
+                "RESETS  {} / {} KEY REQ",
+                now.decoder_resets, now.keyframe_requests
+            ),
+            match frame_age {
+                Some(age) => format!("AGE     {} MS", age.as_millis()),
+                None => "AGE     -".to_string(),
+            },
+        ];
+        lines.push(match stats.clock.sync() {
+            Some((_, rtt)) => format!("SYNC    +-{} MS", (rtt / 2).as_millis()),
+            None => "SYNC    -".to_string(),
+        });
+        // p50/p95/p99 per stage
+        for stage in Stage::ALL {
+            lines.push(match stats.latency.percentiles(stage) {
+                Some(p) => format!(
+                    "{} {}/{}/{} MS",
+                    stage.label(),
+                    p.p50.as_millis(),
+                    p.p95.as_millis(),
+                    p.p99.as_millis()
+                ),
+                None => format!("{} -", stage.label()),
+            });
+        }
+        if let Some(device) = device {
+            if let Some(cpu) = device.cpu_percent {
+                lines.push(format!("DEV CPU {:.0}%", cpu));
+            }
+            if device.memory_max > 0 {
+                lines.push(format!(
+                    "SRV MEM {}/{} MB",
+                    device.memory_used / 1_048_576,
+                    device.memory_max / 1_048_576
+                ));
+            }
+        }
+        lines
+    }
+
+    fn advance(&mut self, stats: &SessionStats, rendered: u64) -> (Rates, Counters) {
+        let now = stats.snapshot();
+        let secs = self.last_time.elapsed().as_secs_f64().max(0.001);
+        let rates = Rates {
+            render_fps: (rendered - self.last_rendered) as f64 / secs,
+            decode_fps: (now.frames_decoded - self.last.frames_decoded) as f64 / secs,
+            mbps: (now.bytes_received - self.last.bytes_received) as f64 * 8.0 / secs / 1e6,
+        };
+        self.last = now;
+        self.last_rendered = rendered;
+        self.last_time = Instant::now();
+        (rates, now)
+    }
+}
+
+impl Default for StatsSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
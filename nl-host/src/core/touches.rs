@@ -0,0 +1,348 @@
+//! Touch and key press visualization
+//!
+//! Built from the `InputCommand`s that `MirrorApp` injects, so the markers show exactly
+//! what the device received. Positions are in video frame pixels.
+
+use crate::input::InputCommand;
+use crate::video::font;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long a marker fades out after its gesture ends
+const FADE_TIME: Duration = Duration::from_millis(500);
+/// How long a key label stays visible
+const KEY_LABEL_TIME: Duration = Duration::from_millis(1200);
+/// Most recent key labels shown at once
+const MAX_KEY_LABELS: usize = 6;
+
+/// Marker radius as a fraction of the frame's shorter side
+const TOUCH_RADIUS: f32 = 0.035;
+
+const TOUCH_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.85];
+const TRAIL_COLOR: [f32; 4] = [0.3, 0.8, 1.0, 0.8];
+const LONG_PRESS_COLOR: [f32; 4] = [1.0, 0.75, 0.2, 0.9];
+
+/// A capsule from `p0` to `p1` (a circle when both are equal), in frame pixels
+#[derive(Debug, Clone, Copy)]
+pub struct Shape {
+    pub p0: [f32; 2],
+    pub p1: [f32; 2],
+    pub radius: f32,
+    /// Outline width; 0 = filled
+    pub outline: f32,
+    /// Straight (non-premultiplied) RGBA
+    pub color: [f32; 4],
+}
+
+/// What the overlay draws at one instant
+#[derive(Debug, Clone, Default)]
+pub struct TouchFrame {
+    pub shapes: Vec<Shape>,
+    /// Recent key presses, oldest first (e.g. "CTRL+C HOME")
+    pub keys: Option<String>,
+}
+
+enum Gesture {
+    Tap([f32; 2]),
+    LongPress([f32; 2], Duration),
+    Swipe([f32; 2], [f32; 2], Duration),
+}
+
+struct Marker {
+    gesture: Gesture,
+    start: Instant,
+}
+
+impl Marker {
+    fn duration(&self) -> Duration {
+        match self.gesture {
+            Gesture::Tap(_) => Duration::ZERO,
+            Gesture::LongPress(_, d) | Gesture::Swipe(_, _, d) => d,
+        }
+    }
+}
+
+/// Recent gestures and key presses
+#[derive(Default)]
+pub struct TouchOverlay {
+    markers: VecDeque<Marker>,
+    keys: VecDeque<(String, Instant)>,
+}
+
+impl TouchOverlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an injected command
+    pub fn record(&mut self, command: &InputCommand) {
+        let now = Instant::now();
+        // Nothing calls `frame` while the markers are hidden
+        self.prune(now);
+        let gesture = match command {
+            InputCommand::Tap(x, y) => Gesture::Tap([*x, *y]),
+            InputCommand::LongPress(x, y, ms) => {
+                Gesture::LongPress([*x, *y], Duration::from_millis(*ms))
+            }
+            InputCommand::Swipe(x1, y1, x2, y2, ms) => {
+                Gesture::Swipe([*x1, *y1], [*x2, *y2], Duration::from_millis(*ms))
+            }
+            InputCommand::Keycode(action, keycode, meta) => {
+                if action == "down" {
+                    self.push_key(key_label(*keycode, *meta), now);
+                }
+                return;
+            }
+            InputCommand::InjectText(text) => {
+                self.push_key(format!("TEXT({})", text.chars().count()), now);
+                return;
+            }
+            InputCommand::SetClipboard(_, true) | InputCommand::SetClipboardImage(.., true) => {
+                self.push_key("PASTE".to_string(), now);
+                return;
+            }
+            _ => return,
+        };
+        self.markers.push_back(Marker {
+            gesture,
+            start: now,
+        });
+    }
+
+    fn prune(&mut self, now: Instant) {
+        self.markers
+            .retain(|m| now.duration_since(m.start) < m.duration() + FADE_TIME);
+        self.keys
+            .retain(|(_, t)| now.duration_since(*t) < KEY_LABEL_TIME);
+    }
+
+    fn push_key(&mut self, label: String, now: Instant) {
+        self.keys.push_back((label, now));
+        while self.keys.len() > MAX_KEY_LABELS {
+            self.keys.pop_front();
+        }
+    }
+
+    /// True while something is visible (the window must keep redrawing)
+    pub fn is_active(&self) -> bool {
+        !self.markers.is_empty() || !self.keys.is_empty()
+    }
+
+    /// Shapes and key labels for `now`, for a frame of `width` x `height`.
+    /// Expired markers are dropped.
+    pub fn frame(&mut self, width: u32, height: u32) -> TouchFrame {
+        let now = Instant::now();
+        self.prune(now);
+
+        let radius = width.min(height) as f32 * TOUCH_RADIUS;
+        let mut shapes = Vec::new();
+        for marker in &self.markers {
+            let elapsed = now.duration_since(marker.start);
+            let duration = marker.duration();
+            // 0..1 through the gesture, then 1 while fading
+            let progress = if duration.is_zero() {
+                1.0
+            } else {
+                (elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.0)
+            };
+            let fade = 1.0
+                - (elapsed.saturating_sub(duration).as_secs_f32() / FADE_TIME.as_secs_f32())
+                    .min(1.0);
+            let with_alpha = |color: [f32; 4]| [color[0], color[1], color[2], color[3] * fade];
+
+            match marker.gesture {
+                Gesture::Tap(p) => {
+                    // Ring that expands slightly as it fades
+                    shapes.push(Shape {
+                        p0: p,
+                        p1: p,
+                        radius: radius * (1.0 + 0.5 * (1.0 - fade)),
+                        outline: radius * 0.25,
+                        color: with_alpha(TOUCH_COLOR),
+                    });
+                    shapes.push(Shape {
+                        p0: p,
+                        p1: p,
+                        radius: radius * 0.4,
+                        outline: 0.0,
+                        color: with_alpha(TOUCH_COLOR),
+                    });
+                }
+                Gesture::LongPress(p, _) => {
+                    // Ring that fills in over the press duration
+                    shapes.push(Shape {
+                        p0: p,
+                        p1: p,
+                        radius,
+                        outline: radius * 0.25,
+                        color: with_alpha(LONG_PRESS_COLOR),
+                    });
+                    shapes.push(Shape {
+                        p0: p,
+                        p1: p,
+                        radius: radius * progress,
+                        outline: 0.0,
+                        color: with_alpha([
+                            LONG_PRESS_COLOR[0],
+                            LONG_PRESS_COLOR[1],
+                            LONG_PRESS_COLOR[2],
+                            LONG_PRESS_COLOR[3] * 0.5,
+                        ]),
+                    });
+                }
+                Gesture::Swipe(from, to, _) => {
+                    let head = [
+                        from[0] + (to[0] - from[0]) * progress,
+                        from[1] + (to[1] - from[1]) * progress,
+                    ];
+                    shapes.push(Shape {
+                        p0: from,
+                        p1: head,
+                        radius: radius * 0.3,
+                        outline: 0.0,
+                        color: with_alpha(TRAIL_COLOR),
+                    });
+                    shapes.push(Shape {
+                        p0: head,
+                        p1: head,
+                        radius: radius * 0.6,
+                        outline: 0.0,
+                        color: with_alpha(TOUCH_COLOR),
+                    });
+                }
+            }
+        }
+
+        let keys = if self.keys.is_empty() {
+            None
+        } else {
+            Some(
+                self.keys
+                    .iter()
+                    .map(|(label, _)| label.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+        };
+
+        TouchFrame { shapes, keys }
+    }
+}
+
+/// Short label for an Android keycode with its modifiers
+fn key_label(keycode: i32, meta: i32) -> String {
+    use crate::network::ControlClient;
+
+    let name = match keycode {
+        3 => "HOME".to_string(),
+        4 => "BACK".to_string(),
+        7..=16 => ((b'0' + (keycode - 7) as u8) as char).to_string(),
+        19 => "UP".to_string(),
+        20 => "DOWN".to_string(),
+        21 => "LEFT".to_string(),
+        22 => "RIGHT".to_string(),
+        24 => "VOL+".to_string(),
+        25 => "VOL-".to_string(),
+        26 => "POWER".to_string(),
+        29..=54 => ((b'A' + (keycode - 29) as u8) as char).to_string(),
+        55 => ",".to_string(),
+        56 => ".".to_string(),
+        61 => "TAB".to_string(),
+        62 => "SPACE".to_string(),
+        66 => "ENTER".to_string(),
+        67 => "DEL".to_string(),
+        69 => "-".to_string(),
+        76 => "/".to_string(),
+        82 => "MENU".to_string(),
+        111 => "ESC".to_string(),
+        112 => "FWD-DEL".to_string(),
+        187 => "RECENTS".to_string(),
+        _ => format!("KEY{}", keycode),
+    };
+
+    let mut label = String::new();
+    if meta & ControlClient::META_CTRL_ON != 0 {
+        label.push_str("CTRL+");
+    }
+    if meta & ControlClient::META_META_ON != 0 {
+        label.push_str("META+");
+    }
+    if meta & ControlClient::META_SHIFT_ON != 0 {
+        label.push_str("SHIFT+");
+    }
+    label.push_str(&name);
+    label
+}
+
+/// Draw a touch frame into an RGBA image of the video frame (screenshots)
+pub fn burn_in(rgba: &mut [u8], width: u32, height: u32, frame: &TouchFrame) {
+    let (w, h) = (width as i64, height as i64);
+    for shape in &frame.shapes {
+        let min_x = (shape.p0[0].min(shape.p1[0]) - shape.radius - 1.0)
+            .floor()
+            .max(0.0) as i64;
+        let max_x = (shape.p0[0].max(shape.p1[0]) + shape.radius + 1.0)
+            .ceil()
+            .min(w as f32) as i64;
+        let min_y = (shape.p0[1].min(shape.p1[1]) - shape.radius - 1.0)
+            .floor()
+            .max(0.0) as i64;
+        let max_y = (shape.p0[1].max(shape.p1[1]) + shape.radius + 1.0)
+            .ceil()
+            .min(h as f32) as i64;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let coverage = shape_coverage(shape, [x as f32 + 0.5, y as f32 + 0.5]);
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let alpha = shape.color[3] * coverage;
+                let idx = ((y * w + x) * 4) as usize;
+                for c in 0..3 {
+                    let dst = rgba[idx + c] as f32 / 255.0;
+                    let blended = dst * (1.0 - alpha) + shape.color[c] * alpha;
+                    rgba[idx + c] = (blended.clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        }
+    }
+
+    if let Some(keys) = &frame.keys {
+        let scale = ((height as usize) / 400).max(1);
+        let (text_w, text_h) = font::text_size(keys.chars().count(), 1, scale);
+        let x = (width as usize).saturating_sub(text_w) / 2;
+        let y = (height as usize).saturating_sub(text_h * 3);
+
+        // Darken the area behind the text so it stays readable
+        let pad = 4 * scale;
+        for py in y.saturating_sub(pad)..(y + text_h + pad).min(height as usize) {
+            for px in x.saturating_sub(pad)..(x + text_w + pad).min(width as usize) {
+                let idx = (py * width as usize + px) * 4;
+                for c in &mut rgba[idx..idx + 3] {
+                    *c /= 3;
+                }
+            }
+        }
+        font::draw_text(rgba, width as usize, x, y, keys, scale, [255; 4]);
+    }
+}
+
+/// Anti-aliased coverage (0-1) of a shape at pixel center `p`; same SDF as overlay.wgsl
+fn shape_coverage(shape: &Shape, p: [f32; 2]) -> f32 {
+    let pa = [p[0] - shape.p0[0], p[1] - shape.p0[1]];
+    let ba = [shape.p1[0] - shape.p0[0], shape.p1[1] - shape.p0[1]];
+    let len2 = ba[0] * ba[0] + ba[1] * ba[1];
+    let t = if len2 > 0.0 {
+        ((pa[0] * ba[0] + pa[1] * ba[1]) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let d = ((pa[0] - ba[0] * t).powi(2) + (pa[1] - ba[1] * t).powi(2)).sqrt() - shape.radius;
+    let d = if shape.outline > 0.0 {
+        d.abs() - shape.outline * 0.5
+    } else {
+        d
+    };
+    (0.5 - d).clamp(0.0, 1.0)
+}
@@ -0,0 +1,161 @@
+//! Host-side zoom, pan and loupe
+//!
+//! The device always streams the full screen; the viewport only selects which part of the
+//! frame fills the letterboxed video area. Input mapping goes through the same math, so
+//! taps land on the device pixel under the cursor at any zoom.
+
+/// Zoom range (1 = whole frame)
+const MIN_ZOOM: f32 = 1.0;
+const MAX_ZOOM: f32 = 8.0;
+
+/// Zoom factor per hotkey press or wheel notch
+pub const ZOOM_STEP: f32 = 1.25;
+
+/// Loupe radius in screen pixels
+const LOUPE_RADIUS: f32 = 110.0;
+/// Loupe magnification relative to the current view
+const LOUPE_MAGNIFICATION: f32 = 3.0;
+
+/// Where the letterboxed video sits in the window, in window pixels
+#[derive(Debug, Clone, Copy)]
+pub struct VideoRect {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl VideoRect {
+    /// Largest rect with the frame's aspect ratio centered in the window (see MirrorRenderer)
+    pub fn fit(window: (u32, u32), frame: (u32, u32)) -> Self {
+        let (win_w, win_h) = (window.0.max(1) as f64, window.1.max(1) as f64);
+        let (vid_w, vid_h) = (frame.0.max(1) as f64, frame.1.max(1) as f64);
+        let scale = (win_w / vid_w).min(win_h / vid_h);
+        Self {
+            x: (win_w - vid_w * scale) / 2.0,
+            y: (win_h - vid_h * scale) / 2.0,
+            width: vid_w * scale,
+            height: vid_h * scale,
+        }
+    }
+
+    /// Window position -> 0..1 within the rect (clamped to its edges)
+    pub fn normalize(&self, pos: (f64, f64)) -> [f32; 2] {
+        [
+            ((pos.0 - self.x) / self.width).clamp(0.0, 1.0) as f32,
+            ((pos.1 - self.y) / self.height).clamp(0.0, 1.0) as f32,
+        ]
+    }
+
+    pub fn contains(&self, pos: (f64, f64)) -> bool {
+        pos.0 >= self.x
+            && pos.0 < self.x + self.width
+            && pos.1 >= self.y
+            && pos.1 < self.y + self.height
+    }
+}
+
+/// Magnifier drawn around the cursor. It is centered on the cursor, so the device point
+/// under the cursor is the same with or without it and input mapping ignores it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loupe {
+    /// Center in window pixels
+    pub center: [f32; 2],
+    pub radius: f32,
+    pub magnification: f32,
+}
+
+/// Visible part of the frame plus the loupe state
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    zoom: f32,
+    /// Center of the visible region in normalized frame coordinates
+    center: [f32; 2],
+    pub loupe: bool,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            zoom: MIN_ZOOM,
+            center: [0.5, 0.5],
+            loupe: false,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn is_zoomed(&self) -> bool {
+        self.zoom > MIN_ZOOM
+    }
+
+    /// Visible region as [x, y, width, height] in normalized frame coordinates
+    pub fn region(&self) -> [f32; 4] {
+        let size = 1.0 / self.zoom;
+        [
+            self.center[0] - size / 2.0,
+            self.center[1] - size / 2.0,
+            size,
+            size,
+        ]
+    }
+
+    /// Multiply the zoom by `factor`, keeping the frame point at `anchor` (0..1 within
+    /// the video area) under the same spot
+    pub fn zoom_by(&mut self, factor: f32, anchor: [f32; 2]) {
+        let before = self.to_frame(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let size = 1.0 / self.zoom;
+        self.center = [
+            before[0] - (anchor[0] - 0.5) * size,
+            before[1] - (anchor[1] - 0.5) * size,
+        ];
+        self.clamp_center();
+    }
+
+    /// Move the visible region by `delta`, in fractions of the visible area
+    pub fn pan(&mut self, delta: [f32; 2]) {
+        let size = 1.0 / self.zoom;
+        self.center[0] += delta[0] * size;
+        self.center[1] += delta[1] * size;
+        self.clamp_center();
+    }
+
+    /// Back to the whole frame (the loupe setting is kept)
+    pub fn reset(&mut self) {
+        self.zoom = MIN_ZOOM;
+        self.center = [0.5, 0.5];
+    }
+
+    /// 0..1 within the video area -> normalized frame coordinates
+    pub fn to_frame(&self, pos: [f32; 2]) -> [f32; 2] {
+        let [x, y, w, h] = self.region();
+        [x + pos[0] * w, y + pos[1] * h]
+    }
+
+    /// Loupe at the cursor, if enabled and the cursor is over the video
+    pub fn loupe_at(&self, rect: &VideoRect, cursor: Option<(f64, f64)>) -> Option<Loupe> {
+        let cursor = cursor.filter(|&pos| self.loupe && rect.contains(pos))?;
+        Some(Loupe {
+            center: [cursor.0 as f32, cursor.1 as f32],
+            radius: LOUPE_RADIUS,
+            magnification: LOUPE_MAGNIFICATION,
+        })
+    }
+
+    /// Keep the region inside the frame
+    fn clamp_center(&mut self) {
+        let half = 0.5 / self.zoom;
+        for c in &mut self.center {
+            *c = c.clamp(half, 1.0 - half);
+        }
+    }
+}
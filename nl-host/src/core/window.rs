@@ -0,0 +1,137 @@
+//! Window sizing and remembered window geometry
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use winit::dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Size};
+use winit::window::Window;
+
+/// Default window height cap (logical pixels) when no size is remembered
+const DEFAULT_MAX_HEIGHT: f64 = 800.0;
+
+/// Fraction of the monitor used by fit-to-screen (leaves room for docks/taskbars)
+const FIT_SCREEN_RATIO: f64 = 0.9;
+
+/// Window position and size saved per device
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowGeometry {
+    /// Capture the current geometry of a window
+    pub fn of(window: &Window) -> Option<Self> {
+        let position = window.outer_position().ok()?;
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+        Some(Self {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        })
+    }
+
+    pub fn position(&self) -> PhysicalPosition<i32> {
+        PhysicalPosition::new(self.x, self.y)
+    }
+
+    pub fn size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.width, self.height)
+    }
+}
+
+fn state_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("nl-mirror").join("window-state.json"))
+}
+
+fn load_all() -> HashMap<String, WindowGeometry> {
+    state_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Load the remembered geometry for a device
+pub fn load_geometry(key: &str) -> Option<WindowGeometry> {
+    load_all().get(key).copied()
+}
+
+/// Remember the geometry for a device
+pub fn save_geometry(key: &str, geometry: WindowGeometry) {
+    let Some(path) = state_path() else {
+        return;
+    };
+    let mut all = load_all();
+    all.insert(key.to_string(), geometry);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    match serde_json::to_string_pretty(&all) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&path, json) {
+                log_warn!("WINDOW", "Failed to save window state: {}", e);
+            }
+        }
+        Err(e) => {
+            log_warn!("WINDOW", "Failed to encode window state: {}", e);
+        }
+    }
+}
+
+/// Default size for a frame: native size, capped to 800 logical pixels tall
+pub fn default_size(width: u32, height: u32) -> Size {
+    let scale = if (height as f64) > DEFAULT_MAX_HEIGHT {
+        DEFAULT_MAX_HEIGHT / (height as f64)
+    } else {
+        1.0
+    };
+    LogicalSize::new(width as f64 * scale, height as f64 * scale).into()
+}
+
+/// One device pixel per screen pixel
+pub fn pixel_perfect_size(width: u32, height: u32) -> Size {
+    PhysicalSize::new(width, height).into()
+}
+
+/// Largest size with the frame's aspect ratio that fits on the window's monitor
+pub fn fit_to_screen_size(window: &Window, width: u32, height: u32) -> Size {
+    let Some(monitor) = window.current_monitor() else {
+        return default_size(width, height);
+    };
+    let screen = monitor.size();
+    let scale = (screen.width as f64 * FIT_SCREEN_RATIO / width as f64)
+        .min(screen.height as f64 * FIT_SCREEN_RATIO / height as f64);
+    PhysicalSize::new(
+        (width as f64 * scale).round() as u32,
+        (height as f64 * scale).round() as u32,
+    )
+    .into()
+}
+
+/// Size for a new frame that keeps the window's current scale, e.g. a portrait window
+/// becomes a landscape window of the same scale when the device rotates. The result
+/// is clamped to the monitor.
+pub fn rescaled_size(window: &Window, old: (u32, u32), new: (u32, u32)) -> Size {
+    let inner = window.inner_size();
+    if old.0 == 0 || old.1 == 0 || inner.width == 0 || inner.height == 0 {
+        return default_size(new.0, new.1);
+    }
+    let scale = (inner.width as f64 / old.0 as f64).min(inner.height as f64 / old.1 as f64);
+    let (width, height) = (new.0 as f64 * scale, new.1 as f64 * scale);
+
+    if let Some(monitor) = window.current_monitor() {
+        let screen = monitor.size();
+        if width > screen.width as f64 * FIT_SCREEN_RATIO
+            || height > screen.height as f64 * FIT_SCREEN_RATIO
+        {
+            return fit_to_screen_size(window, new.0, new.1);
+        }
+    }
+    PhysicalSize::new(width.round() as u32, height.round() as u32).into()
+}
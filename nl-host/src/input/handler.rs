@@ -1,9 +1,11 @@
 //! Input command processing
 
-use crate::network::ControlClient;
-use crate::{log_error, log_verbose};
-use crossbeam_channel::Receiver;
+use crate::core::{Link, Session};
+use crate::network::{ControlClient, Endpoint, VideoSettings};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 /// Input commands sent from UI to background thread
 #[derive(Debug)]
@@ -14,50 +16,118 @@ pub enum InputCommand {
     Keycode(String, i32, i32),
     GetClipboard(bool),
     SetClipboard(String, bool),
-    InjectText(String), // type text directly
+    SetClipboardImage(u32, u32, Vec<u8>, bool), // width, height, RGBA pixels, paste
+    InjectText(String),                         // type text directly
     SetScreenPowerMode(i32),
+    Rotate,
+    SetVideo(VideoSettings),
+    RequestKeyframe,
 }
 
-/// Start the input handler thread that processes commands non-blocking
-pub fn start_input_thread(host: String, port: u16, rx: Receiver<InputCommand>) -> JoinHandle<()> {
+impl InputCommand {
+    /// User input that makes no sense replayed later (the screen has moved on)
+    fn expires(&self) -> bool {
+        matches!(
+            self,
+            InputCommand::Tap(..)
+                | InputCommand::Swipe(..)
+                | InputCommand::LongPress(..)
+                | InputCommand::Keycode(..)
+                | InputCommand::InjectText(_)
+                | InputCommand::Rotate
+                | InputCommand::RequestKeyframe
+        )
+    }
+}
+
+/// An idle control connection is checked this often, so a dead one is noticed
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Start the input handler thread that processes commands non-blocking.
+/// A dropped control connection is reconnected per the `session`'s policy.
+pub fn start_input_thread(
+    endpoint: Endpoint,
+    port: u16,
+    rx: Receiver<InputCommand>,
+    session: Arc<Session>,
+) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut client = {
-            let mut delay_ms = 500u64;
+        let mut reconnect = false;
+        while let Some(mut client) = connect(&endpoint, port, &session) {
+            // Increase timeout for control commands (clipboard can be slow)
+            if let Err(e) = client.set_timeout(Duration::from_millis(1000)) {
+                log_error!("INPUT", "Failed to set timeout: {}", e);
+            }
+            if reconnect {
+                let (expired, pending): (Vec<_>, Vec<_>) =
+                    rx.try_iter().partition(InputCommand::expires);
+                if !expired.is_empty() {
+                    log_info!(
+                        "INPUT",
+                        "Dropped {} commands queued while disconnected",
+                        expired.len()
+                    );
+                }
+                for cmd in pending {
+                    process_command(&mut client, cmd);
+                }
+            }
+            reconnect = true;
+
             loop {
-                match ControlClient::connect(&host, port) {
-                    Ok(c) => {
-                        log_verbose!("INPUT", "Connected to {}:{}", host, port);
-                        break c;
+                match rx.recv_timeout(PING_INTERVAL) {
+                    Ok(cmd) => process_command(&mut client, cmd),
+                    Err(RecvTimeoutError::Timeout) => {
+                        if session.is_closed() {
+                            log_verbose!("INPUT", "Thread exiting");
+                            return;
+                        }
+                        if let Err(e) = client.ping() {
+                            log_info!("INPUT", "Control connection lost: {}", e);
+                            break;
+                        }
                     }
-                    Err(e) => {
-                        log_verbose!(
-                            "INPUT",
-                            "Connect to {}:{} failed: {}, retrying in {}ms...",
-                            host,
-                            port,
-                            e,
-                            delay_ms
-                        );
-                        std::thread::sleep(std::time::Duration::from_millis(delay_ms));
-                        // Exponential backoff: 500ms -> 1s -> 2s -> 4s -> 5s (max)
-                        delay_ms = (delay_ms * 2).min(5000);
+                    Err(RecvTimeoutError::Disconnected) => {
+                        log_verbose!("INPUT", "Thread exiting");
+                        return;
                     }
                 }
+                if !client.is_alive() {
+                    log_info!("INPUT", "Control connection closed by the device");
+                    break;
+                }
             }
-        };
-
-        // Increase timeout for control commands (clipboard can be slow)
-        if let Err(e) = client.set_timeout(std::time::Duration::from_millis(1000)) {
-            log_error!("INPUT", "Failed to set timeout: {}", e);
-        }
 
-        while let Ok(cmd) = rx.recv() {
-            process_command(&mut client, cmd);
+            if !session.backoff(Link::Control, || false) {
+                break;
+            }
         }
         log_verbose!("INPUT", "Thread exiting");
     })
 }
 
+/// Connect to the control service, retrying per the session's policy.
+/// None once the session is closed or lost.
+fn connect(endpoint: &Endpoint, port: u16, session: &Session) -> Option<ControlClient> {
+    let host = endpoint.host();
+    while !session.is_closed() {
+        match ControlClient::connect(endpoint, port) {
+            Ok(client) => {
+                log_verbose!("INPUT", "Connected to {}:{}", host, port);
+                session.link_up(Link::Control);
+                return Some(client);
+            }
+            Err(e) => {
+                log_verbose!("INPUT", "Connect to {}:{} failed: {}", host, port, e);
+                if !session.backoff(Link::Control, || false) {
+                    break;
+                }
+            }
+        }
+    }
+    None
+}
+
 fn process_command(client: &mut ControlClient, cmd: InputCommand) {
     match cmd {
         InputCommand::Tap(x, y) => {
@@ -81,40 +151,36 @@ fn process_command(client: &mut ControlClient, cmd: InputCommand) {
             }
         }
         InputCommand::GetClipboard(copy) => {
-            match client.get_clipboard(copy) {
-                Ok(response) => {
-                    // Parse JSON response: {"cmd": "get_clipboard", "text": "..."}
-                    // Find "text": " pattern and extract value
-                    if let Some(text_start) = response.find("\"text\": \"") {
-                        let rest = &response[text_start + 9..]; // Skip "text": "
-                                                                // Find closing quote (not escaped)
-                        let mut end_idx = 0;
-                        let mut chars = rest.chars().peekable();
-                        let mut escaped = false;
-                        while let Some(c) = chars.next() {
-                            if escaped {
-                                escaped = false;
-                            } else if c == '\\' {
-                                escaped = true;
-                            } else if c == '"' {
-                                break;
-                            }
-                            end_idx += c.len_utf8();
-                        }
+            let response = match client.get_clipboard(copy) {
+                Ok(response) => response,
+                Err(e) => {
+                    log_verbose!("CLIPBOARD", "Get failed: {}", e);
+                    return;
+                }
+            };
+            // Response: {"cmd": "get_clipboard", "text": "...", "image": false}
+            let json: serde_json::Value = match serde_json::from_str(&response) {
+                Ok(json) => json,
+                Err(e) => {
+                    log_verbose!("CLIPBOARD", "Invalid response: {}", e);
+                    return;
+                }
+            };
+            let text = json.get("text").and_then(|t| t.as_str()).unwrap_or("");
+            let has_image = json.get("image").and_then(|i| i.as_bool()).unwrap_or(false);
 
-                        if end_idx > 0 {
-                            let text = &rest[..end_idx];
-                            let unescaped = text
-                                .replace("\\n", "\n")
-                                .replace("\\\"", "\"")
-                                .replace("\\\\", "\\");
-                            if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                                let _ = clipboard.set_text(unescaped.clone());
-                            }
-                        }
+            if !text.is_empty() {
+                if let Ok(mut clipboard) = arboard::Clipboard::new() {
+                    let _ = clipboard.set_text(text.to_string());
+                }
+            } else if has_image {
+                match client.get_clipboard_image() {
+                    Ok(Some(png)) => set_desktop_clipboard_image(&png),
+                    Ok(None) => {}
+                    Err(e) => {
+                        log_error!("CLIPBOARD", "Image transfer failed: {}", e);
                     }
                 }
-                Err(_) => {}
             }
         }
         InputCommand::SetClipboard(text, paste) => {
@@ -122,6 +188,25 @@ fn process_command(client: &mut ControlClient, cmd: InputCommand) {
                 log_verbose!("CLIPBOARD", "Set failed: {}", e);
             }
         }
+        InputCommand::SetClipboardImage(width, height, rgba, paste) => {
+            let png = match encode_png(width, height, rgba) {
+                Ok(png) => png,
+                Err(e) => {
+                    log_error!("CLIPBOARD", "PNG encode failed: {}", e);
+                    return;
+                }
+            };
+            log_verbose!(
+                "CLIPBOARD",
+                "Sending {}x{} image ({}KB)",
+                width,
+                height,
+                png.len() / 1024
+            );
+            if let Err(e) = client.set_clipboard_image(&png, paste) {
+                log_error!("CLIPBOARD", "Image transfer failed: {}", e);
+            }
+        }
         InputCommand::InjectText(text) => {
             if let Err(e) = client.inject_text(&text) {
                 log_verbose!("INPUT", "Inject text failed: {}", e);
@@ -132,5 +217,63 @@ fn process_command(client: &mut ControlClient, cmd: InputCommand) {
                 log_verbose!("INPUT", "Set Power Mode failed: {}", e);
             }
         }
+        InputCommand::SetVideo(settings) => {
+            match client.set_video(
+                Some(settings.bitrate),
+                Some(settings.max_size),
+                Some(settings.max_fps),
+            ) {
+                Ok(applied) => {
+                    log_verbose!("INPUT", "Device video settings: {}", applied);
+                }
+                Err(e) => {
+                    log_error!("INPUT", "Set video failed: {}", e);
+                }
+            }
+        }
+        InputCommand::Rotate => {
+            if let Err(e) = client.rotate() {
+                log_verbose!("INPUT", "Rotate failed: {}", e);
+            }
+        }
+        InputCommand::RequestKeyframe => {
+            if let Err(e) = client.request_keyframe() {
+                log_verbose!("INPUT", "Keyframe request failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Encode RGBA pixels from the desktop clipboard as PNG
+fn encode_png(width: u32, height: u32, rgba: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let buffer = image::RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| anyhow::anyhow!("Invalid image buffer {}x{}", width, height))?;
+    let mut png = Vec::new();
+    buffer.write_to(
+        &mut std::io::Cursor::new(&mut png),
+        image::ImageOutputFormat::Png,
+    )?;
+    Ok(png)
+}
+
+/// Decode a PNG received from the device and place it on the desktop clipboard
+fn set_desktop_clipboard_image(png: &[u8]) {
+    let rgba = match image::load_from_memory_with_format(png, image::ImageFormat::Png) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => {
+            log_error!("CLIPBOARD", "PNG decode failed: {}", e);
+            return;
+        }
+    };
+    let (width, height) = rgba.dimensions();
+    let image = arboard::ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: std::borrow::Cow::Owned(rgba.into_raw()),
+    };
+    if let Ok(mut clipboard) = arboard::Clipboard::new() {
+        if let Err(e) = clipboard.set_image(image) {
+            log_error!("CLIPBOARD", "Set image failed: {}", e);
+        }
     }
 }
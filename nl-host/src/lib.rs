@@ -12,4 +12,5 @@ pub mod video;
 
 // Re-export commonly used items
 pub use core::run;
+pub use core::{AudioMode, Session, SessionBuilder, SessionHandle};
 pub use network::ControlClient;
@@ -1,6 +1,14 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use nl_host::{audio, core, network::ControlClient};
+use nl_host::core::events::{EventFormat, EventSink};
+use nl_host::core::{ColorMatrix, ColorRange};
+use nl_host::network::secure::Psk;
+use nl_host::network::{
+    transfer::DEFAULT_PUSH_TARGET, AdaptiveBounds, ControlClient, Endpoint, VideoSettings,
+};
+use nl_host::video::ScaleFilter;
+use nl_host::core;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "NL-Mirror: High-speed Android mirroring")]
@@ -11,86 +19,259 @@ struct Args {
     #[arg(short, long, default_value_t = 8888)]
     port: u16,
 
+    /// Pre-shared key (64 hex chars) from launcher pairing; encrypts and authenticates all
+    /// connections. The device must have been started with the same key.
+    #[arg(long, global = true)]
+    key_file: Option<PathBuf>,
+
+    /// Carry video, audio and control over the one connection to --port instead of three
+    /// ports. Needs a device server with multiplexing support.
+    #[arg(long, global = true)]
+    mux: bool,
+
+    /// Offer the device UDP for video and audio, so a lost packet over WiFi does not stall
+    /// the ones behind it. Falls back to TCP when the device declines (e.g. over adb).
+    /// Ignored with --key-file: datagrams are not encrypted.
+    #[arg(long, global = true)]
+    udp: bool,
+
+    /// Write session events (connected, resolution, stats, errors, ...) to stdout as one
+    /// JSON object per line, for the launcher. Logs stay on stderr.
+    #[arg(long, global = true, value_name = "FORMAT")]
+    events: Option<EventFormat>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
-#[derive(Subcommand, Debug)]
-enum Commands {
-    Mirror {
-        #[arg(long, default_value_t = 8000000)]
-        bitrate: u32,
+#[derive(Parser, Debug)]
+struct MirrorArgs {
+    #[arg(long, default_value_t = 8000000)]
+    bitrate: u32,
 
-        #[arg(long, default_value_t = 1080)]
-        max_size: u32,
+    /// Cap the longer side of the video in pixels (0 = native resolution)
+    #[arg(long, default_value_t = 0)]
+    max_size: u32,
 
-        /// Enable verbose logging
-        #[arg(short, long)]
-        verbose: bool,
+    /// Cap the device frame rate (0 = uncapped; cycle with MOD+;)
+    #[arg(long, default_value_t = 0)]
+    max_fps: u32,
 
-        /// Turn screen off while mirroring
-        #[arg(long)]
-        turn_screen_off: bool,
+    /// Lower bitrate and resolution when the link is congested and raise them again when it
+    /// clears (--bitrate and --max-size are the upper bounds)
+    #[arg(long)]
+    adaptive: bool,
 
-        /// Enable audio streaming (Android 11+ required)
-        #[arg(long, default_value_t = true)]
-        audio: bool,
+    /// Lowest bitrate --adaptive may go down to
+    #[arg(long, default_value_t = 1000000)]
+    min_bitrate: u32,
 
-        /// Disable audio streaming
-        #[arg(long)]
-        no_audio: bool,
-    },
+    /// Smallest max size --adaptive may go down to
+    #[arg(long, default_value_t = 720)]
+    min_size: u32,
+
+    /// Enable verbose logging
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Turn screen off while mirroring
+    #[arg(long)]
+    turn_screen_off: bool,
+
+    /// Enable audio streaming (Android 11+ required)
+    #[arg(long, default_value_t = true)]
+    audio: bool,
+
+    /// Disable audio streaming
+    #[arg(long)]
+    no_audio: bool,
+
+    /// Reconnect attempts before the session gives up (0 = keep trying)
+    #[arg(long, default_value_t = 5)]
+    reconnect_attempts: u32,
+
+    /// Longest wait between reconnect attempts, in seconds (the wait doubles from 1s)
+    #[arg(long, default_value_t = 10)]
+    reconnect_max_delay: u64,
+
+    /// Device folder for files dropped onto the mirror window (APKs are installed instead)
+    #[arg(long, default_value = DEFAULT_PUSH_TARGET)]
+    push_target: String,
+
+    /// Device serial, used to remember the window position and size per device
+    #[arg(long)]
+    serial: Option<String>,
+
+    /// Start in fullscreen (toggle with MOD+F)
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Keep the window above other windows (toggle with MOD+T)
+    #[arg(long)]
+    always_on_top: bool,
+
+    /// Hide the window title bar and borders (toggle with MOD+B)
+    #[arg(long)]
+    borderless: bool,
+
+    /// Size the window so one device pixel is one screen pixel (toggle with MOD+G)
+    #[arg(long)]
+    pixel_perfect: bool,
+
+    /// Force the YUV matrix (bt601, bt709, bt2020) instead of the one signalled by the stream
+    #[arg(long)]
+    color_matrix: Option<ColorMatrix>,
+
+    /// Force the YUV range (limited, full) instead of the one signalled by the stream
+    #[arg(long)]
+    color_range: Option<ColorRange>,
+
+    /// Scaling filter: nearest, bilinear, bicubic or lanczos (cycle with MOD+K)
+    #[arg(long, default_value = "bilinear")]
+    scale_filter: ScaleFilter,
+
+    /// Sharpen the picture with the given strength, e.g. 0.5 (toggle with MOD+J)
+    #[arg(long, default_value_t = 0.0)]
+    sharpen: f32,
+
+    /// Hold frames this long and present them by timestamp, for smooth playback on jittery
+    /// networks (e.g. 50 or 150). 0 shows every frame at once, for the lowest latency.
+    #[arg(long, default_value_t = 0)]
+    buffer_ms: u64,
+
+    /// Show the performance HUD (toggle with MOD+I)
+    #[arg(long)]
+    hud: bool,
+
+    /// Show markers for taps, swipes and key presses (toggle with MOD+O)
+    #[arg(long)]
+    show_touches: bool,
+
+    /// Also draw the touch markers into screenshots (implies --show-touches)
+    #[arg(long)]
+    burn_touches: bool,
+
+    /// Accept JSON commands (screenshot, rotate, set_video, screen_power, start_recording,
+    /// stop_recording, quit) on this Unix socket, or named pipe (\\.\pipe\NAME) on Windows
+    #[arg(long)]
+    ipc: Option<PathBuf>,
+
+    /// Modifier (MOD) for the window shortcuts: alt, ctrl or super. Ctrl+C, Ctrl+V and
+    /// Ctrl+S always work; other Ctrl combinations go to the device unless this is ctrl.
+    #[arg(long, default_value = "alt")]
+    shortcut_mod: core::ShortcutMod,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    Mirror(MirrorArgs),
     Tap {
         x: f32,
         y: f32,
     },
     Stats,
     Hierarchy,
+    /// Change the encoder of a running mirror session
+    Video {
+        #[arg(long)]
+        bitrate: Option<u32>,
+        #[arg(long)]
+        max_size: Option<u32>,
+        #[arg(long)]
+        max_fps: Option<u32>,
+    },
+    /// Set the device display power mode (0 = off, 2 = on)
+    ScreenPower { mode: i32 },
 }
 
 fn main() -> Result<()> {
     env_logger::init();
     let args = Args::parse();
 
-    match args.command.unwrap_or(Commands::Mirror {
-        bitrate: 8000000,
-        max_size: 1080,
-        verbose: false,
-        turn_screen_off: false,
-        audio: true,
-        no_audio: false,
-    }) {
+    let mut endpoint = Endpoint::new(&args.host).with_udp(args.udp);
+    if let Some(path) = &args.key_file {
+        endpoint = endpoint.with_key(Psk::load(path)?);
+    }
+    if args.mux {
+        endpoint = endpoint.multiplexed(args.port);
+    }
+
+    match args
+        .command
+        .unwrap_or_else(|| Commands::Mirror(MirrorArgs::parse_from(["mirror"])))
+    {
         Commands::Tap { x, y } => {
-            let mut client = ControlClient::connect(&args.host, args.port + 1)?;
+            let mut client = ControlClient::connect(&endpoint, args.port + 1)?;
             client.tap(x, y)?;
             println!("Tap sent to ({}, {})", x, y);
         }
         Commands::Stats => {
-            let mut client = ControlClient::connect(&args.host, args.port + 1)?;
+            let mut client = ControlClient::connect(&endpoint, args.port + 1)?;
             println!("{}", client.get_stats()?);
         }
         Commands::Hierarchy => {
-            let mut client = ControlClient::connect(&args.host, args.port + 1)?;
+            let mut client = ControlClient::connect(&endpoint, args.port + 1)?;
             println!("{}", client.get_hierarchy()?);
         }
-        Commands::Mirror {
+        Commands::Video {
             bitrate,
             max_size,
-            verbose,
-            turn_screen_off,
-            audio: enable_audio,
-            no_audio,
+            max_fps,
         } => {
+            let mut client = ControlClient::connect(&endpoint, args.port + 1)?;
+            println!("{}", client.set_video(bitrate, max_size, max_fps)?);
+        }
+        Commands::ScreenPower { mode } => {
+            let mut client = ControlClient::connect(&endpoint, args.port + 1)?;
+            client.set_screen_power_mode(mode)?;
+        }
+        Commands::Mirror(mirror) => {
             // Apply verbose config
-            core::VERBOSE.store(verbose, std::sync::atomic::Ordering::SeqCst);
-
-            // Start audio pipeline if enabled
-            let audio_enabled = enable_audio && !no_audio;
-            if audio_enabled {
-                audio::start_audio_pipeline(args.host.clone(), args.port + 2);
-            }
+            core::VERBOSE.store(mirror.verbose, std::sync::atomic::Ordering::SeqCst);
 
-            core::run(args.host, args.port, bitrate, max_size, turn_screen_off)?;
+            core::run(core::MirrorOptions {
+                endpoint,
+                port: args.port,
+                video: VideoSettings {
+                    bitrate: mirror.bitrate,
+                    max_size: mirror.max_size,
+                    max_fps: mirror.max_fps,
+                },
+                adaptive: mirror.adaptive.then_some(AdaptiveBounds {
+                    min_bitrate: mirror.min_bitrate.min(mirror.bitrate),
+                    max_bitrate: mirror.bitrate,
+                    min_size: mirror.min_size,
+                    max_size: mirror.max_size,
+                }),
+                audio: mirror.audio && !mirror.no_audio,
+                retry: core::RetryPolicy {
+                    max_attempts: mirror.reconnect_attempts,
+                    max_delay: std::time::Duration::from_secs(mirror.reconnect_max_delay.max(1)),
+                    ..Default::default()
+                },
+                turn_screen_off: mirror.turn_screen_off,
+                push_target: mirror.push_target,
+                color: core::ColorOverride {
+                    matrix: mirror.color_matrix,
+                    range: mirror.color_range,
+                },
+                scale_filter: mirror.scale_filter,
+                sharpen: mirror.sharpen,
+                buffer: std::time::Duration::from_millis(mirror.buffer_ms),
+                hud: mirror.hud,
+                show_touches: mirror.show_touches,
+                burn_touches: mirror.burn_touches,
+                ipc: mirror.ipc,
+                events: args.events.map(EventSink::stdout).unwrap_or_default(),
+                shortcut_mod: mirror.shortcut_mod,
+                window: core::WindowOptions {
+                    fullscreen: mirror.fullscreen,
+                    always_on_top: mirror.always_on_top,
+                    borderless: mirror.borderless,
+                    pixel_perfect: mirror.pixel_perfect,
+                    serial: mirror.serial,
+                },
+            })?;
         }
     }
     Ok(())
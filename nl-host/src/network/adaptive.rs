@@ -0,0 +1,444 @@
+//! Adaptive bitrate: degrades the device encoder when the link is congested and
+//! recovers it once the link is clear again
+
+use crate::core::{Session, SessionStats};
+use crate::network::{ControlClient, Endpoint, VideoSettings};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the congestion signals are sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Packets waiting for the decoder before the link counts as congested
+const BACKLOG_LIMIT: u64 = 30;
+/// Frame age (display time past decoding or the playout schedule) before the host counts
+/// as falling behind
+const FRAME_AGE_LIMIT: u64 = 150;
+
+/// Bitrate factor when congested (also capped just below the measured receive rate)
+const DECREASE_FACTOR: f64 = 0.7;
+/// Bitrate factor per recovery step
+const INCREASE_FACTOR: f64 = 1.25;
+
+/// No changes for this long after one (an encoder restart causes a short gap)
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+/// Clear samples needed before probing upwards; doubles when a probe fails
+const MIN_PROBE_WAIT: u32 = 5;
+const MAX_PROBE_WAIT: u32 = 60;
+/// Congestion within this time of an increase counts as a failed probe
+const PROBE_FAILURE_WINDOW: Duration = Duration::from_secs(10);
+
+/// Range the controller may move the encoder in.
+/// The session's starting bitrate and max size are the upper bounds.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBounds {
+    pub min_bitrate: u32,
+    pub max_bitrate: u32,
+    /// Smallest max size (longer encoded side)
+    pub min_size: u32,
+    /// Largest max size (0 = native)
+    pub max_size: u32,
+}
+
+/// Congestion signals over one sample interval
+#[derive(Debug, Clone, Copy, Default)]
+struct Sample {
+    bytes: u64,
+    packets: u64,
+    /// Packets dropped before decoding plus decoder resets
+    drops: u64,
+    backlog: u64,
+    frame_age_ms: u64,
+}
+
+impl Sample {
+    fn read(stats: &SessionStats) -> Self {
+        Self {
+            bytes: SessionStats::get(&stats.bytes_received),
+            packets: SessionStats::get(&stats.packets_received),
+            drops: SessionStats::get(&stats.packets_dropped)
+                + SessionStats::get(&stats.decoder_resets),
+            backlog: SessionStats::get(&stats.channel_backlog),
+            frame_age_ms: SessionStats::get(&stats.frame_age_ms),
+        }
+    }
+}
+
+/// Decides encoder changes from successive stats samples
+pub struct AdaptiveController {
+    bounds: AdaptiveBounds,
+    last: Sample,
+    last_time: Instant,
+    last_change: Instant,
+    last_increase: Option<Instant>,
+    clear_samples: u32,
+    probe_wait: u32,
+}
+
+impl AdaptiveController {
+    pub fn new(bounds: AdaptiveBounds, stats: &SessionStats) -> Self {
+        Self {
+            bounds,
+            last: Sample::read(stats),
+            last_time: Instant::now(),
+            last_change: Instant::now(),
+            last_increase: None,
+            clear_samples: 0,
+            probe_wait: MIN_PROBE_WAIT,
+        }
+    }
+
+    /// Sample `stats` and return new settings if the encoder should change
+    pub fn update(
+        &mut self,
+        stats: &SessionStats,
+        current: VideoSettings,
+    ) -> Option<VideoSettings> {
+        self.update_at(stats, current, Instant::now())
+    }
+
+    fn update_at(
+        &mut self,
+        stats: &SessionStats,
+        current: VideoSettings,
+        at: Instant,
+    ) -> Option<VideoSettings> {
+        let now = Sample::read(stats);
+        let secs = at
+            .saturating_duration_since(self.last_time)
+            .as_secs_f64()
+            .max(0.001);
+        let received_bps = (now.bytes - self.last.bytes) as f64 * 8.0 / secs;
+        let stalled = now.packets == self.last.packets;
+        let dropped = now.drops > self.last.drops;
+        self.last = now;
+        self.last_time = at;
+
+        if at.saturating_duration_since(self.last_change) < SETTLE_TIME {
+            return None;
+        }
+
+        // The device repeats frames on a static screen, so silence means a stalled link
+        let congested =
+            stalled || dropped || now.backlog > BACKLOG_LIMIT || now.frame_age_ms > FRAME_AGE_LIMIT;
+
+        let next = if congested {
+            self.clear_samples = 0;
+            if self
+                .last_increase
+                .is_some_and(|t| at.saturating_duration_since(t) < PROBE_FAILURE_WINDOW)
+            {
+                self.probe_wait = (self.probe_wait * 2).min(MAX_PROBE_WAIT);
+            }
+            self.decrease(current, received_bps)
+        } else {
+            self.clear_samples += 1;
+            if self.clear_samples < self.probe_wait {
+                return None;
+            }
+            self.clear_samples = 0;
+            let next = self.increase(current);
+            if next.is_some() {
+                self.last_increase = Some(at);
+                self.probe_wait = (self.probe_wait / 2).max(MIN_PROBE_WAIT);
+            }
+            next
+        }?;
+
+        log_info!(
+            "ABR",
+            "{} -> {} (received {:.2} Mbps, backlog {}, age {} ms{}{})",
+            if congested { "Congested" } else { "Clear" },
+            next,
+            received_bps / 1e6,
+            now.backlog,
+            now.frame_age_ms,
+            if stalled { ", stalled" } else { "" },
+            if dropped { ", drops" } else { "" }
+        );
+        self.last_change = at;
+        Some(next)
+    }
+
+    /// Lower the bitrate first; once it is at the floor, lower the resolution
+    fn decrease(&self, current: VideoSettings, received_bps: f64) -> Option<VideoSettings> {
+        if current.bitrate > self.bounds.min_bitrate {
+            let mut target = current.bitrate as f64 * DECREASE_FACTOR;
+            if received_bps > 0.0 {
+                // What actually got through is a better estimate of the link
+                target = target.min(received_bps * 0.9);
+            }
+            return Some(VideoSettings {
+                bitrate: (target as u32).max(self.bounds.min_bitrate),
+                ..current
+            });
+        }
+        let smaller = current.step_max_size(false);
+        (size_rank(smaller.max_size) < size_rank(current.max_size)
+            && size_rank(smaller.max_size) >= size_rank(self.bounds.min_size))
+        .then_some(smaller)
+    }
+
+    /// Undo in reverse order: resolution back up first, then the bitrate
+    fn increase(&self, current: VideoSettings) -> Option<VideoSettings> {
+        if size_rank(current.max_size) < size_rank(self.bounds.max_size) {
+            let larger = current.step_max_size(true);
+            let max_size = if size_rank(larger.max_size) > size_rank(self.bounds.max_size) {
+                self.bounds.max_size
+            } else {
+                larger.max_size
+            };
+            return Some(VideoSettings {
+                max_size,
+                ..current
+            });
+        }
+        if current.bitrate < self.bounds.max_bitrate {
+            let target = (current.bitrate as f64 * INCREASE_FACTOR) as u32;
+            return Some(VideoSettings {
+                bitrate: target.min(self.bounds.max_bitrate),
+                ..current
+            });
+        }
+        None
+    }
+}
+
+/// Orders max sizes with 0 (native) as the largest
+fn size_rank(max_size: u32) -> u32 {
+    if max_size == 0 {
+        u32::MAX
+    } else {
+        max_size
+    }
+}
+
+/// Run the controller on a dedicated control connection. Changes are written to `settings`
+/// (so reconnects and hotkeys start from them) and sent to the device. Ends with the `session`.
+pub fn start_adaptive_thread(
+    endpoint: Endpoint,
+    port: u16,
+    bounds: AdaptiveBounds,
+    settings: Arc<Mutex<VideoSettings>>,
+    stats: Arc<SessionStats>,
+    session: Arc<Session>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut controller = AdaptiveController::new(bounds, &stats);
+        let mut client: Option<ControlClient> = None;
+
+        while !session.is_closed() {
+            thread::sleep(SAMPLE_INTERVAL);
+            let current = *settings.lock().unwrap();
+            let Some(next) = controller.update(&stats, current) else {
+                continue;
+            };
+
+            if client.is_none() {
+                match ControlClient::connect(&endpoint, port) {
+                    Ok(c) => client = Some(c),
+                    Err(e) => {
+                        log_verbose!("ABR", "Connect failed: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some(c) = client.as_mut() else {
+                continue;
+            };
+
+            match c.set_video(Some(next.bitrate), Some(next.max_size), Some(next.max_fps)) {
+                Ok(applied) => *settings.lock().unwrap() = applied,
+                Err(e) => {
+                    log_verbose!("ABR", "Set video failed: {}", e);
+                    client = None;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: AdaptiveBounds = AdaptiveBounds {
+        min_bitrate: 1_000_000,
+        max_bitrate: 8_000_000,
+        min_size: 1280,
+        max_size: 1920,
+    };
+    const START: VideoSettings = VideoSettings {
+        bitrate: 8_000_000,
+        max_size: 1920,
+        max_fps: 60,
+    };
+
+    /// Feeds the controller one sample per second, starting after the settle time
+    struct Link {
+        controller: AdaptiveController,
+        stats: SessionStats,
+        at: Instant,
+    }
+
+    impl Link {
+        fn new() -> Self {
+            let stats = SessionStats::default();
+            let mut controller = AdaptiveController::new(BOUNDS, &stats);
+            let at = controller.last_change + SETTLE_TIME;
+            controller.last_time = at;
+            Self {
+                controller,
+                stats,
+                at,
+            }
+        }
+
+        /// One second of 1 MB and 60 packets; `tweak` adds the congestion signals
+        fn sample(
+            &mut self,
+            current: VideoSettings,
+            tweak: impl FnOnce(&SessionStats),
+        ) -> Option<VideoSettings> {
+            SessionStats::add(&self.stats.bytes_received, 1_000_000);
+            SessionStats::add(&self.stats.packets_received, 60);
+            SessionStats::set(&self.stats.channel_backlog, 0);
+            SessionStats::set(&self.stats.frame_age_ms, 20);
+            tweak(&self.stats);
+            self.at += SAMPLE_INTERVAL;
+            self.controller.update_at(&self.stats, current, self.at)
+        }
+
+        fn clear(&mut self, current: VideoSettings) -> Option<VideoSettings> {
+            self.sample(current, |_| {})
+        }
+
+        /// Clear samples until the controller changes something (at most `limit`)
+        fn until_change(
+            &mut self,
+            current: VideoSettings,
+            limit: u32,
+        ) -> Option<(u32, VideoSettings)> {
+            (1..=limit).find_map(|n| self.clear(current).map(|next| (n, next)))
+        }
+    }
+
+    #[test]
+    fn steps_down_on_backlog() {
+        let mut link = Link::new();
+        let next = link
+            .sample(START, |s| {
+                SessionStats::set(&s.channel_backlog, BACKLOG_LIMIT + 1)
+            })
+            .unwrap();
+        assert_eq!(next.bitrate, (8_000_000.0 * DECREASE_FACTOR) as u32);
+        assert_eq!(next.max_size, START.max_size);
+    }
+
+    #[test]
+    fn steps_down_on_drops_to_what_got_through() {
+        let mut link = Link::new();
+        let fast = VideoSettings {
+            bitrate: 16_000_000,
+            ..START
+        };
+        let next = link
+            .sample(fast, |s| SessionStats::add(&s.packets_dropped, 1))
+            .unwrap();
+        // Only 8 Mbps got through: capped just below that rather than at 70%
+        assert_eq!(next.bitrate, 7_200_000);
+    }
+
+    #[test]
+    fn waits_for_the_settle_time_after_a_change() {
+        let mut link = Link::new();
+        let first = link
+            .sample(START, |s| {
+                SessionStats::set(&s.frame_age_ms, FRAME_AGE_LIMIT + 1)
+            })
+            .unwrap();
+        for _ in 1..SETTLE_TIME.as_secs() {
+            assert!(link
+                .sample(first, |s| SessionStats::set(
+                    &s.frame_age_ms,
+                    FRAME_AGE_LIMIT + 1
+                ))
+                .is_none());
+        }
+        assert!(link
+            .sample(first, |s| SessionStats::set(
+                &s.frame_age_ms,
+                FRAME_AGE_LIMIT + 1
+            ))
+            .is_some());
+    }
+
+    #[test]
+    fn lowers_the_resolution_only_at_the_bitrate_floor_and_within_bounds() {
+        let congested = |s: &SessionStats| SessionStats::add(&s.packets_dropped, 1);
+        let floor = VideoSettings {
+            bitrate: BOUNDS.min_bitrate,
+            ..START
+        };
+        let mut link = Link::new();
+        let smaller = link.sample(floor, congested).unwrap();
+        assert_eq!(
+            (smaller.bitrate, smaller.max_size),
+            (BOUNDS.min_bitrate, 1280)
+        );
+
+        link.at += SETTLE_TIME;
+        assert!(link.sample(smaller, congested).is_none());
+    }
+
+    #[test]
+    fn steps_up_only_after_a_clear_period() {
+        let mut link = Link::new();
+        let current = VideoSettings {
+            bitrate: 4_000_000,
+            max_size: 1280,
+            ..START
+        };
+        // Resolution first, then the bitrate
+        let (waited, next) = link.until_change(current, 100).unwrap();
+        assert_eq!(waited, MIN_PROBE_WAIT);
+        assert_eq!((next.bitrate, next.max_size), (4_000_000, 1920));
+
+        link.at += SETTLE_TIME;
+        let (_, next) = link.until_change(next, 100).unwrap();
+        assert_eq!(next.bitrate, 5_000_000);
+    }
+
+    #[test]
+    fn never_steps_past_the_upper_bounds() {
+        let mut link = Link::new();
+        let near = VideoSettings {
+            bitrate: 7_000_000,
+            ..START
+        };
+        let (_, next) = link.until_change(near, 100).unwrap();
+        assert_eq!(next, START);
+
+        link.at += SETTLE_TIME;
+        assert!(link.until_change(START, 100).is_none());
+    }
+
+    #[test]
+    fn failed_probe_doubles_the_wait() {
+        let mut link = Link::new();
+        let lower = VideoSettings {
+            bitrate: 4_000_000,
+            ..START
+        };
+        let (_, probe) = link.until_change(lower, 100).unwrap();
+        // Congested right after the increase: back down, and wait longer next time
+        link.at += SETTLE_TIME;
+        let down = link
+            .sample(probe, |s| SessionStats::add(&s.packets_dropped, 1))
+            .unwrap();
+        link.at += SETTLE_TIME;
+        let (waited, _) = link.until_change(down, 100).unwrap();
+        assert_eq!(waited, MIN_PROBE_WAIT * 2);
+    }
+}
@@ -0,0 +1,146 @@
+//! NTP-style sync of the device clock, for latencies measured from capture
+
+use crate::core::{Session, SessionStats};
+use crate::network::{ControlClient, Endpoint};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Exchanges per sync; the one with the shortest round trip wins
+const SAMPLES: usize = 8;
+/// Resync this often to follow clock drift and route changes
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Host times a clock request was sent and answered, and the device time it returned
+type Exchange = (Instant, Instant, u64);
+
+/// Keep `stats.clock` synced to the device over a dedicated control connection.
+/// Devices without the `clock` command leave it unsynced (capture latencies stay empty).
+/// Ends with the `session`.
+pub fn start_clock_sync_thread(
+    endpoint: Endpoint,
+    port: u16,
+    stats: Arc<SessionStats>,
+    session: Arc<Session>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut client: Option<ControlClient> = None;
+
+        while !session.is_closed() {
+            if client.is_none() {
+                match ControlClient::connect(&endpoint, port) {
+                    Ok(c) => client = Some(c),
+                    Err(e) => {
+                        log_verbose!("CLOCK", "Connect failed: {}", e);
+                        thread::sleep(SYNC_INTERVAL);
+                        continue;
+                    }
+                }
+            }
+            let Some(c) = client.as_mut() else {
+                continue;
+            };
+
+            let mut best: Option<Exchange> = None;
+            for _ in 0..SAMPLES {
+                let sent = Instant::now();
+                match c.get_clock() {
+                    Ok(device_us) => {
+                        best = Some(fastest(best, (sent, Instant::now(), device_us)));
+                    }
+                    Err(e) if e.to_string().starts_with("Unknown command") => {
+                        log_verbose!("CLOCK", "Device has no clock sync; capture latency off");
+                        return;
+                    }
+                    Err(e) => {
+                        log_verbose!("CLOCK", "Sync failed: {}", e);
+                        client = None;
+                        break;
+                    }
+                }
+            }
+
+            if let Some((sent, received, device_us)) = best {
+                let first = stats.clock.sync().is_none();
+                stats.clock.update(sent, received, device_us);
+                if let Some((offset, rtt)) = stats.clock.sync() {
+                    if first {
+                        log_info!(
+                            "CLOCK",
+                            "Synced to device: offset {} us, round trip {} us",
+                            offset,
+                            rtt.as_micros()
+                        );
+                    } else {
+                        log_debug!(
+                            "CLOCK",
+                            "offset {} us, round trip {} us",
+                            offset,
+                            rtt.as_micros()
+                        );
+                    }
+                }
+            }
+            thread::sleep(SYNC_INTERVAL);
+        }
+    })
+}
+
+/// The exchange with the shorter round trip: it bounds the offset error tighter
+fn fastest(best: Option<Exchange>, exchange: Exchange) -> Exchange {
+    match best {
+        Some((sent, received, device_us)) if received - sent <= exchange.1 - exchange.0 => {
+            (sent, received, device_us)
+        }
+        _ => exchange,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::DeviceClock;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn the_shortest_round_trip_sets_the_offset() {
+        let clock = DeviceClock::new();
+        let start = Instant::now();
+        let base = clock.host_us(start);
+        // The device clock is 5s ahead; replies are stamped at the midpoint of a
+        // symmetric route except for the slow exchange, which was delayed on the way back
+        let exchanges = [
+            (start, start + ms(40), 5_000_000 + base as u64 + 10_000),
+            (
+                start + ms(100),
+                start + ms(104),
+                5_000_000 + base as u64 + 102_000,
+            ),
+            (
+                start + ms(200),
+                start + ms(210),
+                5_000_000 + base as u64 + 205_000,
+            ),
+        ];
+        let best = exchanges
+            .into_iter()
+            .fold(None, |best, e| Some(fastest(best, e)));
+        let (sent, received, device_us) = best.unwrap();
+        assert_eq!(sent, start + ms(100));
+
+        clock.update(sent, received, device_us);
+        assert_eq!(clock.sync(), Some((5_000_000, ms(4))));
+    }
+
+    #[test]
+    fn ties_keep_the_earlier_exchange() {
+        let start = Instant::now();
+        let first = (start, start + ms(4), 1);
+        let second = (start + ms(10), start + ms(14), 2);
+        assert_eq!(fastest(Some(first), second), first);
+        assert_eq!(fastest(None, second), second);
+    }
+}
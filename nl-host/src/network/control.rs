@@ -1,15 +1,26 @@
-use anyhow::Result;
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
+use crate::network::transport::{self, Connection, Endpoint, Handle, Reader, Service, Writer};
+use crate::network::VideoSettings;
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
 
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 /// ControlClient for sending commands to nl-android.
 pub struct ControlClient {
-    input_stream: TcpStream,
-    rpc_stream: TcpStream,
+    input_stream: Handle,
+    input: Writer,
+    rpc_stream: Handle,
+    rpc_writer: Writer,
+    rpc_reader: BufReader<Reader>,
     drain_running: Arc<AtomicBool>,
+    /// Cleared when the input connection closes or fails
+    input_alive: Arc<AtomicBool>,
     drain_handle: Option<JoinHandle<()>>,
 }
 
@@ -18,19 +29,30 @@ impl ControlClient {
     pub const META_CTRL_ON: i32 = 0x1000; // AMETA_CTRL_ON
     pub const META_META_ON: i32 = 0x10000; // AMETA_META_ON
 
-    pub fn connect(host: &str, port: u16) -> Result<Self> {
+    /// Raw bytes carried by one clipboard image chunk (~87KB once base64-encoded)
+    pub const CLIPBOARD_CHUNK_SIZE: usize = 64 * 1024;
+    /// Largest PNG accepted for clipboard transfer in either direction
+    pub const MAX_CLIPBOARD_IMAGE_SIZE: usize = 16 * 1024 * 1024;
+    /// Raw bytes carried by one file transfer chunk
+    pub const FILE_CHUNK_SIZE: usize = 256 * 1024;
+
+    pub fn connect(endpoint: &Endpoint, port: u16) -> Result<Self> {
         // 1. Input Connection (Async writes + Background Drain)
         // We use a separate connection for input to allow fire-and-forget sending
         // while a background thread continuously drains the responses from the server.
         // This prevents the TCP Receive Window from filling up (Deadlock)
         // without incurring the RTT latency of synchronous reads for every keystroke.
-        let input_stream = TcpStream::connect(format!("{}:{}", host, port))?;
-        input_stream.set_nodelay(true)?;
+        let Connection {
+            handle: input_stream,
+            reader: drain_stream,
+            writer: input,
+        } = transport::connect(endpoint, port, Service::Control, CONNECT_TIMEOUT)?;
+        input_stream.set_read_timeout(Some(std::time::Duration::from_millis(500)))?;
 
         let drain_running = Arc::new(AtomicBool::new(true));
         let drain_running_clone = drain_running.clone();
-        let drain_stream = input_stream.try_clone()?;
-        drain_stream.set_read_timeout(Some(std::time::Duration::from_millis(500)))?;
+        let input_alive = Arc::new(AtomicBool::new(true));
+        let input_alive_clone = input_alive.clone();
 
         let drain_handle = thread::spawn(move || {
             let mut reader = BufReader::new(drain_stream);
@@ -51,18 +73,26 @@ impl ControlClient {
                     }
                 }
             }
+            input_alive_clone.store(false, Ordering::SeqCst);
         });
 
         // 2. RPC Connection (Synchronous Request-Response)
         // Used for commands that need a return value (e.g. get_clipboard)
-        let rpc_stream = TcpStream::connect(format!("{}:{}", host, port))?;
-        rpc_stream.set_nodelay(true)?;
+        let Connection {
+            handle: rpc_stream,
+            reader: rpc_reader,
+            writer: rpc_writer,
+        } = transport::connect(endpoint, port, Service::Control, CONNECT_TIMEOUT)?;
         rpc_stream.set_read_timeout(Some(std::time::Duration::from_millis(500)))?;
 
         Ok(Self {
             input_stream,
+            input,
             rpc_stream,
+            rpc_writer,
+            rpc_reader: BufReader::new(rpc_reader),
             drain_running,
+            input_alive,
             drain_handle: Some(drain_handle),
         })
     }
@@ -72,6 +102,21 @@ impl ControlClient {
         Ok(())
     }
 
+    /// False once the device closed the input connection (or it failed)
+    pub fn is_alive(&self) -> bool {
+        self.input_alive.load(Ordering::SeqCst)
+    }
+
+    /// Round trip on the RPC connection. Any answer counts, so this also works with
+    /// devices that do not know the command.
+    pub fn ping(&mut self) -> Result<()> {
+        let line = self.send_command_sync(r#"{"cmd": "ping"}"#)?;
+        if line.is_empty() {
+            return Err(anyhow!("Connection closed"));
+        }
+        Ok(())
+    }
+
     // ===== Keyboard Events =====
 
     /// Inject a keycode event (down/up) with meta state
@@ -99,6 +144,96 @@ impl ControlClient {
         self.send_command_sync(&cmd)
     }
 
+    /// Send a PNG image to the device clipboard, split into base64 chunks.
+    /// The device reassembles the chunks and sets the clipboard once the last one arrives.
+    pub fn set_clipboard_image(&mut self, png: &[u8], paste: bool) -> Result<()> {
+        if png.is_empty() {
+            return Err(anyhow!("Clipboard image is empty"));
+        }
+        if png.len() > Self::MAX_CLIPBOARD_IMAGE_SIZE {
+            return Err(anyhow!(
+                "Clipboard image too large: {}KB (max {}KB)",
+                png.len() / 1024,
+                Self::MAX_CLIPBOARD_IMAGE_SIZE / 1024
+            ));
+        }
+
+        let total = png.len().div_ceil(Self::CLIPBOARD_CHUNK_SIZE);
+        for (index, chunk) in png.chunks(Self::CLIPBOARD_CHUNK_SIZE).enumerate() {
+            let data = base64::engine::general_purpose::STANDARD.encode(chunk);
+            let cmd = format!(
+                r#"{{"cmd": "set_clipboard_image", "index": {}, "total": {}, "size": {}, "paste": {}, "data": "{}"}}"#,
+                index,
+                total,
+                png.len(),
+                paste,
+                data
+            );
+            self.send_command_async(&cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the device clipboard image as PNG bytes, chunk by chunk.
+    /// Returns `None` if the device clipboard holds no image.
+    pub fn get_clipboard_image(&mut self) -> Result<Option<Vec<u8>>> {
+        // The device encodes the PNG on the first request, which can be slow for large images
+        let previous_timeout = self.rpc_stream.read_timeout()?;
+        self.rpc_stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+        let result = self.read_clipboard_image_chunks();
+        self.rpc_stream.set_read_timeout(previous_timeout)?;
+        result
+    }
+
+    fn read_clipboard_image_chunks(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut png = Vec::new();
+        let mut index = 0usize;
+        loop {
+            let cmd = format!(r#"{{"cmd": "get_clipboard_image", "index": {}}}"#, index);
+            let response = self.send_command_sync(&cmd)?;
+            let json: serde_json::Value = serde_json::from_str(&response)?;
+            if let Some(err) = json.get("error").and_then(|e| e.as_str()) {
+                return Err(anyhow!("get_clipboard_image failed: {}", err));
+            }
+
+            let total = json.get("total").and_then(|t| t.as_u64()).unwrap_or(0) as usize;
+            let size = json.get("size").and_then(|s| s.as_u64()).unwrap_or(0) as usize;
+            if total == 0 {
+                return Ok(None);
+            }
+            if size > Self::MAX_CLIPBOARD_IMAGE_SIZE {
+                return Err(anyhow!("Device clipboard image too large: {}KB", size / 1024));
+            }
+
+            let data = json.get("data").and_then(|d| d.as_str()).unwrap_or("");
+            let chunk = base64::engine::general_purpose::STANDARD.decode(data)?;
+            // Trust the bytes received, not the announced size and chunk count
+            if chunk.is_empty() {
+                return Err(anyhow!("Empty clipboard image chunk {} of {}", index, total));
+            }
+            if png.len() + chunk.len() > size.min(Self::MAX_CLIPBOARD_IMAGE_SIZE) {
+                return Err(anyhow!(
+                    "Device clipboard image larger than announced ({} bytes)",
+                    size
+                ));
+            }
+            png.extend_from_slice(&chunk);
+
+            index += 1;
+            if index >= total {
+                if png.len() != size {
+                    return Err(anyhow!(
+                        "Device clipboard image incomplete: {} of {} bytes",
+                        png.len(),
+                        size
+                    ));
+                }
+                return Ok(Some(png));
+            }
+        }
+    }
+
     /// Inject text directly as key events (like scrcpy)
     pub fn inject_text(&mut self, text: &str) -> Result<()> {
         let escaped = text
@@ -142,38 +277,218 @@ impl ControlClient {
         self.send_command_sync(cmd)
     }
 
+    /// Current device time in microseconds, on the clock of the video and audio PTS
+    pub fn get_clock(&mut self) -> Result<u64> {
+        let line = self.send_command_sync(r#"{"cmd": "clock"}"#)?;
+        let response: serde_json::Value =
+            serde_json::from_str(&line).map_err(|e| anyhow!("Invalid response to clock: {}", e))?;
+        if let Some(err) = response.get("error").and_then(|e| e.as_str()) {
+            return Err(anyhow!("{}", err));
+        }
+        response
+            .get("time_us")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| anyhow!("clock response is missing 'time_us'"))
+    }
+
     pub fn set_screen_power_mode(&mut self, mode: i32) -> Result<()> {
         let cmd = format!(r#"{{"cmd": "set_screen_power_mode", "mode": {}}}"#, mode);
         self.send_command_async(&cmd)
     }
 
+    /// Switch the device display between portrait and landscape
+    pub fn rotate(&mut self) -> Result<()> {
+        self.send_command_async(r#"{"cmd": "rotate"}"#)
+    }
+
+    // ===== Video =====
+
+    /// Ask the device encoder for an IDR frame (with SPS/PPS) right away
+    pub fn request_keyframe(&mut self) -> Result<()> {
+        let cmd = r#"{"cmd": "request_keyframe"}"#;
+        self.send_command_async(cmd)
+    }
+
+    /// Change the device encoder mid-session; `None` keeps the current value.
+    /// Bitrate changes apply immediately, size and frame-rate changes restart the encoder
+    /// (the stream then carries a new SPS). Returns the settings now in effect.
+    pub fn set_video(
+        &mut self,
+        bitrate: Option<u32>,
+        max_size: Option<u32>,
+        max_fps: Option<u32>,
+    ) -> Result<VideoSettings> {
+        let mut payload = serde_json::json!({ "cmd": "set_video" });
+        for (key, value) in [
+            ("bitrate", bitrate),
+            ("max_size", max_size),
+            ("max_fps", max_fps),
+        ] {
+            if let Some(value) = value {
+                payload[key] = serde_json::Value::from(value);
+            }
+        }
+
+        let line = self.send_command_sync(&payload.to_string())?;
+        let response: serde_json::Value = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("Invalid response to set_video: {}", e))?;
+        if let Some(err) = response.get("error").and_then(|e| e.as_str()) {
+            return Err(anyhow!("{}", err));
+        }
+        let field = |key: &str| {
+            response
+                .get(key)
+                .and_then(|v| v.as_u64())
+                .map(|v| v as u32)
+                .ok_or_else(|| anyhow!("set_video response is missing '{}'", key))
+        };
+        Ok(VideoSettings {
+            bitrate: field("bitrate")?,
+            max_size: field("max_size")?,
+            max_fps: field("max_fps")?,
+        })
+    }
+
+    // ===== File Transfer =====
+
+    /// Push a local file into `dest_dir` on the device. Returns the device path.
+    /// `progress` is called with (bytes_sent, total_bytes) after every chunk.
+    pub fn push_file(
+        &mut self,
+        path: &Path,
+        dest_dir: &str,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<String> {
+        let response = self.upload_file("push_file", path, Some(dest_dir), progress)?;
+        Ok(response
+            .get("path")
+            .and_then(|p| p.as_str())
+            .unwrap_or(dest_dir)
+            .to_string())
+    }
+
+    /// Upload an `.apk` or `.apks` bundle and install it with the package manager.
+    /// Returns the package manager output.
+    pub fn install_apk(&mut self, path: &Path, progress: impl FnMut(u64, u64)) -> Result<String> {
+        let response = self.upload_file("install_apk", path, None, progress)?;
+        Ok(response
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("Success")
+            .to_string())
+    }
+
     // ===== Internal =====
 
     fn send_command_async(&mut self, cmd: &str) -> Result<()> {
-        writeln!(self.input_stream, "{}", cmd)?;
-        self.input_stream.flush()?;
+        writeln!(self.input, "{}", cmd)?;
+        self.input.flush()?;
         Ok(())
     }
 
     fn send_command_sync(&mut self, cmd: &str) -> Result<String> {
-        writeln!(self.rpc_stream, "{}", cmd)?;
-        self.rpc_stream.flush()?;
+        writeln!(self.rpc_writer, "{}", cmd)?;
+        self.rpc_writer.flush()?;
 
-        let mut reader = BufReader::new(&self.rpc_stream);
         let mut response = String::new();
-        reader.read_line(&mut response)?;
+        self.rpc_reader.read_line(&mut response)?;
+        Ok(response)
+    }
+
+    /// Stream a file as base64 chunks over the RPC connection, waiting for an ack per chunk.
+    /// The device acts on the file once the last chunk has been written.
+    fn upload_file(
+        &mut self,
+        cmd: &str,
+        path: &Path,
+        dest_dir: Option<&str>,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<serde_json::Value> {
+        let previous_timeout = self.rpc_stream.read_timeout()?;
+        let result = self.upload_chunks(cmd, path, dest_dir, progress);
+        self.rpc_stream.set_read_timeout(previous_timeout)?;
+        result
+    }
+
+    fn upload_chunks(
+        &mut self,
+        cmd: &str,
+        path: &Path,
+        dest_dir: Option<&str>,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<serde_json::Value> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid file name: {:?}", path))?
+            .to_string();
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let total = size.div_ceil(Self::FILE_CHUNK_SIZE as u64).max(1);
+
+        let mut buf = vec![0u8; Self::FILE_CHUNK_SIZE];
+        let mut sent = 0u64;
+        let mut response = serde_json::Value::Null;
+
+        for index in 0..total {
+            let len = read_chunk(&mut file, &mut buf)?;
+            // Installing happens before the last chunk is acknowledged, which can take a while
+            let timeout_secs = if index == total - 1 { 120 } else { 10 };
+            self.rpc_stream
+                .set_read_timeout(Some(std::time::Duration::from_secs(timeout_secs)))?;
+
+            let mut payload = serde_json::json!({
+                "cmd": cmd,
+                "name": name,
+                "index": index,
+                "total": total,
+                "size": size,
+                "data": base64::engine::general_purpose::STANDARD.encode(&buf[..len]),
+            });
+            if let Some(dir) = dest_dir {
+                payload["dir"] = serde_json::Value::from(dir);
+            }
+
+            let line = self.send_command_sync(&payload.to_string())?;
+            response = serde_json::from_str(&line)
+                .map_err(|e| anyhow!("Invalid response to {}: {}", cmd, e))?;
+            if let Some(err) = response.get("error").and_then(|e| e.as_str()) {
+                return Err(anyhow!("{}", err));
+            }
+            if response.get("success").and_then(|s| s.as_bool()) == Some(false) {
+                let message = response.get("message").and_then(|m| m.as_str()).unwrap_or("");
+                return Err(anyhow!("{} failed: {}", cmd, message.trim()));
+            }
+
+            sent += len as u64;
+            progress(sent, size);
+        }
+
         Ok(response)
     }
 }
 
+/// Fill `buf` from `file`, returning the number of bytes read (short only at EOF)
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = file.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
+
 impl Drop for ControlClient {
     fn drop(&mut self) {
         // Signal drain thread to stop
         self.drain_running.store(false, Ordering::SeqCst);
 
         // Shutdown streams to unblock any blocking reads
-        let _ = self.input_stream.shutdown(std::net::Shutdown::Both);
-        let _ = self.rpc_stream.shutdown(std::net::Shutdown::Both);
+        self.input_stream.shutdown();
+        self.rpc_stream.shutdown();
 
         // Wait for drain thread to finish
         if let Some(handle) = self.drain_handle.take() {
@@ -181,3 +496,131 @@ impl Drop for ControlClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::net::TcpListener;
+
+    const CHUNK: usize = ControlClient::CLIPBOARD_CHUNK_SIZE;
+
+    /// A device on loopback: answers RPCs with `serve(index)` and returns the input commands
+    /// once the client is dropped
+    fn device(
+        serve: impl Fn(usize) -> Value + Send + 'static,
+    ) -> (ControlClient, JoinHandle<Vec<Value>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let device = thread::spawn(move || {
+            let (input, _) = listener.accept().unwrap();
+            let (rpc, _) = listener.accept().unwrap();
+            thread::spawn(move || {
+                let mut writer = rpc.try_clone().unwrap();
+                for line in BufReader::new(rpc).lines().map_while(Result::ok) {
+                    let request: Value = serde_json::from_str(&line).unwrap();
+                    let index = request["index"].as_u64().unwrap() as usize;
+                    if writeln!(writer, "{}", serve(index)).is_err() {
+                        break;
+                    }
+                }
+            });
+            BufReader::new(input)
+                .lines()
+                .map_while(Result::ok)
+                .map(|line| serde_json::from_str(&line).unwrap())
+                .collect()
+        });
+        let client = ControlClient::connect(&Endpoint::new("127.0.0.1"), port).unwrap();
+        (client, device)
+    }
+
+    /// Serves `png` in clipboard chunks, announcing `size` bytes in `total` chunks
+    fn image(png: Vec<u8>, size: usize, total: usize) -> impl Fn(usize) -> Value {
+        move |index| {
+            let chunk = png.chunks(CHUNK).nth(index).unwrap_or(&[]);
+            json!({
+                "total": total,
+                "size": size,
+                "data": base64::engine::general_purpose::STANDARD.encode(chunk),
+            })
+        }
+    }
+
+    fn png(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn set_clipboard_image_is_sent_in_chunks() {
+        let (mut client, device) = device(|_| json!({}));
+        let png = png(CHUNK * 2 + 100);
+        client.set_clipboard_image(&png, true).unwrap();
+        drop(client);
+
+        let commands = device.join().unwrap();
+        assert_eq!(commands.len(), 3);
+        let mut received = Vec::new();
+        for (index, command) in commands.iter().enumerate() {
+            assert_eq!(command["cmd"], "set_clipboard_image");
+            assert_eq!(command["index"], index);
+            assert_eq!(command["total"], 3);
+            assert_eq!(command["size"], png.len());
+            assert_eq!(command["paste"], true);
+            let chunk = base64::engine::general_purpose::STANDARD
+                .decode(command["data"].as_str().unwrap())
+                .unwrap();
+            assert_eq!(chunk.len(), if index < 2 { CHUNK } else { 100 });
+            received.extend(chunk);
+        }
+        assert_eq!(received, png);
+    }
+
+    #[test]
+    fn set_clipboard_image_rejects_empty_and_oversized_images() {
+        let (mut client, device) = device(|_| json!({}));
+        assert!(client.set_clipboard_image(&[], false).is_err());
+        let error = client
+            .set_clipboard_image(&png(ControlClient::MAX_CLIPBOARD_IMAGE_SIZE + 1), false)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Clipboard image too large"));
+        drop(client);
+        assert!(device.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn clipboard_image_chunks_are_reassembled() {
+        let png = png(CHUNK + 10);
+        let (mut client, _device) = device(image(png.clone(), png.len(), 2));
+        assert_eq!(client.get_clipboard_image().unwrap(), Some(png));
+    }
+
+    #[test]
+    fn no_clipboard_image() {
+        let (mut client, _device) = device(image(Vec::new(), 0, 0));
+        assert_eq!(client.get_clipboard_image().unwrap(), None);
+    }
+
+    #[test]
+    fn clipboard_image_over_the_limit_is_rejected() {
+        let size = ControlClient::MAX_CLIPBOARD_IMAGE_SIZE + 1;
+        let (mut client, _device) = device(image(png(CHUNK), size, size.div_ceil(CHUNK)));
+        let error = client.get_clipboard_image().unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Device clipboard image too large"));
+    }
+
+    #[test]
+    fn clipboard_image_must_match_the_announced_size() {
+        // More bytes than announced
+        let (mut client, _device) = device(image(png(CHUNK + 10), CHUNK + 5, 2));
+        let error = client.get_clipboard_image().unwrap_err();
+        assert!(error.to_string().contains("larger than announced"));
+
+        // The last chunk arrived short of the announced size
+        let (mut client, _device) = device(image(png(CHUNK + 10), CHUNK + 10, 1));
+        let error = client.get_clipboard_image().unwrap_err();
+        assert!(error.to_string().contains("incomplete"));
+    }
+}
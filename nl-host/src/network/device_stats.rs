@@ -0,0 +1,90 @@
+//! Periodic polling of device stats for the HUD
+
+use crate::core::DeviceStats;
+use crate::network::{ControlClient, Endpoint};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Poll `stats` on a dedicated control connection while `enabled` is set (HUD visible).
+/// The latest result is stored in `out`.
+pub fn start_device_stats_thread(
+    endpoint: Endpoint,
+    port: u16,
+    enabled: Arc<AtomicBool>,
+    out: Arc<Mutex<Option<DeviceStats>>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut client: Option<ControlClient> = None;
+        // (used, total) CPU jiffies from the previous poll
+        let mut last_cpu: Option<(u64, u64)> = None;
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if !enabled.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            if client.is_none() {
+                match ControlClient::connect(&endpoint, port) {
+                    Ok(c) => client = Some(c),
+                    Err(e) => {
+                        log_verbose!("STATS", "Connect failed: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some(c) = client.as_mut() else {
+                continue;
+            };
+
+            match c.get_stats() {
+                Ok(response) => {
+                    let stats = parse_stats(&response, &mut last_cpu);
+                    *out.lock().unwrap() = stats;
+                }
+                Err(e) => {
+                    log_verbose!("STATS", "Poll failed: {}", e);
+                    client = None;
+                }
+            }
+        }
+    })
+}
+
+/// Parse `{"cmd":"stats","data":{"cpu":{...},"memory":{...}}}`
+fn parse_stats(response: &str, last_cpu: &mut Option<(u64, u64)>) -> Option<DeviceStats> {
+    let json: serde_json::Value = serde_json::from_str(response.trim()).ok()?;
+    let data = json.get("data")?;
+
+    // The device reports cumulative jiffies; the usage is the delta between polls
+    let cpu = data.get("cpu");
+    let used = cpu.and_then(|c| c.get("used")).and_then(|v| v.as_u64());
+    let total = cpu.and_then(|c| c.get("total")).and_then(|v| v.as_u64());
+    let cpu_percent = match (used, total, *last_cpu) {
+        (Some(used), Some(total), Some((last_used, last_total))) if total > last_total => {
+            Some(used.saturating_sub(last_used) as f64 * 100.0 / (total - last_total) as f64)
+        }
+        _ => None,
+    };
+    if let (Some(used), Some(total)) = (used, total) {
+        *last_cpu = Some((used, total));
+    }
+
+    let memory = data.get("memory");
+    let memory_field = |key: &str| {
+        memory
+            .and_then(|m| m.get(key))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+    };
+
+    Some(DeviceStats {
+        cpu_percent,
+        memory_used: memory_field("used"),
+        memory_max: memory_field("max"),
+    })
+}
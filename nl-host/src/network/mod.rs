@@ -1,7 +1,22 @@
 //! Network module - Communication with Android device
 
+pub mod adaptive;
+mod clock_sync;
 mod control;
+pub mod device_stats;
+mod mux;
+pub mod secure;
 pub mod stream;
+pub mod transfer;
+pub mod transport;
+pub mod udp;
+mod video_settings;
 
+pub use adaptive::{start_adaptive_thread, AdaptiveBounds};
+pub use clock_sync::start_clock_sync_thread;
 pub use control::ControlClient;
-pub use stream::{start_video_receiver, VideoReceiverHandle};
+pub use device_stats::start_device_stats_thread;
+pub use stream::{start_video_receiver, VideoPacket, VideoReceiverHandle};
+pub use transfer::{start_transfer_thread, TransferStatus};
+pub use transport::Endpoint;
+pub use video_settings::VideoSettings;
@@ -0,0 +1,580 @@
+//! Video, audio and control over a single socket (`--mux`)
+//!
+//! The session connects to the video port, sends a `mux` handshake line (after the secure
+//! handshake when a key is set), then carries frames in both directions:
+//!
+//! ```text
+//! kind (1) | channel (2, BE) | length (4, BE) | payload
+//! ```
+//!
+//! OPEN (payload: the service) starts a channel, DATA carries a piece of its byte stream and
+//! CLOSE ends it. Every channel behaves like its own connection to that service's port, so the
+//! protocols on top are unchanged. Must match nl-android's `MuxSession`.
+
+use crate::network::secure::{self, LinkWriter, Psk};
+use crate::network::transport::Service;
+use crossbeam_channel::{
+    bounded, Receiver, RecvTimeoutError, SendTimeoutError, Sender, TrySendError,
+};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+const HELLO: &[u8] = b"mux\n";
+const OPEN: u8 = 0;
+const DATA: u8 = 1;
+const CLOSE: u8 = 2;
+const HEADER_SIZE: usize = 7;
+/// Largest payload per frame; bigger writes are split
+const MAX_FRAME: usize = 64 * 1024;
+/// Frames queued per channel (up to 4 MiB). Once the video backlog is full the demux waits
+/// for its reader, so like a TCP window the socket is held back instead of the queue
+/// growing. Any other channel that falls this far behind is closed instead, so a consumer
+/// that stopped reading audio cannot stall video and control.
+const CHANNEL_BACKLOG: usize = 64;
+/// How often a demux waiting on a full channel checks whether the channel was closed
+const BACKLOG_POLL: Duration = Duration::from_millis(100);
+
+/// Where the demux delivers a channel's incoming data
+struct Route {
+    /// Dropping the last sender ends the channel's reader
+    tx: Sender<Vec<u8>>,
+    service: Service,
+    shared: Weak<ChannelShared>,
+}
+
+struct Session {
+    stream: TcpStream,
+    writer: Mutex<LinkWriter>,
+    channels: Mutex<HashMap<u16, Route>>,
+    next_id: Mutex<u16>,
+    alive: AtomicBool,
+}
+
+impl Session {
+    fn connect(endpoint: &str, psk: Option<&Psk>, timeout: Duration) -> io::Result<Arc<Self>> {
+        let addr = endpoint
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for device"))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_nodelay(true)?;
+        let (mut reader, mut writer) = secure::open(&stream, psk)?;
+        writer.write_all(HELLO)?;
+        writer.flush()?;
+        log_verbose!("MUX", "Connected {}", endpoint);
+
+        let session = Arc::new(Self {
+            stream,
+            writer: Mutex::new(writer),
+            channels: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(1),
+            alive: AtomicBool::new(true),
+        });
+
+        let demux = session.clone();
+        thread::spawn(move || {
+            if let Err(e) = demux.receive(&mut reader) {
+                log_verbose!("MUX", "Connection lost: {}", e);
+            }
+            demux.shutdown();
+            // Every open channel sees end of stream
+            demux.channels.lock().unwrap().clear();
+        });
+        Ok(session)
+    }
+
+    /// Fail every channel; the demux thread ends and drops them
+    fn shutdown(&self) {
+        self.alive.store(false, Ordering::SeqCst);
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    /// Route incoming frames to their channels until the socket fails
+    fn receive(&self, reader: &mut impl Read) -> io::Result<()> {
+        loop {
+            let (kind, id, payload) = read_frame(reader)?;
+            match kind {
+                DATA => {
+                    // Not sent under the lock: this may wait for the reader
+                    let route = self
+                        .channels
+                        .lock()
+                        .unwrap()
+                        .get(&id)
+                        .map(|route| (route.tx.clone(), route.service, route.shared.clone()));
+                    match route {
+                        Some((tx, Service::Video, _)) => self.deliver(id, &tx, payload),
+                        Some((tx, service, shared)) => {
+                            if let Err(TrySendError::Full(_)) = tx.try_send(payload) {
+                                log_info!(
+                                    "MUX",
+                                    "{:?} channel {} is not being read, closing it",
+                                    service,
+                                    id
+                                );
+                                if let Some(shared) = shared.upgrade() {
+                                    shared.close();
+                                }
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                CLOSE => {
+                    self.channels.lock().unwrap().remove(&id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Queue `payload` for a channel, waiting while its backlog is full. Gives up once the
+    /// channel is closed, so a reader that stopped reading cannot stall the other channels.
+    fn deliver(&self, id: u16, tx: &Sender<Vec<u8>>, mut payload: Vec<u8>) {
+        loop {
+            match tx.send_timeout(payload, BACKLOG_POLL) {
+                Ok(()) | Err(SendTimeoutError::Disconnected(_)) => return,
+                Err(SendTimeoutError::Timeout(rejected)) => {
+                    let open = self
+                        .channels
+                        .lock()
+                        .unwrap()
+                        .get(&id)
+                        .is_some_and(|current| current.tx.same_channel(tx));
+                    if !open {
+                        return;
+                    }
+                    payload = rejected;
+                }
+            }
+        }
+    }
+
+    fn send(&self, kind: u8, id: u16, payload: &[u8]) -> io::Result<()> {
+        if !self.alive.load(Ordering::SeqCst) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let mut writer = self.writer.lock().unwrap();
+        write_frame(&mut *writer, kind, id, payload)?;
+        writer.flush()
+    }
+
+    fn open_channel(
+        self: &Arc<Self>,
+        service: Service,
+    ) -> io::Result<(Channel, ChannelReader, ChannelWriter)> {
+        let (tx, rx) = bounded(CHANNEL_BACKLOG);
+        let shared = {
+            let mut channels = self.channels.lock().unwrap();
+            let mut next_id = self.next_id.lock().unwrap();
+            while channels.contains_key(&*next_id) {
+                *next_id = next_id.wrapping_add(1).max(1);
+            }
+            let id = *next_id;
+            *next_id = next_id.wrapping_add(1).max(1);
+            let shared = Arc::new(ChannelShared {
+                session: self.clone(),
+                id,
+                timeout: Mutex::new(None),
+                closed: AtomicBool::new(false),
+            });
+            let route = Route {
+                tx,
+                service,
+                shared: Arc::downgrade(&shared),
+            };
+            channels.insert(id, route);
+            shared
+        };
+        let id = shared.id;
+        let channel = Channel {
+            shared: shared.clone(),
+        };
+        self.send(OPEN, id, &[service as u8])?;
+
+        let reader = ChannelReader {
+            shared: shared.clone(),
+            rx,
+            buf: Vec::new(),
+            pos: 0,
+        };
+        let writer = ChannelWriter {
+            shared,
+            pending: Vec::new(),
+        };
+        Ok((channel, reader, writer))
+    }
+}
+
+fn write_frame(writer: &mut impl Write, kind: u8, id: u16, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    frame.push(kind);
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+/// Next frame as (kind, channel, payload)
+fn read_frame(reader: &mut impl Read) -> io::Result<(u8, u16, Vec<u8>)> {
+    let mut header = [0u8; HEADER_SIZE];
+    reader.read_exact(&mut header)?;
+    let id = u16::from_be_bytes([header[1], header[2]]);
+    let length = u32::from_be_bytes(header[3..7].try_into().unwrap()) as usize;
+    if length > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid frame length {}", length),
+        ));
+    }
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload)?;
+    Ok((header[0], id, payload))
+}
+
+/// The shared socket of one mirror session, connected by its first channel and reconnected
+/// on demand once it is lost. It is closed by `close` or when the last `Endpoint` clone goes.
+pub struct Multiplexer {
+    endpoint: String,
+    session: Mutex<Option<Arc<Session>>>,
+    closed: AtomicBool,
+}
+
+impl Multiplexer {
+    /// Channels will go to `endpoint` (`host:port` of the video port)
+    pub fn new(endpoint: String) -> Self {
+        Self {
+            endpoint,
+            session: Mutex::new(None),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Close the socket for good: open channels see end of stream, new ones fail
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(session) = self.session.lock().unwrap().take() {
+            session.shutdown();
+        }
+    }
+
+    /// Open a channel to `service`, connecting the socket (encrypted with `psk`) if needed
+    pub fn open(
+        &self,
+        psk: Option<&Psk>,
+        service: Service,
+        timeout: Duration,
+    ) -> io::Result<(Channel, ChannelReader, ChannelWriter)> {
+        let session = {
+            let mut current = self.session.lock().unwrap();
+            if self.closed.load(Ordering::SeqCst) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "session closed",
+                ));
+            }
+            match current.as_ref() {
+                Some(session) if session.alive.load(Ordering::SeqCst) => session.clone(),
+                _ => {
+                    let session = Session::connect(&self.endpoint, psk, timeout)?;
+                    *current = Some(session.clone());
+                    session
+                }
+            }
+        };
+
+        session.open_channel(service)
+    }
+}
+
+impl Drop for Multiplexer {
+    fn drop(&mut self) {
+        // Ends the demux thread, which would otherwise keep the socket open
+        if let Some(session) = self.session.get_mut().unwrap().take() {
+            session.shutdown();
+        }
+    }
+}
+
+struct ChannelShared {
+    session: Arc<Session>,
+    id: u16,
+    timeout: Mutex<Option<Duration>>,
+    closed: AtomicBool,
+}
+
+impl ChannelShared {
+    fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.session.channels.lock().unwrap().remove(&self.id);
+        let _ = self.session.send(CLOSE, self.id, &[]);
+    }
+}
+
+/// Owner of a channel: timeouts and shutdown, like the `TcpStream` of a plain connection.
+/// The channel is closed when this is dropped.
+pub struct Channel {
+    shared: Arc<ChannelShared>,
+}
+
+impl Channel {
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        *self.shared.timeout.lock().unwrap() = timeout;
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        *self.shared.timeout.lock().unwrap()
+    }
+
+    /// Address of the device end of the shared socket
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.shared.session.stream.peer_addr()
+    }
+
+    /// Close the channel; pending reads see end of stream
+    pub fn shutdown(&self) {
+        self.shared.close();
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+/// Reading half of a channel
+pub struct ChannelReader {
+    shared: Arc<ChannelShared>,
+    rx: Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            return Ok(0);
+        }
+        while self.pos == self.buf.len() {
+            let timeout = *self.shared.timeout.lock().unwrap();
+            let next = match timeout {
+                Some(timeout) => match self.rx.recv_timeout(timeout) {
+                    Ok(data) => data,
+                    Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match self.rx.recv() {
+                    Ok(data) => data,
+                    Err(_) => return Ok(0),
+                },
+            };
+            self.buf = next;
+            self.pos = 0;
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Writing half of a channel. Data is only sent on `flush` (or once a full frame is buffered).
+pub struct ChannelWriter {
+    shared: Arc<ChannelShared>,
+    pending: Vec<u8>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.pending.extend_from_slice(buf);
+        while self.pending.len() >= MAX_FRAME {
+            self.shared
+                .session
+                .send(DATA, self.shared.id, &self.pending[..MAX_FRAME])?;
+            self.pending.drain(..MAX_FRAME);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.shared
+            .session
+            .send(DATA, self.shared.id, &self.pending)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// A session connected to a loopback "device", which has read the hello line
+    fn connect() -> (Arc<Session>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = listener.local_addr().unwrap().to_string();
+        let session = Session::connect(&endpoint, None, Duration::from_secs(1)).unwrap();
+        let (mut device, _) = listener.accept().unwrap();
+        device
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut hello = [0u8; HELLO.len()];
+        device.read_exact(&mut hello).unwrap();
+        assert_eq!(hello, HELLO);
+        (session, device)
+    }
+
+    fn open(
+        session: &Arc<Session>,
+        device: &mut TcpStream,
+        service: Service,
+    ) -> (Channel, ChannelReader, ChannelWriter) {
+        let opened = session.open_channel(service).unwrap();
+        let (kind, id, payload) = read_frame(device).unwrap();
+        assert_eq!(
+            (kind, id, payload),
+            (OPEN, opened.0.shared.id, vec![service as u8])
+        );
+        opened
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, DATA, 0x1234, b"hello").unwrap();
+        write_frame(&mut wire, CLOSE, 7, &[]).unwrap();
+        assert_eq!(&wire[..HEADER_SIZE], &[DATA, 0x12, 0x34, 0, 0, 0, 5]);
+
+        let mut reader = &wire[..];
+        assert_eq!(
+            read_frame(&mut reader).unwrap(),
+            (DATA, 0x1234, b"hello".to_vec())
+        );
+        assert_eq!(read_frame(&mut reader).unwrap(), (CLOSE, 7, Vec::new()));
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn oversized_length_is_rejected() {
+        let mut wire = vec![DATA, 0, 1];
+        wire.extend_from_slice(&(MAX_FRAME as u32 + 1).to_be_bytes());
+        wire.resize(wire.len() + MAX_FRAME + 1, 0);
+        let error = read_frame(&mut &wire[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // The largest allowed payload still goes through
+        let mut wire = Vec::new();
+        write_frame(&mut wire, DATA, 1, &vec![0xAB; MAX_FRAME]).unwrap();
+        assert_eq!(read_frame(&mut &wire[..]).unwrap().2.len(), MAX_FRAME);
+    }
+
+    #[test]
+    fn writes_are_split_at_max_frame() {
+        let (session, mut device) = connect();
+        let (channel, _reader, mut writer) = open(&session, &mut device, Service::Video);
+        let id = channel.shared.id;
+
+        let data: Vec<u8> = (0..MAX_FRAME * 2 + 10).map(|i| i as u8).collect();
+        writer.write_all(&data).unwrap();
+        // Only whole frames are sent before the flush
+        writer.flush().unwrap();
+
+        let mut received = Vec::new();
+        for expected in [MAX_FRAME, MAX_FRAME, 10] {
+            let (kind, frame_id, payload) = read_frame(&mut device).unwrap();
+            assert_eq!((kind, frame_id, payload.len()), (DATA, id, expected));
+            received.extend_from_slice(&payload);
+        }
+        assert_eq!(received, data);
+
+        drop(channel);
+        assert_eq!(read_frame(&mut device).unwrap(), (CLOSE, id, Vec::new()));
+        // Writing to a closed channel fails
+        assert!(writer.write(b"x").is_err());
+    }
+
+    #[test]
+    fn close_ends_the_reader() {
+        let (session, mut device) = connect();
+        let (channel, mut reader, _writer) = open(&session, &mut device, Service::Control);
+        let id = channel.shared.id;
+
+        write_frame(&mut device, DATA, id, b"abc").unwrap();
+        write_frame(&mut device, DATA, id, b"def").unwrap();
+        write_frame(&mut device, CLOSE, id, &[]).unwrap();
+
+        // Data queued before the CLOSE is still read, then end of stream
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"abcdef");
+        assert_eq!(reader.read(&mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn full_channel_does_not_stall_others_once_closed() {
+        let (session, mut device) = connect();
+        let (video, video_reader, _) = open(&session, &mut device, Service::Video);
+        let (control, mut control_reader, _) = open(&session, &mut device, Service::Control);
+
+        // The video reader never reads: the demux waits once its backlog is full
+        for _ in 0..=CHANNEL_BACKLOG {
+            write_frame(&mut device, DATA, video.shared.id, &[0u8; 16]).unwrap();
+        }
+        write_frame(&mut device, DATA, control.shared.id, b"ping").unwrap();
+        control.set_read_timeout(Some(BACKLOG_POLL * 2));
+        let mut buf = [0u8; 4];
+        let error = control_reader.read(&mut buf).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        // Closing the video channel lets the control data through
+        drop(video);
+        control.set_read_timeout(Some(Duration::from_secs(5)));
+        control_reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        drop(video_reader);
+    }
+
+    #[test]
+    fn full_audio_channel_is_closed_instead_of_stalling() {
+        let (session, mut device) = connect();
+        let (audio, mut audio_reader, mut audio_writer) =
+            open(&session, &mut device, Service::Audio);
+        let (control, mut control_reader, _) = open(&session, &mut device, Service::Control);
+        let id = audio.shared.id;
+
+        // The audio reader never reads: one frame past its backlog closes the channel
+        for i in 0..=CHANNEL_BACKLOG {
+            write_frame(&mut device, DATA, id, &[i as u8; 16]).unwrap();
+        }
+        write_frame(&mut device, DATA, control.shared.id, b"ping").unwrap();
+        control.set_read_timeout(Some(Duration::from_secs(5)));
+        let mut buf = [0u8; 4];
+        control_reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The device is told, and the reader gets what was queued, then end of stream
+        assert_eq!(read_frame(&mut device).unwrap(), (CLOSE, id, Vec::new()));
+        let mut received = Vec::new();
+        audio_reader.read_to_end(&mut received).unwrap();
+        assert_eq!(received.len(), CHANNEL_BACKLOG * 16);
+        assert!(audio_writer.write(b"x").is_err());
+    }
+}
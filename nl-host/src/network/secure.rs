@@ -0,0 +1,497 @@
+//! Optional authenticated encryption for the video, audio and control connections
+//!
+//! With a pre-shared key (from launcher pairing, see `--key-file`) every connection starts
+//! with a mutual proof of the key, then carries AES-256-GCM records:
+//!
+//! ```text
+//! host   -> device: "NLS1" | client nonce (32)
+//! device -> host:   server nonce (32) | HMAC(psk, "nl-server" | nonces)
+//! host   -> device: HMAC(psk, "nl-client" | nonces)
+//! record:           length (4, BE) | AES-256-GCM(payload) with a per-direction counter nonce
+//! ```
+//!
+//! Each direction uses its own key derived with HKDF-SHA256 from the PSK and both nonces.
+//! Must match nl-android's `SecureTransport`.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Context, Result};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"NLS1";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+const MAC_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+/// Largest plaintext per record; bigger writes are split
+const MAX_RECORD: usize = 64 * 1024;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pre-shared key, pinned per device by the launcher
+pub struct Psk([u8; KEY_LEN]);
+
+impl Psk {
+    /// Parse 64 hex characters
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+            return Err(anyhow!("Key must be {} hex characters", KEY_LEN * 2));
+        }
+        let mut key = [0u8; KEY_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| anyhow!("Key must be hex"))?;
+        }
+        Ok(Self(key))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let hex = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?;
+        Self::from_hex(&hex)
+    }
+}
+
+/// Reading and writing halves of a device connection: the socket itself, or the encrypted
+/// channel after a successful handshake with `psk`. Both halves share the socket, so
+/// timeouts and shutdown still go through the `TcpStream`.
+pub fn open(stream: &TcpStream, psk: Option<&Psk>) -> io::Result<(LinkReader, LinkWriter)> {
+    let Some(psk) = psk else {
+        return Ok((
+            LinkReader {
+                stream: stream.try_clone()?,
+                secure: None,
+            },
+            LinkWriter {
+                stream: stream.try_clone()?,
+                secure: None,
+            },
+        ));
+    };
+
+    let previous_timeout = stream.read_timeout()?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let (send_key, recv_key) = handshake(stream, psk)?;
+    stream.set_read_timeout(previous_timeout)?;
+
+    Ok((
+        LinkReader {
+            stream: stream.try_clone()?,
+            secure: Some(RecvState {
+                cipher: Aes256Gcm::new(&recv_key.into()),
+                counter: 0,
+                record: Vec::new(),
+                plain: Vec::new(),
+                pos: 0,
+            }),
+        },
+        LinkWriter {
+            stream: stream.try_clone()?,
+            secure: Some(SendState {
+                cipher: Aes256Gcm::new(&send_key.into()),
+                counter: 0,
+                pending: Vec::new(),
+            }),
+        },
+    ))
+}
+
+/// Mutual proof of the PSK; returns the (host -> device, device -> host) keys
+fn handshake(mut stream: &TcpStream, psk: &Psk) -> io::Result<([u8; KEY_LEN], [u8; KEY_LEN])> {
+    let mut client_nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut client_nonce).map_err(|e| io::Error::other(e.to_string()))?;
+    let mut hello = MAGIC.to_vec();
+    hello.extend_from_slice(&client_nonce);
+    stream.write_all(&hello)?;
+    stream.flush()?;
+
+    let mut reply = [0u8; NONCE_LEN + MAC_LEN];
+    stream.read_exact(&mut reply).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "device closed the connection during the secure handshake (no key pushed?)",
+        ),
+        _ => e,
+    })?;
+    let (server_nonce, server_mac) = reply.split_at(NONCE_LEN);
+
+    let mut salt = client_nonce.to_vec();
+    salt.extend_from_slice(server_nonce);
+
+    if keyed_mac(psk, b"nl-server", &salt)
+        .verify_slice(server_mac)
+        .is_err()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "device failed authentication (wrong key, or the device is not in secure mode)",
+        ));
+    }
+    let client_mac = keyed_mac(psk, b"nl-client", &salt).finalize().into_bytes();
+    stream.write_all(&client_mac)?;
+    stream.flush()?;
+
+    session_keys(psk, &salt)
+}
+
+/// (host -> device, device -> host) record keys for the nonces in `salt`
+fn session_keys(psk: &Psk, salt: &[u8]) -> io::Result<([u8; KEY_LEN], [u8; KEY_LEN])> {
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), &psk.0);
+    let mut c2s = [0u8; KEY_LEN];
+    let mut s2c = [0u8; KEY_LEN];
+    hkdf.expand(b"nl-mirror c2s", &mut c2s)
+        .and_then(|_| hkdf.expand(b"nl-mirror s2c", &mut s2c))
+        .map_err(|_| io::Error::other("key derivation failed"))?;
+    Ok((c2s, s2c))
+}
+
+fn keyed_mac(psk: &Psk, label: &[u8], nonces: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(&psk.0).expect("HMAC accepts any key length");
+    mac.update(label);
+    mac.update(nonces);
+    mac
+}
+
+/// 4 zero bytes followed by the big-endian record counter
+fn record_nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+struct RecvState {
+    cipher: Aes256Gcm,
+    counter: u64,
+    /// Raw bytes of the record being received (kept across read timeouts)
+    record: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+impl RecvState {
+    /// Receive and decrypt the next record; false at end of stream
+    fn fill(&mut self, mut stream: &TcpStream) -> io::Result<bool> {
+        loop {
+            let wanted = if self.record.len() < 4 {
+                4
+            } else {
+                let length = u32::from_be_bytes(self.record[..4].try_into().unwrap()) as usize;
+                if !(TAG_LEN..=MAX_RECORD + TAG_LEN).contains(&length) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid record length {}", length),
+                    ));
+                }
+                4 + length
+            };
+            if self.record.len() == wanted {
+                break;
+            }
+
+            let mut buf = [0u8; 16 * 1024];
+            let n = (wanted - self.record.len()).min(buf.len());
+            // A timeout here leaves the partial record in place for the next call
+            match stream.read(&mut buf[..n])? {
+                0 if self.record.is_empty() => return Ok(false),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => self.record.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let nonce = record_nonce(self.counter);
+        self.counter += 1;
+        self.plain = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), &self.record[4..])
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "record failed authentication")
+            })?;
+        self.pos = 0;
+        self.record.clear();
+        Ok(true)
+    }
+}
+
+/// Reading half of a device connection
+pub struct LinkReader {
+    stream: TcpStream,
+    secure: Option<RecvState>,
+}
+
+impl Read for LinkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(state) = &mut self.secure else {
+            return self.stream.read(buf);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while state.pos == state.plain.len() {
+            if !state.fill(&self.stream)? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(state.plain.len() - state.pos);
+        buf[..n].copy_from_slice(&state.plain[state.pos..state.pos + n]);
+        state.pos += n;
+        Ok(n)
+    }
+}
+
+struct SendState {
+    cipher: Aes256Gcm,
+    counter: u64,
+    /// Plaintext collected until the next flush
+    pending: Vec<u8>,
+}
+
+impl SendState {
+    /// Encrypt and send up to `MAX_RECORD` bytes from the front of `pending`
+    fn seal(&mut self, mut stream: &TcpStream) -> io::Result<()> {
+        let n = self.pending.len().min(MAX_RECORD);
+        let nonce = record_nonce(self.counter);
+        self.counter += 1;
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), &self.pending[..n])
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        let mut record = Vec::with_capacity(4 + sealed.len());
+        record.extend_from_slice(&(sealed.len() as u32).to_be_bytes());
+        record.extend_from_slice(&sealed);
+        stream.write_all(&record)?;
+        self.pending.drain(..n);
+        Ok(())
+    }
+}
+
+/// Writing half of a device connection. Encrypted data is only sent on `flush`
+/// (or once a full record is buffered).
+pub struct LinkWriter {
+    stream: TcpStream,
+    secure: Option<SendState>,
+}
+
+impl Write for LinkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(state) = &mut self.secure else {
+            return self.stream.write(buf);
+        };
+        state.pending.extend_from_slice(buf);
+        while state.pending.len() >= MAX_RECORD {
+            state.seal(&self.stream)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(state) = &mut self.secure {
+            while !state.pending.is_empty() {
+                state.seal(&self.stream)?;
+            }
+        }
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn psk(byte: u8) -> Psk {
+        Psk([byte; KEY_LEN])
+    }
+
+    /// Connected (host, device) sockets
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (device, _) = listener.accept().unwrap();
+        for stream in [&host, &device] {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+        }
+        (host, device)
+    }
+
+    /// Device side of the handshake, like `SecureTransport`; returns its (reader, writer)
+    fn accept(mut stream: &TcpStream, psk: &Psk) -> io::Result<(LinkReader, LinkWriter)> {
+        let mut hello = [0u8; 4 + NONCE_LEN];
+        stream.read_exact(&mut hello)?;
+        assert_eq!(&hello[..4], MAGIC);
+        let server_nonce = [0x5A; NONCE_LEN];
+        let mut salt = hello[4..].to_vec();
+        salt.extend_from_slice(&server_nonce);
+
+        let mut reply = server_nonce.to_vec();
+        reply.extend(keyed_mac(psk, b"nl-server", &salt).finalize().into_bytes());
+        stream.write_all(&reply)?;
+        let mut client_mac = [0u8; MAC_LEN];
+        stream.read_exact(&mut client_mac)?;
+        keyed_mac(psk, b"nl-client", &salt)
+            .verify_slice(&client_mac)
+            .map_err(|_| io::Error::from(io::ErrorKind::PermissionDenied))?;
+
+        let (c2s, s2c) = session_keys(psk, &salt)?;
+        Ok((reader(stream, c2s), writer(stream, s2c)))
+    }
+
+    fn reader(stream: &TcpStream, key: [u8; KEY_LEN]) -> LinkReader {
+        LinkReader {
+            stream: stream.try_clone().unwrap(),
+            secure: Some(RecvState {
+                cipher: Aes256Gcm::new(&key.into()),
+                counter: 0,
+                record: Vec::new(),
+                plain: Vec::new(),
+                pos: 0,
+            }),
+        }
+    }
+
+    fn writer(stream: &TcpStream, key: [u8; KEY_LEN]) -> LinkWriter {
+        LinkWriter {
+            stream: stream.try_clone().unwrap(),
+            secure: Some(SendState {
+                cipher: Aes256Gcm::new(&key.into()),
+                counter: 0,
+                pending: Vec::new(),
+            }),
+        }
+    }
+
+    /// Run the handshake on both ends: the host's halves and the device's halves
+    fn connect(host_psk: Psk, device_psk: Psk) -> io::Result<[(LinkReader, LinkWriter); 2]> {
+        let (host, device) = pair();
+        let device = thread::spawn(move || accept(&device, &device_psk));
+        let host = open(&host, Some(&host_psk));
+        let device = device.join().unwrap();
+        Ok([host?, device?])
+    }
+
+    fn read_record(mut stream: &TcpStream) -> Vec<u8> {
+        let mut length = [0u8; 4];
+        stream.read_exact(&mut length).unwrap();
+        let mut record = length.to_vec();
+        record.resize(4 + u32::from_be_bytes(length) as usize, 0);
+        stream.read_exact(&mut record[4..]).unwrap();
+        record
+    }
+
+    /// What the host's reader makes of `wire` (raw records sent by the "device")
+    fn host_reads(wire: &[u8], key: [u8; KEY_LEN]) -> io::Result<Vec<u8>> {
+        let (host, mut device) = pair();
+        device.write_all(wire).unwrap();
+        drop(device);
+        let mut received = Vec::new();
+        reader(&host, key).read_to_end(&mut received)?;
+        Ok(received)
+    }
+
+    #[test]
+    fn round_trip_across_record_split() {
+        let [(mut host_reader, mut host_writer), (mut device_reader, mut device_writer)] =
+            connect(psk(1), psk(1)).unwrap();
+
+        let data: Vec<u8> = (0..MAX_RECORD * 2 + 5).map(|i| (i * 7) as u8).collect();
+        host_writer.write_all(&data).unwrap();
+        host_writer.flush().unwrap();
+        let mut received = vec![0u8; data.len()];
+        device_reader.read_exact(&mut received).unwrap();
+        assert_eq!(received, data);
+
+        device_writer.write_all(b"pong").unwrap();
+        device_writer.flush().unwrap();
+        let mut pong = [0u8; 4];
+        host_reader.read_exact(&mut pong).unwrap();
+        assert_eq!(&pong, b"pong");
+    }
+
+    #[test]
+    fn writes_are_sealed_in_records_of_max_record() {
+        let (host, device) = pair();
+        let key = [3u8; KEY_LEN];
+        let mut host_writer = writer(&host, key);
+        host_writer.write_all(&vec![0xAB; MAX_RECORD + 10]).unwrap();
+        // Full records go out right away, the rest on flush
+        let first = read_record(&device);
+        host_writer.flush().unwrap();
+        let second = read_record(&device);
+        assert_eq!(first.len(), 4 + MAX_RECORD + TAG_LEN);
+        assert_eq!(second.len(), 4 + 10 + TAG_LEN);
+
+        let received = host_reads(&[first, second].concat(), key).unwrap();
+        assert_eq!(received, vec![0xAB; MAX_RECORD + 10]);
+    }
+
+    #[test]
+    fn wrong_psk_is_rejected() {
+        let error = connect(psk(1), psk(2)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn tampered_record_fails_authentication() {
+        let (device, wire) = pair();
+        let key = [4u8; KEY_LEN];
+        let mut device_writer = writer(&device, key);
+        device_writer.write_all(b"hello").unwrap();
+        device_writer.flush().unwrap();
+        let mut record = read_record(&wire);
+        assert_eq!(host_reads(&record, key).unwrap(), b"hello");
+
+        let last = record.len() - 1;
+        record[last] ^= 1;
+        let error = host_reads(&record, key).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "record failed authentication");
+    }
+
+    #[test]
+    fn replayed_record_fails_authentication() {
+        let (device, wire) = pair();
+        let key = [5u8; KEY_LEN];
+        let mut device_writer = writer(&device, key);
+        device_writer.write_all(b"once").unwrap();
+        device_writer.flush().unwrap();
+        let record = read_record(&wire);
+
+        // The second copy is checked against the next counter nonce
+        let (host, mut device) = pair();
+        device
+            .write_all(&[record.clone(), record].concat())
+            .unwrap();
+        let mut host_reader = reader(&host, key);
+        let mut buf = [0u8; 4];
+        host_reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"once");
+        let error = host_reader.read(&mut buf).unwrap_err();
+        assert_eq!(error.to_string(), "record failed authentication");
+    }
+
+    #[test]
+    fn out_of_range_lengths_are_rejected() {
+        let key = [6u8; KEY_LEN];
+        for length in [0, TAG_LEN - 1, MAX_RECORD + TAG_LEN + 1, u32::MAX as usize] {
+            let mut wire = (length as u32).to_be_bytes().to_vec();
+            wire.extend_from_slice(&[0u8; 64]);
+            let error = host_reads(&wire, key).unwrap_err();
+            assert_eq!(
+                error.kind(),
+                io::ErrorKind::InvalidData,
+                "length {}",
+                length
+            );
+            assert!(error.to_string().starts_with("invalid record length"));
+        }
+    }
+}
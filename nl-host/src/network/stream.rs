@@ -1,12 +1,32 @@
 //! Network video streaming module
 
-use crate::log_verbose;
+use crate::core::events::Event;
+use crate::core::{Link, Session, SessionStats, Stage};
+use crate::network::transport::{self, Connection, Endpoint, Service};
+use crate::network::udp::{self, Route, UdpReceiver};
+use crate::network::VideoSettings;
 use crossbeam_channel::Sender;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{Cursor, Read, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The device sends at least every 100ms (it repeats the last frame on a static screen),
+/// so this much silence means the connection is gone even if TCP has not noticed
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One packet from the device encoder
+pub struct VideoPacket {
+    /// Presentation time in microseconds (0 for codec config)
+    pub pts: u64,
+    /// Annex B NAL units
+    pub data: Vec<u8>,
+    /// Packets before this one were lost (dropped on a full channel or a reconnect)
+    pub discontinuity: bool,
+    /// When the whole packet had arrived
+    pub received_at: Instant,
+}
 
 /// Handle for controlling the video receiver thread
 pub struct VideoReceiverHandle {
@@ -31,90 +51,106 @@ impl Drop for VideoReceiverHandle {
     }
 }
 
-/// Start the video receiver thread that connects to Android and sends data to decoder
+/// Start the video receiver thread that connects to Android and sends data to decoder.
+/// Each (re)connect sends the current `settings`, so runtime changes survive a reconnect.
+/// Dropped connections are retried per the `session`'s policy until it is closed or lost.
 pub fn start_video_receiver(
-    host: String,
+    endpoint: Endpoint,
     port: u16,
-    bitrate: u32,
-    max_size: u32,
-    tx: Sender<Vec<u8>>,
+    settings: Arc<Mutex<VideoSettings>>,
+    tx: Sender<VideoPacket>,
+    session: Arc<Session>,
+    stats: Arc<SessionStats>,
 ) -> VideoReceiverHandle {
     let running = Arc::new(AtomicBool::new(true));
     let running_clone = running.clone();
 
     let handle = thread::spawn(move || {
-        let mut reconnect_delay = 1;
-        let mut consecutive_failures = 0;
-        const MAX_RECONNECT_FAILURES: u32 = 3;
-
-        while running_clone.load(Ordering::SeqCst) {
-            log_verbose!("NET", "Connecting {}:{}...", host, port);
-            match TcpStream::connect_timeout(
-                &format!("{}:{}", host, port).parse().unwrap(),
+        let mut reconnect = false;
+        let stopped = || !running_clone.load(Ordering::SeqCst);
+
+        while !stopped() && !session.is_closed() {
+            log_verbose!("NET", "Connecting {}:{}...", endpoint.host(), port);
+            match transport::connect(
+                &endpoint,
+                port,
+                Service::Video,
                 std::time::Duration::from_secs(5),
             ) {
-                Ok(mut stream) => {
+                Ok(Connection {
+                    handle,
+                    mut reader,
+                    mut writer,
+                }) => {
                     log_verbose!("NET", "Connected");
-                    consecutive_failures = 0; // Reset on successful connect
-
-                    // Handshake: Send config
-                    log_verbose!(
-                        "NET",
-                        "Sending config: bitrate={}, max_size={}",
-                        bitrate,
-                        max_size
-                    );
-                    let handshake = format!("bitrate={}&max_size={}\n", bitrate, max_size);
-                    if let Err(e) = stream.write_all(handshake.as_bytes()) {
+
+                    // Handshake: Send config, offering UDP with --udp
+                    let config = *settings.lock().unwrap();
+                    log_verbose!("NET", "Sending config: {}", config);
+                    let offer = udp::offer(&endpoint);
+                    let mut handshake = config.handshake();
+                    if let Some(socket) = &offer {
+                        handshake = udp::add_offer(&handshake, socket).unwrap_or(handshake);
+                    }
+                    if let Err(e) = writer.write_all(handshake.as_bytes()) {
                         log_verbose!("NET", "WARNING: Failed to send handshake: {}", e);
                     }
-                    let _ = stream.flush();
+                    let _ = writer.flush();
 
-                    reconnect_delay = 1;
-                    let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(30)));
-                    let _ = stream.set_nodelay(true);
-
-                    if let Err(_) = receive_packets(&mut stream, &tx, &running_clone) {
-                        // Connection lost, will reconnect
-                        consecutive_failures += 1;
+                    let _ = handle.set_read_timeout(Some(STALL_TIMEOUT));
+                    let result = match offer.map(|s| udp::read_answer(s, &handle, &mut reader)) {
+                        None => receive_packets(
+                            &mut reader,
+                            &tx,
+                            reconnect,
+                            &running_clone,
+                            &session,
+                            &stats,
+                        ),
+                        Some(Ok(Route::Tcp(prefix))) => receive_packets(
+                            &mut Cursor::new(prefix).chain(&mut reader),
+                            &tx,
+                            reconnect,
+                            &running_clone,
+                            &session,
+                            &stats,
+                        ),
+                        // The connection stays open: the device streams until it closes
+                        Some(Ok(Route::Udp(mut receiver))) => receive_datagrams(
+                            &mut receiver,
+                            &tx,
+                            reconnect,
+                            &running_clone,
+                            &session,
+                            &stats,
+                        ),
+                        Some(Err(e)) => {
+                            log_verbose!("NET", "Failed to read UDP answer: {}", e);
+                            Err(())
+                        }
+                    };
+                    reconnect = true;
+                    if result.is_ok() {
+                        // Stopped while streaming
+                        break;
                     }
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    log_error!("NET", "Secure handshake failed: {}", e);
+                    session.events().emit(Event::Error {
+                        message: format!("Secure handshake failed: {}", e),
+                        fatal: false,
+                    });
+                }
                 Err(e) => {
                     log_verbose!("NET", "Connect failed: {}", e);
-                    consecutive_failures += 1;
                 }
             }
 
-            // Exit if too many consecutive failures (device disconnected)
-            if consecutive_failures >= MAX_RECONNECT_FAILURES {
-                log_verbose!(
-                    "NET",
-                    "Too many consecutive failures ({}), device likely disconnected. Exiting.",
-                    consecutive_failures
-                );
-                // Exit the process to notify launcher
-                std::process::exit(1);
-            }
-
-            if !running_clone.load(Ordering::SeqCst) {
+            // Connection failed or dropped: retry per the session's policy
+            if !session.backoff(Link::Video, stopped) {
                 break;
             }
-
-            log_verbose!(
-                "NET",
-                "Reconnecting in {}s... (attempt {}/{})",
-                reconnect_delay,
-                consecutive_failures,
-                MAX_RECONNECT_FAILURES
-            );
-            // Sleep in small increments to allow early exit
-            for _ in 0..(reconnect_delay * 10) {
-                if !running_clone.load(Ordering::SeqCst) {
-                    return;
-                }
-                thread::sleep(std::time::Duration::from_millis(100));
-            }
-            reconnect_delay = (reconnect_delay * 2).min(10);
         }
         log_verbose!("NET", "Video receiver stopped");
     });
@@ -125,18 +161,22 @@ pub fn start_video_receiver(
     }
 }
 
-/// Read packets from stream and send to decoder channel
+/// Read packets from stream and send to decoder channel.
+/// `reconnect` marks the first packet as a discontinuity (the old stream was cut off).
 fn receive_packets(
-    stream: &mut TcpStream,
-    tx: &Sender<Vec<u8>>,
+    stream: &mut impl Read,
+    tx: &Sender<VideoPacket>,
+    reconnect: bool,
     running: &Arc<AtomicBool>,
+    session: &Session,
+    stats: &SessionStats,
 ) -> Result<(), ()> {
     let mut total = 0u64;
     let start = std::time::Instant::now();
     let mut last_log = std::time::Instant::now();
-    let mut consecutive_timeouts = 0;
     let mut header_buf = [0u8; 12];
     let mut read_count = 0u64;
+    let mut discontinuity = reconnect;
 
     while running.load(Ordering::SeqCst) {
         read_count += 1;
@@ -144,7 +184,7 @@ fn receive_packets(
         // Read 12-byte Header
         match stream.read_exact(&mut header_buf) {
             Ok(()) => {
-                let _pts_flags = u64::from_be_bytes(header_buf[0..8].try_into().unwrap());
+                let pts = u64::from_be_bytes(header_buf[0..8].try_into().unwrap());
                 let body_size = u32::from_be_bytes(header_buf[8..12].try_into().unwrap()) as usize;
 
                 if body_size > 10 * 1024 * 1024 {
@@ -157,9 +197,10 @@ fn receive_packets(
                 match stream.read_exact(&mut body_buf) {
                     Ok(()) => {
                         total += (12 + body_size) as u64;
-                        consecutive_timeouts = 0;
+                        SessionStats::add(&stats.bytes_received, (12 + body_size) as u64);
+                        SessionStats::add(&stats.packets_received, 1);
 
-                        if read_count % 100 == 0 {
+                        if read_count.is_multiple_of(100) {
                             log_verbose!(
                                 "NET",
                                 "Packet #{}: {} bytes, total={}MB",
@@ -170,15 +211,9 @@ fn receive_packets(
                         }
 
                         // Send to decoder
-                        match tx.try_send(body_buf) {
-                            Ok(()) => {}
-                            Err(crossbeam_channel::TrySendError::Full(_)) => {
-                                log_verbose!("NET", "Channel full, dropping frame");
-                            }
-                            Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
-                                log_verbose!("NET", "Channel disconnected");
-                                return Err(());
-                            }
+                        deliver(tx, pts, body_buf, &mut discontinuity, stats)?;
+                        if read_count == 1 {
+                            session.link_up(Link::Video);
                         }
                     }
                     Err(e) => {
@@ -191,11 +226,9 @@ fn receive_packets(
                 if e.kind() == std::io::ErrorKind::TimedOut
                     || e.kind() == std::io::ErrorKind::WouldBlock
                 {
-                    consecutive_timeouts += 1;
-                    if consecutive_timeouts > 10 {
-                        return Err(());
-                    }
-                    continue;
+                    // A partial read lost its bytes, so the stream cannot resume anyway
+                    log_verbose!("NET", "No data for {:?}, connection stalled", STALL_TIMEOUT);
+                    return Err(());
                 } else if e.kind() == std::io::ErrorKind::UnexpectedEof {
                     log_verbose!("NET", "Server closed connection");
                     return Err(());
@@ -221,3 +254,79 @@ fn receive_packets(
     }
     Ok(())
 }
+
+/// Like `receive_packets`, for packets arriving as datagrams
+fn receive_datagrams(
+    receiver: &mut UdpReceiver,
+    tx: &Sender<VideoPacket>,
+    reconnect: bool,
+    running: &Arc<AtomicBool>,
+    session: &Session,
+    stats: &SessionStats,
+) -> Result<(), ()> {
+    let mut discontinuity = reconnect;
+
+    while running.load(Ordering::SeqCst) {
+        match receiver.recv() {
+            Ok(Some(packet)) => {
+                SessionStats::add(&stats.bytes_received, (12 + packet.data.len()) as u64);
+                SessionStats::add(&stats.packets_received, 1);
+                if packet.discontinuity {
+                    SessionStats::add(&stats.packets_dropped, 1);
+                    discontinuity = true;
+                }
+                deliver(tx, packet.pts, packet.data, &mut discontinuity, stats)?;
+                session.link_up(Link::Video);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log_verbose!("NET", "UDP receive failed: {}", e);
+                return Err(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Send a packet to the decoder, dropping it if the decoder is behind.
+/// `discontinuity` is sent along and then tracks whether the next packet follows a gap.
+/// Also records the capture-to-receive latency.
+fn deliver(
+    tx: &Sender<VideoPacket>,
+    pts: u64,
+    data: Vec<u8>,
+    discontinuity: &mut bool,
+    stats: &SessionStats,
+) -> Result<(), ()> {
+    let received_at = Instant::now();
+    // Codec config carries no timestamp
+    if pts != 0 {
+        if let Some(latency) = stats.clock.since_capture(pts, received_at) {
+            stats.latency.record(Stage::Network, latency);
+        }
+    }
+
+    let packet = VideoPacket {
+        pts,
+        data,
+        discontinuity: *discontinuity,
+        received_at,
+    };
+    match tx.try_send(packet) {
+        Ok(()) => {
+            *discontinuity = false;
+            SessionStats::set(&stats.channel_backlog, tx.len() as u64);
+        }
+        Err(crossbeam_channel::TrySendError::Full(_)) => {
+            // The next packet references this one
+            *discontinuity = true;
+            SessionStats::add(&stats.packets_dropped, 1);
+            log_verbose!("NET", "Channel full, dropping frame");
+        }
+        Err(crossbeam_channel::TrySendError::Disconnected(_)) => {
+            log_verbose!("NET", "Channel disconnected");
+            return Err(());
+        }
+    }
+    Ok(())
+}
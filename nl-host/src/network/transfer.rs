@@ -0,0 +1,104 @@
+//! File transfer to the device (drag-and-drop install/push)
+
+use crate::network::{ControlClient, Endpoint};
+use crossbeam_channel::Receiver;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Default device folder for pushed files
+pub const DEFAULT_PUSH_TARGET: &str = "/sdcard/Download";
+
+/// How long a finished/failed transfer message stays visible
+const RESULT_DISPLAY_TIME: Duration = Duration::from_secs(4);
+
+/// Transfer progress shared with the UI thread (shown in the window title)
+#[derive(Default)]
+pub struct TransferStatus {
+    message: Mutex<Option<(String, Option<Instant>)>>,
+}
+
+impl TransferStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current status message, or `None` if nothing is in progress
+    pub fn message(&self) -> Option<String> {
+        let mut guard = self.message.lock().unwrap();
+        if let Some((_, Some(expires))) = guard.as_ref() {
+            if Instant::now() >= *expires {
+                *guard = None;
+            }
+        }
+        guard.as_ref().map(|(msg, _)| msg.clone())
+    }
+
+    fn set_progress(&self, message: String) {
+        *self.message.lock().unwrap() = Some((message, None));
+    }
+
+    fn set_result(&self, message: String) {
+        *self.message.lock().unwrap() = Some((message, Some(Instant::now() + RESULT_DISPLAY_TIME)));
+    }
+}
+
+/// Returns true for files that should be installed rather than pushed
+pub fn is_apk(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.eq_ignore_ascii_case("apk") || e.eq_ignore_ascii_case("apks"))
+        .unwrap_or(false)
+}
+
+/// Start the transfer thread. Dropped files are queued on `rx` and handled one at a time
+/// on a dedicated control connection so large uploads never delay input events.
+pub fn start_transfer_thread(
+    endpoint: Endpoint,
+    port: u16,
+    push_target: String,
+    rx: Receiver<PathBuf>,
+    status: Arc<TransferStatus>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while let Ok(path) = rx.recv() {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let install = is_apk(&path);
+            let verb = if install { "Installing" } else { "Pushing" };
+            status.set_progress(format!("{} {}...", verb, name));
+
+            let result = ControlClient::connect(&endpoint, port).and_then(|mut client| {
+                let progress = |sent: u64, total: u64| {
+                    let percent = (sent * 100).checked_div(total).unwrap_or(100);
+                    status.set_progress(format!("{} {} {}%", verb, name, percent));
+                };
+                if install {
+                    client.install_apk(&path, progress)
+                } else {
+                    client.push_file(&path, &push_target, progress)
+                }
+            });
+
+            match result {
+                Ok(detail) => {
+                    log_info!("TRANSFER", "{} {}: {}", verb, name, detail.trim());
+                    let done = if install {
+                        format!("Installed {}", name)
+                    } else {
+                        format!("Pushed {} to {}", name, detail)
+                    };
+                    status.set_result(done);
+                }
+                Err(e) => {
+                    log_error!("TRANSFER", "{} {} failed: {}", verb, name, e);
+                    status.set_result(format!("{} failed: {}", name, e));
+                }
+            }
+        }
+        log_verbose!("TRANSFER", "Thread exiting");
+    })
+}
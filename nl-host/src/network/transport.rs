@@ -0,0 +1,211 @@
+//! Connections to the device services
+//!
+//! By default every service has its own TCP port. With `--mux` they all become channels of
+//! one socket on the video port (see `mux`), so only that port has to be reachable.
+
+use crate::network::mux::{Channel, ChannelReader, ChannelWriter, Multiplexer};
+use crate::network::secure::{self, LinkReader, LinkWriter, Psk};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A device service; the value is its id in the mux OPEN frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Video = 0,
+    Audio = 1,
+    Control = 2,
+}
+
+/// The device of one session and how to reach it: key (`--key-file`), `--mux` and `--udp`.
+/// Every thread of the session connects through a clone; clones share the mux socket.
+#[derive(Clone)]
+pub struct Endpoint {
+    host: String,
+    key: Option<Arc<Psk>>,
+    mux: Option<Arc<Multiplexer>>,
+    udp: bool,
+}
+
+impl Endpoint {
+    /// Plain TCP connections to `host`, one port per service
+    pub fn new(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            key: None,
+            mux: None,
+            udp: false,
+        }
+    }
+
+    /// Encrypt every connection with this pre-shared key
+    pub fn with_key(mut self, key: Psk) -> Self {
+        self.key = Some(Arc::new(key));
+        self
+    }
+
+    /// Carry every connection over one socket to `port` (the video port)
+    pub fn multiplexed(mut self, port: u16) -> Self {
+        let endpoint = format!("{}:{}", self.host, port);
+        self.mux = Some(Arc::new(Multiplexer::new(endpoint)));
+        self
+    }
+
+    /// Offer UDP for the video and audio streams
+    pub fn with_udp(mut self, udp: bool) -> Self {
+        self.udp = udp;
+        self
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn key(&self) -> Option<&Psk> {
+        self.key.as_deref()
+    }
+
+    pub fn udp(&self) -> bool {
+        self.udp
+    }
+
+    /// Close the `--mux` socket now instead of when the last clone is dropped (threads that
+    /// are still sleeping hold one), so the device can serve the next client. Connections
+    /// over it end and new ones fail. Nothing to do without `--mux`.
+    pub fn close(&self) {
+        if let Some(mux) = &self.mux {
+            mux.close();
+        }
+    }
+}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("host", &self.host)
+            .field("key", &self.key.is_some())
+            .field("mux", &self.mux.is_some())
+            .field("udp", &self.udp)
+            .finish()
+    }
+}
+
+/// An open connection to a device service
+pub struct Connection {
+    pub handle: Handle,
+    pub reader: Reader,
+    pub writer: Writer,
+}
+
+/// Connect to `service`, which listens on `port` of the endpoint's host unless multiplexed.
+/// `timeout` bounds the TCP connect.
+pub fn connect(
+    endpoint: &Endpoint,
+    port: u16,
+    service: Service,
+    timeout: Duration,
+) -> io::Result<Connection> {
+    if let Some(mux) = &endpoint.mux {
+        let (channel, reader, writer) = mux.open(endpoint.key(), service, timeout)?;
+        return Ok(Connection {
+            handle: Handle::Channel(channel),
+            reader: Reader::Channel(reader),
+            writer: Writer::Channel(writer),
+        });
+    }
+
+    let addr = (endpoint.host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for device"))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_nodelay(true)?;
+    let (reader, writer) = secure::open(&stream, endpoint.key())?;
+    Ok(Connection {
+        handle: Handle::Tcp(stream),
+        reader: Reader::Tcp(Box::new(reader)),
+        writer: Writer::Tcp(Box::new(writer)),
+    })
+}
+
+/// Timeouts and shutdown for a connection. Dropping it closes a mux channel.
+pub enum Handle {
+    Tcp(TcpStream),
+    Channel(Channel),
+}
+
+impl Handle {
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Handle::Tcp(stream) => stream.set_read_timeout(timeout),
+            Handle::Channel(channel) => {
+                channel.set_read_timeout(timeout);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match self {
+            Handle::Tcp(stream) => stream.read_timeout(),
+            Handle::Channel(channel) => Ok(channel.read_timeout()),
+        }
+    }
+
+    /// Address of the device, as seen by this connection
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Handle::Tcp(stream) => stream.peer_addr(),
+            Handle::Channel(channel) => channel.peer_addr(),
+        }
+    }
+
+    /// Unblock pending reads and end the connection
+    pub fn shutdown(&self) {
+        match self {
+            Handle::Tcp(stream) => {
+                let _ = stream.shutdown(std::net::Shutdown::Both);
+            }
+            Handle::Channel(channel) => channel.shutdown(),
+        }
+    }
+}
+
+/// Reading half of a connection
+pub enum Reader {
+    Tcp(Box<LinkReader>),
+    Channel(ChannelReader),
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Reader::Tcp(reader) => reader.read(buf),
+            Reader::Channel(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// Writing half of a connection; call `flush` to send
+pub enum Writer {
+    Tcp(Box<LinkWriter>),
+    Channel(ChannelWriter),
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Tcp(writer) => writer.write(buf),
+            Writer::Channel(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Tcp(writer) => writer.flush(),
+            Writer::Channel(writer) => writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,581 @@
+//! Low-latency UDP transport for the video and audio streams (`--udp`)
+//!
+//! Over WiFi a single lost TCP segment stalls every packet behind it. With `--udp` the host
+//! offers a datagram port in the stream handshake (`udp=<port>`). A device that can reach the
+//! host directly answers `UDP1` + its own port on the TCP connection, then sends each packet
+//! as datagrams:
+//!
+//! ```text
+//! kind (1) | seq (4) | frame (4) | index (2) | count (2) | pts (8) | payload (<= 1200)
+//! ```
+//!
+//! A packet is split into `count` DATA datagrams with consecutive sequence numbers. Each group
+//! of up to 8 fragments also gets a PARITY datagram that repairs one loss without a round trip;
+//! other gaps are NACKed by sequence number. A packet still incomplete after `FRAME_DEADLINE`
+//! is skipped and the next one marked as a discontinuity. The TCP connection stays open for
+//! the session; devices that decline (e.g. behind adb forward) just keep streaming over it.
+//! Datagrams are not encrypted, so nothing is offered when the session has a key.
+//! Must match nl-android's `UdpStream`.
+
+use crate::network::transport::{Endpoint, Handle};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read};
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"UDP1";
+const DATA: u8 = 0;
+const PARITY: u8 = 1;
+const NACK: u8 = 2;
+const HEADER_SIZE: usize = 21;
+const MAX_PAYLOAD: usize = 1200;
+const FEC_GROUP: usize = 8;
+/// Wait this long for reordering or parity before asking for a missing datagram
+const NACK_DELAY: Duration = Duration::from_millis(10);
+const NACK_INTERVAL: Duration = Duration::from_millis(40);
+const MAX_NACKS: u8 = 3;
+/// Larger sequence jumps are not worth repairing one by one
+const MAX_GAP: u32 = 512;
+/// Give up on an incomplete packet this long after its first datagram
+const FRAME_DEADLINE: Duration = Duration::from_millis(150);
+const MAX_PENDING_FRAMES: usize = 64;
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// The device sends at least every 100ms; this much silence means it is gone
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A socket to offer in the next handshake, if `--udp` is on for `endpoint` and it has no
+/// key (the stream would leave the encrypted connection)
+pub fn offer(endpoint: &Endpoint) -> Option<UdpSocket> {
+    if !endpoint.udp() {
+        return None;
+    }
+    if endpoint.key().is_some() {
+        log_verbose!("UDP", "Not offering UDP: the session is encrypted");
+        return None;
+    }
+    match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            log_error!("UDP", "Failed to bind: {}", e);
+            None
+        }
+    }
+}
+
+/// `line` (a handshake line) with the offer of `socket` appended
+pub fn add_offer(line: &str, socket: &UdpSocket) -> io::Result<String> {
+    let params = line.trim_end_matches('\n');
+    let separator = if params.is_empty() { "" } else { "&" };
+    Ok(format!(
+        "{}{}udp={}\n",
+        params,
+        separator,
+        socket.local_addr()?.port()
+    ))
+}
+
+/// How the packets of a stream arrive after an offer
+pub enum Route {
+    Udp(UdpReceiver),
+    /// Declined; these are the first bytes of the TCP stream
+    Tcp(Vec<u8>),
+}
+
+/// Read the device's answer to the offer of `socket` from the start of the TCP stream
+pub fn read_answer(
+    socket: UdpSocket,
+    handle: &Handle,
+    reader: &mut impl Read,
+) -> io::Result<Route> {
+    let mut prefix = [0u8; 4];
+    reader.read_exact(&mut prefix)?;
+    if &prefix != MAGIC {
+        return Ok(Route::Tcp(prefix.to_vec()));
+    }
+    let mut port = [0u8; 2];
+    reader.read_exact(&mut port)?;
+    let device = SocketAddr::new(handle.peer_addr()?.ip(), u16::from_be_bytes(port));
+    log_info!("UDP", "Receiving from {} over UDP", device);
+    Ok(Route::Udp(UdpReceiver::new(socket, device)?))
+}
+
+/// One reassembled packet
+pub struct UdpPacket {
+    pub pts: u64,
+    pub data: Vec<u8>,
+    /// Packets before this one were given up on
+    pub discontinuity: bool,
+}
+
+struct Assembly {
+    pts: u64,
+    /// Sequence number of fragment 0, once any DATA datagram has arrived
+    base_seq: Option<u32>,
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// Parity payloads by group start
+    parity: HashMap<usize, Vec<u8>>,
+    first_seen: Instant,
+}
+
+impl Assembly {
+    fn new(pts: u64, count: usize) -> Self {
+        Self {
+            pts,
+            base_seq: None,
+            fragments: vec![None; count],
+            received: 0,
+            parity: HashMap::new(),
+            first_seen: Instant::now(),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.received == self.fragments.len()
+    }
+
+    /// Rebuild the one missing fragment of a group from its parity; returns its index
+    fn repair(&mut self, group_start: usize) -> Option<usize> {
+        let group_end = (group_start + FEC_GROUP).min(self.fragments.len());
+        let parity = self.parity.get(&group_start)?;
+        let mut missing = (group_start..group_end).filter(|&i| self.fragments[i].is_none());
+        let index = missing.next()?;
+        if missing.next().is_some() || parity.len() < 2 {
+            return None;
+        }
+
+        let mut length = u16::from_be_bytes([parity[0], parity[1]]) as usize;
+        let mut data = parity[2..].to_vec();
+        for fragment in self.fragments[group_start..group_end].iter().flatten() {
+            length ^= fragment.len();
+            for (byte, other) in data.iter_mut().zip(fragment) {
+                *byte ^= other;
+            }
+        }
+        if length > data.len() {
+            return None;
+        }
+        data.truncate(length);
+        self.fragments[index] = Some(data);
+        self.received += 1;
+        Some(index)
+    }
+}
+
+struct Missing {
+    since: Instant,
+    last_nack: Option<Instant>,
+    nacks: u8,
+}
+
+/// Reassembles, repairs and orders the packets of one stream
+pub struct UdpReceiver {
+    socket: UdpSocket,
+    frames: BTreeMap<u32, Assembly>,
+    next_frame: Option<u32>,
+    highest_seq: Option<u32>,
+    missing: BTreeMap<u32, Missing>,
+    discontinuity: bool,
+    last_datagram: Instant,
+}
+
+impl UdpReceiver {
+    /// Receive from `device` (its address and answered port) on the offered socket
+    fn new(socket: UdpSocket, device: SocketAddr) -> io::Result<Self> {
+        socket.connect(device)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        // An empty NACK opens the way back through firewalls that only allow replies
+        let _ = socket.send(&[NACK, 0, 0]);
+        Ok(Self {
+            socket,
+            frames: BTreeMap::new(),
+            next_frame: None,
+            highest_seq: None,
+            missing: BTreeMap::new(),
+            discontinuity: false,
+            last_datagram: Instant::now(),
+        })
+    }
+
+    /// The next packet in order, or `None` if there is none yet (call again).
+    /// Fails once the device has gone quiet.
+    pub fn recv(&mut self) -> io::Result<Option<UdpPacket>> {
+        let mut buf = [0u8; HEADER_SIZE + MAX_PAYLOAD + 2];
+        loop {
+            if let Some(packet) = self.pop_ready() {
+                return Ok(Some(packet));
+            }
+            self.send_nacks();
+
+            match self.socket.recv(&mut buf) {
+                Ok(n) => {
+                    self.last_datagram = Instant::now();
+                    self.handle(&buf[..n]);
+                }
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if self.last_datagram.elapsed() > IDLE_TIMEOUT {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "no datagrams from device",
+                        ));
+                    }
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn handle(&mut self, datagram: &[u8]) {
+        if datagram.len() < HEADER_SIZE {
+            return;
+        }
+        let kind = datagram[0];
+        let seq = u32::from_be_bytes(datagram[1..5].try_into().unwrap());
+        let frame = u32::from_be_bytes(datagram[5..9].try_into().unwrap());
+        let index = u16::from_be_bytes([datagram[9], datagram[10]]) as usize;
+        let count = u16::from_be_bytes([datagram[11], datagram[12]]) as usize;
+        let pts = u64::from_be_bytes(datagram[13..21].try_into().unwrap());
+        let payload = &datagram[HEADER_SIZE..];
+
+        if kind == DATA {
+            self.track_seq(seq);
+        }
+        let next_frame = *self.next_frame.get_or_insert(frame);
+        if frame < next_frame || count == 0 || index >= count {
+            // Already delivered or given up on (e.g. a late retransmission)
+            return;
+        }
+
+        let assembly = self
+            .frames
+            .entry(frame)
+            .or_insert_with(|| Assembly::new(pts, count));
+        if assembly.fragments.len() != count {
+            return;
+        }
+        let group_start = index - index % FEC_GROUP;
+        match kind {
+            DATA => {
+                assembly.base_seq = Some(seq.wrapping_sub(index as u32));
+                if assembly.fragments[index].is_none() {
+                    assembly.fragments[index] = Some(payload.to_vec());
+                    assembly.received += 1;
+                }
+            }
+            PARITY => {
+                assembly.parity.insert(group_start, payload.to_vec());
+            }
+            _ => return,
+        }
+
+        if let Some(repaired) = assembly.repair(group_start) {
+            if let Some(base) = assembly.base_seq {
+                self.missing.remove(&base.wrapping_add(repaired as u32));
+            }
+        }
+    }
+
+    fn track_seq(&mut self, seq: u32) {
+        self.missing.remove(&seq);
+        match self.highest_seq {
+            Some(highest) if seq <= highest => {}
+            Some(highest) => {
+                if seq - highest <= MAX_GAP {
+                    let now = Instant::now();
+                    for lost in highest + 1..seq {
+                        self.missing.insert(
+                            lost,
+                            Missing {
+                                since: now,
+                                last_nack: None,
+                                nacks: 0,
+                            },
+                        );
+                    }
+                }
+                self.highest_seq = Some(seq);
+            }
+            None => self.highest_seq = Some(seq),
+        }
+    }
+
+    fn send_nacks(&mut self) {
+        let now = Instant::now();
+        self.missing.retain(|_, missing| {
+            missing.nacks < MAX_NACKS
+                || missing
+                    .last_nack
+                    .is_some_and(|last| now.duration_since(last) < NACK_INTERVAL)
+        });
+
+        let mut nack = vec![NACK, 0, 0];
+        let mut count = 0u16;
+        for (&seq, missing) in self.missing.iter_mut() {
+            let due = match missing.last_nack {
+                None => now.duration_since(missing.since) >= NACK_DELAY,
+                Some(last) => {
+                    missing.nacks < MAX_NACKS && now.duration_since(last) >= NACK_INTERVAL
+                }
+            };
+            if !due || nack.len() + 4 > MAX_PAYLOAD {
+                continue;
+            }
+            nack.extend_from_slice(&seq.to_be_bytes());
+            missing.last_nack = Some(now);
+            missing.nacks += 1;
+            count += 1;
+        }
+        if count > 0 {
+            nack[1..3].copy_from_slice(&count.to_be_bytes());
+            let _ = self.socket.send(&nack);
+        }
+    }
+
+    /// Deliver the next packet if complete; skip it once it is hopeless
+    fn pop_ready(&mut self) -> Option<UdpPacket> {
+        loop {
+            let next = self.next_frame?;
+            if self.frames.get(&next).is_some_and(Assembly::is_complete) {
+                let assembly = self.frames.remove(&next).unwrap();
+                self.next_frame = Some(next.wrapping_add(1));
+                return Some(UdpPacket {
+                    pts: assembly.pts,
+                    data: assembly.fragments.into_iter().flatten().flatten().collect(),
+                    discontinuity: std::mem::take(&mut self.discontinuity),
+                });
+            }
+
+            // Give up when the oldest packet we wait for (this one or, if nothing of it
+            // arrived, the next one seen) has passed its deadline
+            let oldest = self.frames.values().next()?;
+            let newer_waiting = self.frames.keys().next_back().is_some_and(|&f| f > next);
+            if !newer_waiting
+                || (oldest.first_seen.elapsed() < FRAME_DEADLINE
+                    && self.frames.len() <= MAX_PENDING_FRAMES)
+            {
+                return None;
+            }
+            if self.frames.remove(&next).is_some() {
+                log_verbose!("UDP", "Gave up on packet {}", next);
+            }
+            self.next_frame = Some(next.wrapping_add(1));
+            self.discontinuity = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::secure::Psk;
+
+    /// A receiver and the "device" socket it is connected to, past the opening empty NACK
+    fn receiver() -> (UdpReceiver, UdpSocket) {
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        device
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receiver = UdpReceiver::new(socket, device.local_addr().unwrap()).unwrap();
+        assert_eq!(recv_nack(&device), Vec::<u32>::new());
+        (receiver, device)
+    }
+
+    fn recv_nack(device: &UdpSocket) -> Vec<u32> {
+        let mut buf = [0u8; 2048];
+        let n = device.recv(&mut buf).unwrap();
+        assert_eq!(buf[0], NACK);
+        let count = u16::from_be_bytes([buf[1], buf[2]]) as usize;
+        assert_eq!(n, 3 + count * 4);
+        buf[3..n]
+            .chunks(4)
+            .map(|seq| u32::from_be_bytes(seq.try_into().unwrap()))
+            .collect()
+    }
+
+    fn datagram(
+        kind: u8,
+        seq: u32,
+        frame: u32,
+        index: usize,
+        count: usize,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut datagram = vec![kind];
+        datagram.extend_from_slice(&seq.to_be_bytes());
+        datagram.extend_from_slice(&frame.to_be_bytes());
+        datagram.extend_from_slice(&(index as u16).to_be_bytes());
+        datagram.extend_from_slice(&(count as u16).to_be_bytes());
+        datagram.extend_from_slice(&(1000 + frame as u64).to_be_bytes());
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    /// Datagrams of one packet as the device sends them: (DATA datagrams, PARITY per group)
+    fn packet(first_seq: u32, frame: u32, data: &[u8]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let fragments: Vec<&[u8]> = data.chunks(MAX_PAYLOAD).collect();
+        let count = fragments.len();
+        let mut parities = Vec::new();
+        for (group, chunk) in fragments.chunks(FEC_GROUP).enumerate() {
+            let mut parity = vec![0u8; 2 + MAX_PAYLOAD];
+            for fragment in chunk {
+                let length = (fragment.len() as u16).to_be_bytes();
+                parity[0] ^= length[0];
+                parity[1] ^= length[1];
+                for (byte, other) in parity[2..].iter_mut().zip(*fragment) {
+                    *byte ^= other;
+                }
+            }
+            parities.push(datagram(
+                PARITY,
+                0,
+                frame,
+                group * FEC_GROUP,
+                count,
+                &parity,
+            ));
+        }
+        let datas = fragments
+            .iter()
+            .enumerate()
+            .map(|(i, fragment)| datagram(DATA, first_seq + i as u32, frame, i, count, fragment))
+            .collect();
+        (datas, parities)
+    }
+
+    fn payload(length: usize, seed: u8) -> Vec<u8> {
+        (0..length)
+            .map(|i| (i as u8).wrapping_mul(31) ^ seed)
+            .collect()
+    }
+
+    /// Pretend the missing datagrams were noticed `age` ago
+    fn age_missing(receiver: &mut UdpReceiver, age: Duration) {
+        for missing in receiver.missing.values_mut() {
+            missing.since = Instant::now() - age;
+        }
+    }
+
+    #[test]
+    fn no_offer_with_a_key() {
+        let endpoint = Endpoint::new("127.0.0.1").with_udp(true);
+        assert!(offer(&endpoint).is_some());
+        let key = Psk::from_hex(&"ab".repeat(32)).unwrap();
+        assert!(offer(&endpoint.with_key(key)).is_none());
+        assert!(offer(&Endpoint::new("127.0.0.1")).is_none());
+    }
+
+    #[test]
+    fn single_loss_is_repaired_by_parity() {
+        let (mut receiver, _device) = receiver();
+        let data = payload(MAX_PAYLOAD * 2 + 300, 1);
+        let (datas, parities) = packet(1, 0, &data);
+
+        receiver.handle(&datas[0]);
+        receiver.handle(&datas[2]);
+        assert!(receiver.pop_ready().is_none());
+        assert_eq!(receiver.missing.keys().copied().collect::<Vec<_>>(), [2]);
+
+        receiver.handle(&parities[0]);
+        let packet = receiver.pop_ready().unwrap();
+        assert_eq!(packet.data, data);
+        assert_eq!(packet.pts, 1000);
+        assert!(!packet.discontinuity);
+        // Nothing left to NACK
+        assert!(receiver.missing.is_empty());
+    }
+
+    #[test]
+    fn repairs_a_short_last_fragment() {
+        let (mut receiver, _device) = receiver();
+        let data = payload(MAX_PAYLOAD + 17, 2);
+        let (datas, parities) = packet(1, 0, &data);
+
+        receiver.handle(&datas[0]);
+        receiver.handle(&parities[0]);
+        assert_eq!(receiver.pop_ready().unwrap().data, data);
+    }
+
+    #[test]
+    fn double_loss_is_nacked() {
+        let (mut receiver, device) = receiver();
+        let data = payload(MAX_PAYLOAD * 4, 3);
+        let (datas, parities) = packet(10, 0, &data);
+
+        receiver.handle(&datas[0]);
+        receiver.handle(&datas[3]);
+        receiver.handle(&parities[0]);
+        assert!(receiver.pop_ready().is_none());
+
+        // Not before NACK_DELAY, to leave time for reordering
+        receiver.send_nacks();
+        assert!(receiver.missing.values().all(|m| m.nacks == 0));
+        age_missing(&mut receiver, NACK_DELAY);
+        receiver.send_nacks();
+        assert_eq!(recv_nack(&device), [11, 12]);
+
+        // One retransmission is enough: the parity rebuilds the other
+        receiver.handle(&datas[1]);
+        assert_eq!(receiver.pop_ready().unwrap().data, data);
+        assert!(receiver.missing.is_empty());
+    }
+
+    #[test]
+    fn gives_up_after_frame_deadline() {
+        let (mut receiver, _device) = receiver();
+        let first = payload(MAX_PAYLOAD * 3, 4);
+        let second = payload(500, 5);
+        let third = payload(700, 6);
+        let (first_datas, _) = packet(1, 0, &first);
+        let (second_datas, _) = packet(4, 1, &second);
+        let (third_datas, _) = packet(5, 2, &third);
+
+        receiver.handle(&first_datas[0]);
+        receiver.handle(&second_datas[0]);
+        // The second packet waits for the first until its deadline
+        assert!(receiver.pop_ready().is_none());
+
+        receiver.frames.get_mut(&0).unwrap().first_seen = Instant::now() - FRAME_DEADLINE;
+        let packet = receiver.pop_ready().unwrap();
+        assert_eq!(packet.data, second);
+        assert!(packet.discontinuity);
+
+        receiver.handle(&third_datas[0]);
+        let packet = receiver.pop_ready().unwrap();
+        assert_eq!(packet.data, third);
+        assert!(!packet.discontinuity);
+    }
+
+    #[test]
+    fn duplicates_and_late_retransmissions_are_ignored() {
+        let (mut receiver, _device) = receiver();
+        let first = payload(MAX_PAYLOAD * 3, 7);
+        let second = payload(MAX_PAYLOAD + 1, 8);
+        let (first_datas, _) = packet(1, 0, &first);
+        let (second_datas, _) = packet(4, 1, &second);
+
+        // A duplicate fragment does not count twice
+        receiver.handle(&first_datas[0]);
+        receiver.handle(&second_datas[0]);
+        receiver.handle(&second_datas[0]);
+        assert_eq!(receiver.frames[&1].received, 1);
+
+        // Give up on the first packet, of which only one fragment arrived
+        receiver.frames.get_mut(&0).unwrap().first_seen = Instant::now() - FRAME_DEADLINE;
+        receiver.handle(&second_datas[1]);
+        let packet = receiver.pop_ready().unwrap();
+        assert_eq!(packet.data, second);
+        assert!(packet.discontinuity);
+
+        // Its retransmissions and repeats of the delivered packet come too late
+        for datagram in first_datas.iter().chain(&second_datas) {
+            receiver.handle(datagram);
+        }
+        assert!(receiver.frames.is_empty());
+        assert!(receiver.pop_ready().is_none());
+    }
+}
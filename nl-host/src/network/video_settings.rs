@@ -0,0 +1,103 @@
+//! Encoder settings requested from the device
+
+use std::fmt;
+
+/// Bitrate range for runtime changes (bits per second)
+const MIN_BITRATE: u32 = 500_000;
+const MAX_BITRATE: u32 = 50_000_000;
+/// Bitrate factor per hotkey step
+const BITRATE_STEP: f64 = 1.5;
+
+/// Max size presets stepped through by the hotkeys (longer side of 720p, 1080p, ... 4K)
+const MAX_SIZE_STEPS: [u32; 5] = [720, 1280, 1920, 2560, 3840];
+/// Frame rate caps cycled by the hotkey (0 = uncapped)
+const MAX_FPS_STEPS: [u32; 4] = [0, 60, 30, 15];
+
+/// Bitrate, size and frame rate of the device encoder.
+/// Sent in the video handshake and changed mid-session with `ControlClient::set_video`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoSettings {
+    pub bitrate: u32,
+    /// Largest encoded width or height in pixels (0 = native); the longer side is capped,
+    /// so the resolution stays the same when the device rotates
+    pub max_size: u32,
+    /// Frame rate cap (0 = uncapped)
+    pub max_fps: u32,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            bitrate: 8_000_000,
+            max_size: 0,
+            max_fps: 0,
+        }
+    }
+}
+
+impl VideoSettings {
+    /// Handshake line sent when the video connection opens
+    pub fn handshake(&self) -> String {
+        format!(
+            "bitrate={}&max_size={}&max_fps={}\n",
+            self.bitrate, self.max_size, self.max_fps
+        )
+    }
+
+    /// One bitrate step up or down, rounded to 100 kbps
+    pub fn step_bitrate(self, up: bool) -> Self {
+        let factor = if up { BITRATE_STEP } else { 1.0 / BITRATE_STEP };
+        let bitrate = (self.bitrate as f64 * factor / 100_000.0).round() as u32 * 100_000;
+        Self {
+            bitrate: bitrate.clamp(MIN_BITRATE, MAX_BITRATE),
+            ..self
+        }
+    }
+
+    /// Next larger or smaller max size preset (native counts as the largest)
+    pub fn step_max_size(self, up: bool) -> Self {
+        let max_size = if up {
+            MAX_SIZE_STEPS
+                .iter()
+                .copied()
+                .find(|&s| self.max_size != 0 && s > self.max_size)
+                .unwrap_or(0)
+        } else if self.max_size == 0 {
+            MAX_SIZE_STEPS[MAX_SIZE_STEPS.len() - 1]
+        } else {
+            MAX_SIZE_STEPS
+                .iter()
+                .rev()
+                .copied()
+                .find(|&s| s < self.max_size)
+                .unwrap_or(MAX_SIZE_STEPS[0])
+        };
+        Self { max_size, ..self }
+    }
+
+    /// Next frame rate cap in the cycle
+    pub fn next_max_fps(self) -> Self {
+        let index = MAX_FPS_STEPS
+            .iter()
+            .position(|&f| f == self.max_fps)
+            .map_or(0, |i| (i + 1) % MAX_FPS_STEPS.len());
+        Self {
+            max_fps: MAX_FPS_STEPS[index],
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for VideoSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} Mbps, ", self.bitrate as f64 / 1_000_000.0)?;
+        match self.max_size {
+            0 => write!(f, "native size, ")?,
+            size => write!(f, "max {}px, ", size)?,
+        }
+        match self.max_fps {
+            0 => write!(f, "uncapped fps"),
+            fps => write!(f, "max {} fps", fps),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Screenshot saving utility
 
-use crate::core::FrameData;
+use crate::core::events::{Event, EventSink};
+use crate::core::{burn_in, FrameData, TouchFrame};
 use chrono::Local;
 use image::{ImageBuffer, Rgba};
 use std::sync::Arc;
@@ -10,6 +11,7 @@ fn yuv_to_rgba(frame: &FrameData) -> Vec<u8> {
     let w = frame.width as usize;
     let h = frame.height as usize;
     let mut rgba = vec![0u8; w * h * 4];
+    let conversion = frame.color.conversion();
 
     for row in 0..h {
         let uv_row = row / 2;
@@ -19,19 +21,17 @@ fn yuv_to_rgba(frame: &FrameData) -> Vec<u8> {
             let u_idx = uv_row * frame.uv_stride + uv_col;
             let v_idx = uv_row * frame.uv_stride + uv_col;
 
-            let y = frame.y_plane[y_idx] as f32 / 255.0;
-            let u = frame.u_plane[u_idx] as f32 / 255.0 - 0.5;
-            let v = frame.v_plane[v_idx] as f32 / 255.0 - 0.5;
-
-            // BT.601 YUV to RGB (full range)
-            let r = (y + 1.402 * v).clamp(0.0, 1.0);
-            let g = (y - 0.344136 * u - 0.714136 * v).clamp(0.0, 1.0);
-            let b = (y + 1.772 * u).clamp(0.0, 1.0);
+            // Same coefficients as the shader (matrix/range from the stream)
+            let [r, g, b] = conversion.convert(
+                frame.y_plane[y_idx],
+                frame.u_plane[u_idx],
+                frame.v_plane[v_idx],
+            );
 
             let rgba_idx = (row * w + col) * 4;
-            rgba[rgba_idx] = (r * 255.0) as u8;
-            rgba[rgba_idx + 1] = (g * 255.0) as u8;
-            rgba[rgba_idx + 2] = (b * 255.0) as u8;
+            rgba[rgba_idx] = r;
+            rgba[rgba_idx + 1] = g;
+            rgba[rgba_idx + 2] = b;
             rgba[rgba_idx + 3] = 255;
         }
     }
@@ -39,14 +39,18 @@ fn yuv_to_rgba(frame: &FrameData) -> Vec<u8> {
     rgba
 }
 
-/// Save YUV frame data to a PNG file on Desktop
-pub fn save_screenshot_yuv(frame: FrameData) {
+/// Save YUV frame data to a PNG file on Desktop, with touch markers drawn in if given.
+/// The result is reported to `events`.
+pub fn save_screenshot_yuv(frame: FrameData, touches: Option<TouchFrame>, events: EventSink) {
     std::thread::spawn(move || {
         let width = frame.width;
         let height = frame.height;
 
         eprintln!("[SNAPSHOT] Converting YUV to RGBA...");
-        let rgba = yuv_to_rgba(&frame);
+        let mut rgba = yuv_to_rgba(&frame);
+        if let Some(touches) = &touches {
+            burn_in(&mut rgba, width, height, touches);
+        }
 
         if rgba.is_empty() {
             eprintln!("[SNAPSHOT] Frame buffer empty, cannot save.");
@@ -74,8 +78,19 @@ pub fn save_screenshot_yuv(frame: FrameData) {
 
         match ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, rgba) {
             Some(buffer) => match buffer.save(&save_path) {
-                Ok(_) => eprintln!("[SNAPSHOT] Saved to {:?}", save_path),
-                Err(e) => eprintln!("[SNAPSHOT] Failed to save: {}", e),
+                Ok(_) => {
+                    eprintln!("[SNAPSHOT] Saved to {:?}", save_path);
+                    events.emit(Event::ScreenshotSaved {
+                        path: save_path.to_string_lossy().to_string(),
+                    });
+                }
+                Err(e) => {
+                    eprintln!("[SNAPSHOT] Failed to save: {}", e);
+                    events.emit(Event::Error {
+                        message: format!("Failed to save screenshot: {}", e),
+                        fatal: false,
+                    });
+                }
             },
             None => eprintln!("[SNAPSHOT] Failed to create image buffer."),
         }
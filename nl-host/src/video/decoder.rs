@@ -1,7 +1,8 @@
 //! H264 Video Decoder using OpenH264
 //! Cross-platform decoder that works on macOS/Windows/Linux without system dependencies
 
-use crate::core;
+use crate::core::{self, VideoSignal};
+use crate::video::sps::parse_sps_signal;
 use anyhow::{anyhow, Result};
 use openh264::decoder::Decoder;
 use openh264::formats::YUVSource;
@@ -36,6 +37,11 @@ fn is_keyframe(nal_data: &[u8]) -> bool {
     false
 }
 
+/// Check if NAL unit is an IDR slice (decodable without earlier frames)
+fn is_idr(nal_data: &[u8]) -> bool {
+    nal_data.len() > 4 && nal_data[4] & 0x1F == 5
+}
+
 /// Decoded YUV frame data for GPU upload
 pub struct YuvFrame {
     pub width: u32,
@@ -45,6 +51,8 @@ pub struct YuvFrame {
     pub v_plane: Vec<u8>,
     pub y_stride: usize,
     pub uv_stride: usize,
+    /// Colour description from the most recent SPS
+    pub signal: VideoSignal,
 }
 
 pub struct VideoDecoder {
@@ -54,7 +62,12 @@ pub struct VideoDecoder {
     frame_count: u64,
     last_log: Instant,
     last_frame_time: Instant,
+    /// Degraded: output is frozen and only parameter sets and IDR slices are decoded
     waiting_for_keyframe: bool,
+    /// Why the decoder last became degraded
+    degrade_reason: &'static str,
+    signal: VideoSignal,
+    reset_count: u64,
 }
 
 impl VideoDecoder {
@@ -70,18 +83,54 @@ impl VideoDecoder {
             last_log: now,
             last_frame_time: now,
             waiting_for_keyframe: false,
+            degrade_reason: "",
+            signal: VideoSignal::default(),
+            reset_count: 0,
         })
     }
 
+    /// Number of decoder resets (watchdog, overflow, decode errors)
+    pub fn reset_count(&self) -> u64 {
+        self.reset_count
+    }
+
+    /// Output is frozen on the last good frame until a clean IDR arrives
+    pub fn is_degraded(&self) -> bool {
+        self.waiting_for_keyframe
+    }
+
+    /// Why the decoder last became degraded
+    pub fn degrade_reason(&self) -> &'static str {
+        self.degrade_reason
+    }
+
+    /// Stop showing frames until the next IDR. Everything decoded before it would reference
+    /// missing or damaged pictures.
+    pub fn degrade(&mut self, reason: &'static str) {
+        if !self.waiting_for_keyframe {
+            dec_log!("[DEC] Degraded ({}), waiting for keyframe", reason);
+        }
+        self.waiting_for_keyframe = true;
+        self.degrade_reason = reason;
+    }
+
+    /// Data was lost between packets (dropped, reconnected or a PTS gap)
+    pub fn discontinuity(&mut self, reason: &'static str) {
+        // The buffered tail belongs to before the gap and is not worth decoding alone
+        self.buffer.clear();
+        self.degrade(reason);
+    }
+
     /// Reset decoder to recover from errors
-    fn reset_decoder(&mut self) -> Result<()> {
+    fn reset_decoder(&mut self, reason: &'static str) -> Result<()> {
         dec_log!("[DEC] Resetting decoder...");
+        self.reset_count += 1;
 
         // Recreate decoder from scratch
         self.decoder = Decoder::new().map_err(|e| anyhow!("OpenH264 reset failed: {:?}", e))?;
 
-        // Set flag to wait for keyframe after reset
-        self.waiting_for_keyframe = true;
+        // Wait for keyframe after reset
+        self.degrade(reason);
 
         dec_log!("[DEC] Decoder reset complete, waiting for keyframe...");
         Ok(())
@@ -111,7 +160,7 @@ impl VideoDecoder {
                     "[DEC] WATCHDOG: No frames for {}ms, resetting decoder",
                     stall_ms
                 );
-                let _ = self.reset_decoder();
+                let _ = self.reset_decoder("watchdog");
                 self.last_frame_time = Instant::now();
                 return Ok(Vec::new());
             }
@@ -123,7 +172,7 @@ impl VideoDecoder {
                 "Buffer overflow ({}KB), resetting decoder",
                 self.buffer.len() / 1024
             );
-            let _ = self.reset_decoder();
+            let _ = self.reset_decoder("buffer overflow");
             let keep_start = self.buffer.len().saturating_sub(256 * 1024);
             self.buffer.drain(0..keep_start);
             self.last_frame_time = Instant::now();
@@ -163,47 +212,49 @@ impl VideoDecoder {
                     let nal_unit: Vec<u8> = self.buffer.drain(0..end_pos).collect();
                     self.packet_count += 1;
 
-                    // If waiting for keyframe, skip non-keyframes
-                    if self.waiting_for_keyframe {
-                        if is_keyframe(&nal_unit) {
+                    // While degraded only parameter sets and IDR slices reach the decoder;
+                    // anything else would reference pictures we never decoded correctly
+                    let idr = is_idr(&nal_unit);
+                    if self.waiting_for_keyframe && !is_keyframe(&nal_unit) {
+                        if self.packet_count.is_multiple_of(100) {
                             dec_log!(
-                                "[DEC] Found keyframe (packet {}), resuming decode",
+                                "[DEC] Skipping non-keyframe NAL (packet {}), waiting for keyframe",
                                 self.packet_count
                             );
-                            self.waiting_for_keyframe = false;
-                        } else {
-                            if self.packet_count % 100 == 0 {
-                                dec_log!(
-                                    "[DEC] Skipping non-keyframe NAL (packet {}), waiting for keyframe",
-                                    self.packet_count
-                                );
-                            }
-                            continue;
                         }
+                        continue;
                     }
 
-                    // Decode this NAL unit
+                    // Decode this NAL unit (OpenH264 reports lost references as errors too)
                     let nal_size = nal_unit.len();
                     let frames_before = decoded_frames.len();
                     if let Err(e) = self.decode_nal(&nal_unit, &mut decoded_frames) {
-                        if self.packet_count % 50 == 0 {
-                            dec_log!(
-                                "[DEC] ERROR: Decode NAL error (packet {}, size={}): {}, resetting decoder",
-                                self.packet_count, nal_size, e
-                            );
-                            let _ = self.reset_decoder();
+                        dec_log!(
+                            "[DEC] Decode NAL error (packet {}, size={}): {}",
+                            self.packet_count,
+                            nal_size,
+                            e
+                        );
+                        if idr {
+                            // Even a keyframe failed: the decoder state itself is broken
+                            let _ = self.reset_decoder("keyframe decode error");
                             self.last_frame_time = Instant::now();
-                            break;
-                        } else if self.packet_count % 10 == 0 {
+                        } else {
+                            self.degrade("decode error");
+                        }
+                    } else if self.waiting_for_keyframe {
+                        if idr {
                             dec_log!(
-                                "[DEC] Decode NAL error (packet {}): {}",
-                                self.packet_count,
-                                e
+                                "[DEC] Found keyframe (packet {}), resuming decode",
+                                self.packet_count
                             );
+                            self.waiting_for_keyframe = false;
                         }
+                        // Nothing decoded before the IDR is trustworthy
+                        decoded_frames.truncate(frames_before + usize::from(idr));
                     } else {
                         let frames_after = decoded_frames.len();
-                        if frames_after > frames_before && self.packet_count % 200 == 0 {
+                        if frames_after > frames_before && self.packet_count.is_multiple_of(200) {
                             dec_log!(
                                 "[DEC] Decoded NAL #{} ({} bytes) -> {} frames",
                                 self.packet_count,
@@ -223,7 +274,7 @@ impl VideoDecoder {
         let frames_produced = decoded_frames.len();
         if frames_produced > 0 {
             self.last_frame_time = Instant::now();
-            if self.packet_count % 300 == 0 {
+            if self.packet_count.is_multiple_of(300) {
                 dec_log!(
                     "[DEC] Produced {} frames from {} bytes input (buffer: {}KB -> {}KB)",
                     frames_produced,
@@ -232,7 +283,7 @@ impl VideoDecoder {
                     self.buffer.len() / 1024
                 );
             }
-        } else if input_size > 0 && self.packet_count % 100 == 0 {
+        } else if input_size > 0 && self.packet_count.is_multiple_of(100) {
             dec_log!(
                 "[DEC] WARNING: No frames produced from {} bytes input (buffer: {}KB, packets={})",
                 input_size,
@@ -245,6 +296,14 @@ impl VideoDecoder {
     }
 
     fn decode_nal(&mut self, nal_data: &[u8], frames_out: &mut Vec<YuvFrame>) -> Result<()> {
+        // Track the colour description signalled in the SPS
+        if let Some(signal) = parse_sps_signal(nal_data) {
+            if signal != self.signal {
+                dec_log!("[DEC] SPS colour description: {:?}", signal);
+                self.signal = signal;
+            }
+        }
+
         // Decode NAL unit with OpenH264
         match self.decoder.decode(nal_data) {
             Ok(Some(yuv)) => {
@@ -292,6 +351,7 @@ impl VideoDecoder {
                     v_plane: v_packed,
                     y_stride: w,
                     uv_stride: uv_w,
+                    signal: self.signal,
                 });
             }
             Ok(None) => {
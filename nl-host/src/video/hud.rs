@@ -0,0 +1,313 @@
+//! Text layers drawn by `MirrorRenderer` over the video (performance HUD, key captions)
+
+use crate::video::font;
+use wgpu::util::DeviceExt;
+
+/// Glyph scale in screen pixels
+const HUD_SCALE: usize = 2;
+/// Padding around the text in screen pixels
+const HUD_PADDING: usize = 8;
+/// Distance from the window edge in screen pixels
+const HUD_MARGIN: f32 = 8.0;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 170];
+
+/// Where a text layer sits in the window
+#[derive(Clone, Copy)]
+pub enum HudAnchor {
+    TopLeft,
+    BottomCenter,
+    Center,
+}
+
+/// Placement and look of a text layer
+#[derive(Clone, Copy)]
+pub struct HudStyle {
+    pub anchor: HudAnchor,
+    /// Pad lines to this many characters so the texture size stays stable
+    pub columns: Option<usize>,
+    pub text_color: [u8; 4],
+}
+
+impl HudStyle {
+    /// Performance HUD in the top-left corner
+    pub const STATS: Self = Self {
+        anchor: HudAnchor::TopLeft,
+        columns: Some(24),
+        text_color: [120, 255, 120, 255],
+    };
+
+    /// Key press caption at the bottom of the window
+    pub const CAPTION: Self = Self {
+        anchor: HudAnchor::BottomCenter,
+        columns: None,
+        text_color: [255, 255, 255, 255],
+    };
+
+    /// Connection status in the middle of the window
+    pub const BANNER: Self = Self {
+        anchor: HudAnchor::Center,
+        columns: None,
+        text_color: [255, 210, 80, 255],
+    };
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct RectUniform {
+    bounds: [f32; 4],
+}
+
+struct HudTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+pub struct HudLayer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    rect_buffer: wgpu::Buffer,
+    texture: Option<HudTexture>,
+    visible: bool,
+    style: HudStyle,
+}
+
+impl HudLayer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, style: HudStyle) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("HUD Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // The HUD is drawn 1:1, so nearest keeps the glyphs crisp
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let rect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("HUD Rect Buffer"),
+            contents: bytemuck::cast_slice(&[RectUniform { bounds: [0.0; 4] }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("HUD Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("hud.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            rect_buffer,
+            texture: None,
+            visible: false,
+            style,
+        }
+    }
+
+    /// Show `lines` (or hide the HUD with `None`)
+    pub fn set_text(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        lines: Option<&[String]>,
+        surface_size: (u32, u32),
+    ) {
+        let Some(lines) = lines else {
+            self.visible = false;
+            return;
+        };
+        self.visible = true;
+
+        let columns = self
+            .style
+            .columns
+            .unwrap_or_else(|| lines.iter().map(|l| l.chars().count()).max().unwrap_or(0));
+        let (text_w, text_h) = font::text_size(columns, lines.len().max(1), HUD_SCALE);
+        let width = (text_w + HUD_PADDING * 2) as u32;
+        let height = (text_h + HUD_PADDING * 2) as u32;
+
+        let mut rgba = BACKGROUND.repeat((width * height) as usize);
+        let line_height = font::text_size(0, 1, HUD_SCALE).1;
+        for (i, line) in lines.iter().enumerate() {
+            let text: String = line.chars().take(columns).collect();
+            font::draw_text(
+                &mut rgba,
+                width as usize,
+                HUD_PADDING,
+                HUD_PADDING + i * line_height,
+                &text,
+                HUD_SCALE,
+                self.style.text_color,
+            );
+        }
+
+        let reuse = matches!(&self.texture, Some(t) if t.width == width && t.height == height);
+        if !reuse {
+            self.texture = Some(self.create_texture(device, width, height));
+            self.resize(queue, surface_size);
+        }
+        if let Some(t) = &self.texture {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &t.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &rgba,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    /// Keep the layer at 1:1 pixels at its anchor after a surface resize
+    pub fn resize(&self, queue: &wgpu::Queue, (surface_w, surface_h): (u32, u32)) {
+        let Some(t) = &self.texture else {
+            return;
+        };
+        let (sw, sh) = (surface_w.max(1) as f32, surface_h.max(1) as f32);
+        let (w, h) = (2.0 * t.width as f32 / sw, 2.0 * t.height as f32 / sh);
+        let (left, top) = match self.style.anchor {
+            HudAnchor::TopLeft => (-1.0 + 2.0 * HUD_MARGIN / sw, 1.0 - 2.0 * HUD_MARGIN / sh),
+            HudAnchor::BottomCenter => (-w / 2.0, -1.0 + h + 2.0 * HUD_MARGIN * 4.0 / sh),
+            HudAnchor::Center => (-w / 2.0, h / 2.0),
+        };
+        let bounds = [left, top, left + w, top - h];
+        queue.write_buffer(
+            &self.rect_buffer,
+            0,
+            bytemuck::cast_slice(&[RectUniform { bounds }]),
+        );
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if !self.visible {
+            return;
+        }
+        if let Some(t) = &self.texture {
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &t.bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+    }
+
+    fn create_texture(&self, device: &wgpu::Device, width: u32, height: u32) -> HudTexture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HUD Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("HUD Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.rect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        HudTexture {
+            texture,
+            bind_group,
+            width,
+            height,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Performance HUD: a textured quad drawn over the video with alpha blending
+
+struct Rect {
+    bounds: vec4<f32>,  // left, top, right, bottom in clip space
+};
+
+@group(0) @binding(0)
+var<uniform> rect: Rect;
+@group(0) @binding(1)
+var hud_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var hud_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    // Two triangles, texture (0,0) = top-left
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+    );
+    let uv = corners[vertex_index];
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(
+        mix(rect.bounds.x, rect.bounds.z, uv.x),
+        mix(rect.bounds.y, rect.bounds.w, uv.y),
+        0.0,
+        1.0,
+    );
+    out.tex_coords = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(hud_texture, hud_sampler, in.tex_coords);
+}
@@ -1,9 +1,16 @@
 //! Video module - Decoding and rendering pipeline
 
 mod decoder;
+pub(crate) mod font;
+mod hud;
+mod mp4;
+mod overlay;
 pub mod pipeline;
+mod recorder;
 mod renderer;
+mod sps;
 
 pub use decoder::{VideoDecoder, YuvFrame};
 pub use pipeline::start_decoder_thread;
-pub use renderer::MirrorRenderer;
+pub use recorder::{Recorder, RecordingSummary};
+pub use renderer::{MirrorRenderer, ScaleFilter};
@@ -0,0 +1,486 @@
+//! Minimal MP4 muxer for a single H.264 track, used by the recorder
+//!
+//! Samples go straight into `mdat` as they arrive; their sizes and timestamps are kept in
+//! memory and written as `moov` by `finish`. A file that is never finished does not play.
+
+use crate::video::sps::parse_sps_size;
+use anyhow::{anyhow, Result};
+use std::io::{Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Sample timestamps are in microseconds, like the stream
+const TIMESCALE: u32 = 1_000_000;
+
+/// Duration of the last sample, which has no successor to measure against (one 60 fps frame)
+const LAST_SAMPLE_US: u32 = 16_667;
+
+/// Seconds from 1904-01-01, where MP4 times start, to the Unix epoch
+const MP4_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+/// `mdat` header with a 64-bit size, so recordings may pass 4 GB
+const MDAT_HEADER: u64 = 16;
+
+/// Identity transform of `mvhd` and `tkhd`
+const MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+struct Sample {
+    size: u32,
+    pts: u64,
+    sync: bool,
+}
+
+/// What `Mp4Writer::finish` wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mp4Summary {
+    pub frames: u64,
+    pub duration_us: u64,
+}
+
+/// Writes one H.264 track. Samples are access units in AVCC form (every NAL unit prefixed
+/// with its 4-byte length) in decoding order, which is presentation order for the device
+/// encoder (no B-frames).
+pub struct Mp4Writer<W: Write + Seek> {
+    out: W,
+    mdat_start: u64,
+    samples: Vec<Sample>,
+    created: u64,
+}
+
+impl<W: Write + Seek> Mp4Writer<W> {
+    /// Write the file header; the sample description follows with `finish`
+    pub fn new(mut out: W) -> Result<Self> {
+        let mut ftyp = Vec::new();
+        ftyp.extend_from_slice(b"isom");
+        ftyp.u32(0x200);
+        for brand in [b"isom", b"iso2", b"avc1", b"mp41"] {
+            ftyp.extend_from_slice(brand);
+        }
+        out.write_all(&boxed(b"ftyp", &ftyp))?;
+
+        let mdat_start = out.stream_position()?;
+        let mut mdat = Vec::new();
+        mdat.u32(1); // the size follows as 64 bits
+        mdat.extend_from_slice(b"mdat");
+        mdat.u64(0);
+        out.write_all(&mdat)?;
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            + MP4_EPOCH_OFFSET;
+        Ok(Self {
+            out,
+            mdat_start,
+            samples: Vec::new(),
+            created,
+        })
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.samples.len() as u64
+    }
+
+    /// Append one access unit; `pts` in microseconds, increasing
+    pub fn write_sample(&mut self, data: &[u8], pts: u64, sync: bool) -> Result<()> {
+        let size = u32::try_from(data.len())?;
+        self.out.write_all(data)?;
+        self.samples.push(Sample { size, pts, sync });
+        Ok(())
+    }
+
+    /// Close `mdat` and write the sample tables for a stream with this SPS and PPS (NAL
+    /// units without start codes)
+    pub fn finish(mut self, sps: &[u8], pps: &[u8]) -> Result<(W, Mp4Summary)> {
+        let (width, height) = parse_sps_size(sps).ok_or_else(|| anyhow!("Invalid SPS"))?;
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(anyhow!("Frame size {}x{} too large", width, height));
+        };
+
+        let mdat_end = self.out.stream_position()?;
+        let durations: Vec<u32> = self
+            .samples
+            .windows(2)
+            .map(|pair| {
+                let delta = pair[1].pts.saturating_sub(pair[0].pts);
+                delta.clamp(1, u32::MAX as u64) as u32
+            })
+            .chain((!self.samples.is_empty()).then_some(LAST_SAMPLE_US))
+            .collect();
+        let duration = durations.iter().map(|&d| d as u64).sum();
+
+        let moov = self.moov(&durations, duration, width, height, sps, pps);
+        self.out.write_all(&moov)?;
+        self.out.seek(SeekFrom::Start(self.mdat_start + 8))?;
+        self.out
+            .write_all(&(mdat_end - self.mdat_start).to_be_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+
+        let summary = Mp4Summary {
+            frames: self.frames(),
+            duration_us: duration,
+        };
+        Ok((self.out, summary))
+    }
+
+    fn moov(
+        &self,
+        durations: &[u32],
+        duration: u64,
+        width: u16,
+        height: u16,
+        sps: &[u8],
+        pps: &[u8],
+    ) -> Vec<u8> {
+        let mut mvhd = Vec::new();
+        mvhd.u64(self.created);
+        mvhd.u64(self.created);
+        mvhd.u32(TIMESCALE);
+        mvhd.u64(duration);
+        mvhd.u32(0x0001_0000); // rate 1.0
+        mvhd.u16(0x0100); // volume 1.0
+        mvhd.extend_from_slice(&[0; 10]);
+        mvhd.matrix();
+        mvhd.extend_from_slice(&[0; 24]);
+        mvhd.u32(2); // next track ID
+
+        let mut tkhd = Vec::new();
+        tkhd.u64(self.created);
+        tkhd.u64(self.created);
+        tkhd.u32(1); // track ID
+        tkhd.u32(0);
+        tkhd.u64(duration);
+        tkhd.extend_from_slice(&[0; 16]); // reserved, layer, group, volume, reserved
+        tkhd.matrix();
+        tkhd.u32((width as u32) << 16);
+        tkhd.u32((height as u32) << 16);
+
+        let mut mdhd = Vec::new();
+        mdhd.u64(self.created);
+        mdhd.u64(self.created);
+        mdhd.u32(TIMESCALE);
+        mdhd.u64(duration);
+        mdhd.u16(0x55c4); // "und"
+        mdhd.u16(0);
+
+        let mut hdlr = Vec::new();
+        hdlr.u32(0);
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0; 12]);
+        hdlr.extend_from_slice(b"VideoHandler\0");
+
+        let mut dref = Vec::new();
+        dref.u32(1);
+        dref.extend_from_slice(&full_box(b"url ", 0, 1, &[])); // media in this file
+
+        let stbl = [
+            full_box(b"stsd", 0, 0, &sample_entry(width, height, sps, pps)),
+            full_box(b"stts", 0, 0, &time_to_sample(durations)),
+            full_box(b"stss", 0, 0, &self.sync_samples()),
+            full_box(b"stsc", 0, 0, &self.sample_to_chunk()),
+            full_box(b"stsz", 0, 0, &self.sample_sizes()),
+            full_box(b"co64", 0, 0, &self.chunk_offsets()),
+        ]
+        .concat();
+        let minf = [
+            full_box(b"vmhd", 0, 1, &[0; 8]),
+            boxed(b"dinf", &full_box(b"dref", 0, 0, &dref)),
+            boxed(b"stbl", &stbl),
+        ]
+        .concat();
+        let mdia = [
+            full_box(b"mdhd", 1, 0, &mdhd),
+            full_box(b"hdlr", 0, 0, &hdlr),
+            boxed(b"minf", &minf),
+        ]
+        .concat();
+        let trak = [full_box(b"tkhd", 1, 3, &tkhd), boxed(b"mdia", &mdia)].concat();
+        boxed(
+            b"moov",
+            &[full_box(b"mvhd", 1, 0, &mvhd), boxed(b"trak", &trak)].concat(),
+        )
+    }
+
+    fn sync_samples(&self) -> Vec<u8> {
+        let numbers: Vec<u32> = (1..)
+            .zip(&self.samples)
+            .filter(|(_, sample)| sample.sync)
+            .map(|(number, _)| number)
+            .collect();
+        let mut body = Vec::new();
+        body.u32(numbers.len() as u32);
+        for number in numbers {
+            body.u32(number);
+        }
+        body
+    }
+
+    /// Every sample in one chunk: `mdat` holds nothing else
+    fn sample_to_chunk(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        if self.samples.is_empty() {
+            body.u32(0);
+        } else {
+            body.u32(1);
+            body.u32(1); // first chunk
+            body.u32(self.samples.len() as u32);
+            body.u32(1); // sample description
+        }
+        body
+    }
+
+    fn sample_sizes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.u32(0); // sizes differ
+        body.u32(self.samples.len() as u32);
+        for sample in &self.samples {
+            body.u32(sample.size);
+        }
+        body
+    }
+
+    fn chunk_offsets(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        if self.samples.is_empty() {
+            body.u32(0);
+        } else {
+            body.u32(1);
+            body.u64(self.mdat_start + MDAT_HEADER);
+        }
+        body
+    }
+}
+
+/// `avc1` entry with the `avcC` decoder configuration
+fn sample_entry(width: u16, height: u16, sps: &[u8], pps: &[u8]) -> Vec<u8> {
+    let mut avcc = vec![1, sps[1], sps[2], sps[3], 0xff, 0xe1]; // 4-byte lengths, one SPS
+    avcc.u16(sps.len() as u16);
+    avcc.extend_from_slice(sps);
+    avcc.push(1);
+    avcc.u16(pps.len() as u16);
+    avcc.extend_from_slice(pps);
+
+    let mut avc1 = vec![0; 6];
+    avc1.u16(1); // data reference
+    avc1.extend_from_slice(&[0; 16]);
+    avc1.u16(width);
+    avc1.u16(height);
+    avc1.u32(0x0048_0000); // 72 dpi
+    avc1.u32(0x0048_0000);
+    avc1.u32(0);
+    avc1.u16(1); // frames per sample
+    avc1.extend_from_slice(&[0; 32]); // compressor name
+    avc1.u16(0x18); // depth
+    avc1.u16(0xffff);
+    avc1.extend_from_slice(&boxed(b"avcC", &avcc));
+
+    let mut body = Vec::new();
+    body.u32(1);
+    body.extend_from_slice(&boxed(b"avc1", &avc1));
+    body
+}
+
+/// Run-length coded sample durations
+fn time_to_sample(durations: &[u32]) -> Vec<u8> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for &duration in durations {
+        match runs.last_mut() {
+            Some((count, last)) if *last == duration => *count += 1,
+            _ => runs.push((1, duration)),
+        }
+    }
+    let mut body = Vec::new();
+    body.u32(runs.len() as u32);
+    for (count, duration) in runs {
+        body.u32(count);
+        body.u32(duration);
+    }
+    body
+}
+
+fn boxed(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + body.len());
+    out.u32(8 + body.len() as u32);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    out
+}
+
+fn full_box(kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + body.len());
+    out.u32((version as u32) << 24 | flags);
+    out.extend_from_slice(body);
+    boxed(kind, &out)
+}
+
+/// Big-endian fields of a box body
+trait BoxBody {
+    fn u16(&mut self, value: u16);
+    fn u32(&mut self, value: u32);
+    fn u64(&mut self, value: u64);
+    fn matrix(&mut self);
+}
+
+impl BoxBody for Vec<u8> {
+    fn u16(&mut self, value: u16) {
+        self.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn matrix(&mut self) {
+        for value in MATRIX {
+            self.u32(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Baseline 1280x720 without VUI
+    const SPS: &[u8] = &[0x67, 0x42, 0x00, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe4];
+    const PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+
+    /// Boxes directly inside `data`, as (type, body)
+    fn boxes(mut data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        let mut out = Vec::new();
+        while !data.is_empty() {
+            let mut size = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = data[4..8].try_into().unwrap();
+            let mut header = 8;
+            if size == 1 {
+                size = u64::from_be_bytes(data[8..16].try_into().unwrap()) as usize;
+                header = 16;
+            }
+            out.push((kind, &data[header..size]));
+            data = &data[size..];
+        }
+        out
+    }
+
+    /// Body of the box at `path`; full boxes keep their version and flags
+    fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+        path.iter().fold(data, |data, kind| {
+            boxes(data)
+                .into_iter()
+                .find(|(k, _)| k == *kind)
+                .unwrap_or_else(|| panic!("no {}", String::from_utf8_lossy(*kind)))
+                .1
+        })
+    }
+
+    fn words(body: &[u8]) -> Vec<u32> {
+        body[4..]
+            .chunks(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    const STBL: [&[u8; 4]; 5] = [b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+    fn stbl<'a>(file: &'a [u8], kind: &[u8; 4]) -> &'a [u8] {
+        let mut path = STBL.to_vec();
+        path.push(kind);
+        find(file, &path)
+    }
+
+    /// Three frames 33.3 ms apart, the first a keyframe
+    fn record() -> (Vec<u8>, Mp4Summary) {
+        let mut writer = Mp4Writer::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .write_sample(&[0, 0, 0, 2, 0x65, 1], 1_000, true)
+            .unwrap();
+        writer
+            .write_sample(&[0, 0, 0, 1, 0x41], 34_333, false)
+            .unwrap();
+        writer
+            .write_sample(&[0, 0, 0, 1, 0x41], 67_666, false)
+            .unwrap();
+        let (out, summary) = writer.finish(SPS, PPS).unwrap();
+        (out.into_inner(), summary)
+    }
+
+    #[test]
+    fn samples_fill_mdat_before_moov() {
+        let (file, summary) = record();
+        let kinds: Vec<[u8; 4]> = boxes(&file).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(kinds, [*b"ftyp", *b"mdat", *b"moov"]);
+        assert_eq!(
+            find(&file, &[b"mdat"]),
+            [0, 0, 0, 2, 0x65, 1, 0, 0, 0, 1, 0x41, 0, 0, 0, 1, 0x41]
+        );
+        assert_eq!(
+            summary,
+            Mp4Summary {
+                frames: 3,
+                duration_us: 66_666 + LAST_SAMPLE_US as u64,
+            }
+        );
+    }
+
+    #[test]
+    fn sample_tables() {
+        let (file, _) = record();
+        assert_eq!(
+            words(stbl(&file, b"stts")),
+            [2, 2, 33_333, 1, LAST_SAMPLE_US]
+        );
+        assert_eq!(words(stbl(&file, b"stss")), [1, 1]);
+        assert_eq!(words(stbl(&file, b"stsc")), [1, 1, 3, 1]);
+        assert_eq!(words(stbl(&file, b"stsz")), [0, 3, 6, 5, 5]);
+
+        let co64 = stbl(&file, b"co64");
+        let offset = u64::from_be_bytes(co64[8..16].try_into().unwrap()) as usize;
+        assert_eq!(file[offset..offset + 6], [0, 0, 0, 2, 0x65, 1]);
+    }
+
+    #[test]
+    fn sample_entry_from_parameter_sets() {
+        let (file, summary) = record();
+        let stsd = stbl(&file, b"stsd");
+        let avc1 = find(&stsd[8..], &[b"avc1"]);
+        assert_eq!(avc1[24..28], [0x05, 0x00, 0x02, 0xd0]); // 1280x720
+        let avcc = find(&avc1[78..], &[b"avcC"]);
+        assert_eq!(avcc[..6], [1, 0x42, 0x00, 0x1f, 0xff, 0xe1]);
+        assert_eq!(avcc[8..8 + SPS.len()], *SPS);
+        assert_eq!(avcc[8 + SPS.len() + 3..], *PPS);
+
+        // Version 1: 64-bit times after the version and flags
+        let tkhd = find(&file, &[b"moov", b"trak", b"tkhd"]);
+        assert_eq!(words(&tkhd[tkhd.len() - 12..]), [1280 << 16, 720 << 16]);
+        let mdhd = find(&file, &[b"moov", b"trak", b"mdia", b"mdhd"]);
+        assert_eq!(mdhd[20..24], TIMESCALE.to_be_bytes());
+        assert_eq!(mdhd[24..32], summary.duration_us.to_be_bytes());
+    }
+
+    #[test]
+    fn timestamps_that_do_not_advance_still_take_time() {
+        let mut writer = Mp4Writer::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .write_sample(&[0, 0, 0, 1, 0x65], 5_000, true)
+            .unwrap();
+        writer
+            .write_sample(&[0, 0, 0, 1, 0x41], 5_000, false)
+            .unwrap();
+        let (out, _) = writer.finish(SPS, PPS).unwrap();
+        assert_eq!(
+            words(stbl(&out.into_inner(), b"stts")),
+            [2, 1, 1, 1, LAST_SAMPLE_US]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_sps() {
+        let writer = Mp4Writer::new(Cursor::new(Vec::new())).unwrap();
+        assert!(writer.finish(PPS, PPS).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Touch visualization layer drawn by `MirrorRenderer` over the video
+
+use crate::core::Shape;
+use wgpu::util::DeviceExt;
+
+/// Instance capacity allocated up front (grows if exceeded)
+const INITIAL_CAPACITY: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewUniform {
+    frame_size: [f32; 2],
+    scale: [f32; 2],
+    region: [f32; 4],
+}
+
+/// Mirrors `ShapeInput` in overlay.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShapeInstance {
+    p0: [f32; 2],
+    p1: [f32; 2],
+    params: [f32; 2],
+    color: [f32; 4],
+}
+
+impl From<&Shape> for ShapeInstance {
+    fn from(shape: &Shape) -> Self {
+        Self {
+            p0: shape.p0,
+            p1: shape.p1,
+            params: [shape.radius, shape.outline],
+            color: shape.color,
+        }
+    }
+}
+
+pub struct OverlayLayer {
+    pipeline: wgpu::RenderPipeline,
+    view_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    capacity: usize,
+    count: u32,
+}
+
+impl OverlayLayer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Overlay Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay View Buffer"),
+            contents: bytemuck::cast_slice(&[ViewUniform {
+                frame_size: [1.0, 1.0],
+                scale: [1.0, 1.0],
+                region: [0.0, 0.0, 1.0, 1.0],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Overlay Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: view_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("overlay.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Overlay Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShapeInstance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Float32x2,
+                        3 => Float32x4,
+                    ],
+                }],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let instance_buffer = Self::create_instance_buffer(device, INITIAL_CAPACITY);
+
+        Self {
+            pipeline,
+            view_buffer,
+            bind_group,
+            instance_buffer,
+            capacity: INITIAL_CAPACITY,
+            count: 0,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Overlay Instance Buffer"),
+            size: (capacity * std::mem::size_of::<ShapeInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Map frame pixels to the letterboxed video area, showing only `region` of the frame
+    pub fn set_view(
+        &self,
+        queue: &wgpu::Queue,
+        frame_size: (u32, u32),
+        scale: [f32; 2],
+        region: [f32; 4],
+    ) {
+        let view = ViewUniform {
+            frame_size: [frame_size.0.max(1) as f32, frame_size.1.max(1) as f32],
+            scale,
+            region,
+        };
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[view]));
+    }
+
+    pub fn set_shapes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, shapes: &[Shape]) {
+        let instances: Vec<ShapeInstance> = shapes.iter().map(ShapeInstance::from).collect();
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.capacity);
+        }
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+        self.count = instances.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        pass.draw(0..6, 0..self.count);
+    }
+}
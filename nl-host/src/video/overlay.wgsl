@@ -0,0 +1,85 @@
+// Touch visualization: instanced capsules (circles, rings, trails) in video frame pixels
+
+struct View {
+    frame_size: vec2<f32>,  // video frame size in pixels
+    scale: vec2<f32>,       // aspect ratio scale, same as the video quad
+    region: vec4<f32>,      // visible part of the frame (zoom/pan): x, y, width, height (0-1)
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+struct ShapeInput {
+    @location(0) p0: vec2<f32>,
+    @location(1) p1: vec2<f32>,
+    @location(2) params: vec2<f32>,  // radius, outline width (0 = filled)
+    @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) pos: vec2<f32>,
+    @location(1) @interpolate(flat) p0: vec2<f32>,
+    @location(2) @interpolate(flat) p1: vec2<f32>,
+    @location(3) @interpolate(flat) params: vec2<f32>,
+    @location(4) @interpolate(flat) color: vec4<f32>,
+    @location(5) quad: vec2<f32>,  // position in the video area (0-1 when visible)
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, shape: ShapeInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+
+    // Bounding box of the capsule plus room for the outline and anti-aliasing
+    let pad = shape.params.x + shape.params.y + 2.0;
+    let lo = min(shape.p0, shape.p1) - vec2<f32>(pad);
+    let hi = max(shape.p0, shape.p1) + vec2<f32>(pad);
+    let pos = mix(lo, hi, corners[vertex_index]);
+
+    // Frame pixels (y down) -> visible region -> clip space, letterboxed like the video
+    let quad = (pos / view.frame_size - view.region.xy) / view.region.zw;
+    let ndc = vec2<f32>(quad.x * 2.0 - 1.0, 1.0 - quad.y * 2.0) * view.scale;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.pos = pos;
+    out.quad = quad;
+    out.p0 = shape.p0;
+    out.p1 = shape.p1;
+    out.params = shape.params;
+    out.color = shape.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Zoomed in: do not draw over the letterbox bars
+    if any(in.quad < vec2<f32>(0.0)) || any(in.quad > vec2<f32>(1.0)) {
+        discard;
+    }
+
+    // Signed distance to the capsule (same as shape_coverage in core/touches.rs)
+    let pa = in.pos - in.p0;
+    let ba = in.p1 - in.p0;
+    let len2 = dot(ba, ba);
+    var t = 0.0;
+    if len2 > 0.0 {
+        t = clamp(dot(pa, ba) / len2, 0.0, 1.0);
+    }
+    var d = length(pa - ba * t) - in.params.x;
+    if in.params.y > 0.0 {
+        d = abs(d) - in.params.y * 0.5;
+    }
+
+    // Anti-alias over one screen pixel
+    let aa = max(fwidth(d), 1e-4);
+    let coverage = clamp(0.5 - d / aa, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -1,27 +1,61 @@
 //! Video decoding pipeline
 
-use crate::core::{FrameBuffer, FrameData};
-use crate::video::{VideoDecoder, YuvFrame};
-use crate::{log_error, log_verbose};
-use crossbeam_channel::Receiver;
+use crate::core::events::{Event, EventSink};
+use crate::core::{ColorOverride, ColorSpace, FrameBuffer, FrameData, SessionStats, Stage};
+use crate::input::InputCommand;
+use crate::network::VideoPacket;
+use crate::video::{Recorder, VideoDecoder, YuvFrame};
+use crossbeam_channel::{Receiver, Sender};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-/// Start the decoder thread that processes H264 data and produces frames
+/// Minimum time between keyframe requests (the device needs a round trip plus one encode)
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Largest PTS step between frames before frames count as missing. The device repeats the
+/// last frame every 100ms on a static screen, so real gaps are much shorter.
+const MAX_PTS_GAP_US: u64 = 1_000_000;
+
+/// Start the decoder thread that processes H264 data and produces frames.
+/// Every packet also goes to `recorder`. Keyframe requests go out through `control` whenever
+/// the decoder loses sync or a recording waits for a keyframe.
 pub fn start_decoder_thread(
-    rx: Receiver<Vec<u8>>,
+    rx: Receiver<VideoPacket>,
     frame_buffer: Arc<FrameBuffer>,
+    color_override: ColorOverride,
+    control: Sender<InputCommand>,
+    stats: Arc<SessionStats>,
+    recorder: Arc<Recorder>,
+    events: EventSink,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let mut decoder = match VideoDecoder::new() {
             Ok(d) => d,
             Err(e) => {
                 log_error!("DEC", "Init failed: {}", e);
+                events.emit(Event::Error {
+                    message: format!("Decoder init failed: {}", e),
+                    fatal: true,
+                });
                 return;
             }
         };
 
         let mut frame_count = 0u64;
+        let mut color = ColorTracker {
+            color_override,
+            current: None,
+        };
+        let mut keyframes = KeyframeRequester {
+            control,
+            last_request: None,
+        };
+        let mut health = StreamHealth {
+            last_pts: None,
+            degraded_since: None,
+        };
         let mut recv_count = 0u64;
         let start = std::time::Instant::now();
         let mut last_log = std::time::Instant::now();
@@ -35,31 +69,55 @@ pub fn start_decoder_thread(
             }
 
             match rx.recv_timeout(std::time::Duration::from_secs(1)) {
-                Ok(data) => {
+                Ok(packet) => {
                     recv_count += 1;
-                    let data_size = data.len();
+                    let data_size = packet.data.len();
 
-                    if recv_count % 100 == 0 {
+                    if recv_count.is_multiple_of(100) {
                         log_verbose!("DEC", "Recv #{}: {} bytes", recv_count, data_size);
                     }
 
-                    match decoder.decode(&data) {
+                    if recorder.push(&packet) {
+                        keyframes.request(&stats);
+                    }
+                    health.check_continuity(&packet, &mut decoder);
+                    let result = decoder.decode(&packet.data);
+                    let resets = decoder.reset_count();
+                    if resets != stats.decoder_resets.swap(resets, Ordering::Relaxed) {
+                        events.emit(Event::DecoderReset {
+                            count: resets,
+                            reason: decoder.degrade_reason().to_string(),
+                        });
+                    }
+                    match result {
                         Ok(frames) => {
-                            process_decoded_frames(
+                            SessionStats::add(&stats.frames_decoded, frames.len() as u64);
+                            if !frames.is_empty() {
+                                stats
+                                    .latency
+                                    .record(Stage::Decode, packet.received_at.elapsed());
+                            }
+                            let skipped = process_decoded_frames(
                                 frames,
                                 &frame_buffer,
                                 &mut frame_count,
+                                &mut color,
                                 recv_count,
-                                data_size,
+                                &packet,
                                 &start,
                             );
+                            SessionStats::add(&stats.frames_dropped, skipped);
                         }
                         Err(e) => {
-                            if recv_count % 50 == 0 {
+                            if recv_count.is_multiple_of(50) {
                                 log_verbose!("DEC", "Error: {}", e);
                             }
                         }
                     }
+                    health.update(&decoder, &stats);
+                    if decoder.is_degraded() {
+                        keyframes.request(&stats);
+                    }
 
                     // Stats every 10 seconds
                     if last_log.elapsed().as_secs() >= 10 {
@@ -78,21 +136,118 @@ pub fn start_decoder_thread(
     })
 }
 
+/// Detects lost packets and tracks the degraded state for the HUD and logs
+struct StreamHealth {
+    last_pts: Option<u64>,
+    degraded_since: Option<Instant>,
+}
+
+impl StreamHealth {
+    /// Degrade the decoder if packets before this one are missing
+    fn check_continuity(&mut self, packet: &VideoPacket, decoder: &mut VideoDecoder) {
+        if packet.discontinuity {
+            decoder.discontinuity("packets lost");
+            self.last_pts = None;
+        }
+        // Codec config carries no timestamp
+        if packet.pts == 0 {
+            return;
+        }
+        if let Some(last) = self.last_pts {
+            if packet.pts.abs_diff(last) > MAX_PTS_GAP_US {
+                decoder.discontinuity("PTS gap");
+            }
+        }
+        self.last_pts = Some(packet.pts);
+    }
+
+    /// Publish and log degraded/recovered transitions
+    fn update(&mut self, decoder: &VideoDecoder, stats: &SessionStats) {
+        match (decoder.is_degraded(), self.degraded_since) {
+            (true, None) => {
+                log_info!(
+                    "DEC",
+                    "Stream degraded ({}), holding last good frame",
+                    decoder.degrade_reason()
+                );
+                self.degraded_since = Some(Instant::now());
+                stats.degraded.store(true, Ordering::Relaxed);
+            }
+            (false, Some(since)) => {
+                log_info!(
+                    "DEC",
+                    "Stream recovered after {} ms",
+                    since.elapsed().as_millis()
+                );
+                self.degraded_since = None;
+                stats.degraded.store(false, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Asks the device for an IDR frame instead of waiting out the GOP, at most once per
+/// `KEYFRAME_REQUEST_INTERVAL` (repeated while the decoder is still out of sync)
+struct KeyframeRequester {
+    control: Sender<InputCommand>,
+    last_request: Option<Instant>,
+}
+
+impl KeyframeRequester {
+    fn request(&mut self, stats: &SessionStats) {
+        if self
+            .last_request
+            .is_some_and(|t| t.elapsed() < KEYFRAME_REQUEST_INTERVAL)
+        {
+            return;
+        }
+        self.last_request = Some(Instant::now());
+        if self.control.try_send(InputCommand::RequestKeyframe).is_ok() {
+            SessionStats::add(&stats.keyframe_requests, 1);
+            log_verbose!("DEC", "Requested keyframe");
+        }
+    }
+}
+
+/// Resolves each frame's colour space and logs when it changes
+struct ColorTracker {
+    color_override: ColorOverride,
+    current: Option<ColorSpace>,
+}
+
+impl ColorTracker {
+    fn resolve(&mut self, yuv: &YuvFrame) -> ColorSpace {
+        let color = ColorSpace::resolve(yuv.signal, self.color_override, yuv.width, yuv.height);
+        if self.current != Some(color) {
+            log_info!("DEC", "Colour space: {}", color);
+            self.current = Some(color);
+        }
+        color
+    }
+}
+
+/// Push decoded frames to the frame buffer; returns how many pending frames were replaced
 fn process_decoded_frames(
     frames: Vec<YuvFrame>,
     frame_buffer: &Arc<FrameBuffer>,
     frame_count: &mut u64,
+    color: &mut ColorTracker,
     recv_count: u64,
-    data_size: usize,
+    packet: &VideoPacket,
     start: &std::time::Instant,
-) {
+) -> u64 {
+    let data_size = packet.data.len();
     let frames_count = frames.len();
     let mut frames_stored = 0;
+    let mut frames_skipped = 0;
 
     for yuv in frames {
         *frame_count += 1;
         frames_stored += 1;
 
+        let color = color.resolve(&yuv);
+        let now = Instant::now();
         let frame = FrameData {
             width: yuv.width,
             height: yuv.height,
@@ -101,23 +256,31 @@ fn process_decoded_frames(
             v_plane: Arc::new(yuv.v_plane),
             y_stride: yuv.y_stride,
             uv_stride: yuv.uv_stride,
+            color,
+            pts: packet.pts,
+            decoded_at: now,
+            ready_at: now,
         };
 
         let skipped = frame_buffer.push(frame);
-        if skipped && *frame_count % 100 == 0 {
+        if skipped {
+            frames_skipped += 1;
+        }
+        if skipped && frame_count.is_multiple_of(100) {
             log_verbose!("DEC", "Frame skipped at #{}", *frame_count);
         }
 
         // Progress log every 600 frames (~10s at 60fps)
-        if *frame_count % 600 == 0 {
+        if frame_count.is_multiple_of(600) {
             let elapsed = start.elapsed().as_secs();
             log_verbose!("DEC", "Frame #{} at {}s", *frame_count, elapsed);
         }
     }
 
-    if frames_stored == 0 && recv_count % 50 == 0 {
+    if frames_stored == 0 && recv_count.is_multiple_of(50) {
         log_verbose!("DEC", "No frames from {} bytes", data_size);
-    } else if recv_count % 200 == 0 && frames_count > 0 {
+    } else if recv_count.is_multiple_of(200) && frames_count > 0 {
         log_verbose!("DEC", "{} bytes -> {} frames", data_size, frames_stored);
     }
+    frames_skipped
 }
@@ -0,0 +1,537 @@
+//! Recording of the video stream to MP4 (`start_recording` over `--ipc`)
+//!
+//! The H.264 stream is stored as the device encoded it, without decoding or re-encoding, so
+//! a recording shows the device screen only: no HUD, touch markers or other overlays. It
+//! begins at a keyframe and skips from lost packets to the next one.
+
+use crate::core::events::{Event, EventSink};
+use crate::network::VideoPacket;
+use crate::video::mp4::Mp4Writer;
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::fs::{File, OpenOptions};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const NAL_IDR: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+const NAL_AUD: u8 = 9;
+
+/// A finished recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingSummary {
+    pub path: PathBuf,
+    pub frames: u64,
+    pub duration_ms: u64,
+}
+
+struct Recording {
+    writer: Mp4Writer<BufWriter<File>>,
+    path: PathBuf,
+    /// SPS and PPS of the recorded stream, set by its first keyframe
+    config: Option<(Vec<u8>, Vec<u8>)>,
+    /// Frames are skipped until the next keyframe: at the start and after lost packets
+    waiting: bool,
+    /// Stream PTS, arrival and file PTS of the last frame written
+    last: Option<(u64, Instant, u64)>,
+}
+
+#[derive(Default)]
+struct RecorderState {
+    /// Latest parameter sets of the stream, kept while not recording too
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    recording: Option<Recording>,
+}
+
+/// Records the video of a session; the decoder thread feeds it every packet
+pub struct Recorder {
+    state: Mutex<RecorderState>,
+    events: EventSink,
+}
+
+impl Recorder {
+    /// Write errors are reported to `events`
+    pub fn new(events: EventSink) -> Self {
+        Self {
+            state: Mutex::new(RecorderState::default()),
+            events,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state.lock().unwrap().recording.is_some()
+    }
+
+    /// Record to `path` (a new file; `recording_<time>.mp4` on the Desktop if `None`) from
+    /// the next keyframe on. Returns the path.
+    pub fn start(&self, path: Option<PathBuf>) -> Result<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(recording) = &state.recording {
+            return Err(anyhow!("Already recording to {}", recording.path.display()));
+        }
+
+        let path = path.unwrap_or_else(default_path);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Cannot create {}: {}", path.display(), e))?;
+        let writer = Mp4Writer::new(BufWriter::new(file))?;
+        state.recording = Some(Recording {
+            writer,
+            path: path.clone(),
+            config: None,
+            waiting: true,
+            last: None,
+        });
+        log_info!("REC", "Recording to {}", path.display());
+        self.events.emit(Event::RecordingStarted {
+            path: path.to_string_lossy().to_string(),
+        });
+        Ok(path)
+    }
+
+    /// Finish the recording. A recording that never reached a keyframe is deleted.
+    pub fn stop(&self) -> Result<RecordingSummary> {
+        let recording = self
+            .state
+            .lock()
+            .unwrap()
+            .recording
+            .take()
+            .ok_or_else(|| anyhow!("Not recording"))?;
+        self.finish(recording)
+    }
+
+    /// Add a packet of the stream to the recording, if there is one. Returns true while the
+    /// recording waits for a keyframe, so the caller can ask the device for one.
+    pub fn push(&self, packet: &VideoPacket) -> bool {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        // Parameter sets come in codec config packets, which carry no timestamp
+        if packet.pts != 0 && state.recording.is_none() {
+            return false;
+        }
+
+        let nals = nal_units(&packet.data);
+        for nal in &nals {
+            match nal_type(nal) {
+                NAL_SPS => state.sps = Some(nal.to_vec()),
+                NAL_PPS => state.pps = Some(nal.to_vec()),
+                _ => {}
+            }
+        }
+        let Some(recording) = state.recording.as_mut() else {
+            return false;
+        };
+        if packet.discontinuity {
+            recording.waiting = true;
+        }
+        if packet.pts == 0 {
+            return recording.waiting;
+        }
+
+        let sync = nals.iter().any(|nal| nal_type(nal) == NAL_IDR);
+        let resumed = recording.waiting;
+        if sync {
+            let (Some(sps), Some(pps)) = (&state.sps, &state.pps) else {
+                return true;
+            };
+            match &recording.config {
+                None => recording.config = Some((sps.clone(), pps.clone())),
+                // One MP4 track holds one stream format (a rotation changes the size)
+                Some((old_sps, old_pps)) if old_sps != sps || old_pps != pps => {
+                    log_info!("REC", "Video format changed, ending the recording");
+                    if let Some(recording) = state.recording.take() {
+                        let _ = self.finish(recording);
+                    }
+                    return false;
+                }
+                Some(_) => {}
+            }
+            recording.waiting = false;
+        } else if recording.waiting {
+            return true;
+        }
+
+        // Timestamps continue across a gap by the time that passed, in case the stream
+        // restarted its clock
+        let pts = match recording.last {
+            None => 0,
+            Some((_, last_arrival, last_pts)) if resumed => {
+                let gap = packet.received_at.saturating_duration_since(last_arrival);
+                last_pts + (gap.as_micros() as u64).max(1)
+            }
+            Some((last_stream_pts, _, last_pts)) => {
+                last_pts + packet.pts.saturating_sub(last_stream_pts).max(1)
+            }
+        };
+
+        let sample = to_avcc(&nals);
+        if sample.is_empty() {
+            return false;
+        }
+        if let Err(e) = recording.writer.write_sample(&sample, pts, sync) {
+            log_error!(
+                "REC",
+                "Recording to {} failed: {}",
+                recording.path.display(),
+                e
+            );
+            self.events.emit(Event::Error {
+                message: format!("Recording failed: {}", e),
+                fatal: false,
+            });
+            // Without its sample tables the file does not play
+            if let Some(recording) = state.recording.take() {
+                drop(recording.writer);
+                let _ = std::fs::remove_file(&recording.path);
+            }
+            return false;
+        }
+        recording.last = Some((packet.pts, packet.received_at, pts));
+        false
+    }
+
+    /// Write the sample tables, or delete a file without any
+    fn finish(&self, recording: Recording) -> Result<RecordingSummary> {
+        let Recording {
+            writer,
+            path,
+            config,
+            ..
+        } = recording;
+        let result = match &config {
+            Some((sps, pps)) => writer.finish(sps, pps),
+            None => Err(anyhow!("No keyframe arrived, nothing was recorded")),
+        };
+        match result {
+            Ok((_, summary)) => {
+                let summary = RecordingSummary {
+                    path,
+                    frames: summary.frames,
+                    duration_ms: summary.duration_us / 1000,
+                };
+                log_info!(
+                    "REC",
+                    "Saved {} frames ({:.1}s) to {}",
+                    summary.frames,
+                    summary.duration_ms as f64 / 1000.0,
+                    summary.path.display()
+                );
+                self.events.emit(Event::RecordingStopped {
+                    path: summary.path.to_string_lossy().to_string(),
+                    frames: summary.frames,
+                    duration_ms: summary.duration_ms,
+                });
+                Ok(summary)
+            }
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                Err(e)
+            }
+        }
+    }
+}
+
+fn default_path() -> PathBuf {
+    let filename = format!("recording_{}.mp4", Local::now().format("%Y%m%d_%H%M%S"));
+    match dirs::desktop_dir() {
+        Some(dir) => dir.join(filename),
+        None => PathBuf::from(filename),
+    }
+}
+
+fn nal_type(nal: &[u8]) -> u8 {
+    nal.first().map_or(0, |header| header & 0x1F)
+}
+
+/// NAL units of an Annex B buffer, without start codes
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    // (start code, NAL unit) positions
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            starts.push((i, i + 3));
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &(_, begin))| {
+            let end = starts.get(n + 1).map_or(data.len(), |&(code, _)| code);
+            // Trailing zeros are the first byte of a 4-byte start code, or padding
+            let nal = &data[begin..end];
+            let len = nal.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+            &nal[..len]
+        })
+        .filter(|nal| !nal.is_empty())
+        .collect()
+}
+
+/// Length-prefixed NAL units of one sample; parameter sets live in the sample description
+fn to_avcc(nals: &[&[u8]]) -> Vec<u8> {
+    let mut sample = Vec::new();
+    for nal in nals {
+        if matches!(nal_type(nal), NAL_SPS | NAL_PPS | NAL_AUD) {
+            continue;
+        }
+        sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+        sample.extend_from_slice(nal);
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // Baseline 1280x720 and 720x1280
+    const SPS: &[u8] = &[0x67, 0x42, 0x00, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe4];
+    const SPS_ROTATED: &[u8] = &[
+        0x67, 0x42, 0x00, 0x1f, 0xda, 0x02, 0xd0, 0x28, 0x69, 0xb8, 0x30, 0x30, 0x32, 0x00, 0x00,
+        0x03, 0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x78, 0x1b, 0x41, 0x10, 0x8d, 0x40,
+    ];
+    const PPS: &[u8] = &[0x68, 0xce, 0x3c, 0x80];
+    const IDR: &[u8] = &[0x65, 0x88, 0x84];
+    const SLICE: &[u8] = &[0x41, 0x9a, 0x02];
+
+    fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| [&[0, 0, 0, 1], *nal].concat())
+            .collect()
+    }
+
+    /// Feeds a recorder like the decoder thread, one frame every 16 ms
+    struct Stream {
+        recorder: Recorder,
+        start: Instant,
+        frame: u64,
+    }
+
+    impl Stream {
+        fn new() -> Self {
+            Self {
+                recorder: Recorder::new(EventSink::default()),
+                start: Instant::now(),
+                frame: 0,
+            }
+        }
+
+        fn push(&self, pts: u64, nals: &[&[u8]], discontinuity: bool) -> bool {
+            self.recorder.push(&VideoPacket {
+                pts,
+                data: annex_b(nals),
+                discontinuity,
+                received_at: self.start + Duration::from_micros(pts),
+            })
+        }
+
+        fn config(&self, sps: &[u8]) -> bool {
+            self.push(0, &[sps, PPS], false)
+        }
+
+        fn frame(&mut self, nal: &[u8]) -> bool {
+            self.frame += 1;
+            self.push(self.frame * 16_000, &[nal], false)
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("nl-recorder-{}-{}.mp4", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn splits_annex_b() {
+        let data = [
+            &[0, 0, 0, 1][..],
+            SPS,
+            &[0, 0, 1],
+            PPS,
+            &[0, 0, 0, 1],
+            IDR,
+            &[0],
+        ]
+        .concat();
+        assert_eq!(nal_units(&data), [SPS, PPS, IDR]);
+        assert_eq!(
+            to_avcc(&nal_units(&data)),
+            [&[0, 0, 0, 3][..], IDR].concat()
+        );
+        assert!(nal_units(&[0x65, 1, 2]).is_empty());
+    }
+
+    #[test]
+    fn records_from_the_first_keyframe() {
+        let path = temp_path("keyframe");
+        let mut stream = Stream::new();
+        stream.config(SPS);
+        stream.recorder.start(Some(path.clone())).unwrap();
+        assert!(stream.frame(SLICE), "waits for a keyframe");
+        assert!(!stream.frame(IDR));
+        assert!(!stream.frame(SLICE));
+
+        let summary = stream.recorder.stop().unwrap();
+        assert_eq!(summary.path, path);
+        assert_eq!(summary.frames, 2);
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(file[4..8], *b"ftyp");
+        assert!(file.windows(4).any(|w| w == b"moov"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recorded_samples_decode() {
+        use openh264::decoder::Decoder;
+        use openh264::encoder::Encoder;
+        use openh264::formats::YUVBuffer;
+
+        let path = temp_path("decode");
+        let recorder = Recorder::new(EventSink::default());
+        recorder.start(Some(path.clone())).unwrap();
+        let mut encoder = Encoder::new().unwrap();
+        let start = Instant::now();
+        for i in 0..10u8 {
+            let yuv = YUVBuffer::from_vec(vec![i * 20; 64 * 64 * 3 / 2], 64, 64);
+            let pts = (i as u64 + 1) * 16_000;
+            recorder.push(&VideoPacket {
+                pts,
+                data: encoder.encode(&yuv).unwrap().to_vec(),
+                discontinuity: false,
+                received_at: start + Duration::from_micros(pts),
+            });
+        }
+        let summary = recorder.stop().unwrap();
+        assert_eq!(summary.frames, 10);
+
+        // Samples are length-prefixed NAL units at the start of `mdat`, after the 16-byte
+        // header; parameter sets are not repeated in them
+        let file = std::fs::read(&path).unwrap();
+        let mdat = file.windows(4).position(|w| w == b"mdat").unwrap() - 4;
+        let size = u64::from_be_bytes(file[mdat + 8..mdat + 16].try_into().unwrap()) as usize;
+        let (sps, pps) = {
+            let state = recorder.state.lock().unwrap();
+            (state.sps.clone().unwrap(), state.pps.clone().unwrap())
+        };
+        let mut stream = annex_b(&[&sps, &pps]);
+        let mut samples = &file[mdat + 16..mdat + size];
+        while !samples.is_empty() {
+            let len = u32::from_be_bytes(samples[..4].try_into().unwrap()) as usize;
+            assert!(!matches!(nal_type(&samples[4..]), NAL_SPS | NAL_PPS));
+            stream.extend_from_slice(&annex_b(&[&samples[4..4 + len]]));
+            samples = &samples[4 + len..];
+        }
+
+        let mut decoder = Decoder::new().unwrap();
+        let decoded = openh264::nal_units(&stream)
+            .filter(|nal| decoder.decode(nal).unwrap().is_some())
+            .count();
+        assert_eq!(decoded, 10);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn skips_from_lost_packets_to_the_next_keyframe() {
+        let path = temp_path("gap");
+        let mut stream = Stream::new();
+        stream.config(SPS);
+        stream.recorder.start(Some(path.clone())).unwrap();
+        stream.frame(IDR);
+        stream.frame(SLICE);
+        assert!(stream.push(1_000_000, &[SLICE], true));
+        assert!(stream.frame(SLICE));
+        assert!(!stream.frame(IDR));
+
+        assert_eq!(stream.recorder.stop().unwrap().frames, 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ends_when_the_format_changes() {
+        let path = temp_path("format");
+        let mut stream = Stream::new();
+        stream.config(SPS);
+        stream.recorder.start(Some(path.clone())).unwrap();
+        stream.frame(IDR);
+        stream.config(SPS_ROTATED);
+        stream.frame(IDR);
+
+        assert!(!stream.recorder.is_recording());
+        assert!(stream.recorder.stop().is_err());
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .windows(4)
+            .any(|w| w == b"moov"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reports_start_and_stop() {
+        let path = temp_path("events");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let mut stream = Stream::new();
+        stream.recorder = Recorder::new(EventSink::new(move |event| {
+            sink.lock()
+                .unwrap()
+                .push(serde_json::to_value(event).unwrap());
+        }));
+        stream.config(SPS);
+        stream.recorder.start(Some(path.clone())).unwrap();
+        stream.frame(IDR);
+        stream.frame(SLICE);
+        stream.recorder.stop().unwrap();
+
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                serde_json::json!({ "event": "recording_started", "path": path }),
+                serde_json::json!({
+                    "event": "recording_stopped",
+                    "path": path,
+                    "frames": 2,
+                    "duration_ms": 32,
+                }),
+            ]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn nothing_recorded_is_deleted() {
+        let path = temp_path("empty");
+        let mut stream = Stream::new();
+        stream.recorder.start(Some(path.clone())).unwrap();
+        stream.frame(SLICE);
+        assert!(stream.recorder.stop().is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn does_not_overwrite_or_record_twice() {
+        let path = temp_path("twice");
+        let recorder = Recorder::new(EventSink::default());
+        recorder.start(Some(path.clone())).unwrap();
+        assert!(recorder.start(Some(temp_path("other"))).is_err());
+        let _ = recorder.stop();
+
+        std::fs::write(&path, b"keep").unwrap();
+        assert!(recorder.start(Some(path.clone())).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,19 +1,122 @@
 use anyhow::{anyhow, Result};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
-use winit::dpi::LogicalSize;
 use winit::window::Window;
 
-use crate::core::FrameData;
+use crate::core::{ColorSpace, FrameData, Loupe, TouchFrame};
+use crate::video::hud::{HudLayer, HudStyle};
+use crate::video::overlay::OverlayLayer;
 
+/// Mirrors `AspectRatio` in shader.wgsl
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct AspectRatioUniform {
     scale: [f32; 2],
-    _padding: [f32; 2], // Align to 16 bytes
+    loupe_uv: [f32; 2],
+    region: [f32; 4],
+    loupe: [f32; 4],
+}
+
+/// Whole frame visible (no zoom)
+const FULL_REGION: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Scaling filter used to map the frame onto the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScaleFilter {
+    /// Blocky, exact pixels (pixel inspection)
+    Nearest,
+    #[default]
+    Bilinear,
+    /// Catmull-Rom, sharper than bilinear
+    Bicubic,
+    /// Lanczos-3, sharpest, most expensive
+    Lanczos,
+}
+
+impl ScaleFilter {
+    /// Next filter in the hotkey cycle
+    pub fn next(self) -> Self {
+        match self {
+            Self::Nearest => Self::Bilinear,
+            Self::Bilinear => Self::Bicubic,
+            Self::Bicubic => Self::Lanczos,
+            Self::Lanczos => Self::Nearest,
+        }
+    }
+
+    /// Value of `RenderParams.scale_filter` in shader.wgsl
+    fn shader_id(self) -> u32 {
+        match self {
+            Self::Nearest => 0,
+            Self::Bilinear => 1,
+            Self::Bicubic => 2,
+            Self::Lanczos => 3,
+        }
+    }
+}
+
+impl fmt::Display for ScaleFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Nearest => "nearest",
+            Self::Bilinear => "bilinear",
+            Self::Bicubic => "bicubic",
+            Self::Lanczos => "lanczos",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ScaleFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Self::Nearest),
+            "bilinear" | "linear" => Ok(Self::Bilinear),
+            "bicubic" | "cubic" => Ok(Self::Bicubic),
+            "lanczos" => Ok(Self::Lanczos),
+            _ => Err(anyhow!(
+                "unknown scale filter '{}' (nearest, bilinear, bicubic, lanczos)",
+                s
+            )),
+        }
+    }
+}
+
+/// Mirrors `RenderParams` in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct RenderParamsUniform {
+    filter: u32,
+    sharpen: f32,
+    _padding: [f32; 2],
+}
+
+/// Mirrors `ColorConversion` in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorUniform {
+    offset: [f32; 4],
+    scale: [f32; 4],
+    coeffs: [f32; 4],
+}
+
+impl ColorUniform {
+    fn new(color: ColorSpace) -> Self {
+        let c = color.conversion();
+        Self {
+            offset: [c.y_offset, c.uv_offset, c.uv_offset, 0.0],
+            scale: [c.y_scale, c.uv_scale, c.uv_scale, 1.0],
+            coeffs: [c.cr_r, c.cb_g, c.cr_g, c.cb_b],
+        }
+    }
 }
 
 pub struct MirrorRenderer {
+    window: Arc<Window>,
     surface: wgpu::Surface<'static>,
     device: wgpu::Device,
     queue: wgpu::Queue,
@@ -25,10 +128,21 @@ pub struct MirrorRenderer {
     v_texture: wgpu::Texture,
     texture_bind_group: wgpu::BindGroup,
     aspect_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    color: ColorSpace,
+    params_buffer: wgpu::Buffer,
+    params: RenderParamsUniform,
     bind_group_layout: wgpu::BindGroupLayout,
     sampler: wgpu::Sampler,
+    hud: HudLayer,
+    touches: OverlayLayer,
+    captions: HudLayer,
+    banner: HudLayer,
     frame_width: u32,
     frame_height: u32,
+    /// Visible part of the frame (zoom/pan), see `Viewport::region`
+    region: [f32; 4],
+    loupe: Option<Loupe>,
 }
 
 impl MirrorRenderer {
@@ -38,27 +152,9 @@ impl MirrorRenderer {
             width, height
         );
 
-        // Scale window to fit on screen (max 800 logical height)
-        let max_height = 800.0_f64;
-        let scale = if (height as f64) > max_height {
-            max_height / (height as f64)
-        } else {
-            1.0
-        };
-
-        let logical_w = (width as f64 * scale) as f64;
-        let logical_h = (height as f64 * scale) as f64;
-
-        // Resize existing window
-        let _ = window.request_inner_size(LogicalSize::new(logical_w, logical_h));
-
+        // The window is sized by MirrorApp; render into whatever size it currently has
         let physical_size = window.inner_size();
 
-        println!(
-            "Window: {}x{} logical -> {}x{} physical",
-            logical_w as u32, logical_h as u32, physical_size.width, physical_size.height
-        );
-
         // Initialize wgpu
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
 
@@ -93,19 +189,14 @@ impl MirrorRenderer {
             .copied()
             .unwrap_or(surface_caps.formats[0]);
 
-        // Use Mailbox for lower latency
+        // Use Mailbox for lower latency. Frames are only drawn when one arrives (or an
+        // overlay changes), so vsync no longer costs a spinning loop; Immediate would only tear.
         let present_mode = if surface_caps
             .present_modes
             .contains(&wgpu::PresentMode::Mailbox)
         {
             println!("Using PresentMode::Mailbox for low latency");
             wgpu::PresentMode::Mailbox
-        } else if surface_caps
-            .present_modes
-            .contains(&wgpu::PresentMode::Immediate)
-        {
-            println!("Using PresentMode::Immediate");
-            wgpu::PresentMode::Immediate
         } else {
             println!("Using PresentMode::Fifo (vsync)");
             wgpu::PresentMode::Fifo
@@ -123,59 +214,7 @@ impl MirrorRenderer {
         };
         surface.configure(&device, &config);
 
-        // Create Y texture (full resolution, single channel)
-        let y_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Y Texture"),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        // Create U and V textures (half resolution, single channel each)
-        let uv_width = width / 2;
-        let uv_height = height / 2;
-
-        let u_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("U Texture"),
-            size: wgpu::Extent3d {
-                width: uv_width,
-                height: uv_height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        let v_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("V Texture"),
-            size: wgpu::Extent3d {
-                width: uv_width,
-                height: uv_height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::R8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        let y_view = y_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let u_view = u_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let v_view = v_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let (y_texture, u_texture, v_texture) = Self::create_yuv_textures(&device, width, height);
 
         // Create sampler with LINEAR filtering for smooth UV upscaling
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
@@ -189,8 +228,14 @@ impl MirrorRenderer {
         });
 
         // Calculate initial aspect ratio scale
-        let aspect_uniform =
+        let scale =
             Self::calculate_aspect_scale(width, height, physical_size.width, physical_size.height);
+        let aspect_uniform = AspectRatioUniform {
+            scale,
+            loupe_uv: [0.0; 2],
+            region: FULL_REGION,
+            loupe: [0.0; 4],
+        };
 
         let aspect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Aspect Ratio Buffer"),
@@ -198,6 +243,24 @@ impl MirrorRenderer {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let color = ColorSpace::default();
+        let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color Conversion Buffer"),
+            contents: bytemuck::cast_slice(&[ColorUniform::new(color)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let params = RenderParamsUniform {
+            filter: ScaleFilter::default().shader_id(),
+            sharpen: 0.0,
+            _padding: [0.0; 2],
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Render Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         // Bind group layout for YUV textures
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("YUV Bind Group Layout"),
@@ -253,36 +316,41 @@ impl MirrorRenderer {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
-        });
-
-        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("YUV Bind Group"),
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&y_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&u_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&v_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: aspect_buffer.as_entire_binding(),
+                // YUV -> RGB coefficients
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                // Scaling filter / sharpening
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
+        let texture_bind_group = Self::create_bind_group(
+            &device,
+            &bind_group_layout,
+            [&y_texture, &u_texture, &v_texture],
+            &aspect_buffer,
+            &color_buffer,
+            &params_buffer,
+            &sampler,
+        );
+
         // Shader
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("YUV Shader"),
@@ -332,9 +400,16 @@ impl MirrorRenderer {
             multiview: None,
         });
 
+        let hud = HudLayer::new(&device, config.format, HudStyle::STATS);
+        let captions = HudLayer::new(&device, config.format, HudStyle::CAPTION);
+        let banner = HudLayer::new(&device, config.format, HudStyle::BANNER);
+        let touches = OverlayLayer::new(&device, config.format);
+        touches.set_view(&queue, (width, height), scale, FULL_REGION);
+
         println!("wgpu renderer initialized (GPU YUV->RGB conversion enabled)");
 
         Ok(Self {
+            window,
             surface,
             device,
             queue,
@@ -345,19 +420,230 @@ impl MirrorRenderer {
             v_texture,
             texture_bind_group,
             aspect_buffer,
+            color_buffer,
+            color,
+            params_buffer,
+            params,
             bind_group_layout,
             sampler,
+            hud,
+            touches,
+            captions,
+            banner,
             frame_width: width,
             frame_height: height,
+            region: FULL_REGION,
+            loupe: None,
+        })
+    }
+
+    /// Y plane at full resolution, U and V at half resolution (single channel each)
+    fn create_yuv_textures(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::Texture, wgpu::Texture) {
+        let plane = |label: &str, width: u32, height: u32| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            })
+        };
+        (
+            plane("Y Texture", width, height),
+            plane("U Texture", width / 2, height / 2),
+            plane("V Texture", width / 2, height / 2),
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [y_texture, u_texture, v_texture]: [&wgpu::Texture; 3],
+        aspect_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+        params_buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let y_view = y_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let u_view = u_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let v_view = v_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("YUV Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&y_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&u_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&v_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: aspect_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: color_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
         })
     }
 
+    /// Reallocate the YUV textures for a new frame size (rotation, resolution change).
+    /// The device, pipeline and surface are kept, so this is cheap and does not flash.
+    pub fn set_frame_size(&mut self, width: u32, height: u32) {
+        if width == self.frame_width && height == self.frame_height {
+            return;
+        }
+        let (y_texture, u_texture, v_texture) =
+            Self::create_yuv_textures(&self.device, width, height);
+        self.texture_bind_group = Self::create_bind_group(
+            &self.device,
+            &self.bind_group_layout,
+            [&y_texture, &u_texture, &v_texture],
+            &self.aspect_buffer,
+            &self.color_buffer,
+            &self.params_buffer,
+            &self.sampler,
+        );
+        self.y_texture = y_texture;
+        self.u_texture = u_texture;
+        self.v_texture = v_texture;
+        self.frame_width = width;
+        self.frame_height = height;
+
+        self.update_aspect();
+    }
+
+    /// Select the scaling filter
+    pub fn set_scale_filter(&mut self, filter: ScaleFilter) {
+        self.params.filter = filter.shader_id();
+        self.write_params();
+    }
+
+    /// Set the luma sharpening strength (0 = off, ~0.5 = moderate)
+    pub fn set_sharpen(&mut self, strength: f32) {
+        self.params.sharpen = strength.max(0.0);
+        self.write_params();
+    }
+
+    /// Show the performance HUD with `lines`, or hide it with `None`
+    pub fn set_hud(&mut self, lines: Option<&[String]>) {
+        let surface_size = (self.config.width, self.config.height);
+        self.hud
+            .set_text(&self.device, &self.queue, lines, surface_size);
+    }
+
+    /// Show touch markers and key captions, or clear them with `None`
+    pub fn set_touches(&mut self, touches: Option<&TouchFrame>) {
+        let surface_size = (self.config.width, self.config.height);
+        let shapes = touches.map(|t| t.shapes.as_slice()).unwrap_or(&[]);
+        self.touches.set_shapes(&self.device, &self.queue, shapes);
+        let caption = touches.and_then(|t| t.keys.clone()).map(|k| vec![k]);
+        self.captions
+            .set_text(&self.device, &self.queue, caption.as_deref(), surface_size);
+    }
+
+    /// Show a status message over the picture (e.g. while reconnecting), or hide it
+    pub fn set_banner(&mut self, text: Option<&str>) {
+        let surface_size = (self.config.width, self.config.height);
+        let lines = text.map(|t| vec![t.to_string()]);
+        self.banner
+            .set_text(&self.device, &self.queue, lines.as_deref(), surface_size);
+    }
+
+    /// Show only `region` of the frame ([x, y, w, h], normalized) and the loupe, if any
+    pub fn set_view(&mut self, region: [f32; 4], loupe: Option<Loupe>) {
+        if region == self.region && loupe == self.loupe {
+            return;
+        }
+        self.region = region;
+        self.loupe = loupe;
+        self.update_aspect();
+    }
+
+    /// Write the letterbox scale, visible region and loupe for the current sizes
+    fn update_aspect(&self) {
+        let (surface_w, surface_h) = (self.config.width as f32, self.config.height as f32);
+        let scale = Self::calculate_aspect_scale(
+            self.frame_width,
+            self.frame_height,
+            self.config.width,
+            self.config.height,
+        );
+
+        // Texture coordinate under the loupe center, so the shader can magnify around it
+        let (loupe_uv, loupe) = match self.loupe {
+            Some(l) => {
+                let quad_x = (l.center[0] / surface_w - 0.5) / scale[0] + 0.5;
+                let quad_y = (l.center[1] / surface_h - 0.5) / scale[1] + 0.5;
+                (
+                    [
+                        self.region[0] + quad_x * self.region[2],
+                        self.region[1] + quad_y * self.region[3],
+                    ],
+                    [l.center[0], l.center[1], l.radius, l.magnification],
+                )
+            }
+            None => ([0.0; 2], [0.0; 4]),
+        };
+
+        let aspect_uniform = AspectRatioUniform {
+            scale,
+            loupe_uv,
+            region: self.region,
+            loupe,
+        };
+        self.queue.write_buffer(
+            &self.aspect_buffer,
+            0,
+            bytemuck::cast_slice(&[aspect_uniform]),
+        );
+        self.touches.set_view(
+            &self.queue,
+            (self.frame_width, self.frame_height),
+            scale,
+            self.region,
+        );
+    }
+
+    fn write_params(&self) {
+        self.queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+    }
+
     fn calculate_aspect_scale(
         frame_w: u32,
         frame_h: u32,
         surface_w: u32,
         surface_h: u32,
-    ) -> AspectRatioUniform {
+    ) -> [f32; 2] {
         let frame_aspect = frame_w as f32 / frame_h as f32;
         let surface_aspect = surface_w as f32 / surface_h as f32;
 
@@ -367,10 +653,7 @@ impl MirrorRenderer {
             (frame_aspect / surface_aspect, 1.0)
         };
 
-        AspectRatioUniform {
-            scale: [scale_x, scale_y],
-            _padding: [0.0, 0.0],
-        }
+        [scale_x, scale_y]
     }
 
     pub fn resize_surface(&mut self, width: u32, height: u32) -> Result<()> {
@@ -380,13 +663,10 @@ impl MirrorRenderer {
             self.config.height = height;
             self.surface.configure(&self.device, &self.config);
 
-            let aspect_uniform =
-                Self::calculate_aspect_scale(self.frame_width, self.frame_height, width, height);
-            self.queue.write_buffer(
-                &self.aspect_buffer,
-                0,
-                bytemuck::cast_slice(&[aspect_uniform]),
-            );
+            self.update_aspect();
+            self.hud.resize(&self.queue, (width, height));
+            self.captions.resize(&self.queue, (width, height));
+            self.banner.resize(&self.queue, (width, height));
         }
         Ok(())
     }
@@ -395,6 +675,15 @@ impl MirrorRenderer {
     pub fn render_yuv_frame(&mut self, frame: &FrameData) -> Result<()> {
         let total_start = std::time::Instant::now();
 
+        if frame.color != self.color {
+            self.color = frame.color;
+            self.queue.write_buffer(
+                &self.color_buffer,
+                0,
+                bytemuck::cast_slice(&[ColorUniform::new(frame.color)]),
+            );
+        }
+
         // Upload Y plane
         self.queue.write_texture(
             wgpu::ImageCopyTexture {
@@ -462,7 +751,29 @@ impl MirrorRenderer {
         );
 
         let upload_time = total_start.elapsed();
+        let (get_texture_time, submit_time) = self.draw()?;
+        let total_time = total_start.elapsed();
 
+        if total_time.as_millis() > 100 {
+            println!(
+                "WARNING: render_yuv_frame total time: {}ms (upload: {}ms, get_texture: {}ms, submit: {}ms)",
+                total_time.as_millis(),
+                upload_time.as_millis(),
+                get_texture_time.as_millis(),
+                submit_time.as_millis()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Draw the last uploaded frame again (e.g. to refresh the HUD on a static screen)
+    pub fn redraw(&mut self) -> Result<()> {
+        self.draw().map(|_| ())
+    }
+
+    /// Draw the current textures and overlays; returns (get_texture, submit) times
+    fn draw(&mut self) -> Result<(std::time::Duration, std::time::Duration)> {
         let get_texture_start = std::time::Instant::now();
         let output = match self.surface.get_current_texture() {
             Ok(output) => output,
@@ -515,25 +826,19 @@ impl MirrorRenderer {
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
             render_pass.draw(0..6, 0..1);
+
+            self.touches.draw(&mut render_pass);
+            self.captions.draw(&mut render_pass);
+            self.hud.draw(&mut render_pass);
+            self.banner.draw(&mut render_pass);
         }
 
         let submit_start = std::time::Instant::now();
         self.queue.submit(std::iter::once(encoder.finish()));
+        // Lets the compositor pace redraw requests to the display (Wayland frame callbacks)
+        self.window.pre_present_notify();
         output.present();
 
-        let submit_time = submit_start.elapsed();
-        let total_time = total_start.elapsed();
-
-        if total_time.as_millis() > 100 {
-            println!(
-                "WARNING: render_yuv_frame total time: {}ms (upload: {}ms, get_texture: {}ms, submit: {}ms)",
-                total_time.as_millis(),
-                upload_time.as_millis(),
-                get_texture_time.as_millis(),
-                submit_time.as_millis()
-            );
-        }
-
-        Ok(())
+        Ok((get_texture_time, submit_start.elapsed()))
     }
 }
@@ -4,9 +4,12 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
 };
 
-// Uniforms for aspect ratio correction
+// Uniforms for aspect ratio correction, zoom/pan and the loupe (see Viewport in core/viewport.rs)
 struct AspectRatio {
     scale: vec2<f32>,
+    loupe_uv: vec2<f32>,  // texture coordinate under the loupe center
+    region: vec4<f32>,    // visible part of the frame: x, y, width, height (0-1)
+    loupe: vec4<f32>,     // center x, y and radius in screen pixels, magnification (0 = off)
 };
 
 @group(0) @binding(3)
@@ -39,7 +42,7 @@ fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
     // Apply aspect ratio correction to positions
     let pos = positions[vertex_index] * aspect.scale;
     out.clip_position = vec4<f32>(pos, 0.0, 1.0);
-    out.tex_coords = tex_coords[vertex_index];
+    out.tex_coords = aspect.region.xy + tex_coords[vertex_index] * aspect.region.zw;
     
     return out;
 }
@@ -54,22 +57,149 @@ var v_texture: texture_2d<f32>;
 @group(0) @binding(4)
 var tex_sampler: sampler;
 
+// YUV -> RGB coefficients for the stream's colour space (see core/color.rs)
+struct ColorConversion {
+    offset: vec4<f32>,  // y, u, v offsets
+    scale: vec4<f32>,   // y, u, v scales
+    coeffs: vec4<f32>,  // cr_r, cb_g, cr_g, cb_b
+};
+
+@group(0) @binding(5)
+var<uniform> color: ColorConversion;
+
+// Scaling filter and sharpening (see ScaleFilter in renderer.rs)
+struct RenderParams {
+    scale_filter: u32,  // 0 = nearest, 1 = bilinear, 2 = bicubic, 3 = Lanczos
+    sharpen: f32,       // Unsharp mask strength on luma (0 = off)
+    _padding: vec2<f32>,
+};
+
+@group(0) @binding(6)
+var<uniform> params: RenderParams;
+
+fn load_nearest(tex: texture_2d<f32>, uv: vec2<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(tex));
+    let p = vec2<i32>(floor(uv * vec2<f32>(size)));
+    return textureLoad(tex, clamp(p, vec2<i32>(0), size - 1), 0).r;
+}
+
+fn load_clamped(tex: texture_2d<f32>, p: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(tex));
+    return textureLoad(tex, clamp(p, vec2<i32>(0), size - 1), 0).r;
+}
+
+fn bilinear(tex: texture_2d<f32>, uv: vec2<f32>) -> f32 {
+    return textureSampleLevel(tex, tex_sampler, uv, 0.0).r;
+}
+
+// Catmull-Rom cubic
+fn cubic_weight(x: f32) -> f32 {
+    let ax = abs(x);
+    if ax < 1.0 {
+        return (1.5 * ax - 2.5) * ax * ax + 1.0;
+    }
+    if ax < 2.0 {
+        return ((-0.5 * ax + 2.5) * ax - 4.0) * ax + 2.0;
+    }
+    return 0.0;
+}
+
+// Lanczos with a = 3
+fn lanczos_weight(x: f32) -> f32 {
+    let ax = abs(x);
+    if ax < 1e-5 {
+        return 1.0;
+    }
+    if ax >= 3.0 {
+        return 0.0;
+    }
+    let px = 3.14159265 * ax;
+    return 3.0 * sin(px) * sin(px / 3.0) / (px * px);
+}
+
+// Separable kernel filter over a (2*radius)^2 texel neighbourhood
+fn sample_kernel(tex: texture_2d<f32>, uv: vec2<f32>, radius: i32, lanczos: bool) -> f32 {
+    let pos = uv * vec2<f32>(textureDimensions(tex)) - 0.5;
+    let base = vec2<i32>(floor(pos));
+    let f = pos - floor(pos);
+
+    var sum = 0.0;
+    var weight_sum = 0.0;
+    for (var j = 1 - radius; j <= radius; j++) {
+        var wy = cubic_weight(f32(j) - f.y);
+        if lanczos {
+            wy = lanczos_weight(f32(j) - f.y);
+        }
+        for (var i = 1 - radius; i <= radius; i++) {
+            var wx = cubic_weight(f32(i) - f.x);
+            if lanczos {
+                wx = lanczos_weight(f32(i) - f.x);
+            }
+            let w = wx * wy;
+            sum += w * load_clamped(tex, base + vec2<i32>(i, j));
+            weight_sum += w;
+        }
+    }
+    return sum / weight_sum;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // Sample Y, U, V planes (values are 0-1 range from R8Unorm texture)
-    // OpenH264 outputs FULL RANGE YUV (0-255 for all components)
-    let y = textureSample(y_texture, tex_sampler, in.tex_coords).r;
-    let u = textureSample(u_texture, tex_sampler, in.tex_coords).r - 0.5;  // Center U around 0
-    let v = textureSample(v_texture, tex_sampler, in.tex_coords).r - 0.5;  // Center V around 0
-    
-    // BT.601 Full Range YUV to RGB conversion
-    // Standard coefficients for full range input
-    // R = Y + 1.402 * V
-    // G = Y - 0.344136 * U - 0.714136 * V
-    // B = Y + 1.772 * U
-    let r = y + 1.402 * v;
-    let g = y - 0.344136 * u - 0.714136 * v;
-    let b = y + 1.772 * u;
+    // Size of one output pixel in texture coordinates (must stay in uniform control flow)
+    var texel = fwidth(in.tex_coords);
+    var uv = in.tex_coords;
+
+    // Loupe: magnify around the cursor, with a thin ring at the edge
+    if aspect.loupe.w > 0.0 {
+        let dist = distance(in.clip_position.xy, aspect.loupe.xy);
+        if dist < aspect.loupe.z {
+            if dist > aspect.loupe.z - 2.0 {
+                return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+            }
+            uv = aspect.loupe_uv + (uv - aspect.loupe_uv) / aspect.loupe.w;
+            texel = texel / aspect.loupe.w;
+        }
+    }
+
+    // Sample Y, U, V planes (values are 0-1 range from R8Unorm texture).
+    // The high-order filters only run on luma; chroma is half resolution anyway.
+    var yuv: vec3<f32>;
+    switch params.scale_filter {
+        case 0u: {
+            yuv = vec3<f32>(load_nearest(y_texture, uv), load_nearest(u_texture, uv), load_nearest(v_texture, uv));
+        }
+        case 2u: {
+            yuv = vec3<f32>(sample_kernel(y_texture, uv, 2, false), bilinear(u_texture, uv), bilinear(v_texture, uv));
+        }
+        case 3u: {
+            yuv = vec3<f32>(sample_kernel(y_texture, uv, 3, true), bilinear(u_texture, uv), bilinear(v_texture, uv));
+        }
+        default: {
+            yuv = vec3<f32>(bilinear(y_texture, uv), bilinear(u_texture, uv), bilinear(v_texture, uv));
+        }
+    }
+
+    // Unsharp mask on luma against the neighbouring output pixels
+    if params.sharpen > 0.0 {
+        let blur = (bilinear(y_texture, uv + vec2<f32>(texel.x, 0.0))
+            + bilinear(y_texture, uv - vec2<f32>(texel.x, 0.0))
+            + bilinear(y_texture, uv + vec2<f32>(0.0, texel.y))
+            + bilinear(y_texture, uv - vec2<f32>(0.0, texel.y))) * 0.25;
+        yuv.x = yuv.x + params.sharpen * (yuv.x - blur);
+    }
+
+    // Expand limited range and center U/V around 0
+    let n = (yuv - color.offset.xyz) * color.scale.xyz;
+    let y = n.x;
+    let u = n.y;
+    let v = n.z;
+
+    // R = Y + cr_r * V
+    // G = Y - cb_g * U - cr_g * V
+    // B = Y + cb_b * U
+    let r = y + color.coeffs.x * v;
+    let g = y - color.coeffs.y * u - color.coeffs.z * v;
+    let b = y + color.coeffs.w * u;
     
     return vec4<f32>(clamp(r, 0.0, 1.0), clamp(g, 0.0, 1.0), clamp(b, 0.0, 1.0), 1.0);
 }
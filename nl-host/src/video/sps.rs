@@ -0,0 +1,362 @@
+//! Minimal H.264 SPS parser - reads the frame size and the colour description from the VUI
+
+use crate::core::{ColorMatrix, ColorRange, VideoSignal};
+
+/// Profiles whose SPS carries chroma format / bit depth / scaling matrices
+const HIGH_PROFILES: &[u8] = &[100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// Exp-Golomb bit reader over an RBSP (emulation prevention bytes removed)
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    fn new(ebsp: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(ebsp.len());
+        let mut zeros = 0;
+        for &byte in ebsp {
+            if zeros >= 2 && byte == 3 {
+                zeros = 0;
+                continue;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            data.push(byte);
+        }
+        Self { data, pos: 0 }
+    }
+
+    fn bit(&mut self) -> Option<u32> {
+        let byte = *self.data.get(self.pos / 8)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Some(bit as u32)
+    }
+
+    fn bits(&mut self, n: u32) -> Option<u32> {
+        (0..n).try_fold(0, |acc, _| Some((acc << 1) | self.bit()?))
+    }
+
+    fn flag(&mut self) -> Option<bool> {
+        self.bit().map(|b| b == 1)
+    }
+
+    fn ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        Some((1u32 << leading_zeros) - 1 + self.bits(leading_zeros)?)
+    }
+
+    fn se(&mut self) -> Option<i32> {
+        let v = self.ue()?;
+        Some(if v % 2 == 1 {
+            v.div_ceil(2) as i32
+        } else {
+            -((v / 2) as i32)
+        })
+    }
+}
+
+/// Parse the cropped frame size from an SPS NAL unit (with or without start code).
+/// Returns `None` if the NAL is not an SPS or is truncated.
+pub fn parse_sps_size(nal: &[u8]) -> Option<(u32, u32)> {
+    parse_until_vui(nal).map(|(_, size)| size)
+}
+
+/// Parse the colour description from an SPS NAL unit (with or without start code).
+/// Returns `None` if the NAL is not an SPS or is truncated.
+pub fn parse_sps_signal(nal: &[u8]) -> Option<VideoSignal> {
+    let (mut r, _) = parse_until_vui(nal)?;
+
+    if !r.flag()? {
+        // No VUI: nothing signalled
+        return Some(VideoSignal::default());
+    }
+
+    if r.flag()? {
+        // aspect_ratio_info_present_flag
+        if r.bits(8)? == 255 {
+            r.bits(32)?; // sar_width, sar_height
+        }
+    }
+    if r.flag()? {
+        r.flag()?; // overscan_appropriate_flag
+    }
+    if !r.flag()? {
+        // video_signal_type_present_flag
+        return Some(VideoSignal::default());
+    }
+    r.bits(3)?; // video_format
+    let range = if r.flag()? {
+        ColorRange::Full
+    } else {
+        ColorRange::Limited
+    };
+    let mut matrix = None;
+    if r.flag()? {
+        r.bits(8)?; // colour_primaries
+        r.bits(8)?; // transfer_characteristics
+        matrix = match r.bits(8)? {
+            1 => Some(ColorMatrix::Bt709),
+            5 | 6 => Some(ColorMatrix::Bt601),
+            9 | 10 => Some(ColorMatrix::Bt2020),
+            _ => None,
+        };
+    }
+
+    Some(VideoSignal {
+        matrix,
+        range: Some(range),
+    })
+}
+
+/// Read an SPS up to `vui_parameters_present_flag`, returning the reader positioned there
+/// and the cropped frame size
+fn parse_until_vui(nal: &[u8]) -> Option<(BitReader, (u32, u32))> {
+    let nal = strip_start_code(nal);
+    if nal.first()? & 0x1F != 7 {
+        return None;
+    }
+    let mut r = BitReader::new(&nal[1..]);
+
+    let profile_idc = r.bits(8)? as u8;
+    r.bits(16)?; // constraint flags + level_idc
+    r.ue()?; // seq_parameter_set_id
+
+    let mut chroma_array_type = 1;
+    if HIGH_PROFILES.contains(&profile_idc) {
+        let chroma_format_idc = r.ue()?;
+        chroma_array_type = chroma_format_idc;
+        if chroma_format_idc == 3 && r.flag()? {
+            // separate_colour_plane_flag: each plane is coded as monochrome
+            chroma_array_type = 0;
+        }
+        r.ue()?; // bit_depth_luma_minus8
+        r.ue()?; // bit_depth_chroma_minus8
+        r.flag()?; // qpprime_y_zero_transform_bypass_flag
+        if r.flag()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.flag()? {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.flag()?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.flag()?; // gaps_in_frame_num_value_allowed_flag
+    let width_mbs = r.ue()? as u64 + 1;
+    let height_map_units = r.ue()? as u64 + 1;
+    let frame_mbs_only = r.flag()?;
+    if !frame_mbs_only {
+        r.flag()?; // mb_adaptive_frame_field_flag
+    }
+    r.flag()?; // direct_8x8_inference_flag
+    let mut crop = [0u64; 4]; // left, right, top, bottom
+    if r.flag()? {
+        for offset in &mut crop {
+            *offset = r.ue()? as u64;
+        }
+    }
+
+    // Crop offsets count in chroma samples; u64 keeps a corrupt SPS from overflowing
+    let (sub_width, sub_height) = match chroma_array_type {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let width = (width_mbs * 16).checked_sub(sub_width * (crop[0] + crop[1]))?;
+    let height = (height_map_units * 16 * field_factor)
+        .checked_sub(sub_height * field_factor * (crop[2] + crop[3]))?;
+    let width = u32::try_from(width).ok()?;
+    let height = u32::try_from(height).ok()?;
+
+    Some((r, (width, height)))
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Option<()> {
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for _ in 0..size {
+        if next_scale != 0 {
+            // delta_scale is -128..=127 in a valid SPS; i64 keeps a corrupt one from overflowing
+            next_scale = (last_scale as i64 + r.se()? as i64 + 256).rem_euclid(256) as i32;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Some(())
+}
+
+fn strip_start_code(nal: &[u8]) -> &[u8] {
+    if nal.starts_with(&[0, 0, 0, 1]) {
+        &nal[4..]
+    } else if nal.starts_with(&[0, 0, 1]) {
+        &nal[3..]
+    } else {
+        nal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // High profile 1080x2400, VUI: primaries/transfer/matrix 1 (BT.709), limited range,
+    // timing and bitstream restriction info after the colour description
+    const HIGH_BT709_LIMITED: &[u8] = &[
+        0x67, 0x64, 0x00, 0x28, 0xac, 0xda, 0x01, 0x10, 0x04, 0xb7, 0x97, 0x9a, 0x80, 0x80, 0x80,
+        0xa0, 0x00, 0x00, 0x03, 0x00, 0x20, 0x00, 0x00, 0x07, 0x81, 0xb4, 0x11, 0x08, 0xd4,
+    ];
+
+    // Baseline 720x1280, VUI: matrix 6 (BT.601), full range
+    const BASELINE_BT601_FULL: &[u8] = &[
+        0x67, 0x42, 0x00, 0x1f, 0xda, 0x02, 0xd0, 0x28, 0x69, 0xb8, 0x30, 0x30, 0x32, 0x00, 0x00,
+        0x03, 0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x78, 0x1b, 0x41, 0x10, 0x8d, 0x40,
+    ];
+
+    // Baseline 1280x720 without VUI
+    const BASELINE_NO_VUI: &[u8] = &[0x67, 0x42, 0x00, 0x1f, 0xda, 0x01, 0x40, 0x16, 0xe4];
+
+    // High profile 1920x1080 with scaling matrices before the BT.709 VUI
+    const HIGH_SCALING_LISTS: &[u8] = &[
+        0x67, 0x64, 0x00, 0x28, 0xad, 0x84, 0x41, 0x10, 0x27, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xf6, 0xd0, 0x0f, 0x00, 0x44, 0xfc, 0xb3, 0x50, 0x10, 0x10, 0x14, 0x00, 0x00, 0x03,
+        0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x36, 0x82, 0x21, 0x1a, 0x80,
+    ];
+
+    // Scaling lists whose delta_scale values are far outside -128..=127
+    const CORRUPT_SCALING_LISTS: &[u8] = &[
+        0x67, 0x64, 0x00, 0x28, 0xad, 0x80, 0x00, 0x00, 0x03, 0x00, 0xff, 0xff, 0xff, 0xfe, 0x00,
+        0x00, 0x03, 0x00, 0x01, 0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x03, 0x00, 0x03, 0xff, 0xff,
+        0xff, 0xf8, 0x00, 0x00, 0x03, 0x00, 0x07, 0xff, 0xff, 0xff, 0xf0, 0x00, 0x00, 0x03, 0x00,
+        0x0f, 0xff, 0xff, 0xff, 0xe0, 0x00, 0x00, 0x03, 0x00, 0x1f, 0xff, 0xff, 0xff, 0xc0, 0x00,
+        0x00, 0x03, 0x00, 0x3f, 0xff, 0xff, 0xff, 0x80, 0x00, 0x00, 0x03, 0x00, 0x7f, 0xff, 0xff,
+        0xff, 0x00, 0x00, 0x03, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfe, 0x00, 0x00, 0x03, 0x00, 0x01,
+        0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x03, 0x00, 0x03, 0xff, 0xff, 0xff, 0xf8, 0x00, 0x00,
+        0x03, 0x00, 0x07, 0xff, 0xff, 0xff, 0xf0, 0x00, 0x00, 0x03, 0x00, 0x0f, 0xff, 0xff, 0xff,
+        0xe0, 0x00, 0x00, 0x03, 0x00, 0x1f, 0xff, 0xff, 0xff, 0xc0, 0x00, 0x00, 0x03, 0x00, 0x3f,
+        0xff, 0xff, 0xff, 0x80, 0x00, 0x00, 0x03, 0x00, 0x7f, 0xff, 0xff, 0xff, 0x00, 0xda, 0x01,
+        0xe0, 0x08, 0x9f, 0x96, 0x6a, 0x02, 0x02, 0x02, 0x80, 0x00, 0x00, 0x03, 0x00, 0x80, 0x00,
+        0x00, 0x1e, 0x06, 0xd0, 0x44, 0x23, 0x50,
+    ];
+
+    #[test]
+    fn reads_bt709_limited() {
+        let signal = parse_sps_signal(HIGH_BT709_LIMITED).unwrap();
+        assert_eq!(signal.matrix, Some(ColorMatrix::Bt709));
+        assert_eq!(signal.range, Some(ColorRange::Limited));
+    }
+
+    #[test]
+    fn reads_bt601_full() {
+        let signal = parse_sps_signal(BASELINE_BT601_FULL).unwrap();
+        assert_eq!(signal.matrix, Some(ColorMatrix::Bt601));
+        assert_eq!(signal.range, Some(ColorRange::Full));
+    }
+
+    #[test]
+    fn accepts_start_code() {
+        let mut nal = vec![0, 0, 0, 1];
+        nal.extend_from_slice(BASELINE_BT601_FULL);
+        assert_eq!(
+            parse_sps_signal(&nal),
+            parse_sps_signal(BASELINE_BT601_FULL)
+        );
+        nal.drain(..1);
+        assert_eq!(
+            parse_sps_signal(&nal),
+            parse_sps_signal(BASELINE_BT601_FULL)
+        );
+    }
+
+    #[test]
+    fn no_vui_signals_nothing() {
+        assert_eq!(
+            parse_sps_signal(BASELINE_NO_VUI),
+            Some(VideoSignal::default())
+        );
+    }
+
+    #[test]
+    fn skips_scaling_lists() {
+        let signal = parse_sps_signal(HIGH_SCALING_LISTS).unwrap();
+        assert_eq!(signal.matrix, Some(ColorMatrix::Bt709));
+        assert_eq!(signal.range, Some(ColorRange::Limited));
+    }
+
+    #[test]
+    fn corrupt_scaling_list_does_not_panic() {
+        let _ = parse_sps_signal(CORRUPT_SCALING_LISTS);
+        let _ = parse_sps_size(CORRUPT_SCALING_LISTS);
+    }
+
+    #[test]
+    fn truncated_sps_is_none() {
+        // The matrix coefficients end in byte 16; everything after them is not read
+        assert!(parse_sps_signal(&HIGH_BT709_LIMITED[..16]).is_some());
+        for len in 1..16 {
+            assert_eq!(
+                parse_sps_signal(&HIGH_BT709_LIMITED[..len]),
+                None,
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn reads_cropped_size() {
+        assert_eq!(parse_sps_size(HIGH_BT709_LIMITED), Some((1080, 2400)));
+        assert_eq!(parse_sps_size(BASELINE_BT601_FULL), Some((720, 1280)));
+        assert_eq!(parse_sps_size(BASELINE_NO_VUI), Some((1280, 720)));
+        assert_eq!(parse_sps_size(HIGH_SCALING_LISTS), Some((1920, 1080)));
+    }
+
+    #[test]
+    fn rejects_other_nal_types() {
+        assert_eq!(parse_sps_signal(&[0x68, 0xee, 0x3c, 0x80]), None); // PPS
+        assert_eq!(parse_sps_size(&[0x68, 0xee, 0x3c, 0x80]), None);
+        assert_eq!(parse_sps_signal(&[]), None);
+    }
+
+    #[test]
+    fn garbage_does_not_panic() {
+        // Deterministic pseudo-random bytes behind an SPS header
+        let mut state = 0x2545_f491u32;
+        for _ in 0..1000 {
+            let mut nal = vec![0x67];
+            for _ in 0..(state % 64) {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                nal.push(state as u8);
+            }
+            let _ = parse_sps_signal(&nal);
+            let _ = parse_sps_size(&nal);
+        }
+    }
+}
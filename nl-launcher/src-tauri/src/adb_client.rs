@@ -0,0 +1,653 @@
+//! Client for the adb server (the `adb` daemon on localhost:5037)
+//!
+//! Speaks the server's socket protocol directly instead of running the `adb` binary and
+//! parsing what it prints: a request is its length as 4 hex digits followed by the text,
+//! answered with `OKAY` or `FAIL` and a length-prefixed message. Device services (shell,
+//! sync, tcpip) first switch the connection to a device with `host:transport:<serial>`.
+
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Default port of the adb server (`ANDROID_ADB_SERVER_PORT` overrides it, as for `adb`)
+pub const DEFAULT_PORT: u16 = 5037;
+
+/// Largest DATA chunk of a sync push
+const SYNC_CHUNK: usize = 64 * 1024;
+
+/// Longest a request, a reply or one sync chunk may take (a hung server or device)
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest a shell command may run (`pm install` of a large APK takes a while)
+const SHELL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// File type bits of a regular file, sent with the permissions like `adb push` does
+const S_IFREG: u32 = 0o100000;
+
+/// Shell protocol v2 packet ids
+const SHELL_STDOUT: u8 = 1;
+const SHELL_STDERR: u8 = 2;
+const SHELL_EXIT: u8 = 3;
+
+#[derive(Debug)]
+pub enum AdbError {
+    /// The server could not be reached or the connection broke
+    Io(std::io::Error),
+    /// The server or device refused the request, with its reason
+    Failed(String),
+    /// A reply that does not follow the protocol
+    Protocol(String),
+}
+
+impl fmt::Display for AdbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdbError::Io(e) => write!(f, "adb server: {}", e),
+            AdbError::Failed(message) => write!(f, "adb: {}", message),
+            AdbError::Protocol(message) => write!(f, "adb protocol error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for AdbError {}
+
+impl From<std::io::Error> for AdbError {
+    fn from(e: std::io::Error) -> Self {
+        AdbError::Io(e)
+    }
+}
+
+impl From<AdbError> for String {
+    fn from(e: AdbError) -> Self {
+        e.to_string()
+    }
+}
+
+pub type Result<T> = std::result::Result<T, AdbError>;
+
+/// Connection state of a device, as reported by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceState {
+    /// Online and authorized
+    Device,
+    Offline,
+    Unauthorized,
+    Recovery,
+    Sideload,
+    Bootloader,
+    /// Any other state, e.g. `no permissions (...)` or `authorizing`
+    Other(String),
+}
+
+impl DeviceState {
+    fn parse(state: &str) -> Self {
+        match state {
+            "device" => DeviceState::Device,
+            "offline" => DeviceState::Offline,
+            "unauthorized" => DeviceState::Unauthorized,
+            "recovery" => DeviceState::Recovery,
+            "sideload" => DeviceState::Sideload,
+            "bootloader" => DeviceState::Bootloader,
+            other => DeviceState::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            DeviceState::Device => "device",
+            DeviceState::Offline => "offline",
+            DeviceState::Unauthorized => "unauthorized",
+            DeviceState::Recovery => "recovery",
+            DeviceState::Sideload => "sideload",
+            DeviceState::Bootloader => "bootloader",
+            DeviceState::Other(state) => state,
+        };
+        f.write_str(state)
+    }
+}
+
+/// One line of `host:devices-l`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub serial: String,
+    pub state: DeviceState,
+    pub product: Option<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub transport_id: Option<u64>,
+}
+
+impl DeviceInfo {
+    /// `<serial> <state> [usb:..] [product:..] [model:..] [device:..] [transport_id:..]`.
+    /// The serial never contains whitespace but the state may (`no permissions (...)`),
+    /// so the properties are taken from the end.
+    fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let (serial, rest) = line.split_once(char::is_whitespace)?;
+        let mut words: Vec<&str> = rest.split_whitespace().collect();
+
+        let mut info = DeviceInfo {
+            serial: serial.to_string(),
+            state: DeviceState::Other(String::new()),
+            product: None,
+            model: None,
+            device: None,
+            transport_id: None,
+        };
+        while let Some(word) = words.last() {
+            let Some((key, value)) = word.split_once(':') else {
+                break;
+            };
+            match key {
+                "product" => info.product = Some(value.to_string()),
+                "model" => info.model = Some(value.to_string()),
+                "device" => info.device = Some(value.to_string()),
+                "transport_id" => info.transport_id = value.parse().ok(),
+                "usb" => {}
+                _ => break,
+            }
+            words.pop();
+        }
+        info.state = DeviceState::parse(&words.join(" "));
+        Some(info)
+    }
+}
+
+/// Parse the body of `host:devices-l` or one `host:track-devices-l` update
+fn parse_devices(list: &str) -> Vec<DeviceInfo> {
+    list.lines().filter_map(DeviceInfo::parse).collect()
+}
+
+/// Result of a shell command
+#[derive(Debug, Clone, Default)]
+pub struct ShellOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// Exit status; `None` for devices without shell protocol v2 (before Android 7)
+    pub exit_code: Option<u8>,
+}
+
+impl ShellOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code.is_none_or(|code| code == 0)
+    }
+
+    pub fn stdout_text(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).trim().to_string()
+    }
+
+    pub fn stderr_text(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).trim().to_string()
+    }
+}
+
+/// File metadata from a sync `STAT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub mode: u32,
+    pub size: u32,
+    pub mtime: u32,
+}
+
+/// Stream of device list changes from `host:track-devices-l`
+pub struct DeviceTracker {
+    stream: TcpStream,
+}
+
+impl DeviceTracker {
+    /// The full device list, each time it changes (the first one right away).
+    /// Waits as long as nothing changes; once an update starts it must arrive in time.
+    pub async fn next(&mut self) -> Result<Vec<DeviceInfo>> {
+        let mut length = [0u8; 4];
+        self.stream.read_exact(&mut length).await?;
+        let list = within(IO_TIMEOUT, read_body(&mut self.stream, length)).await?;
+        Ok(parse_devices(&list))
+    }
+}
+
+/// Address of an adb server. Each request opens its own connection, as the server expects.
+#[derive(Debug, Clone)]
+pub struct AdbClient {
+    port: u16,
+}
+
+impl Default for AdbClient {
+    fn default() -> Self {
+        let port = std::env::var("ANDROID_ADB_SERVER_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+        AdbClient { port }
+    }
+}
+
+impl AdbClient {
+    pub fn new(port: u16) -> Self {
+        AdbClient { port }
+    }
+
+    async fn connect(&self) -> Result<TcpStream> {
+        let stream = within(IO_TIMEOUT, async {
+            Ok(TcpStream::connect(("127.0.0.1", self.port)).await?)
+        })
+        .await?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+
+    /// Server protocol version; also tells whether the server is running
+    pub async fn version(&self) -> Result<u32> {
+        let version = self.query("host:version").await?;
+        u32::from_str_radix(&version, 16)
+            .map_err(|_| AdbError::Protocol(format!("bad version {:?}", version)))
+    }
+
+    /// Send a host request and read its length-prefixed reply
+    async fn query(&self, request: &str) -> Result<String> {
+        let mut stream = self.connect().await?;
+        within(IO_TIMEOUT, async {
+            send_request(&mut stream, request).await?;
+            read_string(&mut stream).await
+        })
+        .await
+    }
+
+    /// A connection switched to one device, ready for a device service
+    async fn transport(&self, serial: &str) -> Result<TcpStream> {
+        let mut stream = self.connect().await?;
+        within(
+            IO_TIMEOUT,
+            send_request(&mut stream, &format!("host:transport:{}", serial)),
+        )
+        .await?;
+        Ok(stream)
+    }
+
+    pub async fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        Ok(parse_devices(&self.query("host:devices-l").await?))
+    }
+
+    pub async fn track_devices(&self) -> Result<DeviceTracker> {
+        let mut stream = self.connect().await?;
+        within(
+            IO_TIMEOUT,
+            send_request(&mut stream, "host:track-devices-l"),
+        )
+        .await?;
+        Ok(DeviceTracker { stream })
+    }
+
+    /// Features of a device and its connection (`shell_v2`, `cmd`, ...)
+    pub async fn features(&self, serial: &str) -> Result<Vec<String>> {
+        let features = self
+            .query(&format!("host-serial:{}:features", serial))
+            .await?;
+        Ok(features.split(',').map(str::to_string).collect())
+    }
+
+    /// Forward a local socket to the device, e.g. `tcp:8888` to `tcp:8888`
+    pub async fn forward(&self, serial: &str, local: &str, remote: &str) -> Result<()> {
+        let mut stream = self.connect().await?;
+        within(IO_TIMEOUT, async {
+            send_request(
+                &mut stream,
+                &format!("host-serial:{}:forward:{};{}", serial, local, remote),
+            )
+            .await?;
+            // The first OKAY accepts the device, the second reports whether the forward was set up
+            match read_status(&mut stream).await {
+                Err(AdbError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
+                result => result,
+            }
+        })
+        .await
+    }
+
+    /// `adb connect <address>`. Returns the server's message, e.g. `connected to 1.2.3.4:5555`.
+    pub async fn connect_device(&self, address: &str) -> Result<String> {
+        let message = self.query(&format!("host:connect:{}", address)).await?;
+        // The request itself succeeds either way; the message tells
+        if message.starts_with("connected to") || message.starts_with("already connected to") {
+            Ok(message)
+        } else {
+            Err(AdbError::Failed(message))
+        }
+    }
+
+    /// Restart adbd on the device listening on TCP `port`
+    pub async fn tcpip(&self, serial: &str, port: u16) -> Result<String> {
+        let mut stream = self.transport(serial).await?;
+        within(IO_TIMEOUT, async {
+            send_request(&mut stream, &format!("tcpip:{}", port)).await?;
+            let mut message = String::new();
+            stream.read_to_string(&mut message).await?;
+            Ok(message.trim().to_string())
+        })
+        .await
+    }
+
+    /// Run a command with `sh -c` on the device and collect its output.
+    /// Uses shell protocol v2 (separate stderr and the exit code) when the device has it.
+    /// Fails with `TimedOut` if the command runs longer than `SHELL_TIMEOUT`.
+    pub async fn shell(&self, serial: &str, command: &str) -> Result<ShellOutput> {
+        let v2 = self
+            .features(serial)
+            .await?
+            .iter()
+            .any(|feature| feature == "shell_v2");
+
+        let mut stream = self.transport(serial).await?;
+        within(SHELL_TIMEOUT, run_shell(&mut stream, command, v2)).await
+    }
+
+    /// Metadata of a file on the device, `None` if it does not exist
+    pub async fn stat(&self, serial: &str, path: &str) -> Result<Option<FileStat>> {
+        let mut sync = self.sync(serial).await?;
+        let stat = within(IO_TIMEOUT, async {
+            sync_request(&mut sync, b"STAT", path.as_bytes()).await?;
+            let mut id = [0u8; 4];
+            sync.read_exact(&mut id).await?;
+            if &id != b"STAT" {
+                return Err(AdbError::Protocol(format!(
+                    "unexpected sync reply {:?}",
+                    String::from_utf8_lossy(&id)
+                )));
+            }
+            Ok(FileStat {
+                mode: sync.read_u32_le().await?,
+                size: sync.read_u32_le().await?,
+                mtime: sync.read_u32_le().await?,
+            })
+        })
+        .await?;
+        quit_sync(sync).await;
+        // Everything is zero when the file is missing
+        Ok((stat.mode != 0).then_some(stat))
+    }
+
+    /// Copy a local file to `remote` on the device with permissions `mode` (e.g. `0o644`).
+    /// The file is streamed, so its size does not matter.
+    pub async fn push(&self, serial: &str, local: &Path, remote: &str, mode: u32) -> Result<()> {
+        let mut file = tokio::fs::File::open(local).await?;
+        let mut sync = self.sync(serial).await?;
+
+        within(
+            IO_TIMEOUT,
+            sync_request(
+                &mut sync,
+                b"SEND",
+                format!("{},{}", remote, S_IFREG | mode).as_bytes(),
+            ),
+        )
+        .await?;
+        let mut chunk = vec![0u8; SYNC_CHUNK];
+        loop {
+            let length = file.read(&mut chunk).await?;
+            if length == 0 {
+                break;
+            }
+            within(
+                IO_TIMEOUT,
+                sync_request(&mut sync, b"DATA", &chunk[..length]),
+            )
+            .await?;
+        }
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+
+        within(IO_TIMEOUT, async {
+            sync.write_all(b"DONE").await?;
+            sync.write_u32_le(mtime).await?;
+
+            let mut id = [0u8; 4];
+            sync.read_exact(&mut id).await?;
+            let length = sync.read_u32_le().await? as usize;
+            match &id {
+                b"OKAY" => Ok(()),
+                b"FAIL" => {
+                    let mut message = vec![0u8; length];
+                    sync.read_exact(&mut message).await?;
+                    Err(AdbError::Failed(
+                        String::from_utf8_lossy(&message).to_string(),
+                    ))
+                }
+                _ => Err(AdbError::Protocol(format!(
+                    "unexpected sync reply {:?}",
+                    String::from_utf8_lossy(&id)
+                ))),
+            }
+        })
+        .await?;
+        quit_sync(sync).await;
+        Ok(())
+    }
+
+    /// Push an APK and install it with the package manager, replacing an existing install
+    pub async fn install(&self, serial: &str, apk: &Path) -> Result<()> {
+        const STAGING: &str = "/data/local/tmp/nl-mirror-install.apk";
+        self.push(serial, apk, STAGING, 0o644).await?;
+        let install = self
+            .shell(serial, &format!("pm install -r {}", STAGING))
+            .await;
+        let _ = self.shell(serial, &format!("rm -f {}", STAGING)).await;
+
+        // pm prints `Success` or `Failure [REASON]`; older devices always exit 0
+        let install = install?;
+        let stdout = install.stdout_text();
+        if install.success() && stdout.contains("Success") {
+            Ok(())
+        } else if stdout.is_empty() {
+            Err(AdbError::Failed(install.stderr_text()))
+        } else {
+            Err(AdbError::Failed(stdout))
+        }
+    }
+
+    /// A connection in sync mode (file transfer)
+    async fn sync(&self, serial: &str) -> Result<TcpStream> {
+        let mut stream = self.transport(serial).await?;
+        within(IO_TIMEOUT, send_request(&mut stream, "sync:")).await?;
+        Ok(stream)
+    }
+}
+
+/// Run `operation`, failing with `TimedOut` if it takes longer than `limit`
+async fn within<T>(limit: Duration, operation: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(limit, operation).await.map_err(|_| {
+        AdbError::Io(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "timed out",
+        ))
+    })?
+}
+
+/// Start `command` on a device connection and collect its output until it exits
+async fn run_shell(stream: &mut TcpStream, command: &str, v2: bool) -> Result<ShellOutput> {
+    if !v2 {
+        send_request(stream, &format!("shell:{}", command)).await?;
+        let mut stdout = Vec::new();
+        stream.read_to_end(&mut stdout).await?;
+        return Ok(ShellOutput {
+            stdout,
+            ..Default::default()
+        });
+    }
+
+    send_request(stream, &format!("shell,v2,raw:{}", command)).await?;
+    let mut output = ShellOutput::default();
+    loop {
+        // [id: u8][length: u32 LE][data]
+        let id = stream.read_u8().await?;
+        let length = stream.read_u32_le().await? as usize;
+        let mut data = vec![0u8; length];
+        stream.read_exact(&mut data).await?;
+        match id {
+            SHELL_STDOUT => output.stdout.extend_from_slice(&data),
+            SHELL_STDERR => output.stderr.extend_from_slice(&data),
+            SHELL_EXIT => {
+                output.exit_code = data.first().copied();
+                return Ok(output);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Write a request and wait for its `OKAY`
+async fn send_request(stream: &mut TcpStream, request: &str) -> Result<()> {
+    stream
+        .write_all(format!("{:04x}{}", request.len(), request).as_bytes())
+        .await?;
+    read_status(stream).await
+}
+
+/// `OKAY`, or `FAIL` followed by the reason
+async fn read_status(stream: &mut TcpStream) -> Result<()> {
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status).await?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => Err(AdbError::Failed(read_string(stream).await?)),
+        _ => Err(AdbError::Protocol(format!(
+            "unexpected status {:?}",
+            String::from_utf8_lossy(&status)
+        ))),
+    }
+}
+
+/// A string prefixed with its length as 4 hex digits
+async fn read_string(stream: &mut TcpStream) -> Result<String> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length).await?;
+    read_body(stream, length).await
+}
+
+/// The rest of a string after its 4 hex digit `length`
+async fn read_body(stream: &mut TcpStream, length: [u8; 4]) -> Result<String> {
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .and_then(|length| usize::from_str_radix(length, 16).ok())
+        .ok_or_else(|| {
+            AdbError::Protocol(format!("bad length {:?}", String::from_utf8_lossy(&length)))
+        })?;
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data).await?;
+    Ok(String::from_utf8_lossy(&data).to_string())
+}
+
+/// Sync packet: 4-byte id, little endian length, payload
+async fn sync_request(stream: &mut TcpStream, id: &[u8; 4], payload: &[u8]) -> Result<()> {
+    stream.write_all(id).await?;
+    stream.write_u32_le(payload.len() as u32).await?;
+    stream.write_all(payload).await?;
+    Ok(())
+}
+
+/// End a sync session (the connection closes either way)
+async fn quit_sync(mut stream: TcpStream) {
+    let _ = sync_request(&mut stream, b"QUIT", &[]).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_usb_device() {
+        let info = DeviceInfo::parse(
+            "R58M1234ABC            device usb:1-1 product:beyond1lteeea model:SM_G973F device:beyond1 transport_id:2",
+        )
+        .unwrap();
+        assert_eq!(info.serial, "R58M1234ABC");
+        assert_eq!(info.state, DeviceState::Device);
+        assert_eq!(info.product.as_deref(), Some("beyond1lteeea"));
+        assert_eq!(info.model.as_deref(), Some("SM_G973F"));
+        assert_eq!(info.device.as_deref(), Some("beyond1"));
+        assert_eq!(info.transport_id, Some(2));
+    }
+
+    #[test]
+    fn parses_no_permissions_state() {
+        let info = DeviceInfo::parse(
+            "0123456789ABCDEF       no permissions (missing udev rules? user is in the plugdev group); see [http://developer.android.com/tools/device.html] usb:3-2 transport_id:7",
+        )
+        .unwrap();
+        assert_eq!(info.serial, "0123456789ABCDEF");
+        assert_eq!(
+            info.state,
+            DeviceState::Other(
+                "no permissions (missing udev rules? user is in the plugdev group); see [http://developer.android.com/tools/device.html]".to_string()
+            )
+        );
+        assert_eq!(info.model, None);
+        assert_eq!(info.transport_id, Some(7));
+    }
+
+    #[test]
+    fn properties_in_any_order() {
+        let info =
+            DeviceInfo::parse("emulator-5554 device transport_id:1 model:sdk_gphone64_x86_64 usb:2-1 product:sdk_gphone64_x86_64")
+                .unwrap();
+        assert_eq!(info.state, DeviceState::Device);
+        assert_eq!(info.model.as_deref(), Some("sdk_gphone64_x86_64"));
+        assert_eq!(info.transport_id, Some(1));
+
+        // Without any properties (unauthorized devices report none)
+        let info = DeviceInfo::parse("emulator-5556\tunauthorized transport_id:3").unwrap();
+        assert_eq!(info.state, DeviceState::Unauthorized);
+        assert_eq!(info.transport_id, Some(3));
+        let info = DeviceInfo::parse("emulator-5556 offline").unwrap();
+        assert_eq!(info.state, DeviceState::Offline);
+        assert_eq!(info.transport_id, None);
+    }
+
+    #[test]
+    fn parses_network_serial() {
+        let info = DeviceInfo::parse(
+            "192.168.1.23:5555       device product:panther model:Pixel_7 device:panther transport_id:5",
+        )
+        .unwrap();
+        assert_eq!(info.serial, "192.168.1.23:5555");
+        assert_eq!(info.state, DeviceState::Device);
+        assert_eq!(info.model.as_deref(), Some("Pixel_7"));
+
+        let info = DeviceInfo::parse(
+            "adb-2A1B3C-xyz._adb-tls-connect._tcp device product:panther model:Pixel_7 device:panther transport_id:6",
+        )
+        .unwrap();
+        assert_eq!(info.serial, "adb-2A1B3C-xyz._adb-tls-connect._tcp");
+        assert_eq!(info.transport_id, Some(6));
+    }
+
+    #[test]
+    fn parses_device_list() {
+        let devices = parse_devices(
+            "emulator-5554 device product:sdk model:sdk device:generic transport_id:1\n\n192.168.1.23:5555 offline transport_id:2\n",
+        );
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].serial, "192.168.1.23:5555");
+        assert_eq!(devices[1].state, DeviceState::Offline);
+        assert!(parse_devices("").is_empty());
+    }
+
+    #[test]
+    fn state_round_trips() {
+        for state in [
+            "device",
+            "offline",
+            "unauthorized",
+            "recovery",
+            "sideload",
+            "bootloader",
+            "authorizing",
+        ] {
+            assert_eq!(DeviceState::parse(state).to_string(), state);
+        }
+    }
+}
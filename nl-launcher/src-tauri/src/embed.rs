@@ -0,0 +1,375 @@
+//! Mirror shown inside the launcher window
+//!
+//! Runs the nl_host pipeline in this process instead of the nl-host sidecar. The webview
+//! waits for each new decoded frame with `embedded_frame` and draws it on a canvas; input
+//! comes back through `embedded_input`. "Pop out" switches to the sidecar window.
+
+use crate::launcher::MirrorState;
+use nl_host::core::events::EventSink;
+use nl_host::core::{SessionState, Stage, StatsSampler};
+use nl_host::input::InputCommand;
+use nl_host::network::secure::Psk;
+use nl_host::network::{AdaptiveBounds, VideoSettings};
+use nl_host::{AudioMode, Session, SessionHandle};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Frame header: width, height, Y stride, UV stride (u32), then the 8 YUV -> RGB
+/// coefficients of `YuvToRgb` (f32), all little endian. The Y, U and V planes follow.
+const HEADER_SIZE: usize = 4 * 4 + 8 * 4;
+
+/// How often the stats line is sent to the UI
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest `embedded_frame` waits for a new frame before answering with none
+const FRAME_WAIT: Duration = Duration::from_millis(100);
+
+/// The running embedded session
+pub struct Embedded {
+    handle: SessionHandle,
+    /// Frames handed to the webview (its render rate)
+    shown: Arc<AtomicU64>,
+    /// Last frame size sent to the UI
+    size: Arc<Mutex<(u32, u32)>>,
+    turn_screen_off: bool,
+}
+
+/// Input from the mirror view, in video pixels
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EmbeddedInput {
+    Tap {
+        x: f32,
+        y: f32,
+    },
+    Swipe {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        duration_ms: u64,
+    },
+    LongPress {
+        x: f32,
+        y: f32,
+    },
+    Key {
+        action: String,
+        keycode: i32,
+        meta: i32,
+    },
+    Text {
+        text: String,
+    },
+    Rotate,
+}
+
+#[tauri::command]
+pub async fn start_embedded_mirror(
+    app: AppHandle,
+    state: State<'_, MirrorState>,
+    serial: String,
+    bitrate: u32,
+    max_size: u32,
+    max_fps: u32,
+    adaptive: bool,
+    secure: bool,
+    turn_screen_off: bool,
+) -> Result<(), String> {
+    // 1. Stop whatever is running (sidecar window or a previous embedded session)
+    {
+        let mut child_guard = state.child.lock().unwrap();
+        if let Some(child) = child_guard.take() {
+            let _ = child.kill();
+        }
+    }
+    if let Some(previous) = take(&state) {
+        tauri::async_runtime::spawn_blocking(move || end(previous))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let current_session = {
+        let mut session_guard = state.session_id.lock().unwrap();
+        *session_guard += 1;
+        *session_guard
+    };
+
+    // 2. Same transport as the sidecar: one multiplexed port, direct UDP over WiFi
+    // unless the session is encrypted (datagrams are not)
+    let (host, udp) = match serial.rsplit_once(':') {
+        Some((ip, _)) => (ip, !secure),
+        None => ("127.0.0.1", false),
+    };
+    let mut builder = Session::builder(host)
+        .video(VideoSettings {
+            bitrate,
+            max_size,
+            max_fps,
+        })
+        .audio(AudioMode::Play)
+        .mux(true)
+        .udp(udp)
+        // Decoder resets and handshake failures, like the sidecar's `--events json`
+        .events(EventSink::new({
+            let app = app.clone();
+            move |event| {
+                let _ = app.emit("mirror-event", event);
+            }
+        }));
+    if adaptive {
+        builder = builder.adaptive(AdaptiveBounds {
+            min_bitrate: 1_000_000.min(bitrate),
+            max_bitrate: bitrate,
+            min_size: 720,
+            max_size,
+        });
+    }
+    if secure {
+        let hw_serial = crate::keys::hardware_serial(&app, &serial).await?;
+        let key_file = crate::keys::device_key_file(&hw_serial)?;
+        builder = builder.key(Psk::load(&key_file).map_err(|e| e.to_string())?);
+    }
+
+    let handle = builder
+        .start()
+        .map_err(|e| format!("Failed to start mirror: {}", e))?;
+    if turn_screen_off {
+        let _ = handle.input().send(InputCommand::SetScreenPowerMode(0));
+    }
+
+    // 3. Session states and stats for the UI, in the shape of `nl-host --events json`
+    let states = handle.states().clone();
+    let stats = handle.stats().clone();
+    let shown = Arc::new(AtomicU64::new(0));
+    let shown_c = shown.clone();
+    let app_c = app.clone();
+    std::thread::spawn(move || {
+        let mut sampler = StatsSampler::new();
+        loop {
+            let state = app_c.state::<MirrorState>();
+            if *state.session_id.lock().unwrap() != current_session {
+                break;
+            }
+            let event = match states.recv_timeout(STATS_INTERVAL) {
+                Ok(SessionState::Streaming) => {
+                    // The device may have restarted with the screen on
+                    if let Some(embedded) = state.embedded.lock().unwrap().as_ref() {
+                        if embedded.turn_screen_off {
+                            let _ = embedded
+                                .handle
+                                .input()
+                                .send(InputCommand::SetScreenPowerMode(0));
+                        }
+                    }
+                    json!({ "event": "connected" })
+                }
+                Ok(SessionState::Reconnecting { attempt }) => {
+                    json!({ "event": "reconnecting", "attempt": attempt })
+                }
+                Ok(SessionState::Lost) => {
+                    let _ = app_c.emit(
+                        "mirror-event",
+                        json!({ "event": "error", "message": "Connection to the device lost", "fatal": true }),
+                    );
+                    if let Some(embedded) = take(&state) {
+                        end(embedded);
+                    }
+                    let _ = app_c.emit("mirror-stopped", ());
+                    break;
+                }
+                Ok(SessionState::Connecting) => continue,
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+                    let rates = sampler.rates(&stats, shown_c.load(Ordering::Relaxed));
+                    let latency = stats.latency.percentiles(Stage::Total);
+                    json!({
+                        "event": "stats",
+                        "render_fps": rates.render_fps,
+                        "decode_fps": rates.decode_fps,
+                        "bitrate_mbps": rates.mbps,
+                        "latency_ms": latency.map(|p| p.p50.as_secs_f64() * 1000.0),
+                    })
+                }
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
+            };
+            let _ = app_c.emit("mirror-event", event);
+        }
+    });
+
+    *state.screen_was_off.lock().unwrap() = turn_screen_off;
+    *state.device_serial.lock().unwrap() = Some(serial);
+    *state.embedded.lock().unwrap() = Some(Embedded {
+        handle,
+        shown,
+        size: Arc::new(Mutex::new((0, 0))),
+        turn_screen_off,
+    });
+    let _ = app.emit("embedded-started", ());
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_embedded_mirror(
+    app: AppHandle,
+    state: State<'_, MirrorState>,
+) -> Result<(), String> {
+    {
+        let mut session_guard = state.session_id.lock().unwrap();
+        *session_guard += 1;
+    }
+    if let Some(embedded) = take(&state) {
+        tauri::async_runtime::spawn_blocking(move || end(embedded))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let _ = app.emit("mirror-stopped", ());
+    Ok(())
+}
+
+/// Newest decoded frame since the last call. Waits for one if none arrived yet, so an
+/// unchanged screen is not copied again; empty after `FRAME_WAIT` or without a session.
+#[tauri::command]
+pub async fn embedded_frame(
+    app: AppHandle,
+    state: State<'_, MirrorState>,
+) -> Result<Response, String> {
+    // Wait and copy outside the state lock: input and stop must not queue behind a frame
+    let (frames, stats, shown, size) = {
+        let embedded_guard = state.embedded.lock().unwrap();
+        let Some(embedded) = embedded_guard.as_ref() else {
+            return Ok(Response::new(Vec::new()));
+        };
+        (
+            embedded.handle.frames().clone(),
+            embedded.handle.stats().clone(),
+            embedded.shown.clone(),
+            embedded.size.clone(),
+        )
+    };
+    let frame = tauri::async_runtime::spawn_blocking(move || {
+        let first = frames.recv_timeout(FRAME_WAIT).ok()?;
+        Some(frames.try_iter().last().unwrap_or(first))
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    let Some(frame) = frame else {
+        return Ok(Response::new(Vec::new()));
+    };
+    shown.fetch_add(1, Ordering::Relaxed);
+
+    // Handed over counts as presented (the canvas draws it on the next animation frame)
+    let presented = Instant::now();
+    stats.latency.record(
+        Stage::Present,
+        presented.saturating_duration_since(frame.decoded_at),
+    );
+    if frame.pts != 0 {
+        if let Some(total) = stats.clock.since_capture(frame.pts, presented) {
+            stats.latency.record(Stage::Total, total);
+        }
+    }
+
+    {
+        let mut size = size.lock().unwrap();
+        if *size != (frame.width, frame.height) {
+            *size = (frame.width, frame.height);
+            let _ = app.emit(
+                "mirror-event",
+                json!({ "event": "resolution", "width": frame.width, "height": frame.height }),
+            );
+        }
+    }
+
+    let height = frame.height as usize;
+    let y_len = (frame.y_stride * height).min(frame.y_plane.len());
+    let uv_len = (frame.uv_stride * height.div_ceil(2))
+        .min(frame.u_plane.len())
+        .min(frame.v_plane.len());
+    let c = frame.color.conversion();
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + y_len + 2 * uv_len);
+    for value in [
+        frame.width,
+        frame.height,
+        frame.y_stride as u32,
+        frame.uv_stride as u32,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [
+        c.y_offset,
+        c.y_scale,
+        c.uv_offset,
+        c.uv_scale,
+        c.cr_r,
+        c.cb_g,
+        c.cr_g,
+        c.cb_b,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&frame.y_plane[..y_len]);
+    bytes.extend_from_slice(&frame.u_plane[..uv_len]);
+    bytes.extend_from_slice(&frame.v_plane[..uv_len]);
+    Ok(Response::new(bytes))
+}
+
+#[tauri::command]
+pub async fn embedded_input(
+    state: State<'_, MirrorState>,
+    input: EmbeddedInput,
+) -> Result<(), String> {
+    let embedded_guard = state.embedded.lock().unwrap();
+    let Some(embedded) = embedded_guard.as_ref() else {
+        return Err("Mirror is not running".to_string());
+    };
+    let command = match input {
+        EmbeddedInput::Tap { x, y } => InputCommand::Tap(x, y),
+        EmbeddedInput::Swipe {
+            x1,
+            y1,
+            x2,
+            y2,
+            duration_ms,
+        } => InputCommand::Swipe(x1, y1, x2, y2, duration_ms),
+        EmbeddedInput::LongPress { x, y } => InputCommand::LongPress(x, y, 500),
+        EmbeddedInput::Key {
+            action,
+            keycode,
+            meta,
+        } => InputCommand::Keycode(action, keycode, meta),
+        EmbeddedInput::Text { text } => InputCommand::InjectText(text),
+        EmbeddedInput::Rotate => InputCommand::Rotate,
+    };
+    embedded
+        .handle
+        .input()
+        .try_send(command)
+        .map_err(|e| e.to_string())
+}
+
+/// Take the embedded session out of the state, if one is running
+pub fn take(state: &MirrorState) -> Option<Embedded> {
+    state.embedded.lock().unwrap().take()
+}
+
+/// End an embedded session: restore the screen, then close it. Dropping the handle also
+/// closes its multiplexed socket, so the device is free for the sidecar or the next session.
+/// Blocks until the video thread has stopped.
+pub fn end(embedded: Embedded) {
+    if embedded.turn_screen_off {
+        // Mode 2 = on. Let the input thread send it before the session closes.
+        let _ = embedded
+            .handle
+            .input()
+            .send(InputCommand::SetScreenPowerMode(2));
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    drop(embedded);
+}
@@ -0,0 +1,57 @@
+//! Client for the control socket of a running nl-host session (`nl-host mirror --ipc`)
+
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(unix)]
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Socket path (Unix) or pipe name (Windows) for one mirror session of this launcher
+pub fn session_path(session_id: u64) -> String {
+    let name = format!("nl-mirror-{}-{}", std::process::id(), session_id);
+    if cfg!(windows) {
+        format!(r"\\.\pipe\{}", name)
+    } else {
+        std::env::temp_dir()
+            .join(format!("{}.sock", name))
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Send one command (e.g. `{"cmd": "screenshot"}`) and wait for its reply
+pub fn send(path: &str, command: &Value) -> Result<Value, String> {
+    let reply = exchange(path, command).map_err(|e| format!("Session not reachable: {}", e))?;
+    let reply: Value =
+        serde_json::from_str(&reply).map_err(|e| format!("Invalid reply: {}", e))?;
+    if reply.get("ok").and_then(|ok| ok.as_bool()) != Some(true) {
+        let error = reply.get("error").and_then(|e| e.as_str()).unwrap_or("unknown error");
+        return Err(error.to_string());
+    }
+    Ok(reply)
+}
+
+#[cfg(unix)]
+fn exchange(path: &str, command: &Value) -> std::io::Result<String> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    roundtrip(stream.try_clone()?, stream, command)
+}
+
+#[cfg(windows)]
+fn exchange(path: &str, command: &Value) -> std::io::Result<String> {
+    // A named pipe client is opened like a file (the server answers right away)
+    let pipe = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    roundtrip(pipe.try_clone()?, pipe, command)
+}
+
+fn roundtrip(reader: impl std::io::Read, mut writer: impl Write, command: &Value) -> std::io::Result<String> {
+    writeln!(writer, "{}", command)?;
+    writer.flush()?;
+    let mut reply = String::new();
+    BufReader::new(reader).read_line(&mut reply)?;
+    Ok(reply)
+}
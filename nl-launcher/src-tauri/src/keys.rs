@@ -0,0 +1,92 @@
+//! Pre-shared keys for the encrypted transport, pinned per device.
+//! A key is generated the first time a device is mirrored with encryption and reused after
+//! that. It reaches the device over ADB and nl-host through `--key-file`.
+
+use std::path::PathBuf;
+use tauri::{AppHandle, Runtime};
+
+/// Where the mirror server looks for its key (see SecureTransport.KEY_PATH)
+pub const DEVICE_KEY_PATH: &str = "/data/local/tmp/nl-mirror.key";
+
+/// Hardware serial of a device. Unlike the adb serial it is the same over USB and WiFi.
+pub async fn hardware_serial<R: Runtime>(app: &AppHandle<R>, serial: &str) -> Result<String, String> {
+    let output = crate::adb::client(app).await?
+        .shell(serial, "getprop ro.serialno")
+        .await?;
+    let hw_serial = output.stdout_text();
+    if hw_serial.is_empty() {
+        Ok(serial.to_string())
+    } else {
+        Ok(hw_serial)
+    }
+}
+
+/// Key file for a device, created on first use
+pub fn device_key_file(hw_serial: &str) -> Result<PathBuf, String> {
+    let dir = dirs::config_dir()
+        .ok_or("No config directory")?
+        .join("nl-mirror")
+        .join("keys");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let name: String = hw_serial
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let path = dir.join(format!("{}.key", name));
+    if !path.exists() {
+        let mut key = [0u8; 32];
+        getrandom::getrandom(&mut key).map_err(|e| e.to_string())?;
+        let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        write_private(&path, &hex).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(path)
+}
+
+/// Write a file only the current user can read
+#[cfg(unix)]
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents.as_bytes())
+}
+
+/// On Windows the file first inherits the ACL of the per-user config directory,
+/// then icacls drops the inherited entries and grants only the current user. If
+/// that fails the key is removed rather than left readable by other accounts.
+#[cfg(windows)]
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    drop(file);
+
+    let restricted = std::env::var("USERNAME").ok().and_then(|user| {
+        std::process::Command::new("icacls")
+            .arg(path)
+            .args(["/inheritance:r", "/grant:r"])
+            .arg(format!("{}:F", user))
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .creation_flags(CREATE_NO_WINDOW)
+            .status()
+            .ok()
+    });
+    if restricted.is_some_and(|status| status.success()) {
+        Ok(())
+    } else {
+        let _ = std::fs::remove_file(path);
+        Err(std::io::Error::other("could not restrict access to the key file"))
+    }
+}
@@ -1,6 +1,4 @@
 use tauri::{AppHandle, State, Manager, Emitter};
-#[cfg(target_os = "macos")]
-use tauri::ActivationPolicy;
 use tauri_plugin_shell::{ShellExt, process::CommandChild};
 use std::sync::Mutex;
 
@@ -11,6 +9,9 @@ pub struct MirrorState {
     pub session_id: Mutex<u64>,
     pub screen_was_off: Mutex<bool>,
     pub device_serial: Mutex<Option<String>>, // Track device serial for ADB commands
+    pub key_file: Mutex<Option<std::path::PathBuf>>, // Transport key of an encrypted session
+    pub ipc_path: Mutex<Option<String>>, // Control socket of the running session
+    pub embedded: Mutex<Option<crate::embed::Embedded>>, // Mirror shown in the launcher window
 }
 
 #[tauri::command]
@@ -20,6 +21,10 @@ pub async fn start_mirror(
     _serial: String,
     bitrate: u32,
     max_size: u32,
+    max_fps: u32,
+    buffer_ms: u32,
+    adaptive: bool,
+    secure: bool,
     turn_screen_off: bool,
 ) -> Result<(), String> {
     // 1. Invalidate previous session and cleanup
@@ -29,6 +34,10 @@ pub async fn start_mirror(
             let _ = child.kill();
         }
     }
+    // Popping out: the embedded view hands the device over to the sidecar window
+    if let Some(embedded) = crate::embed::take(&state) {
+        tauri::async_runtime::spawn_blocking(move || crate::embed::end(embedded)).await.map_err(|e| e.to_string())?;
+    }
     
     // Dispatch Tray cleanup to main thread to avoid macOS crash
     let app_c = app.clone();
@@ -55,16 +64,55 @@ pub async fn start_mirror(
         "mirror".to_string(),
         "--bitrate".to_string(), bitrate.to_string(),
         "--max-size".to_string(), max_size.to_string(),
+        "--max-fps".to_string(), max_fps.to_string(),
+        "--buffer-ms".to_string(), buffer_ms.to_string(),
         "--audio".to_string(),
+        "--serial".to_string(), _serial.clone(),
+        // Everything over the one port init_session forwarded
+        "--mux".to_string(),
+        // Status for the UI as JSON lines on stdout
+        "--events".to_string(), "json".to_string(),
     ];
 
     if turn_screen_off {
         args.push("--turn-screen-off".to_string());
     }
 
+    if adaptive {
+        args.push("--adaptive".to_string());
+    }
+
+    // Wireless adb ("ip:port"): talk to the device directly so it can stream over UDP.
+    // Through the adb forward it only sees a loopback client and stays on TCP.
+    // Datagrams are not encrypted, so a secure session stays on TCP as well.
+    if let Some((ip, _)) = _serial.rsplit_once(':') {
+        args.splice(0..0, ["--host".to_string(), ip.to_string()]);
+        if !secure {
+            args.push("--udp".to_string());
+        }
+    }
+
+    // Same pinned key that init_session pushed to the device
+    let key_file = if secure {
+        let hw_serial = crate::keys::hardware_serial(&app, &_serial).await?;
+        let key_file = crate::keys::device_key_file(&hw_serial)?;
+        args.push("--key-file".to_string());
+        args.push(key_file.to_string_lossy().to_string());
+        Some(key_file)
+    } else {
+        None
+    };
+
+    // Control socket for the tray and stop_mirror
+    let ipc_path = crate::ipc::session_path(current_session);
+    args.push("--ipc".to_string());
+    args.push(ipc_path.clone());
+
     // Record state for stop_mirror
+    *state.ipc_path.lock().unwrap() = Some(ipc_path);
     *state.screen_was_off.lock().unwrap() = turn_screen_off;
     *state.device_serial.lock().unwrap() = Some(_serial.clone());
+    *state.key_file.lock().unwrap() = key_file;
 
     let sidecar_command = app.shell().sidecar("nl-host").map_err(|e| e.to_string())?
         .args(args);
@@ -82,8 +130,19 @@ pub async fn start_mirror(
         while let Some(event) = rx.recv().await {
             match event {
                 CommandEvent::Stdout(line) => {
-                    // Forward nl-host stdout to console
-                    println!("[nl-host] {}", String::from_utf8_lossy(&line));
+                    let line = String::from_utf8_lossy(&line);
+                    // --events json: one event object per line, re-emitted for the UI
+                    match serde_json::from_str::<serde_json::Value>(line.trim()) {
+                        Ok(event) if event.get("event").is_some() => {
+                            // Not from a session that was replaced in the meantime
+                            let state = app_handle.state::<MirrorState>();
+                            if *state.session_id.lock().unwrap() == current_session {
+                                let _ = app_handle.emit("mirror-event", event);
+                            }
+                        }
+                        // Forward other nl-host stdout to console
+                        _ => println!("[nl-host] {}", line),
+                    }
                 }
                 CommandEvent::Stderr(line) => {
                     // Forward nl-host stderr to console
@@ -116,9 +175,6 @@ pub async fn start_mirror(
 
                 let _ = app_handle.emit("mirror-stopped", ());
 
-                #[cfg(target_os = "macos")]
-                let _ = app_handle.set_activation_policy(ActivationPolicy::Regular);
-
                 if let Some(window) = app_handle.get_webview_window("main") {
                     let _ = window.show();
                     let _ = window.set_focus();
@@ -139,12 +195,7 @@ pub async fn start_mirror(
         }
     });
 
-    // 5. App State & Dock Icon
-    #[cfg(target_os = "macos")]
-    let _ = app.set_activation_policy(ActivationPolicy::Accessory);
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.hide();
-    }
+    // 5. The launcher stays open next to the mirror window (device list and controls)
     let _ = app.emit("mirror-started", ());
 
     Ok(())
@@ -161,29 +212,18 @@ pub async fn stop_mirror(
         *session_guard += 1;
     }
 
-    // 1. Restore screen via nl-host control port 8889 (BEFORE killing the process)
-    // This sends set_screen_power_mode command to undo the --turn-screen-off flag
-    println!("Sending screen restore command via control port...");
-    let _ = std::thread::spawn(|| {
-        use std::io::Write;
-        if let Ok(mut stream) = std::net::TcpStream::connect_timeout(
-            &"127.0.0.1:8889".parse().unwrap(),
-            std::time::Duration::from_millis(500)
-        ) {
-            // MODE 2 = ON (Normal mode)
-            let cmd = r#"{"cmd":"set_screen_power_mode","mode":2}"#;
-            let _ = stream.write_all(format!("{}\n", cmd).as_bytes());
-            let _ = stream.flush();
-            println!("Screen restore command sent.");
-        } else {
-            println!("Control port not available, will try keyevent fallback.");
-        }
-    }).join();
-
-    // Small delay to let the command take effect
-    std::thread::sleep(std::time::Duration::from_millis(200));
+    // 1. Ask the session to quit: it restores the screen itself on the way out
+    let ipc_path = state.ipc_path.lock().unwrap().take();
+    let quit = ipc_path.map(|path| crate::ipc::send(&path, &serde_json::json!({ "cmd": "quit" })));
+    if let Some(Ok(_)) = quit {
+        println!("Session quitting.");
+        // Give it time to restore the screen before the kill below
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    } else {
+        restore_screen(&app, &state).await?;
+    }
 
-    // 2. Kill the process
+    // 2. Kill the process (if it has not exited by itself)
     {
         let mut child_guard = state.child.lock().unwrap();
         if let Some(child) = child_guard.take() {
@@ -203,9 +243,6 @@ pub async fn stop_mirror(
     // 4. Notify UI
     let _ = app.emit("mirror-stopped", ());
 
-    #[cfg(target_os = "macos")]
-    let _ = app.set_activation_policy(ActivationPolicy::Regular);
-
     // 5. Restore window visibility
     if let Some(window) = app.get_webview_window("main") {
         let _ = window.show();
@@ -215,6 +252,30 @@ pub async fn stop_mirror(
     Ok(())
 }
 
+/// Fallback when the session cannot be asked to quit: turn the screen back on through
+/// a separate nl-host control connection, to undo --turn-screen-off
+async fn restore_screen(app: &AppHandle, state: &State<'_, MirrorState>) -> Result<(), String> {
+    println!("Sending screen restore command via control channel...");
+    let mut args = vec!["--mux".to_string()];
+    if let Some(key_file) = state.key_file.lock().unwrap().clone() {
+        // Encrypted session: nl-host does the handshake with the pinned key
+        args.push("--key-file".to_string());
+        args.push(key_file.to_string_lossy().to_string());
+    }
+    args.extend(["screen-power".to_string(), "2".to_string()]); // MODE 2 = ON (Normal mode)
+    let restored = app.shell().sidecar("nl-host").map_err(|e| e.to_string())?
+        .args(args)
+        .output().await;
+    match restored {
+        Ok(out) if out.status.success() => println!("Screen restore command sent."),
+        _ => println!("Control channel not available, will try keyevent fallback."),
+    }
+
+    // Small delay to let the command take effect
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn hide_launcher(app: AppHandle) -> Result<(), String> {
     if let Some(window) = app.get_webview_window("main") {
@@ -6,6 +6,10 @@ fn greet(name: &str) -> String {
 }
 
 mod adb;
+mod adb_client;
+mod embed;
+mod ipc;
+mod keys;
 mod launcher;
 mod network;
 mod tray;
@@ -29,6 +33,11 @@ pub fn run() {
                     child_guard.is_some()
                 };
 
+                // The embedded mirror ends with the window; give the device its screen back
+                if let Some(embedded) = embed::take(&state) {
+                    embed::end(embedded);
+                }
+
                 if is_mirroring {
                     // Prevent the app from exiting if mirroring is active
                     api.prevent_close();
@@ -53,6 +62,10 @@ pub fn run() {
             launcher::stop_mirror,
             launcher::hide_launcher,
             launcher::show_launcher,
+            embed::start_embedded_mirror,
+            embed::stop_embedded_mirror,
+            embed::embedded_frame,
+            embed::embedded_input,
             network::get_local_ip,
             network::start_pairing_server,
             network::stop_pairing_server
@@ -63,6 +76,9 @@ pub fn run() {
             session_id: std::sync::Mutex::new(0),
             screen_was_off: std::sync::Mutex::new(false),
             device_serial: std::sync::Mutex::new(None),
+            key_file: std::sync::Mutex::new(None),
+            ipc_path: std::sync::Mutex::new(None),
+            embedded: std::sync::Mutex::new(None),
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
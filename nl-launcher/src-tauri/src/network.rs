@@ -270,37 +270,27 @@ pub fn start_pairing_server(app: AppHandle) -> Result<(), String> {
 
                             // Trigger ADB connect in background
                             tauri::async_runtime::spawn(async move {
-                                use tauri_plugin_shell::ShellExt;
-                                let shell = app_clone.shell();
-                                
                                 println!("Attempting ADB connect to {}", ip_clone);
-                                let output = shell.sidecar("adb")
-                                    .expect("failed sidecar")
-                                    .args(["connect", &format!("{}:5555", ip_clone)])
-                                    .output()
-                                    .await;
+                                let result = match crate::adb::client(&app_clone).await {
+                                    Ok(adb) => adb
+                                        .connect_device(&format!("{}:5555", ip_clone))
+                                        .await
+                                        .map_err(|e| e.to_string()),
+                                    Err(e) => Err(e),
+                                };
 
-                                match output {
-                                    Ok(o) => {
-                                        let stdout = String::from_utf8_lossy(&o.stdout);
-                                        let stderr = String::from_utf8_lossy(&o.stderr);
-                                        println!("ADB Output: {} | {}", stdout.trim(), stderr.trim());
-
-                                        // Check if actually connected (stdout contains "connected")
-                                        if stdout.contains("connected") {
-                                            // Record successful connection for status polling
-                                            if let Ok(mut ip) = LAST_CONNECTED_IP.lock() {
-                                                *ip = Some(ip_clone.clone());
-                                            }
-                                            let _ = app_clone.emit("pair-success", &ip_clone);
-                                        } else {
-                                            let msg = if stderr.is_empty() { stdout.to_string() } else { stderr.to_string() };
-                                            let _ = app_clone.emit("pair-error", format!("Failed: {}", msg.trim()));
+                                match result {
+                                    Ok(message) => {
+                                        println!("ADB Output: {}", message);
+                                        // Record successful connection for status polling
+                                        if let Ok(mut ip) = LAST_CONNECTED_IP.lock() {
+                                            *ip = Some(ip_clone.clone());
                                         }
+                                        let _ = app_clone.emit("pair-success", &ip_clone);
                                     },
                                     Err(e) => {
-                                        eprintln!("Failed to execute sidecar: {}", e);
-                                        let _ = app_clone.emit("pair-error", format!("Execution error: {}", e));
+                                        eprintln!("ADB connect failed: {}", e);
+                                        let _ = app_clone.emit("pair-error", format!("Failed: {}", e));
                                     }
                                 }
                             });
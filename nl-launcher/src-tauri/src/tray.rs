@@ -1,3 +1,4 @@
+use serde_json::{json, Value};
 use tauri::{
     menu::{Menu, MenuItem},
     tray::TrayIconBuilder,
@@ -6,10 +7,33 @@ use tauri::{
 
 pub fn init_tray(app: &AppHandle) -> tauri::Result<tauri::tray::TrayIcon> {
     let show_i = MenuItem::with_id(app, "show", "🖥️ Show Launcher", true, None::<&str>)?;
+    let screenshot_i = MenuItem::with_id(app, "screenshot", "📸 Screenshot", true, None::<&str>)?;
+    let screenshot_touches_i = MenuItem::with_id(
+        app,
+        "screenshot_touches",
+        "👆 Screenshot with Touches",
+        true,
+        None::<&str>,
+    )?;
+    let rotate_i = MenuItem::with_id(app, "rotate", "🔄 Rotate", true, None::<&str>)?;
+    let screen_off_i = MenuItem::with_id(app, "screen_off", "🌑 Screen Off", true, None::<&str>)?;
+    let screen_on_i = MenuItem::with_id(app, "screen_on", "🌕 Screen On", true, None::<&str>)?;
     let stop_i = MenuItem::with_id(app, "stop", "🛑 Stop Mirroring", true, None::<&str>)?;
     let quit_i = MenuItem::with_id(app, "quit", "❌ Quit", true, None::<&str>)?;
 
-    let menu = Menu::with_items(app, &[&show_i, &stop_i, &quit_i])?;
+    let menu = Menu::with_items(
+        app,
+        &[
+            &show_i,
+            &screenshot_i,
+            &screenshot_touches_i,
+            &rotate_i,
+            &screen_off_i,
+            &screen_on_i,
+            &stop_i,
+            &quit_i,
+        ],
+    )?;
 
     let mut builder = TrayIconBuilder::with_id("main-tray")
         .menu(&menu)
@@ -22,6 +46,13 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<tauri::tray::TrayIcon> {
                         let _ = window.set_focus();
                     }
                 }
+                "screenshot" => send_command(app, json!({ "cmd": "screenshot" })),
+                "screenshot_touches" => {
+                    send_command(app, json!({ "cmd": "screenshot", "burn_touches": true }))
+                }
+                "rotate" => send_command(app, json!({ "cmd": "rotate" })),
+                "screen_off" => send_command(app, json!({ "cmd": "screen_power", "on": false })),
+                "screen_on" => send_command(app, json!({ "cmd": "screen_power", "on": true })),
                 "stop" => {
                     let app_clone = app.clone();
                     tauri::async_runtime::spawn(async move {
@@ -42,3 +73,16 @@ pub fn init_tray(app: &AppHandle) -> tauri::Result<tauri::tray::TrayIcon> {
 
     Ok(builder.build(app)?)
 }
+
+/// Forward a tray action to the running session over its control socket
+fn send_command(app: &AppHandle, command: Value) {
+    let state = app.state::<crate::launcher::MirrorState>();
+    let Some(path) = state.ipc_path.lock().unwrap().clone() else {
+        return;
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = crate::ipc::send(&path, &command) {
+            eprintln!("Tray command {} failed: {}", command, e);
+        }
+    });
+}
@@ -1,10 +1,12 @@
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { MonitorPlay } from "lucide-react";
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import "./App.css";
 import { ApkDropZone } from "./components/ApkDropZone";
 import { DeviceList } from "./components/DeviceList";
 import { KeyboardShortcuts } from "./components/KeyboardShortcuts";
 import { MirrorControls } from "./components/MirrorControls";
+import { MirrorView } from "./components/MirrorView";
 
 interface Device {
   serial: string;
@@ -14,6 +16,19 @@ interface Device {
 
 function App() {
   const [selectedDevice, setSelectedDevice] = useState<Device | null>(null);
+  // The mirror is shown in this window (not popped out)
+  const [embedded, setEmbedded] = useState(false);
+
+  useEffect(() => {
+    const unlistenEmbedded = listen('embedded-started', () => setEmbedded(true));
+    const unlistenWindow = listen('mirror-started', () => setEmbedded(false));
+    const unlistenStop = listen('mirror-stopped', () => setEmbedded(false));
+    return () => {
+      unlistenEmbedded.then((f: UnlistenFn) => f());
+      unlistenWindow.then((f: UnlistenFn) => f());
+      unlistenStop.then((f: UnlistenFn) => f());
+    };
+  }, []);
 
   return (
     <div className="app-container">
@@ -30,13 +45,15 @@ function App() {
         </div>
       </header>
 
-      <main className="main-content">
+      <main className={`main-content ${embedded ? 'with-mirror' : ''}`}>
         <DeviceList
           onSelect={setSelectedDevice}
           selectedSerial={selectedDevice?.serial || null}
         />
 
         <MirrorControls selectedSerial={selectedDevice?.serial || null} />
+
+        {embedded && <MirrorView />}
       </main>
 
       <KeyboardShortcuts />
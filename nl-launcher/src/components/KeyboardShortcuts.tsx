@@ -5,8 +5,32 @@ const shortcuts = [
   {
     category: "General", items: [
       { key: "Cmd/Ctrl + S", desc: "Screenshot (saves to Desktop)" },
-      { key: "Cmd/Ctrl + C", desc: "Copy from Android clipboard" },
-      { key: "Cmd/Ctrl + V", desc: "Paste to Android" },
+      { key: "Cmd/Ctrl + C", desc: "Copy from Android clipboard (text or image)" },
+      { key: "Cmd/Ctrl + V", desc: "Paste text or image to Android" },
+    ]
+  },
+  {
+    category: "Window", items: [
+      { key: "Alt + F", desc: "Toggle fullscreen" },
+      { key: "Alt + T", desc: "Toggle always on top" },
+      { key: "Alt + B", desc: "Toggle borderless" },
+      { key: "Alt + G", desc: "Toggle 1:1 pixel scale" },
+      { key: "Alt + W", desc: "Fit to screen" },
+      { key: "Alt + K", desc: "Cycle scaling filter" },
+      { key: "Alt + J", desc: "Toggle sharpening" },
+      { key: "Alt + I", desc: "Toggle performance HUD" },
+      { key: "Alt + O", desc: "Toggle touch visualization" },
+      { key: "Alt + Scroll / + / -", desc: "Zoom in / out at the cursor" },
+      { key: "Alt + 0", desc: "Reset zoom" },
+      { key: "Scroll / Middle-drag", desc: "Pan while zoomed" },
+      { key: "Alt + L", desc: "Toggle loupe (magnifier)" },
+    ]
+  },
+  {
+    category: "Stream Quality", items: [
+      { key: "Alt + ] / [", desc: "Bitrate up / down" },
+      { key: "Alt + . / ,", desc: "Resolution up / down" },
+      { key: "Alt + ;", desc: "Cycle frame rate cap" },
     ]
   },
   {
@@ -36,6 +60,12 @@ const shortcuts = [
       { key: "F10", desc: "Swipe Down (prev video)" },
     ]
   },
+  {
+    category: "Files", items: [
+      { key: "Drop .apk/.apks", desc: "Install on device" },
+      { key: "Drop other file", desc: "Push to /sdcard/Download" },
+    ]
+  },
 ];
 
 export function KeyboardShortcuts() {
@@ -76,7 +106,7 @@ export function KeyboardShortcuts() {
               ))}
             </div>
             <div className="modal-footer">
-              <span className="hint">These shortcuts work in the Mirror window</span>
+              <span className="hint">These shortcuts work in the Mirror window (Alt is Option on macOS)</span>
             </div>
           </div>
         </div>
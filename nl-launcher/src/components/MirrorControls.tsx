@@ -1,21 +1,38 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
-import { AlertCircle, Cast, CheckCircle, Usb, Wifi, Zap } from "lucide-react";
-import { useEffect, useState } from "react";
+import { AlertCircle, Cast, CheckCircle, ExternalLink, Usb, Wifi, Zap } from "lucide-react";
+import { useEffect, useRef, useState } from "react";
 import { CustomSelect } from "./ui/CustomSelect";
 
 interface Props {
   selectedSerial: string | null;
 }
 
+/** One `nl-host --events json` line, re-emitted by the launcher as `mirror-event` */
+type MirrorEvent =
+  | { event: 'connected' }
+  | { event: 'reconnecting', attempt: number }
+  | { event: 'resolution', width: number, height: number }
+  | { event: 'stats', render_fps: number, decode_fps: number, bitrate_mbps: number, latency_ms: number | null }
+  | { event: 'decoder_reset', count: number, reason: string }
+  | { event: 'screenshot_saved', path: string }
+  | { event: 'recording_started', path: string }
+  | { event: 'recording_stopped', path: string, frames: number, duration_ms: number }
+  | { event: 'error', message: string, fatal: boolean };
+
 export function MirrorControls({ selectedSerial }: Props) {
   const [bitrate, setBitrate] = useState(16000000); // Default 16Mbps
-  const [resolution, setResolution] = useState(1080);
+  // Longer side of the video, 0 = native
+  const [resolution, setResolution] = useState(0);
+  const [maxFps, setMaxFps] = useState(0); // 0 = uncapped
+  const [bufferMs, setBufferMs] = useState(0); // 0 = lowest latency
   const [isStarting, setIsStarting] = useState(false);
   const [status, setStatus] = useState<{ msg: string, type: 'info' | 'success' | 'error' } | null>(null);
   const [connectionMode, setConnectionMode] = useState<'usb' | 'wifi'>('usb');
   const [wifiIp, setWifiIp] = useState("");
   const [turnScreenOff, setTurnScreenOff] = useState(false);
+  const [adaptive, setAdaptive] = useState(false);
+  const [secure, setSecure] = useState(false);
 
   // Auto-scan when switching to WiFi tab
   useEffect(() => {
@@ -46,18 +63,80 @@ export function MirrorControls({ selectedSerial }: Props) {
   }
 
   const [isStreaming, setIsStreaming] = useState(false);
+  // Where the running mirror is shown: in this window, or popped out into its own
+  const [mode, setMode] = useState<'embedded' | 'window' | null>(null);
+  const [resolutionInfo, setResolutionInfo] = useState<string | null>(null);
+  const [statsInfo, setStatsInfo] = useState<string | null>(null);
+  // Why the session ended, shown instead of "Mirror Stopped"
+  const fatalError = useRef<string | null>(null);
 
   useEffect(() => {
     const unlistenStop = listen('mirror-stopped', () => {
       setIsStreaming(false);
       setIsStarting(false);
+      setMode(null);
+      setResolutionInfo(null);
+      setStatsInfo(null);
+      if (fatalError.current) {
+        setStatus({ msg: fatalError.current, type: 'error' });
+        fatalError.current = null;
+        return;
+      }
       setStatus({ msg: "Mirror Stopped", type: 'success' });
       setTimeout(() => setStatus(null), 2000);
     });
 
+    const unlistenEvent = listen<MirrorEvent>('mirror-event', ({ payload }) => {
+      switch (payload.event) {
+        case 'connected':
+          setStatus({ msg: "Connected", type: 'success' });
+          setTimeout(() => setStatus(null), 2000);
+          break;
+        case 'reconnecting':
+          setStatus({ msg: `Connection lost, reconnecting (attempt ${payload.attempt})...`, type: 'info' });
+          break;
+        case 'resolution':
+          setResolutionInfo(`${payload.width}×${payload.height}`);
+          break;
+        case 'stats': {
+          const latency = payload.latency_ms !== null ? ` · ${Math.round(payload.latency_ms)} ms` : '';
+          setStatsInfo(`${Math.round(payload.render_fps)} fps · ${payload.bitrate_mbps.toFixed(1)} Mbps${latency}`);
+          break;
+        }
+        case 'decoder_reset':
+          break;
+        case 'screenshot_saved':
+          setStatus({ msg: `Screenshot saved to ${payload.path}`, type: 'success' });
+          setTimeout(() => setStatus(null), 4000);
+          break;
+        case 'recording_started':
+          setStatus({ msg: `Recording to ${payload.path}`, type: 'info' });
+          break;
+        case 'recording_stopped':
+          setStatus({ msg: `Recording saved to ${payload.path} (${Math.round(payload.duration_ms / 1000)} s)`, type: 'success' });
+          setTimeout(() => setStatus(null), 4000);
+          break;
+        case 'error':
+          if (payload.fatal) {
+            fatalError.current = payload.message;
+          }
+          setStatus({ msg: payload.message, type: 'error' });
+          break;
+      }
+    });
+
     const unlistenStart = listen('mirror-started', () => {
       setIsStreaming(true);
       setIsStarting(false);
+      setMode('window');
+      setStatus({ msg: "Mirror Started", type: 'success' });
+      setTimeout(() => setStatus(null), 2000);
+    });
+
+    const unlistenEmbedded = listen('embedded-started', () => {
+      setIsStreaming(true);
+      setIsStarting(false);
+      setMode('embedded');
       setStatus({ msg: "Mirror Started", type: 'success' });
       setTimeout(() => setStatus(null), 2000);
     });
@@ -65,13 +144,15 @@ export function MirrorControls({ selectedSerial }: Props) {
     return () => {
       unlistenStop.then((f: UnlistenFn) => f());
       unlistenStart.then((f: UnlistenFn) => f());
+      unlistenEmbedded.then((f: UnlistenFn) => f());
+      unlistenEvent.then((f: UnlistenFn) => f());
     };
   }, []);
 
   async function handleStop() {
     setIsStarting(true);
     try {
-      await invoke("stop_mirror");
+      await invoke(mode === 'embedded' ? "stop_embedded_mirror" : "stop_mirror");
     } catch (e) {
       setStatus({ msg: `Stop Failed: ${e}`, type: 'error' });
     } finally {
@@ -104,14 +185,17 @@ export function MirrorControls({ selectedSerial }: Props) {
 
       // 1. Unified Init (Push if needed, Forward, Start)
       // This is the "Optimized Algorithm" running in Rust
-      await invoke("init_session", { serial: target });
+      await invoke("init_session", { serial: target, secure: secure });
 
-      // 2. Client Launch
+      // 2. Stream into this window (Pop Out moves it to its own)
       setStatus({ msg: "Launching stream...", type: 'info' });
-      await invoke("start_mirror", {
+      await invoke("start_embedded_mirror", {
         serial: target,
         bitrate: bitrate,
         maxSize: resolution,
+        maxFps: maxFps,
+        adaptive: adaptive,
+        secure: secure,
         turnScreenOff: turnScreenOff
       });
     } catch (e) {
@@ -121,6 +205,28 @@ export function MirrorControls({ selectedSerial }: Props) {
     }
   }
 
+  async function handlePopOut() {
+    const target = connectionMode === 'wifi' ? `${wifiIp}:5555` : selectedSerial;
+    setIsStarting(true);
+    try {
+      // Ends the embedded session and opens the mirror window on the same device
+      await invoke("start_mirror", {
+        serial: target,
+        bitrate: bitrate,
+        maxSize: resolution,
+        maxFps: maxFps,
+        bufferMs: bufferMs,
+        adaptive: adaptive,
+        secure: secure,
+        turnScreenOff: turnScreenOff
+      });
+    } catch (e) {
+      setStatus({ msg: `Pop Out Failed: ${e}`, type: 'error' });
+    } finally {
+      setIsStarting(false);
+    }
+  }
+
   return (
     <div className="panel" style={{ display: 'flex', flexDirection: 'column', gap: 24 }}>
       <div className="panel-header" style={{ marginBottom: 0 }}>
@@ -193,10 +299,10 @@ export function MirrorControls({ selectedSerial }: Props) {
             value={resolution}
             onChange={(v) => setResolution(Number(v))}
             options={[
-              { value: 720, label: "720p" },
-              { value: 1080, label: "1080p (Rec)" },
-              { value: 1440, label: "2K" },
-              { value: 2160, label: "4K" }
+              { value: 0, label: "Native (Rec)" },
+              { value: 1280, label: "720p" },
+              { value: 1920, label: "1080p" },
+              { value: 2560, label: "2K" }
             ]}
           />
         </div>
@@ -214,6 +320,33 @@ export function MirrorControls({ selectedSerial }: Props) {
             ]}
           />
         </div>
+
+        <div className="form-group">
+          <label>Frame Rate</label>
+          <CustomSelect
+            value={maxFps}
+            onChange={(v) => setMaxFps(Number(v))}
+            options={[
+              { value: 0, label: "Unlimited" },
+              { value: 60, label: "60 fps" },
+              { value: 30, label: "30 fps" },
+              { value: 15, label: "15 fps" }
+            ]}
+          />
+        </div>
+
+        <div className="form-group">
+          <label>Playback</label>
+          <CustomSelect
+            value={bufferMs}
+            onChange={(v) => setBufferMs(Number(v))}
+            options={[
+              { value: 0, label: "Lowest latency" },
+              { value: 50, label: "Balanced" },
+              { value: 150, label: "Smooth (video)" }
+            ]}
+          />
+        </div>
       </div>
 
       <div className="form-group fade-in">
@@ -228,8 +361,38 @@ export function MirrorControls({ selectedSerial }: Props) {
         </label>
       </div>
 
+      <div className="form-group fade-in">
+        <label style={{ display: 'flex', alignItems: 'center', gap: 10, cursor: 'pointer', color: 'var(--text-normal)', fontSize: 13 }}>
+          <input
+            type="checkbox"
+            checked={adaptive}
+            onChange={e => setAdaptive(e.target.checked)}
+            style={{ accentColor: 'var(--primary)', width: 16, height: 16, cursor: 'pointer' }}
+          />
+          Adapt quality to the connection
+        </label>
+      </div>
+
+      <div className="form-group fade-in">
+        <label style={{ display: 'flex', alignItems: 'center', gap: 10, cursor: 'pointer', color: 'var(--text-normal)', fontSize: 13 }}>
+          <input
+            type="checkbox"
+            checked={secure}
+            onChange={e => setSecure(e.target.checked)}
+            style={{ accentColor: 'var(--primary)', width: 16, height: 16, cursor: 'pointer' }}
+          />
+          Encrypt connection (key pinned to this device)
+        </label>
+      </div>
+
       <div style={{ flex: 1 }}></div>
 
+      {isStreaming && (resolutionInfo || statsInfo) && (
+        <div className="fade-in" style={{ fontSize: 12, color: 'var(--text-muted)', fontVariantNumeric: 'tabular-nums' }}>
+          {[resolutionInfo, statsInfo].filter(Boolean).join(' · ')}
+        </div>
+      )}
+
       {status && (
         <div className={`fade-in`} style={{
           display: 'flex',
@@ -246,6 +409,12 @@ export function MirrorControls({ selectedSerial }: Props) {
         </div>
       )}
 
+      {mode === 'embedded' && (
+        <button className="tab-btn" style={{ justifyContent: 'center', padding: 10 }} disabled={isStarting} onClick={handlePopOut}>
+          <ExternalLink size={14} /> Pop Out
+        </button>
+      )}
+
       <button
         className={`btn-primary ${isStreaming ? 'btn-danger' : ''}`}
         disabled={(!selectedSerial && connectionMode === 'usb') || (connectionMode === 'wifi' && !wifiIp) || isStarting}
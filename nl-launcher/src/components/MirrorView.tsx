@@ -0,0 +1,259 @@
+import { invoke } from "@tauri-apps/api/core";
+import { ArrowLeft, Circle, RotateCw, Square } from "lucide-react";
+import { useEffect, useRef, type KeyboardEvent, type MouseEvent, type PointerEvent } from "react";
+
+/** Input for `embedded_input`, in video pixels */
+type EmbeddedInput =
+  | { kind: 'tap', x: number, y: number }
+  | { kind: 'swipe', x1: number, y1: number, x2: number, y2: number, duration_ms: number }
+  | { kind: 'long_press', x: number, y: number }
+  | { kind: 'key', action: 'down' | 'up', keycode: number, meta: number }
+  | { kind: 'text', text: string }
+  | { kind: 'rotate' };
+
+// Android keycodes for keys that are not typed as text
+const KEYCODES: Record<string, number> = {
+  Enter: 66,
+  Backspace: 67,
+  Delete: 112,
+  Tab: 61,
+  Escape: 4, // Back
+  ArrowUp: 19,
+  ArrowDown: 20,
+  ArrowLeft: 21,
+  ArrowRight: 22,
+};
+
+const VERTEX_SHADER = `
+attribute vec2 a_pos;
+uniform vec2 u_y_scale;
+uniform vec2 u_uv_scale;
+varying vec2 v_y;
+varying vec2 v_uv;
+void main() {
+  vec2 t = vec2((a_pos.x + 1.0) / 2.0, (1.0 - a_pos.y) / 2.0);
+  v_y = t * u_y_scale;
+  v_uv = t * u_uv_scale;
+  gl_Position = vec4(a_pos, 0.0, 1.0);
+}`;
+
+// Same conversion as nl-host's YuvToRgb (coefficients come with every frame)
+const FRAGMENT_SHADER = `
+precision mediump float;
+uniform sampler2D u_y;
+uniform sampler2D u_u;
+uniform sampler2D u_v;
+uniform vec4 u_offsets; // y_offset, y_scale, uv_offset, uv_scale
+uniform vec4 u_coeffs;  // cr_r, cb_g, cr_g, cb_b
+varying vec2 v_y;
+varying vec2 v_uv;
+void main() {
+  float y = (texture2D(u_y, v_y).r - u_offsets.x) * u_offsets.y;
+  float u = (texture2D(u_u, v_uv).r - u_offsets.z) * u_offsets.w;
+  float v = (texture2D(u_v, v_uv).r - u_offsets.z) * u_offsets.w;
+  gl_FragColor = vec4(
+    y + u_coeffs.x * v,
+    y - u_coeffs.y * u - u_coeffs.z * v,
+    y + u_coeffs.w * u,
+    1.0
+  );
+}`;
+
+/** Size of the frame header written by `embedded_frame` */
+const HEADER_SIZE = 4 * 4 + 8 * 4;
+
+interface Renderer {
+  gl: WebGLRenderingContext;
+  program: WebGLProgram;
+  textures: WebGLTexture[];
+}
+
+function createRenderer(canvas: HTMLCanvasElement): Renderer | null {
+  const gl = canvas.getContext('webgl');
+  if (!gl) return null;
+
+  const compile = (type: number, source: string) => {
+    const shader = gl.createShader(type)!;
+    gl.shaderSource(shader, source);
+    gl.compileShader(shader);
+    return shader;
+  };
+  const program = gl.createProgram()!;
+  gl.attachShader(program, compile(gl.VERTEX_SHADER, VERTEX_SHADER));
+  gl.attachShader(program, compile(gl.FRAGMENT_SHADER, FRAGMENT_SHADER));
+  gl.linkProgram(program);
+  gl.useProgram(program);
+
+  const quad = gl.createBuffer();
+  gl.bindBuffer(gl.ARRAY_BUFFER, quad);
+  gl.bufferData(gl.ARRAY_BUFFER, new Float32Array([-1, -1, 1, -1, -1, 1, 1, 1]), gl.STATIC_DRAW);
+  const pos = gl.getAttribLocation(program, 'a_pos');
+  gl.enableVertexAttribArray(pos);
+  gl.vertexAttribPointer(pos, 2, gl.FLOAT, false, 0, 0);
+
+  gl.pixelStorei(gl.UNPACK_ALIGNMENT, 1);
+  const textures = ['u_y', 'u_u', 'u_v'].map((name, unit) => {
+    const texture = gl.createTexture()!;
+    gl.activeTexture(gl.TEXTURE0 + unit);
+    gl.bindTexture(gl.TEXTURE_2D, texture);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MIN_FILTER, gl.LINEAR);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_MAG_FILTER, gl.LINEAR);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_S, gl.CLAMP_TO_EDGE);
+    gl.texParameteri(gl.TEXTURE_2D, gl.TEXTURE_WRAP_T, gl.CLAMP_TO_EDGE);
+    gl.uniform1i(gl.getUniformLocation(program, name), unit);
+    return texture;
+  });
+  return { gl, program, textures };
+}
+
+/** Upload and draw one frame from `embedded_frame` */
+function drawFrame(canvas: HTMLCanvasElement, { gl, program, textures }: Renderer, buffer: ArrayBuffer) {
+  const header = new DataView(buffer, 0, HEADER_SIZE);
+  const width = header.getUint32(0, true);
+  const height = header.getUint32(4, true);
+  const yStride = header.getUint32(8, true);
+  const uvStride = header.getUint32(12, true);
+  const c = Array.from({ length: 8 }, (_, i) => header.getFloat32(16 + i * 4, true));
+  const uvHeight = Math.ceil(height / 2);
+
+  if (canvas.width !== width || canvas.height !== height) {
+    canvas.width = width;
+    canvas.height = height;
+  }
+  gl.viewport(0, 0, width, height);
+
+  const planes = [
+    { stride: yStride, rows: height, offset: HEADER_SIZE },
+    { stride: uvStride, rows: uvHeight, offset: HEADER_SIZE + yStride * height },
+    { stride: uvStride, rows: uvHeight, offset: HEADER_SIZE + yStride * height + uvStride * uvHeight },
+  ];
+  planes.forEach(({ stride, rows, offset }, unit) => {
+    gl.activeTexture(gl.TEXTURE0 + unit);
+    gl.bindTexture(gl.TEXTURE_2D, textures[unit]);
+    const data = new Uint8Array(buffer, offset, stride * rows);
+    gl.texImage2D(gl.TEXTURE_2D, 0, gl.LUMINANCE, stride, rows, 0, gl.LUMINANCE, gl.UNSIGNED_BYTE, data);
+  });
+
+  // Textures are stride wide; only the picture part is shown
+  gl.uniform2f(gl.getUniformLocation(program, 'u_y_scale'), width / yStride, 1);
+  gl.uniform2f(gl.getUniformLocation(program, 'u_uv_scale'), width / 2 / uvStride, 1);
+  gl.uniform4f(gl.getUniformLocation(program, 'u_offsets'), c[0], c[1], c[2], c[3]);
+  gl.uniform4f(gl.getUniformLocation(program, 'u_coeffs'), c[4], c[5], c[6], c[7]);
+  gl.drawArrays(gl.TRIANGLE_STRIP, 0, 4);
+}
+
+function send(input: EmbeddedInput) {
+  invoke("embedded_input", { input }).catch(() => { });
+}
+
+function pressKey(keycode: number) {
+  send({ kind: 'key', action: 'down', keycode, meta: 0 });
+  send({ kind: 'key', action: 'up', keycode, meta: 0 });
+}
+
+/** The embedded mirror: the device screen inside the launcher window */
+export function MirrorView() {
+  const canvasRef = useRef<HTMLCanvasElement>(null);
+  const dragStart = useRef<{ x: number, y: number, clientX: number, clientY: number } | null>(null);
+
+  // Pull the newest frame once per animation frame; the command waits until there is one
+  useEffect(() => {
+    const canvas = canvasRef.current;
+    if (!canvas) return;
+    const renderer = createRenderer(canvas);
+    if (!renderer) return;
+
+    let running = true;
+    const loop = async () => {
+      try {
+        const buffer = await invoke<ArrayBuffer>("embedded_frame");
+        if (running && buffer.byteLength > HEADER_SIZE) {
+          drawFrame(canvas, renderer, buffer);
+        }
+      } catch {
+        // Session ending; the parent unmounts us
+      }
+      if (running) requestAnimationFrame(loop);
+    };
+    requestAnimationFrame(loop);
+    return () => { running = false; };
+  }, []);
+
+  /** Pointer position in video pixels */
+  function videoPoint(e: PointerEvent<HTMLCanvasElement> | MouseEvent<HTMLCanvasElement>) {
+    const canvas = e.currentTarget;
+    const rect = canvas.getBoundingClientRect();
+    return {
+      x: (e.clientX - rect.left) / rect.width * canvas.width,
+      y: (e.clientY - rect.top) / rect.height * canvas.height,
+    };
+  }
+
+  function handlePointerDown(e: PointerEvent<HTMLCanvasElement>) {
+    if (e.button !== 0) return;
+    e.currentTarget.focus();
+    e.currentTarget.setPointerCapture(e.pointerId);
+    dragStart.current = { ...videoPoint(e), clientX: e.clientX, clientY: e.clientY };
+  }
+
+  function handlePointerUp(e: PointerEvent<HTMLCanvasElement>) {
+    const start = dragStart.current;
+    dragStart.current = null;
+    if (e.button !== 0 || !start) return;
+    const end = videoPoint(e);
+    // Same threshold as the mirror window: under 5 pixels is a tap
+    if (Math.abs(e.clientX - start.clientX) < 5 && Math.abs(e.clientY - start.clientY) < 5) {
+      send({ kind: 'tap', x: start.x, y: start.y });
+    } else {
+      send({ kind: 'swipe', x1: start.x, y1: start.y, x2: end.x, y2: end.y, duration_ms: 100 });
+    }
+  }
+
+  function handleContextMenu(e: MouseEvent<HTMLCanvasElement>) {
+    e.preventDefault();
+    send({ kind: 'long_press', ...videoPoint(e) });
+  }
+
+  function handleKey(e: KeyboardEvent<HTMLCanvasElement>, action: 'down' | 'up') {
+    const keycode = KEYCODES[e.key];
+    if (keycode !== undefined) {
+      e.preventDefault();
+      send({ kind: 'key', action, keycode, meta: 0 });
+    } else if (action === 'down' && e.key.length === 1 && !e.ctrlKey && !e.metaKey && !e.altKey) {
+      e.preventDefault();
+      send({ kind: 'text', text: e.key });
+    }
+  }
+
+  return (
+    <div className="panel fade-in" style={{ display: 'flex', flexDirection: 'column', gap: 12, padding: 16 }}>
+      <div style={{ flex: 1, minHeight: 0, display: 'flex', alignItems: 'center', justifyContent: 'center' }}>
+        <canvas
+          ref={canvasRef}
+          tabIndex={0}
+          onPointerDown={handlePointerDown}
+          onPointerUp={handlePointerUp}
+          onContextMenu={handleContextMenu}
+          onKeyDown={(e) => handleKey(e, 'down')}
+          onKeyUp={(e) => handleKey(e, 'up')}
+          style={{ maxWidth: '100%', maxHeight: '100%', borderRadius: 'var(--radius-md)', background: 'black', outline: 'none', touchAction: 'none' }}
+        />
+      </div>
+
+      <div style={{ display: 'flex', justifyContent: 'center', gap: 10 }}>
+        <button className="tab-btn" onClick={() => pressKey(4)} title="Back">
+          <ArrowLeft size={14} />
+        </button>
+        <button className="tab-btn" onClick={() => pressKey(3)} title="Home">
+          <Circle size={14} />
+        </button>
+        <button className="tab-btn" onClick={() => pressKey(187)} title="Recent Apps">
+          <Square size={14} />
+        </button>
+        <button className="tab-btn" onClick={() => send({ kind: 'rotate' })} title="Rotate">
+          <RotateCw size={14} />
+        </button>
+      </div>
+    </div>
+  );
+}